@@ -1,7 +1,7 @@
 use std::error::Error;
 use std::time::Duration;
 use tokio::fs::File;
-use vdkio::av::{CodecData, CodecDataExt, CodecType, Demuxer, Muxer, Packet};
+use vdkio::av::{CodecData, CodecDataExt, CodecType, Demuxer, Muxer, Packet, Timebase};
 use vdkio::format::aac::{AACDemuxer, AACMuxer};
 
 #[tokio::main]
@@ -59,7 +59,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
             let packet = Packet::new(frame_data)
                 .with_stream_index(0)  // Use stream index 0 since we only have one stream
-                .with_pts(i * 23_220) // 23.22ms per frame at 44.1kHz
+                .with_pts(Timebase::from_rate(44_100).to_nanos(i * 1024)) // 1024 samples per frame
                 .with_duration(Duration::from_micros(23220))
                 .with_key_flag(true);
 
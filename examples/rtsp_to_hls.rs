@@ -6,7 +6,7 @@ use tokio::time;
 use vdkio::av::transcode::{
     StreamCodecData, TranscodeOptions, Transcoder, VideoDecoder, VideoEncoder,
 };
-use vdkio::av::{CodecDataExt, CodecType, Packet};
+use vdkio::av::{CodecDataExt, CodecType, Packet, Timebase};
 use vdkio::codec::h264::transcode::create_transcoder_for_resolution;
 use vdkio::config;
 use vdkio::error::VdkError;
@@ -49,6 +49,7 @@ async fn setup_variant(
     fps: u32,
     output_dir: &Path,
     codecs: Vec<StreamCodecData>,
+    all_variants: &[HLSVariant],
 ) -> Result<HLSVariantConfig, Box<dyn std::error::Error>> {
     let variant = HLSVariant {
         name: name.to_string(),
@@ -60,7 +61,8 @@ async fn setup_variant(
     let segmenter = HLSSegmenter::new(output_dir)
         .with_segment_duration(Duration::from_secs(2))
         .with_max_segments(5)
-        .with_variant(variant);
+        .with_variant(variant)
+        .with_master_variants(all_variants.to_vec());
 
     let ts_output = File::create(output_dir.join(format!("{}.ts", name))).await?;
     let mut muxer = TSMuxer::new(ts_output);
@@ -184,22 +186,37 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         ("low", 500_000, (640, 360), 30),      // 360p @ 30fps
     ];
 
+    // Every variant playlist is listed in the master playlist
+    let hls_variants: Vec<HLSVariant> = variants
+        .iter()
+        .map(|(name, bandwidth, resolution, _)| HLSVariant {
+            name: name.to_string(),
+            bandwidth: *bandwidth,
+            resolution: Some(*resolution),
+            codecs: "avc1.64001f,mp4a.40.2".to_string(),
+        })
+        .collect();
+
     let mut variant_configs = Vec::new();
     for (name, bandwidth, resolution, fps) in &variants {
-        let config =
-            setup_variant(name, *bandwidth, *resolution, *fps, hls_output_dir, codecs.clone()).await?;
+        let config = setup_variant(
+            name,
+            *bandwidth,
+            *resolution,
+            *fps,
+            hls_output_dir,
+            codecs.clone(),
+            &hls_variants,
+        )
+        .await?;
         variant_configs.push(config);
     }
 
-    // Create master playlist
-    let master_playlist_path = hls_output_dir.join("playlist.m3u8");
-    let mut master_playlist_file = File::create(&master_playlist_path).await?;
-
-    // Write initial master playlist
-    variant_configs[0]
-        .segmenter
-        .write_master_playlist(&mut master_playlist_file)
-        .await?;
+    // Write the master playlist and the empty variant playlists
+    let master_playlist_path = hls_output_dir.join("master.m3u8");
+    for config in &variant_configs {
+        config.segmenter.write_playlist_files().await?;
+    }
 
     // Start playback
     client.play().await?;
@@ -217,7 +234,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 Ok(Some(data)) => {
                     // Create base packet
                     let packet = Packet::new(Bytes::from(data))
-                        .with_pts(pts)
+                        .with_pts(Timebase::MPEG.to_nanos(pts))
                         .with_stream_index(0);
                     pts += pts_increment;
 
@@ -225,11 +242,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     for config in &mut variant_configs {
                         if config
                             .segmenter
-                            .should_start_new_segment(Duration::from_millis(pts as u64 / 90))
+                            .should_start_new_segment(Timebase::MPEG.to_duration(pts))
                         {
                             let _segment_file = config
                                 .segmenter
-                                .start_segment(Duration::from_millis(pts as u64 / 90))
+                                .start_segment(Timebase::MPEG.to_duration(pts))
                                 .await?;
 
                             // Transcode packet for this variant
@@ -241,16 +258,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                             config
                                 .segmenter
-                                .finish_segment(Duration::from_millis(
-                                    (pts + pts_increment) as u64 / 90,
-                                ))
+                                .finish_segment(Timebase::MPEG.to_duration(pts + pts_increment))
                                 .await?;
 
-                            // Update variant playlist
-                            let playlist_path =
-                                hls_output_dir.join(format!("{}.m3u8", config.name));
-                            let mut playlist_file = File::create(playlist_path).await?;
-                            config.segmenter.write_playlist(&mut playlist_file).await?;
+                            // Atomically rewrite the variant playlist
+                            config.segmenter.write_playlist_files().await?;
                         } else {
                             // Transcode packet for this variant
                             let transcoded_packets =
@@ -260,15 +272,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             }
                         }
                     }
-
-                    // Update master playlist periodically
-                    if pts % (pts_increment * 30) == 0 {
-                        // Every 30 frames
-                        variant_configs[0]
-                            .segmenter
-                            .write_master_playlist(&mut master_playlist_file)
-                            .await?;
-                    }
                 }
                 Ok(None) => {
                     println!("RTSP stream ended");
@@ -294,7 +297,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             // Re-create variant configs
                             let mut setup_success = true;
                             for (name, bandwidth, resolution, fps) in variants.iter() {
-                                match setup_variant(name, *bandwidth, *resolution, *fps, hls_output_dir, codecs.clone()).await {
+                                match setup_variant(name, *bandwidth, *resolution, *fps, hls_output_dir, codecs.clone(), &hls_variants).await {
                                     Ok(config) => {
                                         variant_configs.push(config);
                                     },
@@ -310,20 +313,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 break;
                             }
                             
-                            // Re-create master playlist file
-                            let master_playlist_path = hls_output_dir.join("playlist.m3u8");
-                            match File::create(&master_playlist_path).await {
-                                Ok(mut master_playlist_file) => {
-                                    if let Err(e) = variant_configs[0].segmenter.write_master_playlist(&mut master_playlist_file).await {
-                                        println!("Error re-writing master playlist: {}", e);
-                                        break;
-                                    }
-                                },
-                                Err(e) => {
-                                    println!("Error creating master playlist: {}", e);
-                                    break;
+                            // Re-write the master playlist and variant playlists
+                            for config in &variant_configs {
+                                if let Err(e) = config.segmenter.write_playlist_files().await {
+                                    println!("Error re-writing playlists: {}", e);
+                                    setup_success = false;
                                 }
                             }
+                            if !setup_success {
+                                break;
+                            }
 
                             match client.play().await {
                                 Ok(_) => {
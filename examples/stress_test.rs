@@ -5,7 +5,7 @@ use std::time::{Duration, Instant};
 use tokio::fs::File;
 use tokio::sync::mpsc;
 use tokio::time;
-use vdkio::av::{CodecData, CodecDataExt, CodecType, Packet};
+use vdkio::av::{CodecData, CodecDataExt, CodecType, Packet, Timebase};
 use vdkio::config;
 use vdkio::error::Result;
 use vdkio::format::rtsp::{MediaStream, RTSPClient, RTSPSetupOptions, TransportInfo};
@@ -181,10 +181,10 @@ async fn run_stress_test(output_dir: &Path, stats: Stats) -> Result<()> {
                     stats.bytes_processed.fetch_add(data.len() as u64, Ordering::Relaxed);
 
                     // Create packet with current timestamp
-                    let pts = start_time.elapsed().as_millis() as i64;
+                    let elapsed_ms = start_time.elapsed().as_millis() as i64;
                     let packet = Packet::new(data)
                         .with_stream_index(0)
-                        .with_pts(pts);
+                        .with_pts(Timebase::MILLISECONDS.to_nanos(elapsed_ms));
 
                     // Process packet for each variant
                     for muxer in &mut muxers {
@@ -195,7 +195,7 @@ async fn run_stress_test(output_dir: &Path, stats: Stats) -> Result<()> {
                         }
 
                         // Create new segment every SEGMENT_DURATION_MS
-                        if pts % SEGMENT_DURATION_MS == 0 {
+                        if elapsed_ms % SEGMENT_DURATION_MS == 0 {
                             if let Err(e) = muxer.write_trailer().await {
                                 stats.errors_encountered.fetch_add(1, Ordering::Relaxed);
                                 println!("Error writing trailer: {}", e);
@@ -1,7 +1,7 @@
 use bytes::Bytes;
 use tokio::fs::File as AsyncFile;
 use tokio::io::{AsyncReadExt, AsyncSeekExt, BufReader, BufWriter};
-use vdkio::av::{CodecData, CodecDataExt, CodecType, Packet};
+use vdkio::av::{CodecData, CodecDataExt, CodecType, Packet, Timebase};
 use vdkio::format::ts::{TSDemuxer, TSMuxer};
 use vdkio::format::{Demuxer, Muxer};
 
@@ -80,7 +80,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         let video_packet = Packet::new(Bytes::from(video_data))
             .with_stream_index(0)
-            .with_pts(i * 3600)
+            .with_pts(Timebase::MPEG.to_nanos(i * 3600))
             .with_key_flag(i % 5 == 0);
         muxer.write_packet(&video_packet).await?;
 
@@ -105,7 +105,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         let audio_packet = Packet::new(Bytes::from(audio_data))
             .with_stream_index(1)
-            .with_pts(i * 1200);
+            .with_pts(Timebase::MPEG.to_nanos(i * 1200));
         muxer.write_packet(&audio_packet).await?;
     }
 
@@ -5,6 +5,7 @@
 //!
 //! - Codec type identification and configuration
 //! - Media packet processing
+//! - Timestamp units and normalization
 //! - Demuxing and muxing operations
 //! - Transcoding capabilities
 //!
@@ -48,10 +49,28 @@ pub enum CodecType {
     H264,
     /// H.265/HEVC video codec
     H265,
+    /// MPEG-2 video codec, also decoding MPEG-1 video
+    MPEG2Video,
     /// Advanced Audio Coding (AAC)
     AAC,
     /// Opus audio codec
     OPUS,
+    /// Dolby Digital (AC-3) audio codec
+    AC3,
+    /// Dolby Digital Plus (E-AC-3) audio codec
+    EAC3,
+    /// MPEG-1/2 audio, Layer I, II or III (MP2, MP3)
+    MPEGAudio,
+    /// ID3 timed metadata
+    ID3,
+    /// DVB subtitles (ETSI EN 300 743)
+    DVBSubtitle,
+    /// EBU teletext (ETSI EN 300 472)
+    Teletext,
+    /// CEA-608/708 closed captions, as the cc_data triplets of ATSC A/53
+    ClosedCaptions,
+    /// Private data of an unknown format, passed through unchanged
+    PrivateData,
 }
 
 /// Trait for accessing codec-specific configuration and metadata
@@ -68,6 +87,33 @@ pub trait CodecData: Send + Sync {
     
     /// Returns codec-specific extra data (e.g., SPS/PPS for H.264)
     fn extra_data(&self) -> Option<&[u8]>;
+
+    /// Returns the sample rate in Hz of audio streams, if known
+    fn sample_rate(&self) -> Option<u32> {
+        None
+    }
+
+    /// Returns the number of channels of audio streams, if known
+    fn channels(&self) -> Option<u8> {
+        None
+    }
+
+    /// Returns the codec profile (e.g., `profile_idc` for H.264 or the audio
+    /// object type for AAC), if known
+    fn profile(&self) -> Option<u8> {
+        None
+    }
+
+    /// Returns the codec level (e.g., `level_idc` for H.264), if known
+    fn level(&self) -> Option<u8> {
+        None
+    }
+
+    /// Returns the ISO 639-2 language code of the stream (e.g., "eng"), if
+    /// known
+    fn language(&self) -> Option<&str> {
+        None
+    }
 }
 
 /// Extension trait for cloning boxed CodecData
@@ -109,6 +155,14 @@ pub trait Muxer: Send {
 pub mod packet;
 pub use packet::Packet;
 
+/// Time bases of timestamps and their conversion
+pub mod timebase;
+pub use timebase::Timebase;
+
+/// Timestamp unwrapping, rebasing and discontinuity repair
+pub mod normalize;
+pub use normalize::{TimestampNormalizer, TimestampUnwrapper};
+
 /// Transcoding functionality for converting between formats
 pub mod transcode;
 pub use transcode::*;
@@ -0,0 +1,479 @@
+use super::{CodecDataExt, CodecType, Packet, Timebase};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Default distance from the stream clock beyond which a timestamp is
+/// treated as a jump
+const DEFAULT_JUMP_THRESHOLD: Duration = Duration::from_secs(5);
+
+/// Largest difference between the timestamp of an audio packet and the
+/// one counted from its samples that is smoothed over, in nanoseconds
+const AUDIO_RESYNC_THRESHOLD: i64 = 100_000_000;
+
+/// Extends fixed-width timestamps across wraparound, e.g. the 33-bit
+/// PTS/DTS of MPEG-2 or the 32-bit timestamps of RTP.
+#[derive(Debug, Clone)]
+pub struct TimestampUnwrapper {
+    /// Number of values of the wrapped timestamps
+    wrap: i64,
+    /// Last extended timestamp
+    last: Option<i64>,
+}
+
+impl TimestampUnwrapper {
+    /// Creates an unwrapper for timestamps of `bits` bits.
+    ///
+    /// # Arguments
+    ///
+    /// * `bits` - Width of the timestamps, from 1 to 62
+    pub fn new(bits: u32) -> Self {
+        assert!((1..=62).contains(&bits), "unsupported timestamp width");
+        Self {
+            wrap: 1 << bits,
+            last: None,
+        }
+    }
+
+    /// Extends a timestamp to the value closest to the previous one.
+    pub fn unwrap(&mut self, timestamp: u64) -> i64 {
+        let value = self.nearest(timestamp);
+        self.last = Some(value);
+        value
+    }
+
+    /// Returns the extension of a timestamp closest to the previous one,
+    /// without updating the state.
+    pub fn nearest(&self, timestamp: u64) -> i64 {
+        let timestamp = (timestamp & (self.wrap as u64 - 1)) as i64;
+        match self.last {
+            Some(last) => {
+                let mut value = last - last.rem_euclid(self.wrap) + timestamp;
+                if value - last > self.wrap / 2 {
+                    value -= self.wrap;
+                } else if last - value > self.wrap / 2 {
+                    value += self.wrap;
+                }
+                value
+            }
+            None => timestamp,
+        }
+    }
+
+    /// Sets the extended timestamp the next ones are compared with, e.g.
+    /// after seeking.
+    pub fn reset(&mut self, last: Option<i64>) {
+        self.last = last;
+    }
+}
+
+/// Timestamp state of one stream of a [`TimestampNormalizer`].
+#[derive(Debug, Clone, Default)]
+struct StreamTimestamps {
+    /// Time base of the input timestamps
+    timebase: Timebase,
+    /// Unwrapper of fixed-width input timestamps
+    unwrapper: Option<TimestampUnwrapper>,
+    /// Sample rate and samples per packet, if fixed, of audio streams to
+    /// smooth
+    audio: Option<(u32, Option<u32>)>,
+    /// First output timestamp and samples counted since, for smoothing
+    sample_clock: Option<(i64, u64)>,
+    /// Time of the previous packet if its samples are not counted yet
+    uncounted: Option<i64>,
+    /// Whether the next packet starts after a discontinuity
+    discontinuity: bool,
+}
+
+/// Normalizes the timestamps of packets from sources that use another unit,
+/// wrap around, start at arbitrary values or jump.
+///
+/// The normalizer converts timestamps to nanoseconds, extends wrapped
+/// timestamps, rebases the first timestamp of all streams to zero and
+/// removes jumps, continuing the timeline where it was and marking the
+/// next packet of each stream with [`Packet::is_discontinuity`]. Audio
+/// timestamps can also be counted from the samples of the packets, hiding
+/// the jitter of the input timestamps.
+///
+/// Streams that are not configured are taken to have nanosecond timestamps
+/// that do not wrap.
+#[derive(Debug, Clone)]
+pub struct TimestampNormalizer {
+    /// Per-stream state, by stream index
+    streams: HashMap<usize, StreamTimestamps>,
+    /// Distance from the clock beyond which a timestamp is a jump
+    jump_threshold: i64,
+    /// Whether the timeline starts at zero
+    rebase: bool,
+    /// Amount subtracted from input timestamps in nanoseconds
+    offset: Option<i64>,
+    /// Latest output decoding time of any stream
+    clock: Option<i64>,
+}
+
+impl Default for TimestampNormalizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimestampNormalizer {
+    /// Creates a normalizer that rebases timestamps to zero and removes
+    /// jumps of more than 5 seconds.
+    pub fn new() -> Self {
+        Self {
+            streams: HashMap::new(),
+            jump_threshold: DEFAULT_JUMP_THRESHOLD.as_nanos() as i64,
+            rebase: true,
+            offset: None,
+            clock: None,
+        }
+    }
+
+    /// Sets the time base of the input timestamps of a stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the stream
+    /// * `timebase` - Unit of the stream's input timestamps
+    ///
+    /// # Returns
+    ///
+    /// Returns self for method chaining
+    pub fn with_timebase(mut self, stream_index: usize, timebase: Timebase) -> Self {
+        self.stream(stream_index).timebase = timebase;
+        self
+    }
+
+    /// Sets the width of the input timestamps of a stream, which are
+    /// extended across wraparound.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the stream
+    /// * `bits` - Width of the input timestamps, e.g. 33 for MPEG-2 PTS/DTS
+    ///   or 32 for RTP
+    ///
+    /// # Returns
+    ///
+    /// Returns self for method chaining
+    pub fn with_wraparound(mut self, stream_index: usize, bits: u32) -> Self {
+        self.stream(stream_index).unwrapper = Some(TimestampUnwrapper::new(bits));
+        self
+    }
+
+    /// Counts the timestamps of an audio stream from its samples.
+    ///
+    /// Packets take the samples of their duration if set, or
+    /// `frame_samples` otherwise. Without either, a packet takes the samples
+    /// up to the input timestamp of the next one, which keeps its jitter.
+    /// Input timestamps more than 100 ms from the counted ones restart the
+    /// count.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the stream
+    /// * `sample_rate` - Sample rate in Hz
+    /// * `frame_samples` - Samples per packet if fixed, e.g. 1024 for AAC
+    ///
+    /// # Returns
+    ///
+    /// Returns self for method chaining
+    pub fn with_audio_stream(
+        mut self,
+        stream_index: usize,
+        sample_rate: u32,
+        frame_samples: Option<u32>,
+    ) -> Self {
+        if sample_rate > 0 {
+            self.stream(stream_index).audio = Some((sample_rate, frame_samples));
+        }
+        self
+    }
+
+    /// Counts the timestamps of all audio streams with a known sample rate
+    /// from their samples, see [`with_audio_stream`](Self::with_audio_stream).
+    ///
+    /// The samples per packet are known for AAC, AC-3 and MPEG audio with
+    /// its layer in the codec profile. E-AC-3 and Opus frames vary in
+    /// length, so their packets without a duration are counted from the
+    /// input timestamps.
+    ///
+    /// # Arguments
+    ///
+    /// * `streams` - Streams of the input, as returned by its demuxer
+    ///
+    /// # Returns
+    ///
+    /// Returns self for method chaining
+    pub fn with_streams(mut self, streams: &[Box<dyn CodecDataExt>]) -> Self {
+        for (index, stream) in streams.iter().enumerate() {
+            let frame_samples = match stream.codec_type() {
+                CodecType::AAC => Some(1024),
+                CodecType::AC3 => Some(1536),
+                // Layer III has half the samples at the MPEG-2/2.5 rates
+                CodecType::MPEGAudio => match (stream.profile(), stream.sample_rate()) {
+                    (Some(1), _) => Some(384),
+                    (Some(2), _) => Some(1152),
+                    (Some(3), Some(rate)) if rate < 32_000 => Some(576),
+                    (Some(3), _) => Some(1152),
+                    _ => None,
+                },
+                CodecType::EAC3 | CodecType::OPUS => None,
+                _ => continue,
+            };
+            if let Some(sample_rate) = stream.sample_rate() {
+                self = self.with_audio_stream(index, sample_rate, frame_samples);
+            }
+        }
+        self
+    }
+
+    /// Sets the distance from the latest timestamp beyond which a timestamp
+    /// is treated as a jump.
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - Largest gap or step back kept in the timeline
+    ///
+    /// # Returns
+    ///
+    /// Returns self for method chaining
+    pub fn with_jump_threshold(mut self, threshold: Duration) -> Self {
+        self.jump_threshold = threshold.as_nanos().min(i64::MAX as u128) as i64;
+        self
+    }
+
+    /// Sets whether the first timestamp becomes zero, or the input timeline
+    /// is kept up to its first jump.
+    ///
+    /// # Arguments
+    ///
+    /// * `rebase` - True to start the timeline at zero, the default
+    ///
+    /// # Returns
+    ///
+    /// Returns self for method chaining
+    pub fn with_rebase(mut self, rebase: bool) -> Self {
+        self.rebase = rebase;
+        self
+    }
+
+    /// Normalizes the timestamps of a packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet with timestamps in its stream's input time base
+    ///
+    /// # Returns
+    ///
+    /// The packet with nanosecond timestamps on the normalized timeline
+    pub fn normalize(&mut self, mut packet: Packet) -> Packet {
+        let stream = self.stream(packet.stream_index);
+        let timebase = stream.timebase;
+        let mut convert = |timestamp: i64| {
+            let timestamp = match stream.unwrapper.as_mut() {
+                Some(unwrapper) => unwrapper.unwrap(timestamp as u64),
+                None => timestamp,
+            };
+            timebase.to_nanos(timestamp)
+        };
+        // The DTS first, so the PTS is unwrapped relative to it
+        let dts = packet.dts.map(&mut convert);
+        let pts = packet.pts.map(&mut convert);
+
+        let Some(reference) = dts.or(pts) else {
+            return packet;
+        };
+        let offset = *self
+            .offset
+            .get_or_insert(if self.rebase { reference } else { 0 });
+        let mut shift = offset;
+        if let Some(clock) = self.clock {
+            let time = reference - offset;
+            if (time - clock).abs() > self.jump_threshold {
+                // Continue the timeline one packet after the latest one
+                let step = packet.duration.map_or(0, |d| d.as_nanos() as i64);
+                shift = offset + time - clock - step;
+                self.offset = Some(shift);
+                for stream in self.streams.values_mut() {
+                    stream.discontinuity = true;
+                    stream.sample_clock = None;
+                }
+            }
+        }
+
+        let stream = self.stream(packet.stream_index);
+        packet.is_discontinuity |= std::mem::take(&mut stream.discontinuity);
+        let mut pts = pts.map(|pts| pts - shift);
+        let mut dts = dts.map(|dts| dts - shift);
+        if let Some((sample_rate, frame_samples)) = stream.audio {
+            if packet.is_discontinuity {
+                stream.sample_clock = None;
+            }
+            let time = dts.or(pts).unwrap_or_default();
+            let timebase = Timebase::from_rate(sample_rate);
+            // The previous packet of unknown length ends where this one starts
+            if let (Some((start, samples)), Some(previous)) =
+                (stream.sample_clock, stream.uncounted.take())
+            {
+                let measured = timebase.from_nanos(time - previous).max(0) as u64;
+                stream.sample_clock = Some((start, samples + measured));
+            }
+            let (start, samples) = match stream.sample_clock {
+                Some((start, samples))
+                    if (start + timebase.to_nanos(samples as i64) - time).abs()
+                        <= AUDIO_RESYNC_THRESHOLD =>
+                {
+                    (start, samples)
+                }
+                _ => (time, 0),
+            };
+            let counted = start + timebase.to_nanos(samples as i64);
+            pts = pts.map(|pts| pts - time + counted);
+            dts = dts.map(|_| counted);
+            let packet_samples = packet
+                .duration
+                .map(|d| timebase.from_duration(d) as u64)
+                .or(frame_samples.map(u64::from));
+            if packet_samples.is_none() {
+                stream.uncounted = Some(time);
+            }
+            stream.sample_clock = Some((start, samples + packet_samples.unwrap_or(0)));
+        }
+
+        let time = dts.or(pts).unwrap_or_default();
+        self.clock = Some(self.clock.map_or(time, |clock| clock.max(time)));
+        packet.pts = pts;
+        packet.dts = dts;
+        packet
+    }
+
+    /// Returns the state of a stream, adding it if needed.
+    fn stream(&mut self, stream_index: usize) -> &mut StreamTimestamps {
+        self.streams.entry(stream_index).or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::av::CodecData;
+
+    const PTS_WRAP: i64 = 1 << 33;
+
+    #[test]
+    fn test_timestamp_unwrapper() {
+        let mut timestamps = TimestampUnwrapper::new(33);
+        assert_eq!(timestamps.unwrap(PTS_WRAP as u64 - 10), PTS_WRAP - 10);
+        assert_eq!(timestamps.unwrap(5), PTS_WRAP + 5);
+        // Slightly out of order values stay in the same period
+        assert_eq!(timestamps.unwrap(PTS_WRAP as u64 - 2), PTS_WRAP - 2);
+        assert_eq!(timestamps.unwrap(20), PTS_WRAP + 20);
+    }
+
+    #[test]
+    fn test_timestamp_normalizer() {
+        let ms = 1_000_000i64;
+        // 90 kHz video wrapping around 32 bits, audio in milliseconds
+        let mut normalizer = TimestampNormalizer::new()
+            .with_timebase(0, Timebase::MPEG)
+            .with_wraparound(0, 32)
+            .with_timebase(1, Timebase::MILLISECONDS)
+            .with_audio_stream(1, 48_000, Some(1024));
+        let video = |ticks: u32| {
+            Packet::new(vec![0])
+                .with_stream_index(0)
+                .with_pts(ticks as i64)
+        };
+        let audio = |time: i64| Packet::new(vec![0]).with_stream_index(1).with_pts(time);
+
+        // A whole number of milliseconds, wrapping after the second packet
+        let start = (u32::MAX - 3599) / 90 * 90;
+        let times: Vec<i64> = (0..3u32)
+            .map(|i| {
+                let packet = normalizer.normalize(video(start.wrapping_add(i * 3600)));
+                packet.pts.unwrap() / ms
+            })
+            .collect();
+        assert_eq!(times, [0, 40, 80]);
+
+        // Audio on the same clock, with jittery millisecond timestamps that
+        // are replaced by the sample count of 21.333 ms per packet
+        let audio_start = start as i64 / 90;
+        let times: Vec<i64> = [0, 22, 42, 64]
+            .iter()
+            .map(|delta| {
+                let packet = normalizer.normalize(audio(audio_start + delta));
+                packet.pts.unwrap()
+            })
+            .collect();
+        let counted = |frames: i64| Timebase::from_rate(48_000).to_nanos(frames * 1024);
+        assert_eq!(times, (0..4).map(counted).collect::<Vec<_>>());
+        assert!(normalizer.clock.unwrap() < 100 * ms);
+
+        // A jump of an hour continues the timeline and marks each stream
+        let packet = normalizer.normalize(
+            video(start.wrapping_add(3 * 3600 + 324_000_000))
+                .with_duration(Duration::from_millis(40)),
+        );
+        assert_eq!(packet.pts, Some(120 * ms));
+        assert!(packet.is_discontinuity);
+        let packet = normalizer.normalize(video(start.wrapping_add(4 * 3600 + 324_000_000)));
+        assert_eq!(packet.pts, Some(160 * ms));
+        assert!(!packet.is_discontinuity);
+        let packet = normalizer.normalize(audio(audio_start + 3_600_000 + 85));
+        assert!(packet.is_discontinuity);
+        assert_eq!(packet.pts, Some(85 * ms));
+    }
+
+    #[derive(Clone)]
+    struct AudioCodec(CodecType, u8);
+
+    impl CodecData for AudioCodec {
+        fn codec_type(&self) -> CodecType {
+            self.0
+        }
+        fn width(&self) -> Option<u32> {
+            None
+        }
+        fn height(&self) -> Option<u32> {
+            None
+        }
+        fn extra_data(&self) -> Option<&[u8]> {
+            None
+        }
+        fn sample_rate(&self) -> Option<u32> {
+            Some(24_000)
+        }
+        fn profile(&self) -> Option<u8> {
+            Some(self.1)
+        }
+    }
+
+    #[test]
+    fn test_timestamp_normalizer_audio_frame_sizes() {
+        let ms = 1_000_000i64;
+        // MPEG-2 Layer III at 24 kHz, and E-AC-3 with 3 blocks per frame
+        let streams: Vec<Box<dyn CodecDataExt>> = vec![
+            Box::new(AudioCodec(CodecType::MPEGAudio, 3)),
+            Box::new(AudioCodec(CodecType::EAC3, 0)),
+        ];
+        let mut normalizer = TimestampNormalizer::new().with_streams(&streams);
+        let mut times = |stream_index: usize, deltas: &[i64]| -> Vec<i64> {
+            deltas
+                .iter()
+                .map(|delta| {
+                    let packet = Packet::new(vec![0])
+                        .with_stream_index(stream_index)
+                        .with_pts(delta * ms);
+                    normalizer.normalize(packet).pts.unwrap()
+                })
+                .collect()
+        };
+
+        // 576 samples, 24 ms per frame, whatever the input jitter
+        assert_eq!(times(0, &[0, 25, 47, 72]), [0, 24 * ms, 48 * ms, 72 * ms]);
+        // 768 samples, 32 ms per frame, taken from the input timestamps
+        assert_eq!(times(1, &[0, 32, 64]), [0, 32 * ms, 64 * ms]);
+    }
+}
@@ -1,3 +1,4 @@
+use super::Timebase;
 use bytes::Bytes;
 use std::time::Duration;
 
@@ -6,13 +7,17 @@ use std::time::Duration;
 /// A `Packet` is the basic unit of media data in the vdkio system. It contains
 /// the actual media data along with timing information (PTS/DTS), stream identification,
 /// and other metadata necessary for proper media handling.
+///
+/// Timestamps are in nanoseconds ([`Packet::TIMEBASE`]) for all muxers and
+/// demuxers. Sources with another clock convert with [`Timebase::rescale`]
+/// or a [`TimestampNormalizer`](crate::av::TimestampNormalizer).
 #[derive(Debug, Clone)]
 pub struct Packet {
     /// The actual media data contained in the packet
     pub data: Bytes,
-    /// Presentation Time Stamp (PTS) in the media's time base
+    /// Presentation Time Stamp (PTS) in nanoseconds
     pub pts: Option<i64>,
-    /// Decoding Time Stamp (DTS) in the media's time base
+    /// Decoding Time Stamp (DTS) in nanoseconds
     pub dts: Option<i64>,
     /// Index of the stream this packet belongs to
     pub stream_index: usize,
@@ -20,9 +25,20 @@ pub struct Packet {
     pub is_key: bool,
     /// Duration of the media content in this packet
     pub duration: Option<Duration>,
+    /// Indicates that data of this stream was lost before this packet
+    pub is_discontinuity: bool,
+    /// Arrival time of the packet's first byte in the container, in 27 MHz
+    /// ticks, for containers that record it (e.g. M2TS)
+    pub arrival_timestamp: Option<u32>,
+    /// Byte offset in the input of the container packet the packet starts
+    /// in, for demuxers that track it
+    pub position: Option<u64>,
 }
 
 impl Packet {
+    /// Time base of the packet timestamps
+    pub const TIMEBASE: Timebase = Timebase::NANOSECONDS;
+
     /// Creates a new media packet with the given data.
     ///
     /// All timing and metadata fields are initialized to their default values:
@@ -43,6 +59,9 @@ impl Packet {
             stream_index: 0,
             is_key: false,
             duration: None,
+            is_discontinuity: false,
+            arrival_timestamp: None,
+            position: None,
         }
     }
 
@@ -50,7 +69,7 @@ impl Packet {
     ///
     /// # Arguments
     ///
-    /// * `pts` - The presentation timestamp value in the media's time base
+    /// * `pts` - The presentation timestamp in nanoseconds
     ///
     /// # Returns
     ///
@@ -64,7 +83,7 @@ impl Packet {
     ///
     /// # Arguments
     ///
-    /// * `dts` - The decoding timestamp value in the media's time base
+    /// * `dts` - The decoding timestamp in nanoseconds
     ///
     /// # Returns
     ///
@@ -122,4 +141,49 @@ impl Packet {
         self.duration = Some(duration);
         self
     }
+
+    /// Marks this packet as following lost data of its stream.
+    ///
+    /// Decoders should treat the stream as interrupted, e.g. by waiting for
+    /// the next key frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `is_discontinuity` - True if data was lost before this packet
+    ///
+    /// # Returns
+    ///
+    /// Returns self for method chaining
+    pub fn with_discontinuity(mut self, is_discontinuity: bool) -> Self {
+        self.is_discontinuity = is_discontinuity;
+        self
+    }
+
+    /// Sets the arrival timestamp of this packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp` - Arrival time of the packet's first byte in 27 MHz ticks
+    ///
+    /// # Returns
+    ///
+    /// Returns self for method chaining
+    pub fn with_arrival_timestamp(mut self, timestamp: u32) -> Self {
+        self.arrival_timestamp = Some(timestamp);
+        self
+    }
+
+    /// Sets the byte offset of this packet in the input.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - Offset of the container packet the packet starts in
+    ///
+    /// # Returns
+    ///
+    /// Returns self for method chaining
+    pub fn with_position(mut self, position: u64) -> Self {
+        self.position = Some(position);
+        self
+    }
 }
@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+/// The unit of a timestamp, as a fraction of a second.
+///
+/// [`Packet`](crate::av::Packet) timestamps are always in
+/// [`Timebase::NANOSECONDS`]. Timestamps of other clocks, e.g. the 90 kHz
+/// clock of MPEG and RTP video, are converted with [`Timebase::rescale`]
+/// when packets are created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Timebase {
+    /// Numerator of the tick length in seconds
+    pub num: u32,
+    /// Denominator of the tick length in seconds
+    pub den: u32,
+}
+
+impl Timebase {
+    /// Nanoseconds, the unit of all packet timestamps
+    pub const NANOSECONDS: Self = Self::new(1, 1_000_000_000);
+    /// Microseconds
+    pub const MICROSECONDS: Self = Self::new(1, 1_000_000);
+    /// Milliseconds
+    pub const MILLISECONDS: Self = Self::new(1, 1_000);
+    /// The 90 kHz clock of MPEG-2 PTS/DTS and RTP video
+    pub const MPEG: Self = Self::new(1, 90_000);
+
+    /// Creates a time base of `num / den` seconds per tick.
+    ///
+    /// # Arguments
+    ///
+    /// * `num` - Numerator of the tick length in seconds
+    /// * `den` - Denominator of the tick length in seconds, not zero
+    pub const fn new(num: u32, den: u32) -> Self {
+        assert!(den != 0, "time base denominator must not be zero");
+        Self { num, den }
+    }
+
+    /// Creates the time base of a clock running at `rate` Hz, e.g. an audio
+    /// sample rate.
+    pub const fn from_rate(rate: u32) -> Self {
+        Self::new(1, rate)
+    }
+
+    /// Converts a timestamp of this time base into another one.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - Timestamp in ticks of this time base
+    /// * `to` - Time base of the result
+    ///
+    /// # Returns
+    ///
+    /// The timestamp in ticks of `to`, rounded to the nearest tick
+    pub fn rescale(&self, value: i64, to: Timebase) -> i64 {
+        if *self == to {
+            return value;
+        }
+        let num = value as i128 * self.num as i128 * to.den as i128;
+        let den = self.den as i128 * to.num as i128;
+        if den == 0 {
+            return 0;
+        }
+        let half = den / 2;
+        let rounded = if num >= 0 { num + half } else { num - half };
+        (rounded / den).clamp(i64::MIN as i128, i64::MAX as i128) as i64
+    }
+
+    /// Converts a timestamp of this time base into nanoseconds.
+    pub fn to_nanos(&self, value: i64) -> i64 {
+        self.rescale(value, Self::NANOSECONDS)
+    }
+
+    /// Converts nanoseconds into a timestamp of this time base.
+    pub fn from_nanos(&self, nanos: i64) -> i64 {
+        Self::NANOSECONDS.rescale(nanos, *self)
+    }
+
+    /// Converts a duration into ticks of this time base.
+    pub fn from_duration(&self, duration: Duration) -> i64 {
+        self.from_nanos(duration.as_nanos().min(i64::MAX as u128) as i64)
+    }
+
+    /// Converts ticks of this time base into a duration, negative values
+    /// becoming zero.
+    pub fn to_duration(&self, value: i64) -> Duration {
+        Duration::from_nanos(self.to_nanos(value).max(0) as u64)
+    }
+}
+
+impl Default for Timebase {
+    fn default() -> Self {
+        Self::NANOSECONDS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rescale() {
+        assert_eq!(Timebase::MPEG.to_nanos(3600), 40_000_000);
+        assert_eq!(Timebase::MPEG.from_nanos(40_000_000), 3600);
+        assert_eq!(
+            Timebase::MILLISECONDS.rescale(1500, Timebase::MPEG),
+            135_000
+        );
+        // Rounded to the nearest tick, in both directions
+        assert_eq!(Timebase::MPEG.to_nanos(1), 11_111);
+        assert_eq!(Timebase::MPEG.to_nanos(-1), -11_111);
+        assert_eq!(Timebase::MPEG.from_nanos(5_556), 1);
+
+        let samples = Timebase::from_rate(44_100);
+        assert_eq!(samples.to_nanos(1024), 23_219_955);
+        assert_eq!(samples.to_nanos(44_100 * 3600), 3_600_000_000_000);
+        assert_eq!(samples.to_duration(-1024), Duration::ZERO);
+        assert_eq!(
+            Timebase::MPEG.from_duration(Duration::from_secs(2)),
+            180_000
+        );
+        assert_eq!(Timebase::new(1001, 30_000).to_nanos(1), 33_366_667);
+    }
+}
@@ -219,20 +219,30 @@ impl Transcoder {
             };
 
             match codec.codec_type() {
-                CodecType::H264 | CodecType::H265 => {
+                CodecType::H264 | CodecType::H265 | CodecType::MPEG2Video => {
                     if let Some(ref find_codec) = options.find_video_codec {
                         let (enc, dec) = find_codec(&codec)?;
                         s.video_encoder = Some(enc);
                         s.video_decoder = Some(dec);
                     }
                 }
-                CodecType::AAC | CodecType::OPUS => {
+                CodecType::AAC
+                | CodecType::OPUS
+                | CodecType::AC3
+                | CodecType::EAC3
+                | CodecType::MPEGAudio => {
                     if let Some(ref find_codec) = options.find_audio_codec {
                         let (enc, dec) = find_codec(&codec)?;
                         s.audio_encoder = Some(enc);
                         s.audio_decoder = Some(dec);
                     }
                 }
+                // Timed metadata, subtitles and private data are passed through as is
+                CodecType::ID3
+                | CodecType::DVBSubtitle
+                | CodecType::Teletext
+                | CodecType::ClosedCaptions
+                | CodecType::PrivateData => {}
             }
 
             out_streams.push(s);
@@ -36,6 +36,35 @@ pub struct AACConfig {
     pub frame_length: u16,
 }
 
+impl AACConfig {
+    /// Gets the actual sample rate in Hz from the sample rate index
+    ///
+    /// # Returns
+    ///
+    /// * `Some(rate)` - The sample rate in Hz if the index is valid
+    /// * `None` - If the sample rate index is invalid
+    pub fn sample_rate(&self) -> Option<u32> {
+        sample_rate_from_index(self.sample_rate_index)
+    }
+
+    /// Builds the AudioSpecificConfig (ISO/IEC 14496-3) for this configuration
+    ///
+    /// This is the decoder configuration that containers such as MP4 carry
+    /// as extra data in place of per-frame ADTS headers.
+    ///
+    /// # Returns
+    ///
+    /// The 2-byte AudioSpecificConfig
+    pub fn audio_specific_config(&self) -> [u8; 2] {
+        // The audio object type is the ADTS profile plus one
+        let object_type = self.profile as u8 + 1;
+        [
+            (object_type << 3) | ((self.sample_rate_index & 0xF) >> 1),
+            ((self.sample_rate_index & 0x1) << 7) | ((self.channel_configuration & 0xF) << 3),
+        ]
+    }
+}
+
 impl Default for AACConfig {
     fn default() -> Self {
         Self {
@@ -100,22 +129,7 @@ impl ADTSHeader {
     /// * `Some(rate)` - The sample rate in Hz if the index is valid (96000, 88200, etc.)
     /// * `None` - If the sample rate index is invalid
     pub fn sample_rate(&self) -> Option<u32> {
-        match self.sample_rate_index {
-            0 => Some(96000),
-            1 => Some(88200),
-            2 => Some(64000),
-            3 => Some(48000),
-            4 => Some(44100),
-            5 => Some(32000),
-            6 => Some(24000),
-            7 => Some(22050),
-            8 => Some(16000),
-            9 => Some(12000),
-            10 => Some(11025),
-            11 => Some(8000),
-            12 => Some(7350),
-            _ => None,
-        }
+        sample_rate_from_index(self.sample_rate_index)
     }
 
     /// Converts the ADTS header to its binary representation following ISO/IEC 13818-7
@@ -168,6 +182,26 @@ impl ADTSHeader {
     }
 }
 
+/// Maps a sampling frequency index to its sample rate in Hz
+fn sample_rate_from_index(index: u8) -> Option<u32> {
+    match index {
+        0 => Some(96000),
+        1 => Some(88200),
+        2 => Some(64000),
+        3 => Some(48000),
+        4 => Some(44100),
+        5 => Some(32000),
+        6 => Some(24000),
+        7 => Some(22050),
+        8 => Some(16000),
+        9 => Some(12000),
+        10 => Some(11025),
+        11 => Some(8000),
+        12 => Some(7350),
+        _ => None,
+    }
+}
+
 /// An AAC frame containing configuration and audio data
 ///
 /// Represents a complete AAC audio frame that can be decoded to produce audio samples.
@@ -221,4 +255,18 @@ mod tests {
         assert_eq!(bytes[0], 0xFF); // First byte of sync word
         assert_eq!(bytes[1] & 0xF0, 0xF0); // Last 4 bits of sync word
     }
+
+    #[test]
+    fn test_audio_specific_config() {
+        let config = AACConfig {
+            profile: ProfileType::LC,
+            sample_rate_index: 4, // 44.1kHz
+            channel_configuration: 2,
+            frame_length: 1024,
+        };
+
+        assert_eq!(config.sample_rate(), Some(44100));
+        // AAC-LC (object type 2), 44.1kHz, stereo
+        assert_eq!(config.audio_specific_config(), [0x12, 0x10]);
+    }
 }
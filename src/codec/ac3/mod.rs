@@ -0,0 +1,136 @@
+//! AC-3 and E-AC-3 (Dolby Digital / Dolby Digital Plus) syncframe headers.
+
+use crate::utils::bits::BitReader;
+use crate::{Result, VdkError};
+
+/// Sync word at the start of every AC-3 and E-AC-3 syncframe
+pub const AC3_SYNC_WORD: u16 = 0x0B77;
+
+/// Sample rates by fscod
+const SAMPLE_RATES: [u32; 3] = [48000, 44100, 32000];
+
+/// Reduced E-AC-3 sample rates by fscod2
+const REDUCED_SAMPLE_RATES: [u32; 3] = [24000, 22050, 16000];
+
+/// Full bandwidth channels by audio coding mode (acmod)
+const ACMOD_CHANNELS: [u8; 8] = [2, 1, 2, 3, 3, 4, 4, 5];
+
+/// Header fields of an AC-3 or E-AC-3 syncframe.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AC3FrameHeader {
+    /// Bit stream identification, 16 for E-AC-3
+    pub bsid: u8,
+    /// Sample rate in Hz
+    pub sample_rate: u32,
+    /// Audio coding mode
+    pub acmod: u8,
+    /// Whether the low frequency effects channel is present
+    pub lfe: bool,
+}
+
+impl AC3FrameHeader {
+    /// Parses the header of a syncframe.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Syncframe bytes starting at the sync word
+    ///
+    /// # Returns
+    ///
+    /// The header fields, or an error if `data` is not an AC-3 or E-AC-3
+    /// syncframe
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < 8 || u16::from_be_bytes([data[0], data[1]]) != AC3_SYNC_WORD {
+            return Err(VdkError::InvalidData("Missing AC-3 sync word".into()));
+        }
+        // bsid is at the same position in both syntaxes
+        let bsid = data[5] >> 3;
+        let mut reader = BitReader::new(&data[2..]);
+        match bsid {
+            0..=10 => {
+                reader.skip_bits(16)?; // crc1
+                let fscod = reader.read_bits(2)? as usize;
+                reader.skip_bits(6 + 5 + 3)?; // frmsizecod, bsid, bsmod
+                let acmod = reader.read_bits(3)? as u8;
+                if acmod & 0x01 != 0 && acmod != 1 {
+                    reader.skip_bits(2)?; // cmixlev
+                }
+                if acmod & 0x04 != 0 {
+                    reader.skip_bits(2)?; // surmixlev
+                }
+                if acmod == 2 {
+                    reader.skip_bits(2)?; // dsurmod
+                }
+                let lfe = reader.read_bit()?;
+                let sample_rate = *SAMPLE_RATES
+                    .get(fscod)
+                    .ok_or_else(|| VdkError::InvalidData("Reserved AC-3 sample rate".into()))?;
+                Ok(Self {
+                    bsid,
+                    sample_rate,
+                    acmod,
+                    lfe,
+                })
+            }
+            11..=16 => {
+                reader.skip_bits(2 + 3 + 11)?; // strmtyp, substreamid, frmsiz
+                let fscod = reader.read_bits(2)? as usize;
+                let fscod2 = reader.read_bits(2)? as usize; // or numblkscod
+                let acmod = reader.read_bits(3)? as u8;
+                let lfe = reader.read_bit()?;
+                let sample_rate = if fscod == 3 {
+                    *REDUCED_SAMPLE_RATES.get(fscod2).ok_or_else(|| {
+                        VdkError::InvalidData("Reserved E-AC-3 sample rate".into())
+                    })?
+                } else {
+                    SAMPLE_RATES[fscod]
+                };
+                Ok(Self {
+                    bsid,
+                    sample_rate,
+                    acmod,
+                    lfe,
+                })
+            }
+            _ => Err(VdkError::InvalidData(format!(
+                "Unsupported AC-3 bsid {}",
+                bsid
+            ))),
+        }
+    }
+
+    /// Returns true for an E-AC-3 syncframe.
+    pub fn is_enhanced(&self) -> bool {
+        self.bsid > 10
+    }
+
+    /// Returns the number of channels, including the LFE channel.
+    pub fn channels(&self) -> u8 {
+        ACMOD_CHANNELS[self.acmod as usize & 0x07] + self.lfe as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ac3_frame_header() {
+        // 48 kHz, 3/2 with LFE
+        let ac3 = [0x0B, 0x77, 0x00, 0x00, 0x04, 0x40, 0xE1, 0x40];
+        let header = AC3FrameHeader::parse(&ac3).unwrap();
+        assert!(!header.is_enhanced());
+        assert_eq!(header.sample_rate, 48000);
+        assert_eq!(header.acmod, 7);
+        assert_eq!(header.channels(), 6);
+
+        // E-AC-3 at 44.1 kHz, stereo without LFE
+        let eac3 = [0x0B, 0x77, 0x00, 0xFF, 0x74, 0x80, 0x00, 0x00];
+        let header = AC3FrameHeader::parse(&eac3).unwrap();
+        assert!(header.is_enhanced());
+        assert_eq!(header.sample_rate, 44100);
+        assert_eq!(header.channels(), 2);
+
+        assert!(AC3FrameHeader::parse(&[0x0B, 0x78, 0, 0, 0, 0, 0, 0]).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! CEA-608/708 closed captions in H.264 and H.265 SEI (ATSC A/53, Part 4).
+//!
+//! Captions travel in `user_data_registered_itu_t_t35` SEI messages as
+//! cc_data: a list of 3-byte triplets, each holding a CEA-608 byte pair or
+//! two bytes of a CEA-708 DTVCC packet.
+
+use crate::codec::h264::parser::remove_emulation_prevention;
+
+/// SEI payload type of user_data_registered_itu_t_t35
+pub const SEI_USER_DATA_REGISTERED: u32 = 4;
+
+/// ITU-T T.35 country code of the United States
+const COUNTRY_CODE_US: u8 = 0xB5;
+
+/// ITU-T T.35 provider code of ATSC
+const PROVIDER_CODE_ATSC: u16 = 0x0031;
+
+/// ATSC user identifier of caption and bar data
+pub const ATSC_IDENTIFIER: [u8; 4] = *b"GA94";
+
+/// ATSC user_data_type_code of cc_data
+const USER_DATA_TYPE_CC_DATA: u8 = 0x03;
+
+/// Size of a cc_data triplet in bytes
+pub const CC_TRIPLET_SIZE: usize = 3;
+
+/// One triplet of cc_data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CCTriplet {
+    /// Whether the triplet carries caption data
+    pub valid: bool,
+    /// 0 and 1 for CEA-608 fields 1 and 2, 2 and 3 for DTVCC packet data
+    /// and DTVCC packet starts
+    pub cc_type: u8,
+    /// The two caption bytes
+    pub data: [u8; 2],
+}
+
+impl CCTriplet {
+    /// Parses the triplets of cc_data.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - cc_data triplets, a trailing partial triplet is ignored
+    pub fn parse_all(data: &[u8]) -> Vec<Self> {
+        data.chunks_exact(CC_TRIPLET_SIZE)
+            .map(|triplet| Self {
+                valid: triplet[0] & 0x04 != 0,
+                cc_type: triplet[0] & 0x03,
+                data: [triplet[1], triplet[2]],
+            })
+            .collect()
+    }
+
+    /// Returns true for a CEA-608 byte pair of field 1 or 2.
+    pub fn is_cea608(&self) -> bool {
+        self.cc_type < 2
+    }
+}
+
+/// Extracts the cc_data triplets of an SEI NAL unit.
+///
+/// # Arguments
+///
+/// * `nal` - NAL unit without start code, emulation prevention included
+/// * `hevc` - Whether the NAL unit is H.265 rather than H.264
+///
+/// # Returns
+///
+/// The triplets of all caption SEI messages in the NAL unit, empty for other
+/// NAL units and SEI messages
+pub fn extract_cc_data(nal: &[u8], hevc: bool) -> Vec<u8> {
+    let (is_sei, header_len) = match nal.first() {
+        // Prefix and suffix SEI
+        Some(&header) if hevc => (matches!((header >> 1) & 0x3F, 39 | 40), 2),
+        Some(&header) => (header & 0x1F == 6, 1),
+        None => return Vec::new(),
+    };
+    if !is_sei || nal.len() <= header_len {
+        return Vec::new();
+    }
+
+    let rbsp = remove_emulation_prevention(&nal[header_len..]);
+    let mut cc_data = Vec::new();
+    let mut pos = 0;
+    while more_rbsp_data(&rbsp[pos..]) {
+        let (Some(payload_type), Some(payload_size)) = (
+            read_sei_value(&rbsp, &mut pos),
+            read_sei_value(&rbsp, &mut pos),
+        ) else {
+            break;
+        };
+        let Some(payload) = rbsp.get(pos..pos + payload_size as usize) else {
+            break;
+        };
+        pos += payload_size as usize;
+        if payload_type == SEI_USER_DATA_REGISTERED {
+            if let Some(triplets) = parse_t35_cc_data(payload) {
+                cc_data.extend_from_slice(triplets);
+            }
+        }
+    }
+    cc_data
+}
+
+/// Returns the cc_data triplets of a user_data_registered_itu_t_t35 payload.
+///
+/// # Arguments
+///
+/// * `payload` - SEI payload starting with the T.35 country code
+///
+/// # Returns
+///
+/// The triplets, or `None` if the payload is not ATSC cc_data or its
+/// captions are not to be processed
+pub fn parse_t35_cc_data(payload: &[u8]) -> Option<&[u8]> {
+    let (&country_code, data) = payload.split_first()?;
+    if country_code != COUNTRY_CODE_US {
+        return None;
+    }
+    let provider_code = u16::from_be_bytes(data.get(..2)?.try_into().ok()?);
+    if provider_code != PROVIDER_CODE_ATSC
+        || data.get(2..6)? != ATSC_IDENTIFIER
+        || *data.get(6)? != USER_DATA_TYPE_CC_DATA
+    {
+        return None;
+    }
+
+    // process_cc_data_flag and cc_count, then em_data
+    let flags = *data.get(7)?;
+    if flags & 0x40 == 0 {
+        return None;
+    }
+    let cc_count = (flags & 0x1F) as usize;
+    data.get(9..9 + cc_count * CC_TRIPLET_SIZE)
+}
+
+/// Reads a payload type or size of an SEI message, coded in bytes of 0xFF
+/// followed by the last byte.
+fn read_sei_value(data: &[u8], pos: &mut usize) -> Option<u32> {
+    let mut value = 0;
+    loop {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        value += byte as u32;
+        if byte != 0xFF {
+            return Some(value);
+        }
+    }
+}
+
+/// Returns true unless only the RBSP trailing bits are left.
+fn more_rbsp_data(data: &[u8]) -> bool {
+    match data.split_first() {
+        None => false,
+        Some((&0x80, rest)) => rest.iter().any(|&byte| byte != 0),
+        Some(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_cc_data() {
+        let cc_data = [0xFC, 0x94, 0x20, 0xFD, 0x80, 0x80, 0xFF, 0x02, 0x21];
+        let mut t35 = vec![0xB5, 0x00, 0x31];
+        t35.extend_from_slice(b"GA94");
+        t35.extend_from_slice(&[0x03, 0x40 | 3, 0xFF]);
+        t35.extend_from_slice(&cc_data);
+        t35.push(0xFF);
+
+        // A recovery point SEI before the captions, then the trailing bits
+        let mut sei = vec![0x06, 0x06, 0x01, 0x84, SEI_USER_DATA_REGISTERED as u8];
+        sei.push(t35.len() as u8);
+        sei.extend_from_slice(&t35);
+        sei.push(0x80);
+        assert_eq!(extract_cc_data(&sei, false), cc_data);
+
+        // The same message in an H.265 prefix SEI
+        let mut hevc = vec![39 << 1, 0x01];
+        hevc.extend_from_slice(&sei[1..]);
+        assert_eq!(extract_cc_data(&hevc, true), cc_data);
+        assert!(extract_cc_data(&hevc, false).is_empty());
+
+        let triplets = CCTriplet::parse_all(&cc_data);
+        assert_eq!(triplets.len(), 3);
+        assert!(triplets[0].valid && triplets[0].is_cea608());
+        assert_eq!(triplets[0].data, [0x94, 0x20]);
+        assert_eq!(triplets[1].cc_type, 1);
+        assert!(triplets[2].valid && !triplets[2].is_cea608());
+
+        // Bar data shares the identifier, and captions can be switched off
+        t35[7] = 0x06;
+        assert_eq!(parse_t35_cc_data(&t35), None);
+        t35[7] = 0x03;
+        t35[8] = 3;
+        assert_eq!(parse_t35_cc_data(&t35), None);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::error::{Result, VdkError};
+use crate::utils::BitReader;
 
 /// H.264/AVC Network Abstraction Layer (NAL) unit types as defined in ITU-T H.264
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -149,6 +150,161 @@ impl<'a> NALUnit<'a> {
     }
 }
 
+/// Fields of an H.264 Sequence Parameter Set needed to describe a stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct SPSInfo {
+    /// Profile indication (e.g., 66 Baseline, 77 Main, 100 High)
+    pub profile_idc: u8,
+    /// Constraint set flags
+    pub constraint_flags: u8,
+    /// Level indication, ten times the level number
+    pub level_idc: u8,
+    /// Sequence parameter set id
+    pub seq_parameter_set_id: u32,
+    /// Chroma format (1 for 4:2:0)
+    pub chroma_format_idc: u32,
+    /// Picture width in pixels after cropping
+    pub width: u32,
+    /// Picture height in pixels after cropping
+    pub height: u32,
+}
+
+impl SPSInfo {
+    /// Parses a Sequence Parameter Set NAL unit
+    ///
+    /// # Arguments
+    ///
+    /// * `nal` - SPS NAL unit including its header byte, without start code
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(SPSInfo)` - The parsed parameter set
+    /// * `Err(_)` - If the NAL unit is not an SPS or is truncated
+    pub fn parse(nal: &[u8]) -> Result<Self> {
+        if nal.first().map(|header| header & 0x1F) != Some(NALType::SPS as u8) {
+            return Err(VdkError::Codec("Not an SPS NAL unit".into()));
+        }
+        let rbsp = remove_emulation_prevention(&nal[1..]);
+        let mut reader = BitReader::new(&rbsp);
+
+        let profile_idc = reader.read_bits(8)? as u8;
+        let constraint_flags = reader.read_bits(8)? as u8;
+        let level_idc = reader.read_bits(8)? as u8;
+        let seq_parameter_set_id = reader.read_golomb()?;
+
+        let mut chroma_format_idc = 1;
+        let mut separate_colour_plane = false;
+        if matches!(
+            profile_idc,
+            100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+        ) {
+            chroma_format_idc = reader.read_golomb()?;
+            if chroma_format_idc == 3 {
+                separate_colour_plane = reader.read_bit()?;
+            }
+            let _bit_depth_luma_minus8 = reader.read_golomb()?;
+            let _bit_depth_chroma_minus8 = reader.read_golomb()?;
+            let _qpprime_y_zero_transform_bypass = reader.read_bit()?;
+            if reader.read_bit()? {
+                // seq_scaling_matrix_present_flag
+                let count = if chroma_format_idc == 3 { 12 } else { 8 };
+                for i in 0..count {
+                    if reader.read_bit()? {
+                        skip_scaling_list(&mut reader, if i < 6 { 16 } else { 64 })?;
+                    }
+                }
+            }
+        }
+
+        let _log2_max_frame_num_minus4 = reader.read_golomb()?;
+        match reader.read_golomb()? {
+            0 => {
+                let _log2_max_pic_order_cnt_lsb_minus4 = reader.read_golomb()?;
+            }
+            1 => {
+                let _delta_pic_order_always_zero = reader.read_bit()?;
+                let _offset_for_non_ref_pic = reader.read_signed_golomb()?;
+                let _offset_for_top_to_bottom_field = reader.read_signed_golomb()?;
+                for _ in 0..reader.read_golomb()? {
+                    let _offset_for_ref_frame = reader.read_signed_golomb()?;
+                }
+            }
+            _ => {}
+        }
+        let _max_num_ref_frames = reader.read_golomb()?;
+        let _gaps_in_frame_num_allowed = reader.read_bit()?;
+
+        let width_in_mbs = reader.read_golomb()? + 1;
+        let height_in_map_units = reader.read_golomb()? + 1;
+        let frame_mbs_only = reader.read_bit()?;
+        if !frame_mbs_only {
+            let _mb_adaptive_frame_field = reader.read_bit()?;
+        }
+        let _direct_8x8_inference = reader.read_bit()?;
+
+        // Field coding doubles the height of a map unit
+        let field_factor = if frame_mbs_only { 1 } else { 2 };
+        let mut width = width_in_mbs * 16;
+        let mut height = height_in_map_units * 16 * field_factor;
+
+        if reader.read_bit()? {
+            // frame_cropping_flag, offsets are in chroma sample units
+            let left = reader.read_golomb()?;
+            let right = reader.read_golomb()?;
+            let top = reader.read_golomb()?;
+            let bottom = reader.read_golomb()?;
+            let (crop_x, crop_y) = match (separate_colour_plane, chroma_format_idc) {
+                (false, 1) => (2, 2 * field_factor),
+                (false, 2) => (2, field_factor),
+                _ => (1, field_factor),
+            };
+            width = width.saturating_sub((left + right) * crop_x);
+            height = height.saturating_sub((top + bottom) * crop_y);
+        }
+
+        Ok(Self {
+            profile_idc,
+            constraint_flags,
+            level_idc,
+            seq_parameter_set_id,
+            chroma_format_idc,
+            width,
+            height,
+        })
+    }
+}
+
+/// Skips a scaling_list() structure of the given size.
+fn skip_scaling_list(reader: &mut BitReader, size: usize) -> Result<()> {
+    let mut last_scale = 8;
+    let mut next_scale = 8;
+    for _ in 0..size {
+        if next_scale != 0 {
+            let delta_scale = reader.read_signed_golomb()?;
+            next_scale = (last_scale + delta_scale + 256) % 256;
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    Ok(())
+}
+
+/// Removes emulation prevention bytes (the 0x03 in 0x000003) from NAL unit data.
+pub(crate) fn remove_emulation_prevention(data: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+    for &byte in data {
+        if zeros >= 2 && byte == 0x03 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        rbsp.push(byte);
+    }
+    rbsp
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -172,4 +328,43 @@ mod tests {
         assert_eq!(units[1].payload(), &[0x03, 0x04]);
         assert_eq!(units[2].payload(), &[0x05, 0x06]);
     }
+
+    /// Packs a string of '0' and '1' characters into bytes.
+    fn bits(pattern: &str) -> Vec<u8> {
+        let bits: Vec<u8> = pattern
+            .bytes()
+            .filter(|b| !b.is_ascii_whitespace())
+            .collect();
+        bits.chunks(8)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0u8, |byte, (i, &bit)| byte | ((bit - b'0') << (7 - i)))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sps_parsing() {
+        // Baseline, level 3.0, 1280x720
+        let mut sps = vec![0x67, 66, 0xC0, 30];
+        sps.extend(bits("1 1 011 010 0 0000001010000 00000101101 1 1 0 0 1"));
+        let info = SPSInfo::parse(&sps).unwrap();
+        assert_eq!(info.profile_idc, 66);
+        assert_eq!(info.level_idc, 30);
+        assert_eq!((info.width, info.height), (1280, 720));
+
+        // High, level 4.0, 1920x1088 cropped to 1080
+        let mut sps = vec![0x67, 100, 0x00, 40];
+        sps.extend(bits(
+            "1 010 1 1 0 0 1 011 010 0 0000001111000 0000001000100 1 1 1 1 1 1 00101 0 1",
+        ));
+        let info = SPSInfo::parse(&sps).unwrap();
+        assert_eq!(info.profile_idc, 100);
+        assert_eq!(info.chroma_format_idc, 1);
+        assert_eq!((info.width, info.height), (1920, 1080));
+
+        assert!(SPSInfo::parse(&[0x68, 0xCE]).is_err());
+    }
 }
@@ -99,6 +99,16 @@ impl H265Parser {
         self.buffer.to_vec()
     }
 
+    /// Returns the most recently parsed Sequence Parameter Set
+    pub fn sps(&self) -> Option<&SPSInfo> {
+        self.sps.as_ref()
+    }
+
+    /// Returns the most recently parsed Video Parameter Set
+    pub fn vps(&self) -> Option<&VPSInfo> {
+        self.vps.as_ref()
+    }
+
     /// Checks if a NAL unit is a keyframe (IDR, CRA, or IdrNlp)
     ///
     /// In H.265, keyframes are essential for starting points in a video stream and
@@ -129,6 +139,7 @@ impl H265Parser {
     ///
     /// * `reader` - BitReader positioned at the start of profile_tier_level data
     /// * `profile_present_flag` - Indicates if profile info is present in bitstream
+    /// * `max_sub_layers_minus1` - Number of sub-layers minus 1, whose entries are skipped
     ///
     /// # Returns
     ///
@@ -138,6 +149,7 @@ impl H265Parser {
         &mut self,
         reader: &mut BitReader,
         profile_present_flag: bool,
+        max_sub_layers_minus1: u8,
     ) -> Result<ProfileTierLevel> {
         let mut ptl = ProfileTierLevel::default();
 
@@ -160,6 +172,26 @@ impl H265Parser {
 
         ptl.level_idc = reader.read_bits(8)? as u8;
 
+        // Sub-layer profile and level entries are not kept
+        let mut sub_layers = Vec::with_capacity(max_sub_layers_minus1 as usize);
+        for _ in 0..max_sub_layers_minus1 {
+            let profile_present = reader.read_bit()?;
+            let level_present = reader.read_bit()?;
+            sub_layers.push((profile_present, level_present));
+        }
+        if max_sub_layers_minus1 > 0 {
+            // reserved_zero_2bits up to eight sub-layers
+            reader.skip_bits(2 * (8 - max_sub_layers_minus1 as u32))?;
+        }
+        for (profile_present, level_present) in sub_layers {
+            if profile_present {
+                reader.skip_bits(88)?;
+            }
+            if level_present {
+                reader.skip_bits(8)?;
+            }
+        }
+
         Ok(ptl)
     }
 
@@ -177,7 +209,8 @@ impl H265Parser {
         reader.skip_bits(16)?;
 
         // Parse profile_tier_level
-        let profile_tier_level = self.parse_profile_tier_level(&mut reader, true)?;
+        let profile_tier_level =
+            self.parse_profile_tier_level(&mut reader, true, max_sub_layers_minus1)?;
 
         Ok(VPSInfo {
             vps_id,
@@ -198,7 +231,8 @@ impl H265Parser {
         let temporal_id_nesting_flag = reader.read_bit()?;
 
         // Parse profile_tier_level
-        let profile_tier_level = self.parse_profile_tier_level(&mut reader, true)?;
+        let profile_tier_level =
+            self.parse_profile_tier_level(&mut reader, true, max_sub_layers_minus1)?;
 
         let sps_id = reader.read_golomb()? as u32;
         let chroma_format_idc = reader.read_golomb()? as u32;
@@ -303,4 +337,20 @@ mod tests {
         assert!(parser.is_keyframe(&idr_nalu));
         assert!(!parser.is_keyframe(&non_idr_nalu));
     }
+
+    #[test]
+    fn test_sps_parsing() {
+        let mut parser = H265Parser::new();
+        // Main profile, level 3.1, 1920x1088 with a conformance window to 1080
+        let sps = [
+            0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x5D, 0xA0, 0x03, 0xC0, 0x80, 0x11, 0x07, 0xCB,
+        ];
+        parser.parse_nalu(&sps).unwrap();
+
+        let info = parser.sps().unwrap();
+        assert_eq!(info.profile_tier_level.profile_idc, 1);
+        assert_eq!(info.profile_tier_level.level_idc, 93);
+        assert_eq!((info.width(), info.height()), (1920, 1080));
+    }
 }
@@ -128,6 +128,26 @@ pub struct SPSInfo {
     pub temporal_id_nesting_flag: bool,
 }
 
+impl SPSInfo {
+    /// Returns the picture width in pixels after the conformance window
+    pub fn width(&self) -> u32 {
+        let sub_width = if matches!(self.chroma_format_idc, 1 | 2) {
+            2
+        } else {
+            1
+        };
+        self.pic_width_in_luma_samples
+            .saturating_sub((self.conf_win_left_offset + self.conf_win_right_offset) * sub_width)
+    }
+
+    /// Returns the picture height in pixels after the conformance window
+    pub fn height(&self) -> u32 {
+        let sub_height = if self.chroma_format_idc == 1 { 2 } else { 1 };
+        self.pic_height_in_luma_samples
+            .saturating_sub((self.conf_win_top_offset + self.conf_win_bottom_offset) * sub_height)
+    }
+}
+
 /// H.265 Picture Parameter Set (PPS) information
 #[derive(Debug)]
 pub struct PPSInfo {
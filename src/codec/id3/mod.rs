@@ -0,0 +1,4 @@
+mod parser;
+mod types;
+
+pub use types::*;
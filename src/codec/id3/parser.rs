@@ -0,0 +1,296 @@
+use super::types::{ID3Frame, ID3Tag, ID3_HEADER_SIZE};
+use crate::{Result, VdkError};
+
+/// Tag header flag: unsynchronisation
+const FLAG_UNSYNCHRONISATION: u8 = 0x80;
+/// Tag header flag: extended header present
+const FLAG_EXTENDED_HEADER: u8 = 0x40;
+
+impl ID3Tag {
+    /// Returns the total size of the tag starting at `data`, including the
+    /// header, or `None` if `data` does not start with an ID3v2 header.
+    pub fn tag_size(data: &[u8]) -> Option<usize> {
+        if data.len() < ID3_HEADER_SIZE || &data[..3] != b"ID3" {
+            return None;
+        }
+        let size = read_syncsafe(&data[6..10])?;
+        // A footer repeats the header at the end
+        let footer = if data[5] & 0x10 != 0 {
+            ID3_HEADER_SIZE
+        } else {
+            0
+        };
+        Some(ID3_HEADER_SIZE + size + footer)
+    }
+
+    /// Parses an ID3v2.3 or ID3v2.4 tag.
+    ///
+    /// Unsynchronisation and the data length indicator are undone.
+    /// Compressed and encrypted frames are returned as
+    /// [`ID3Frame::Unknown`].
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Tag bytes starting at the `ID3` identifier
+    ///
+    /// # Returns
+    ///
+    /// The parsed tag, or an error if the data is not a supported ID3v2 tag
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if Self::tag_size(data).is_none() {
+            return Err(VdkError::InvalidData("Missing ID3v2 header".into()));
+        }
+        let version = data[3];
+        if !(3..=4).contains(&version) {
+            return Err(VdkError::InvalidData(format!(
+                "Unsupported ID3v2.{} tag",
+                version
+            )));
+        }
+        let flags = data[5];
+        let body_size = read_syncsafe(&data[6..10]).unwrap_or_default();
+        let body = data
+            .get(ID3_HEADER_SIZE..ID3_HEADER_SIZE + body_size)
+            .ok_or_else(|| VdkError::InvalidData("ID3 tag truncated".into()))?;
+        let unsynchronised = flags & FLAG_UNSYNCHRONISATION != 0;
+
+        // ID3v2.3 applies unsynchronisation to the whole tag
+        let body = if unsynchronised && version == 3 {
+            remove_unsynchronisation(body)
+        } else {
+            body.to_vec()
+        };
+
+        let mut pos = 0;
+        if flags & FLAG_EXTENDED_HEADER != 0 {
+            let header = body
+                .get(..4)
+                .ok_or_else(|| VdkError::InvalidData("ID3 extended header truncated".into()))?;
+            let size = if version == 4 {
+                read_syncsafe(header)
+            } else {
+                Some(4 + u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize)
+            };
+            pos = size
+                .filter(|&size| size <= body.len())
+                .ok_or_else(|| VdkError::InvalidData("Invalid ID3 extended header size".into()))?;
+        }
+
+        let mut tag = ID3Tag::new();
+        while pos + ID3_HEADER_SIZE <= body.len() {
+            let header = &body[pos..pos + ID3_HEADER_SIZE];
+            // Padding after the last frame
+            if header[0] == 0 {
+                break;
+            }
+            let id = [header[0], header[1], header[2], header[3]];
+            let frame_size = if version == 4 {
+                read_syncsafe(&header[4..8])
+            } else {
+                Some(u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize)
+            }
+            .ok_or_else(|| VdkError::InvalidData("Invalid ID3 frame size".into()))?;
+            let frame_flags = u16::from_be_bytes([header[8], header[9]]);
+            pos += ID3_HEADER_SIZE;
+            let content = body
+                .get(pos..pos + frame_size)
+                .ok_or_else(|| VdkError::InvalidData("ID3 frame truncated".into()))?;
+            pos += frame_size;
+
+            tag.frames.push(parse_frame(
+                version,
+                id,
+                frame_flags,
+                unsynchronised,
+                content,
+            )?);
+        }
+        Ok(tag)
+    }
+}
+
+/// Decodes a frame from its content.
+fn parse_frame(
+    version: u8,
+    id: [u8; 4],
+    flags: u16,
+    unsynchronised: bool,
+    content: &[u8],
+) -> Result<ID3Frame> {
+    let unknown = || ID3Frame::Unknown {
+        id,
+        data: content.to_vec(),
+    };
+    let content = if version == 4 {
+        // Compression or encryption
+        if flags & 0x000C != 0 {
+            return Ok(unknown());
+        }
+        // Data length indicator
+        let content = if flags & 0x0001 != 0 {
+            content.get(4..).unwrap_or_default()
+        } else {
+            content
+        };
+        if unsynchronised || flags & 0x0002 != 0 {
+            remove_unsynchronisation(content)
+        } else {
+            content.to_vec()
+        }
+    } else {
+        if flags & 0x00C0 != 0 {
+            return Ok(unknown());
+        }
+        // Grouping identity
+        let skip = if flags & 0x0020 != 0 { 1 } else { 0 };
+        content.get(skip..).unwrap_or_default().to_vec()
+    };
+
+    let frame = match &id {
+        b"PRIV" => {
+            let (owner, data) = split_terminated(0, &content);
+            ID3Frame::Private {
+                owner: decode_text(0, owner),
+                data: data.to_vec(),
+            }
+        }
+        b"TXXX" => {
+            let Some((&encoding, text)) = content.split_first() else {
+                return Ok(unknown());
+            };
+            let (description, value) = split_terminated(encoding, text);
+            ID3Frame::UserText {
+                description: decode_text(encoding, description),
+                value: decode_text(encoding, value),
+            }
+        }
+        [b'T', ..] => {
+            let Some((&encoding, text)) = content.split_first() else {
+                return Ok(unknown());
+            };
+            ID3Frame::Text {
+                id,
+                text: decode_text(encoding, text),
+            }
+        }
+        _ => ID3Frame::Unknown { id, data: content },
+    };
+    Ok(frame)
+}
+
+/// Decodes a 28-bit syncsafe integer.
+fn read_syncsafe(bytes: &[u8]) -> Option<usize> {
+    bytes.iter().try_fold(0usize, |size, &byte| {
+        (byte & 0x80 == 0).then_some(size << 7 | byte as usize)
+    })
+}
+
+/// Replaces every `FF 00` by `FF`.
+fn remove_unsynchronisation(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for (i, &byte) in data.iter().enumerate() {
+        if byte == 0 && i > 0 && data[i - 1] == 0xFF {
+            continue;
+        }
+        out.push(byte);
+    }
+    out
+}
+
+/// Splits a string terminated by a null character from the data after it.
+fn split_terminated(encoding: u8, data: &[u8]) -> (&[u8], &[u8]) {
+    // UTF-16 strings end with two zero bytes at an even offset
+    let position = if matches!(encoding, 1 | 2) {
+        (0..data.len() / 2)
+            .map(|i| i * 2)
+            .find(|&i| data[i] == 0 && data[i + 1] == 0)
+            .map(|i| (i, i + 2))
+    } else {
+        data.iter().position(|&b| b == 0).map(|i| (i, i + 1))
+    };
+    match position {
+        Some((end, next)) => (&data[..end], &data[next..]),
+        None => (data, &[]),
+    }
+}
+
+/// Decodes a string in one of the ID3v2 text encodings.
+fn decode_text(encoding: u8, data: &[u8]) -> String {
+    let text = match encoding {
+        // ISO-8859-1
+        0 => data.iter().map(|&b| b as char).collect(),
+        // UTF-16 with BOM, UTF-16BE
+        1 | 2 => {
+            let (little_endian, data) = match data {
+                [0xFF, 0xFE, rest @ ..] => (true, rest),
+                [0xFE, 0xFF, rest @ ..] => (false, rest),
+                _ => (false, data),
+            };
+            let units = data.chunks_exact(2).map(|unit| {
+                if little_endian {
+                    u16::from_le_bytes([unit[0], unit[1]])
+                } else {
+                    u16::from_be_bytes([unit[0], unit[1]])
+                }
+            });
+            char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect()
+        }
+        _ => String::from_utf8_lossy(data).into_owned(),
+    };
+    text.trim_end_matches('\0').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_id3_roundtrip() {
+        let tag = ID3Tag::new()
+            .with_frame(ID3Frame::private("com.example.telemetry", &[0xFF, 0, 1, 2]))
+            .with_frame(ID3Frame::user_text("GPS", "52.5200,13.4050"))
+            .with_frame(ID3Frame::Text {
+                id: *b"TIT2",
+                text: "Kamera Süd".into(),
+            });
+        let data = tag.to_bytes().unwrap();
+        assert_eq!(&data[..6], b"ID3\x04\x00\x00");
+        assert_eq!(ID3Tag::tag_size(&data), Some(data.len()));
+
+        let parsed = ID3Tag::parse(&data).unwrap();
+        assert_eq!(parsed, tag);
+        assert_eq!(
+            parsed.private_data("com.example.telemetry"),
+            Some(&[0xFF, 0, 1, 2][..])
+        );
+        assert_eq!(parsed.user_text("GPS"), Some("52.5200,13.4050"));
+    }
+
+    #[test]
+    fn test_id3v23_parsing() {
+        // ID3v2.3 tag with a UTF-16 TXXX frame and padding
+        let mut frame = vec![1, 0xFF, 0xFE, b'I', 0, b'D', 0, 0, 0];
+        frame.extend_from_slice(&[0xFF, 0xFE, b'4', 0, b'2', 0]);
+        let mut data = b"ID3\x03\x00\x00".to_vec();
+        data.extend_from_slice(&[0, 0, 0, (10 + frame.len() + 4) as u8]);
+        data.extend_from_slice(b"TXXX");
+        data.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        data.extend_from_slice(&[0, 0]);
+        data.extend_from_slice(&frame);
+        data.extend_from_slice(&[0; 4]);
+
+        let tag = ID3Tag::parse(&data).unwrap();
+        assert_eq!(tag.frames, vec![ID3Frame::user_text("ID", "42")]);
+
+        assert!(ID3Tag::parse(b"ID3\x04\x00\x00\x00\x00\x01").is_err());
+        // Extended header sizes that are not syncsafe or overrun the tag
+        let mut data = b"ID3\x04\x00\x40\x00\x00\x00\x14\x80\x00\x00\x00".to_vec();
+        data.resize(30, 0);
+        assert!(ID3Tag::parse(&data).is_err());
+        data[10..14].copy_from_slice(&[0, 0, 0, 0x7F]);
+        assert!(ID3Tag::parse(&data).is_err());
+        assert!(ID3Tag::parse(b"TAG").is_err());
+    }
+}
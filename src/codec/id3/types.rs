@@ -0,0 +1,208 @@
+use crate::av::{CodecData, CodecType};
+use crate::{Result, VdkError};
+
+/// Size of the ID3v2 tag header and of a frame header
+pub const ID3_HEADER_SIZE: usize = 10;
+
+/// Largest size representable by a 28-bit syncsafe integer
+const MAX_SYNCSAFE: usize = (1 << 28) - 1;
+
+/// Text encoding byte of UTF-8 strings, the only encoding written
+const ENCODING_UTF8: u8 = 0x03;
+
+/// A frame of an ID3v2 tag.
+///
+/// Private and user-defined text frames carry application data such as
+/// telemetry. Other text frames (`T***`) are decoded, and any remaining
+/// frame is kept as raw bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ID3Frame {
+    /// `PRIV`: binary data identified by its owner, e.g. a URL or reverse domain
+    Private {
+        /// Owner identifier
+        owner: String,
+        /// Private data
+        data: Vec<u8>,
+    },
+    /// `TXXX`: a user-defined text value
+    UserText {
+        /// Description of the value, e.g. "GPS"
+        description: String,
+        /// The value
+        value: String,
+    },
+    /// A standard text frame, e.g. `TIT2`
+    Text {
+        /// Frame ID, starting with `T`
+        id: [u8; 4],
+        /// The text
+        text: String,
+    },
+    /// Any other frame, with its undecoded content
+    Unknown {
+        /// Frame ID
+        id: [u8; 4],
+        /// Frame content
+        data: Vec<u8>,
+    },
+}
+
+impl ID3Frame {
+    /// Creates a `PRIV` frame.
+    pub fn private(owner: &str, data: &[u8]) -> Self {
+        ID3Frame::Private {
+            owner: owner.to_string(),
+            data: data.to_vec(),
+        }
+    }
+
+    /// Creates a `TXXX` frame.
+    pub fn user_text(description: &str, value: &str) -> Self {
+        ID3Frame::UserText {
+            description: description.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    /// Returns the four-character frame ID.
+    pub fn id(&self) -> [u8; 4] {
+        match self {
+            ID3Frame::Private { .. } => *b"PRIV",
+            ID3Frame::UserText { .. } => *b"TXXX",
+            ID3Frame::Text { id, .. } | ID3Frame::Unknown { id, .. } => *id,
+        }
+    }
+
+    /// Serializes the frame content, without frame header.
+    pub(super) fn content(&self) -> Vec<u8> {
+        match self {
+            ID3Frame::Private { owner, data } => {
+                // The owner is a null-terminated ISO-8859-1 string
+                let mut content: Vec<u8> = owner.chars().map(latin1).collect();
+                content.push(0);
+                content.extend_from_slice(data);
+                content
+            }
+            ID3Frame::UserText { description, value } => {
+                let mut content = vec![ENCODING_UTF8];
+                content.extend_from_slice(description.as_bytes());
+                content.push(0);
+                content.extend_from_slice(value.as_bytes());
+                content
+            }
+            ID3Frame::Text { text, .. } => {
+                let mut content = vec![ENCODING_UTF8];
+                content.extend_from_slice(text.as_bytes());
+                content
+            }
+            ID3Frame::Unknown { data, .. } => data.clone(),
+        }
+    }
+}
+
+/// Maps a character to ISO-8859-1, replacing those outside it with `?`.
+fn latin1(c: char) -> u8 {
+    u8::try_from(u32::from(c)).unwrap_or(b'?')
+}
+
+/// Codec data of an ID3 timed metadata stream, for muxers.
+///
+/// Each packet of the stream holds one serialized [`ID3Tag`], with the
+/// presentation time of the media it describes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ID3CodecData;
+
+impl CodecData for ID3CodecData {
+    fn codec_type(&self) -> CodecType {
+        CodecType::ID3
+    }
+
+    fn width(&self) -> Option<u32> {
+        None
+    }
+
+    fn height(&self) -> Option<u32> {
+        None
+    }
+
+    fn extra_data(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+/// An ID3v2.4 tag, as carried in timed metadata PES packets.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ID3Tag {
+    /// Frames of the tag, in order
+    pub frames: Vec<ID3Frame>,
+}
+
+impl ID3Tag {
+    /// Creates an empty tag.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a frame to the tag.
+    pub fn with_frame(mut self, frame: ID3Frame) -> Self {
+        self.frames.push(frame);
+        self
+    }
+
+    /// Returns the data of the first `PRIV` frame of `owner`.
+    pub fn private_data(&self, owner: &str) -> Option<&[u8]> {
+        self.frames.iter().find_map(|frame| match frame {
+            ID3Frame::Private { owner: o, data } if o == owner => Some(data.as_slice()),
+            _ => None,
+        })
+    }
+
+    /// Returns the value of the first `TXXX` frame with `description`.
+    pub fn user_text(&self, description: &str) -> Option<&str> {
+        self.frames.iter().find_map(|frame| match frame {
+            ID3Frame::UserText {
+                description: d,
+                value,
+            } if d == description => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Serializes the tag as ID3v2.4, without unsynchronisation, extended
+    /// header or footer.
+    ///
+    /// # Returns
+    ///
+    /// The tag bytes, or an error if the tag exceeds the 256 MB limit
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        for frame in &self.frames {
+            let content = frame.content();
+            body.extend_from_slice(&frame.id());
+            body.extend_from_slice(&syncsafe(content.len())?);
+            body.extend_from_slice(&[0, 0]); // Flags
+            body.extend_from_slice(&content);
+        }
+
+        let mut tag = Vec::with_capacity(ID3_HEADER_SIZE + body.len());
+        tag.extend_from_slice(b"ID3");
+        tag.extend_from_slice(&[4, 0]); // Version 2.4.0
+        tag.push(0); // Flags
+        tag.extend_from_slice(&syncsafe(body.len())?);
+        tag.extend_from_slice(&body);
+        Ok(tag)
+    }
+}
+
+/// Encodes a size as a 28-bit syncsafe integer.
+fn syncsafe(size: usize) -> Result<[u8; 4]> {
+    if size > MAX_SYNCSAFE {
+        return Err(VdkError::InvalidData("ID3 tag too large".into()));
+    }
+    Ok([
+        (size >> 21) as u8 & 0x7F,
+        (size >> 14) as u8 & 0x7F,
+        (size >> 7) as u8 & 0x7F,
+        size as u8 & 0x7F,
+    ])
+}
@@ -40,6 +40,22 @@
 //! - Audio frame extraction
 //! - Basic stream configuration
 //!
+//! ### ID3 Timed Metadata
+//! Implementation supporting:
+//! - ID3v2.4 tag building with PRIV, TXXX and text frames
+//! - ID3v2.3/v2.4 tag parsing
+//!
+//! ### Closed Captions
+//! Implementation supporting:
+//! - CEA-608/708 cc_data extraction from H.264 and H.265 SEI
+//!
+//! ### Audio and Video Headers
+//! Header parsing for stream parameters of:
+//! - AC-3 and E-AC-3 syncframes
+//! - MPEG-1/2 audio frames (Layer I, II and III)
+//! - MPEG-2 video sequence headers and picture types
+//! - Opus packet durations
+//!
 //! ## Transcoding Support
 //!
 //! The codec module provides transcoding capabilities, particularly for H.264:
@@ -58,23 +74,43 @@
 //! # }
 //! ```
 
+#[doc = "AC-3 and E-AC-3 syncframe header parsing"]
+pub mod ac3;
+
 #[doc = "AAC (Advanced Audio Coding) codec implementation
 
 Provides ADTS frame parsing and audio extraction capabilities"]
 pub mod aac;
 
+#[doc = "CEA-608/708 closed captions carried in video SEI messages"]
+pub mod captions;
+
 #[doc = "H.264/AVC (Advanced Video Coding) codec implementation
 
 Complete implementation supporting NAL parsing, parameter sets,
 frame extraction, and transcoding capabilities"]
 pub mod h264;
 
+#[doc = "ID3v2 tags for timed metadata
+
+Provides building and parsing of ID3v2.4 tags with PRIV and TXXX frames"]
+pub mod id3;
+
 #[doc = "H.265/HEVC (High Efficiency Video Coding) codec implementation
 
 Basic implementation supporting NAL parsing, parameter sets handling,
 and frame extraction"]
 pub mod h265;
 
+#[doc = "MPEG-2 video sequence header parsing and picture type detection"]
+pub mod mpeg2;
+
+#[doc = "MPEG-1/2 audio frame header parsing"]
+pub mod mpeg_audio;
+
+#[doc = "Opus packet durations from the TOC byte"]
+pub mod opus;
+
 // Re-export common types and functions
 #[doc(inline)]
 pub use h264::parser::NALUnit;
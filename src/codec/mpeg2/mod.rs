@@ -0,0 +1,103 @@
+//! MPEG-2 video (ISO/IEC 13818-2) sequence headers and picture types.
+
+use crate::{Result, VdkError};
+
+/// Start code of a sequence header
+pub const SEQUENCE_HEADER_CODE: u8 = 0xB3;
+
+/// Start code of a picture header
+pub const PICTURE_START_CODE: u8 = 0x00;
+
+/// Fields of an MPEG-2 video sequence header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SequenceHeader {
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+    /// Aspect ratio information
+    pub aspect_ratio: u8,
+    /// Frame rate code
+    pub frame_rate_code: u8,
+}
+
+impl SequenceHeader {
+    /// Parses a sequence header.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Bytes starting at the sequence header start code
+    ///
+    /// # Returns
+    ///
+    /// The header fields, or an error if `data` is not a sequence header
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < 8 || data[..4] != [0, 0, 1, SEQUENCE_HEADER_CODE] {
+            return Err(VdkError::InvalidData(
+                "Missing MPEG-2 sequence header".into(),
+            ));
+        }
+        Ok(Self {
+            width: (data[4] as u32) << 4 | (data[5] as u32) >> 4,
+            height: (data[5] as u32 & 0x0F) << 8 | data[6] as u32,
+            aspect_ratio: data[7] >> 4,
+            frame_rate_code: data[7] & 0x0F,
+        })
+    }
+
+    /// Finds the sequence header in an access unit.
+    ///
+    /// # Returns
+    ///
+    /// The sequence header bytes up to the next start code, if any
+    pub fn find(data: &[u8]) -> Option<&[u8]> {
+        let start = find_start_code(data, 0, |code| code == SEQUENCE_HEADER_CODE)?;
+        let end = find_start_code(data, start + 4, |_| true).unwrap_or(data.len());
+        Some(&data[start..end])
+    }
+}
+
+/// Returns true if an access unit contains an intra-coded picture.
+pub fn is_intra_picture(data: &[u8]) -> bool {
+    let mut offset = 0;
+    while let Some(start) = find_start_code(data, offset, |code| code == PICTURE_START_CODE) {
+        // 10-bit temporal_reference, then the 3-bit picture_coding_type
+        if let Some(byte) = data.get(start + 5) {
+            if (byte >> 3) & 0x07 == 1 {
+                return true;
+            }
+        }
+        offset = start + 4;
+    }
+    false
+}
+
+/// Returns the offset of the next start code whose code matches.
+fn find_start_code(data: &[u8], from: usize, matches: impl Fn(u8) -> bool) -> Option<usize> {
+    (from..data.len().saturating_sub(3))
+        .find(|&i| data[i..i + 3] == [0, 0, 1] && matches(data[i + 3]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence_header_and_picture_type() {
+        let mut data = vec![0, 0, 1, 0xB3, 0x2D, 0x01, 0xE0, 0x24, 0xFF, 0xFF];
+        // GOP, then an I picture
+        data.extend_from_slice(&[0, 0, 1, 0xB8, 0, 0, 0, 0]);
+        data.extend_from_slice(&[0, 0, 1, 0x00, 0x00, 0x0F, 0xFF, 0xF8]);
+
+        let sequence = SequenceHeader::find(&data).unwrap();
+        assert_eq!(sequence.len(), 10);
+        let header = SequenceHeader::parse(sequence).unwrap();
+        assert_eq!((header.width, header.height), (720, 480));
+        assert_eq!(header.aspect_ratio, 2);
+        assert_eq!(header.frame_rate_code, 4);
+        assert!(is_intra_picture(&data));
+
+        // A P picture
+        assert!(!is_intra_picture(&[0, 0, 1, 0x00, 0x00, 0x50, 0xFF, 0xF8]));
+    }
+}
@@ -0,0 +1,85 @@
+//! MPEG-1 and MPEG-2 audio (Layer I, II and III) frame headers.
+
+use crate::{Result, VdkError};
+
+/// Sample rates of MPEG-1, indexed by sampling_frequency
+const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+/// Header fields of an MPEG audio frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MPEGAudioHeader {
+    /// MPEG version: 1, 2 or 25 for the unofficial MPEG-2.5
+    pub version: u8,
+    /// Layer 1, 2 or 3
+    pub layer: u8,
+    /// Sample rate in Hz
+    pub sample_rate: u32,
+    /// Number of channels, 1 for single channel mode and 2 otherwise
+    pub channels: u8,
+}
+
+impl MPEGAudioHeader {
+    /// Parses the 4-byte header of an MPEG audio frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Frame bytes starting at the sync word
+    ///
+    /// # Returns
+    ///
+    /// The header fields, or an error if `data` does not start with a valid
+    /// frame header
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < 4 || data[0] != 0xFF || data[1] & 0xE0 != 0xE0 {
+            return Err(VdkError::InvalidData("Missing MPEG audio sync word".into()));
+        }
+        let version = match (data[1] >> 3) & 0x03 {
+            0 => 25,
+            2 => 2,
+            3 => 1,
+            _ => return Err(VdkError::InvalidData("Reserved MPEG audio version".into())),
+        };
+        let layer = match (data[1] >> 1) & 0x03 {
+            0 => return Err(VdkError::InvalidData("Reserved MPEG audio layer".into())),
+            layer => 4 - layer,
+        };
+        let sample_rate = *SAMPLE_RATES
+            .get(((data[2] >> 2) & 0x03) as usize)
+            .ok_or_else(|| VdkError::InvalidData("Reserved MPEG audio sample rate".into()))?;
+        // Lower sampling frequencies halve the rate per extension
+        let sample_rate = match version {
+            1 => sample_rate,
+            2 => sample_rate / 2,
+            _ => sample_rate / 4,
+        };
+        let channels = if data[3] >> 6 == 3 { 1 } else { 2 };
+        Ok(Self {
+            version,
+            layer,
+            sample_rate,
+            channels,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mpeg_audio_header() {
+        // MPEG-1 Layer III, 128 kbit/s, 44.1 kHz, joint stereo
+        let header = MPEGAudioHeader::parse(&[0xFF, 0xFB, 0x90, 0x64]).unwrap();
+        assert_eq!((header.version, header.layer), (1, 3));
+        assert_eq!(header.sample_rate, 44100);
+        assert_eq!(header.channels, 2);
+
+        // MPEG-2 Layer II, 24 kHz, single channel
+        let header = MPEGAudioHeader::parse(&[0xFF, 0xF5, 0x84, 0xC0]).unwrap();
+        assert_eq!((header.version, header.layer), (2, 2));
+        assert_eq!(header.sample_rate, 24000);
+        assert_eq!(header.channels, 1);
+
+        assert!(MPEGAudioHeader::parse(&[0xFF, 0xF9, 0x90, 0x64]).is_err());
+    }
+}
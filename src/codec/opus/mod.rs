@@ -0,0 +1,55 @@
+//! Opus packet (RFC 6716) framing information.
+
+use std::time::Duration;
+
+/// Sample rate of Opus timestamps and durations
+pub const OPUS_SAMPLE_RATE: u32 = 48000;
+
+/// Returns the duration of an Opus packet from its TOC byte.
+///
+/// # Arguments
+///
+/// * `data` - The Opus packet
+///
+/// # Returns
+///
+/// The duration of all frames in the packet, or `None` if the packet is
+/// empty or truncated
+pub fn packet_duration(data: &[u8]) -> Option<Duration> {
+    let toc = *data.first()?;
+    let config = toc >> 3;
+    // Frame sizes in units of 2.5 ms
+    let frame_units: u64 = match config {
+        // SILK: 10, 20, 40, 60 ms
+        0..=11 => [4, 8, 16, 24][config as usize % 4],
+        // Hybrid: 10, 20 ms
+        12..=15 => [4, 8][config as usize % 2],
+        // CELT: 2.5, 5, 10, 20 ms
+        _ => [1, 2, 4, 8][config as usize % 4],
+    };
+    let frames: u64 = match toc & 0x03 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => (*data.get(1)? & 0x3F) as u64,
+    };
+    Some(Duration::from_micros(frame_units * frames * 2500))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_duration() {
+        // CELT 20 ms, one frame
+        assert_eq!(packet_duration(&[0xF8]), Some(Duration::from_millis(20)));
+        // SILK 60 ms, two frames
+        assert_eq!(packet_duration(&[0x19]), Some(Duration::from_millis(120)));
+        // Hybrid 10 ms, three frames signalled in the frame count byte
+        assert_eq!(
+            packet_duration(&[0x63, 0x03]),
+            Some(Duration::from_millis(30))
+        );
+        assert_eq!(packet_duration(&[0x03]), None);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::av::{CodecDataExt, Demuxer as AvDemuxer, Muxer as AvMuxer, Packet};
+use crate::av::{CodecDataExt, Demuxer as AvDemuxer, Muxer as AvMuxer, Packet, Timebase};
 use crate::codec::aac::{AACConfig, AACParser, ADTSHeader};
 use crate::error::{Result, VdkError};
 use crate::format::CodecData;
@@ -87,7 +87,8 @@ impl<W: AsyncWrite + Unpin + Send> AvMuxer for AACMuxer<W> {
 pub struct AACDemuxer<R: AsyncRead + Unpin + Send> {
     reader: R,
     parser: AACParser,
-    current_pts: i64,
+    /// Samples of the frames read so far
+    samples: u64,
 }
 
 impl<R: AsyncRead + Unpin + Send> AACDemuxer<R> {
@@ -103,7 +104,7 @@ impl<R: AsyncRead + Unpin + Send> AACDemuxer<R> {
         Self {
             reader,
             parser: AACParser::new(),
-            current_pts: 0,
+            samples: 0,
         }
     }
 }
@@ -184,13 +185,16 @@ impl<R: AsyncRead + Unpin + Send> AvDemuxer for AACDemuxer<R> {
                         _ => 44100, // Default to 44.1kHz
                     };
 
-                    // Calculate duration for 1024 samples (standard AAC frame size)
+                    // Count 1024 samples (standard AAC frame size) per frame, so
+                    // timestamps do not drift from rounding the frame duration
+                    let timebase = Timebase::from_rate(sample_rate);
+                    let pts = timebase.to_nanos(self.samples as i64);
+                    self.samples += 1024;
                     let duration =
-                        Duration::from_nanos((1_024_000_000_000u64) / sample_rate as u64);
-                    self.current_pts += duration.as_nanos() as i64;
+                        Duration::from_nanos((timebase.to_nanos(self.samples as i64) - pts) as u64);
 
                     Ok(Packet::new(frame_data)
-                        .with_pts(self.current_pts)
+                        .with_pts(pts)
                         .with_duration(duration)
                         .with_key_flag(true)
                         .with_stream_index(0))
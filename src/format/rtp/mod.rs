@@ -3,7 +3,8 @@
 //! This module provides a complete implementation of RTP (Real-time Transport Protocol)
 //! functionality, including:
 //!
-//! - RTP packet parsing and creation
+//! - RTP packet parsing, creation and serialization
+//! - Packetization of audio frames (G.711, AAC)
 //! - Jitter buffer for handling out-of-order packets
 //! - Sequence number management
 //! - Support for RTP extensions and CSRC
@@ -51,6 +52,9 @@
 //! }
 //! ```
 
+mod packetizer;
+pub use packetizer::RTPPacketizer;
+
 use bytes::Bytes;
 use std::collections::BTreeMap;
 use std::fmt;
@@ -238,6 +242,49 @@ impl RTPPacket {
             payload,
         })
     }
+
+    /// Serializes the packet into its wire format
+    ///
+    /// The CSRC list and header extension are written as present; padding is
+    /// never added, so the padding flag is always cleared.
+    ///
+    /// # Returns
+    ///
+    /// The raw packet bytes, suitable for [`RTPPacket::parse`]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let extension_len = self
+            .extension_data
+            .as_ref()
+            .map_or(0, |(_, data)| 4 + data.len());
+        let mut data =
+            Vec::with_capacity(12 + self.csrc.len() * 4 + extension_len + self.payload.len());
+
+        data.push(
+            (self.version << 6)
+                | ((self.extension_data.is_some() as u8) << 4)
+                | (self.csrc.len() as u8 & 0x0f),
+        );
+        data.push(((self.marker as u8) << 7) | (self.payload_type & 0x7f));
+        data.extend_from_slice(&self.sequence_number.to_be_bytes());
+        data.extend_from_slice(&self.timestamp.to_be_bytes());
+        data.extend_from_slice(&self.ssrc.to_be_bytes());
+
+        for csrc in self.csrc.iter().take(15) {
+            data.extend_from_slice(&csrc.to_be_bytes());
+        }
+
+        if let Some((profile, ext)) = &self.extension_data {
+            // Extension length is counted in 32-bit words
+            let words = ext.len().div_ceil(4);
+            data.extend_from_slice(&profile.to_be_bytes());
+            data.extend_from_slice(&(words as u16).to_be_bytes());
+            data.extend_from_slice(ext);
+            data.resize(data.len() + words * 4 - ext.len(), 0);
+        }
+
+        data.extend_from_slice(&self.payload);
+        data
+    }
 }
 
 /// A buffer for handling out-of-order RTP packets
@@ -333,6 +380,22 @@ impl JitterBuffer {
         None
     }
 
+    /// Returns the earliest buffered packet without removing it
+    pub fn peek(&self) -> Option<&RTPPacket> {
+        self.packets.first_key_value().map(|(_, packet)| packet)
+    }
+
+    /// Removes the earliest buffered packet, skipping missing sequence
+    /// numbers before it
+    ///
+    /// Used to give up on lost packets, which [`JitterBuffer::pop`] would
+    /// wait for.
+    pub fn pop_next(&mut self) -> Option<RTPPacket> {
+        let (seq, packet) = self.packets.pop_first()?;
+        self.min_seq = seq.wrapping_add(1);
+        Some(packet)
+    }
+
     /// Returns true if the buffer contains no packets
     pub fn is_empty(&self) -> bool {
         self.packets.is_empty()
@@ -362,6 +425,28 @@ mod tests {
         assert_eq!(packet.payload, payload);
     }
 
+    #[test]
+    fn test_rtp_packet_to_bytes_roundtrip() {
+        let mut packet = RTPPacket::new(0, 65535, 160, 0xdeadbeef, true, Bytes::from(vec![0xff; 4]));
+        packet.csrc = vec![0x01020304];
+        packet.extension_data = Some((0xbede, Bytes::from(vec![1, 2, 3])));
+
+        let data = packet.to_bytes();
+        assert_eq!(data[0], 0x91);
+        assert_eq!(data[1], 0x80);
+
+        let parsed = RTPPacket::parse(&data).unwrap();
+        assert_eq!(parsed.sequence_number, 65535);
+        assert_eq!(parsed.timestamp, 160);
+        assert_eq!(parsed.ssrc, 0xdeadbeef);
+        assert!(parsed.marker);
+        assert_eq!(parsed.csrc, vec![0x01020304]);
+        let (profile, ext) = parsed.extension_data.unwrap();
+        assert_eq!(profile, 0xbede);
+        assert_eq!(&ext[..], &[1, 2, 3, 0]);
+        assert_eq!(&parsed.payload[..], &[0xff; 4]);
+    }
+
     #[test]
     fn test_jitter_buffer_operations() {
         let mut jb = JitterBuffer::new(16);
@@ -388,4 +473,23 @@ mod tests {
 
         assert!(jb.is_empty());
     }
+
+    #[test]
+    fn test_jitter_buffer_skips_lost_packets() {
+        let mut jb = JitterBuffer::new(16);
+
+        for seq in [10, 12, 13] {
+            let packet = RTPPacket::new(33, seq, 0, 0x12345678, false, Bytes::from(vec![seq as u8]));
+            jb.push(packet).unwrap();
+        }
+
+        assert_eq!(jb.pop().unwrap().sequence_number, 10);
+        // Packet 11 is missing
+        assert!(jb.pop().is_none());
+        assert_eq!(jb.peek().unwrap().sequence_number, 12);
+
+        assert_eq!(jb.pop_next().unwrap().sequence_number, 12);
+        assert_eq!(jb.pop().unwrap().sequence_number, 13);
+        assert!(jb.pop_next().is_none());
+    }
 }
@@ -0,0 +1,202 @@
+use super::RTPPacket;
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Default maximum RTP payload size, leaving room for IP/UDP/RTP headers
+const DEFAULT_MAX_PAYLOAD_SIZE: usize = 1400;
+
+/// Size of the AU-headers-length field plus one AAC-hbr AU-header
+const AAC_AU_HEADER_SIZE: usize = 4;
+
+/// Splits encoded media frames into RTP packets
+///
+/// The packetizer owns the sequence number space of one RTP stream, so a
+/// single instance should be used for the lifetime of the stream.
+///
+/// # Example
+///
+/// ```rust
+/// use vdkio::format::rtp::RTPPacketizer;
+///
+/// let mut packetizer = RTPPacketizer::new(0, 0x1234);
+/// let packets = packetizer.packetize_g711(&[0xff; 160], 0);
+/// assert_eq!(packets.len(), 1);
+/// assert_eq!(packets[0].payload_type, 0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RTPPacketizer {
+    payload_type: u8,
+    ssrc: u32,
+    sequence_number: u16,
+    max_payload_size: usize,
+}
+
+impl RTPPacketizer {
+    /// Creates a packetizer for the given payload type and SSRC
+    ///
+    /// # Arguments
+    ///
+    /// * `payload_type` - RTP payload type number
+    /// * `ssrc` - Synchronization source identifier of the stream
+    pub fn new(payload_type: u8, ssrc: u32) -> Self {
+        Self {
+            payload_type,
+            ssrc,
+            sequence_number: 0,
+            max_payload_size: DEFAULT_MAX_PAYLOAD_SIZE,
+        }
+    }
+
+    /// Sets the maximum payload size of generated packets
+    pub fn with_max_payload_size(mut self, size: usize) -> Self {
+        self.max_payload_size = size.max(AAC_AU_HEADER_SIZE + 1);
+        self
+    }
+
+    /// Sets the sequence number of the next packet
+    pub fn with_sequence_number(mut self, sequence_number: u16) -> Self {
+        self.sequence_number = sequence_number;
+        self
+    }
+
+    /// Returns the sequence number the next packet will carry
+    pub fn sequence_number(&self) -> u16 {
+        self.sequence_number
+    }
+
+    /// Creates the next packet of the stream
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - Packet payload
+    /// * `timestamp` - RTP timestamp
+    /// * `marker` - Marker bit
+    pub fn next_packet(&mut self, payload: Bytes, timestamp: u32, marker: bool) -> RTPPacket {
+        let packet = RTPPacket::new(
+            self.payload_type,
+            self.sequence_number,
+            timestamp,
+            self.ssrc,
+            marker,
+            payload,
+        );
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+        packet
+    }
+
+    /// Packetizes G.711 (PCMU/PCMA) samples as described in RFC 3551
+    ///
+    /// Every byte is one sample, so packets split at the maximum payload size
+    /// advance the timestamp by their length.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - G.711 encoded samples
+    /// * `timestamp` - RTP timestamp of the first sample
+    pub fn packetize_g711(&mut self, samples: &[u8], timestamp: u32) -> Vec<RTPPacket> {
+        samples
+            .chunks(self.max_payload_size)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let offset = (i * self.max_payload_size) as u32;
+                self.next_packet(
+                    Bytes::copy_from_slice(chunk),
+                    timestamp.wrapping_add(offset),
+                    false,
+                )
+            })
+            .collect()
+    }
+
+    /// Packetizes one AAC frame using RFC 3640 `mpeg4-generic` AAC-hbr mode
+    ///
+    /// An ADTS header, if present, is stripped. Frames larger than the
+    /// maximum payload size are fragmented; all fragments share the
+    /// timestamp and the marker bit is set on the last one.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Raw or ADTS-framed AAC access unit
+    /// * `timestamp` - RTP timestamp of the frame
+    pub fn packetize_aac(&mut self, frame: &[u8], timestamp: u32) -> Vec<RTPPacket> {
+        let access_unit = strip_adts_header(frame);
+        let chunk_size = self.max_payload_size - AAC_AU_HEADER_SIZE;
+        let mut packets = Vec::with_capacity(access_unit.len() / chunk_size + 1);
+
+        let mut chunks = access_unit.chunks(chunk_size).peekable();
+        while let Some(chunk) = chunks.next() {
+            let mut payload = BytesMut::with_capacity(AAC_AU_HEADER_SIZE + chunk.len());
+            // AU-headers-length in bits, then AU-size (13 bits) and AU-index (3 bits).
+            // Fragments carry the size of the complete access unit.
+            payload.put_u16(16);
+            payload.put_u16((access_unit.len() as u16) << 3);
+            payload.put_slice(chunk);
+
+            let last = chunks.peek().is_none();
+            packets.push(self.next_packet(payload.freeze(), timestamp, last));
+        }
+
+        packets
+    }
+}
+
+/// Returns the raw AAC payload of a frame, removing an ADTS header if present
+fn strip_adts_header(frame: &[u8]) -> &[u8] {
+    if frame.len() >= 7 && frame[0] == 0xff && frame[1] & 0xf6 == 0xf0 {
+        let header_len = if frame[1] & 0x01 == 0 { 9 } else { 7 };
+        &frame[header_len.min(frame.len())..]
+    } else {
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packetize_g711_splits_and_advances_timestamp() {
+        let mut packetizer = RTPPacketizer::new(8, 0x1234)
+            .with_max_payload_size(100)
+            .with_sequence_number(65535);
+
+        let packets = packetizer.packetize_g711(&[0xd5; 250], 1000);
+        assert_eq!(packets.len(), 3);
+        assert_eq!(packets[0].sequence_number, 65535);
+        assert_eq!(packets[1].sequence_number, 0);
+        assert_eq!(packets[2].timestamp, 1200);
+        assert_eq!(packets[2].payload.len(), 50);
+        assert!(packets
+            .iter()
+            .all(|p| p.payload_type == 8 && p.ssrc == 0x1234));
+        assert_eq!(packetizer.sequence_number(), 2);
+    }
+
+    #[test]
+    fn test_packetize_aac_strips_adts() {
+        let mut packetizer = RTPPacketizer::new(97, 1);
+        let mut frame = vec![0xff, 0xf1, 0x50, 0x80, 0x02, 0x1f, 0xfc];
+        frame.extend_from_slice(&[1, 2, 3, 4, 5]);
+
+        let packets = packetizer.packetize_aac(&frame, 2048);
+        assert_eq!(packets.len(), 1);
+        assert!(packets[0].marker);
+        assert_eq!(packets[0].timestamp, 2048);
+        assert_eq!(
+            &packets[0].payload[..],
+            &[0x00, 0x10, 0x00, 5 << 3, 1, 2, 3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn test_packetize_aac_fragments() {
+        let mut packetizer = RTPPacketizer::new(97, 1).with_max_payload_size(10);
+        let packets = packetizer.packetize_aac(&[7; 15], 0);
+
+        assert_eq!(packets.len(), 3);
+        assert_eq!(
+            packets.iter().map(|p| p.marker).collect::<Vec<_>>(),
+            vec![false, false, true]
+        );
+        assert!(packets.iter().all(|p| p.payload[2..4] == [0x00, 15 << 3]));
+    }
+}
@@ -0,0 +1,273 @@
+use super::{connection::InterleavedWriter, MediaDescription};
+use crate::av::Packet;
+use crate::format::rtp::RTPPacketizer;
+use crate::{Result, VdkError};
+use futures::channel::mpsc;
+use futures::{Sink, StreamExt};
+use log::{debug, warn};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::net::UdpSocket;
+
+/// `Require` tag announcing ONVIF backchannel support
+pub const BACKCHANNEL_REQUIRE: &str = "www.onvif.org/ver20/backchannel";
+
+/// Number of RTP packet batches buffered between the sink and the network
+const SEND_QUEUE_SIZE: usize = 32;
+
+/// Samples per AAC access unit
+const AAC_FRAME_SAMPLES: u32 = 1024;
+
+/// Audio encodings accepted on an ONVIF backchannel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackchannelCodec {
+    /// G.711 µ-law, 8 kHz
+    PCMU,
+    /// G.711 A-law, 8 kHz
+    PCMA,
+    /// AAC in RFC 3640 `mpeg4-generic` AAC-hbr mode
+    AAC {
+        /// RTP clock rate, equal to the sample rate
+        clock_rate: u32,
+    },
+}
+
+impl BackchannelCodec {
+    /// Determines the codec of a backchannel media section.
+    ///
+    /// Returns `None` for encodings that cannot be sent.
+    pub fn from_media(media: &MediaDescription) -> Option<(Self, u8)> {
+        let rtpmap = media.rtpmap()?;
+        let codec = match rtpmap.encoding.to_ascii_uppercase().as_str() {
+            "PCMU" => BackchannelCodec::PCMU,
+            "PCMA" => BackchannelCodec::PCMA,
+            "MPEG4-GENERIC" => BackchannelCodec::AAC {
+                clock_rate: rtpmap.clock_rate,
+            },
+            _ => return None,
+        };
+        Some((codec, rtpmap.payload_type))
+    }
+}
+
+/// Where backchannel RTP packets are delivered
+pub(crate) enum BackchannelTransport {
+    /// UDP from the client RTP port to the server RTP port
+    Udp {
+        socket: Arc<UdpSocket>,
+        target: (String, u16),
+    },
+    /// Interleaved on the RTSP connection
+    Interleaved {
+        writer: InterleavedWriter,
+        channel: u8,
+    },
+}
+
+impl BackchannelTransport {
+    async fn send(&self, data: &[u8]) -> Result<()> {
+        match self {
+            BackchannelTransport::Udp { socket, target } => {
+                socket.send_to(data, (target.0.as_str(), target.1)).await?;
+            }
+            BackchannelTransport::Interleaved { writer, channel } => {
+                writer.send(*channel, data).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Send side of an ONVIF audio backchannel.
+///
+/// Accepts encoded audio [`Packet`]s matching the negotiated codec, turns
+/// them into RTP and sends them to the camera over the RTSP session's
+/// transport. RTP timestamps advance by the number of samples in each
+/// packet: one per byte for G.711, 1024 per AAC frame.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use futures::SinkExt;
+/// use vdkio::av::Packet;
+/// use vdkio::format::rtsp::{RTSPClient, RTSPSetupOptions};
+///
+/// # async fn example() -> vdkio::Result<()> {
+/// let options = RTSPSetupOptions::new().with_backchannel(true);
+/// let mut client = RTSPClient::new_with_options("rtsp://camera/stream", options)?;
+/// client.connect().await?;
+///
+/// let media = client.describe().await?;
+/// for m in &media {
+///     client.setup(m).await?;
+/// }
+/// client.play().await?;
+///
+/// let mut sink = client.backchannel_sink()?;
+/// sink.send(Packet::new(vec![0xff; 160])).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct BackchannelSink {
+    codec: BackchannelCodec,
+    packetizer: RTPPacketizer,
+    timestamp: u32,
+    sender: mpsc::Sender<Vec<Vec<u8>>>,
+}
+
+impl std::fmt::Debug for BackchannelSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BackchannelSink")
+            .field("codec", &self.codec)
+            .field("timestamp", &self.timestamp)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BackchannelSink {
+    /// Creates a sink and spawns the task delivering its packets.
+    pub(crate) fn new(
+        codec: BackchannelCodec,
+        payload_type: u8,
+        ssrc: u32,
+        transport: BackchannelTransport,
+    ) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<Vec<Vec<u8>>>(SEND_QUEUE_SIZE);
+
+        tokio::spawn(async move {
+            while let Some(batch) = receiver.next().await {
+                for packet in batch {
+                    if let Err(e) = transport.send(&packet).await {
+                        warn!("Failed to send backchannel RTP: {}", e);
+                        return;
+                    }
+                }
+            }
+            debug!("Backchannel sink closed");
+        });
+
+        Self {
+            codec,
+            packetizer: RTPPacketizer::new(payload_type, ssrc),
+            timestamp: 0,
+            sender,
+        }
+    }
+
+    /// Returns the negotiated codec.
+    pub fn codec(&self) -> BackchannelCodec {
+        self.codec
+    }
+
+    fn packetize(&mut self, packet: &Packet) -> Vec<Vec<u8>> {
+        let (packets, samples) = match self.codec {
+            BackchannelCodec::PCMU | BackchannelCodec::PCMA => (
+                self.packetizer.packetize_g711(&packet.data, self.timestamp),
+                packet.data.len() as u32,
+            ),
+            BackchannelCodec::AAC { .. } => (
+                self.packetizer.packetize_aac(&packet.data, self.timestamp),
+                AAC_FRAME_SAMPLES,
+            ),
+        };
+        self.timestamp = self.timestamp.wrapping_add(samples);
+        packets.iter().map(|p| p.to_bytes()).collect()
+    }
+}
+
+fn closed(_: mpsc::SendError) -> VdkError {
+    VdkError::Protocol("Backchannel is closed".into())
+}
+
+impl Sink<Packet> for BackchannelSink {
+    type Error = VdkError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().sender.poll_ready(cx).map_err(closed)
+    }
+
+    fn start_send(self: Pin<&mut Self>, packet: Packet) -> Result<()> {
+        let this = self.get_mut();
+        let batch = this.packetize(&packet);
+        this.sender.start_send(batch).map_err(closed)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().sender)
+            .poll_flush(cx)
+            .map_err(closed)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().sender)
+            .poll_close(cx)
+            .map_err(closed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::rtp::RTPPacket;
+    use futures::SinkExt;
+    use std::time::Duration;
+
+    #[test]
+    fn test_codec_from_media() {
+        let media = super::super::parse_sdp_media("audio 0 RTP/AVP 8\na=sendonly").unwrap();
+        assert_eq!(
+            BackchannelCodec::from_media(&media),
+            Some((BackchannelCodec::PCMA, 8))
+        );
+
+        let media = super::super::parse_sdp_media(
+            "audio 0 RTP/AVP 96\na=rtpmap:96 MPEG4-GENERIC/16000/1\na=sendonly",
+        )
+        .unwrap();
+        assert_eq!(
+            BackchannelCodec::from_media(&media),
+            Some((BackchannelCodec::AAC { clock_rate: 16000 }, 96))
+        );
+
+        let media =
+            super::super::parse_sdp_media("audio 0 RTP/AVP 96\na=rtpmap:96 L16/8000").unwrap();
+        assert_eq!(BackchannelCodec::from_media(&media), None);
+    }
+
+    #[tokio::test]
+    async fn test_sink_sends_rtp_over_udp() {
+        let camera = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = camera.local_addr().unwrap().port();
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        let mut sink = BackchannelSink::new(
+            BackchannelCodec::PCMU,
+            0,
+            0x1234,
+            BackchannelTransport::Udp {
+                socket: Arc::new(socket),
+                target: ("127.0.0.1".to_string(), port),
+            },
+        );
+        sink.send(Packet::new(vec![0xff; 160])).await.unwrap();
+        sink.send(Packet::new(vec![0x7f; 160])).await.unwrap();
+
+        let mut buf = [0u8; 1500];
+        let mut received = Vec::new();
+        for _ in 0..2 {
+            let n = tokio::time::timeout(Duration::from_secs(2), camera.recv(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            received.push(RTPPacket::parse(&buf[..n]).unwrap());
+        }
+
+        assert_eq!(received[0].payload_type, 0);
+        assert_eq!(received[0].ssrc, 0x1234);
+        assert_eq!(received[0].timestamp, 0);
+        assert_eq!(received[1].timestamp, 160);
+        assert_eq!(received[1].sequence_number, received[0].sequence_number + 1);
+        assert_eq!(&received[1].payload[..], &[0x7f; 160]);
+    }
+}
@@ -1,106 +1,519 @@
+use super::events::{RTSPEvent, ServerRequest};
 use crate::Result;
 use crate::VdkError;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use base64::Engine as _;
+use log::{debug, warn};
+use md5::{Digest, Md5};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+#[cfg(feature = "tls")]
+use {
+    super::tls::{client_config, TlsConfig},
+    rustls::pki_types::ServerName,
+    tokio_rustls::TlsConnector,
+};
 
-#[derive(Debug)]
+type BoxedReader = Box<dyn AsyncRead + Send + Unpin>;
+type BoxedWriter = Box<dyn AsyncWrite + Send + Unpin>;
+type SharedWriter = Arc<tokio::sync::Mutex<BoxedWriter>>;
+type ChannelMap = Arc<Mutex<HashMap<u8, mpsc::Sender<Vec<u8>>>>>;
+type EventSender = Arc<Mutex<Option<mpsc::Sender<RTSPEvent>>>>;
+
+/// Content type used on both legs of an RTSP-over-HTTP tunnel
+const TUNNEL_CONTENT_TYPE: &str = "application/x-rtsp-tunnelled";
+
+/// A unit of data read from the server side of the connection.
+#[derive(Debug, PartialEq)]
+enum Frame {
+    /// A complete RTSP message (headers plus body)
+    Message(Vec<u8>),
+    /// An interleaved binary frame (`$` + channel + length + data)
+    Interleaved(u8, Vec<u8>),
+}
+
+/// Control connection to an RTSP server.
+///
+/// A background task reads from the server and splits the byte stream into
+/// RTSP messages, which are handed to [`read_response`](Self::read_response),
+/// and interleaved RTP/RTCP frames, which are forwarded to the sender
+/// registered for their channel. Requests sent by the server are answered
+/// directly by the background task and reported as [`RTSPEvent`]s.
 pub struct RTSPConnection {
-    stream: TcpStream,
-    buffer: Vec<u8>,
+    writer: SharedWriter,
+    /// Base64-encode outgoing data (POST leg of an HTTP tunnel)
+    encode_base64: bool,
+    messages: mpsc::Receiver<Vec<u8>>,
+    channels: ChannelMap,
+    events: EventSender,
+    reader_task: JoinHandle<()>,
+}
+
+impl std::fmt::Debug for RTSPConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RTSPConnection")
+            .field("encode_base64", &self.encode_base64)
+            .finish_non_exhaustive()
+    }
 }
 
 impl RTSPConnection {
     pub async fn connect(host: &str, port: u16) -> Result<Self> {
+        let stream = Self::connect_tcp(host, port).await?;
+        let (reader, writer) = stream.into_split();
+        Ok(Self::from_parts(
+            Box::new(reader),
+            Box::new(writer),
+            false,
+            Vec::new(),
+        ))
+    }
+
+    /// Opens an RTSP-over-HTTP tunnel as introduced by QuickTime.
+    ///
+    /// The GET connection carries everything the server sends (RTSP
+    /// responses and interleaved RTP), the POST connection carries
+    /// base64-encoded RTSP requests. Both are tied together by a shared
+    /// `x-sessioncookie` header.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - HTTP server host
+    /// * `port` - HTTP server port
+    /// * `path` - Request path, usually the path of the RTSP URL
+    /// * `authorization` - Optional `Authorization` header value for both legs
+    pub async fn connect_http_tunnel(
+        host: &str,
+        port: u16,
+        path: &str,
+        authorization: Option<&str>,
+    ) -> Result<Self> {
+        let cookie = session_cookie();
+        let auth_header = authorization
+            .map(|auth| format!("Authorization: {}\r\n", auth))
+            .unwrap_or_default();
+
+        let mut get = Self::connect_tcp(host, port).await?;
+        let request = format!(
+            "GET {} HTTP/1.0\r\n\
+             User-Agent: vdkio/1.0\r\n\
+             x-sessioncookie: {}\r\n\
+             Accept: {}\r\n\
+             Pragma: no-cache\r\n\
+             Cache-Control: no-cache\r\n\
+             {}\r\n",
+            path, cookie, TUNNEL_CONTENT_TYPE, auth_header
+        );
+        get.write_all(request.as_bytes()).await?;
+
+        let (headers, leftover) = read_http_headers(&mut get).await?;
+        let status = headers
+            .lines()
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|code| code.parse::<u32>().ok())
+            .ok_or_else(|| VdkError::Protocol("Invalid HTTP tunnel response".into()))?;
+        if status != 200 {
+            return Err(VdkError::Protocol(format!(
+                "HTTP tunnel GET failed with status {}",
+                status
+            )));
+        }
+        debug!("HTTP tunnel GET leg established (cookie {})", cookie);
+
+        let mut post = Self::connect_tcp(host, port).await?;
+        let request = format!(
+            "POST {} HTTP/1.0\r\n\
+             User-Agent: vdkio/1.0\r\n\
+             x-sessioncookie: {}\r\n\
+             Content-Type: {}\r\n\
+             Pragma: no-cache\r\n\
+             Cache-Control: no-cache\r\n\
+             Content-Length: 32767\r\n\
+             Expires: Sun, 9 Jan 1972 00:00:00 GMT\r\n\
+             {}\r\n",
+            path, cookie, TUNNEL_CONTENT_TYPE, auth_header
+        );
+        post.write_all(request.as_bytes()).await?;
+        post.flush().await?;
+
+        Ok(Self::from_parts(
+            Box::new(get),
+            Box::new(post),
+            true,
+            leftover,
+        ))
+    }
+
+    /// Connects to an RTSPS server.
+    ///
+    /// The whole session, including interleaved media, runs inside the TLS
+    /// stream.
+    #[cfg(feature = "tls")]
+    pub async fn connect_tls(host: &str, port: u16, config: &TlsConfig) -> Result<Self> {
+        let server_name = ServerName::try_from(host.to_string())
+            .map_err(|e| VdkError::Protocol(format!("Invalid TLS server name {}: {}", host, e)))?;
+        let connector = TlsConnector::from(client_config(config)?);
+
+        let stream = Self::connect_tcp(host, port).await?;
+        let stream = connector
+            .connect(server_name, stream)
+            .await
+            .map_err(|e| VdkError::Protocol(format!("TLS handshake failed: {}", e)))?;
+        debug!("TLS session established with {}:{}", host, port);
+
+        let (reader, writer) = tokio::io::split(stream);
+        Ok(Self::from_parts(
+            Box::new(reader),
+            Box::new(writer),
+            false,
+            Vec::new(),
+        ))
+    }
+
+    async fn connect_tcp(host: &str, port: u16) -> Result<TcpStream> {
         let addr = format!("{}:{}", host, port);
         let stream = TcpStream::connect(&addr)
             .await
             .map_err(|e| VdkError::Protocol(format!("Failed to connect to {}: {}", addr, e)))?;
 
         stream.set_nodelay(true)?;
+        Ok(stream)
+    }
+
+    fn from_parts(
+        reader: BoxedReader,
+        writer: BoxedWriter,
+        encode_base64: bool,
+        buffered: Vec<u8>,
+    ) -> Self {
+        let (message_tx, messages) = mpsc::channel(16);
+        let channels: ChannelMap = Arc::new(Mutex::new(HashMap::new()));
+        let events: EventSender = Arc::new(Mutex::new(None));
+        let writer: SharedWriter = Arc::new(tokio::sync::Mutex::new(writer));
+        let reader_task = tokio::spawn(read_loop(
+            reader,
+            buffered,
+            message_tx,
+            channels.clone(),
+            ServerRequestHandler {
+                writer: writer.clone(),
+                encode_base64,
+                events: events.clone(),
+            },
+        ));
 
-        Ok(Self {
-            stream,
-            buffer: Vec::with_capacity(4096),
-        })
+        Self {
+            writer,
+            encode_base64,
+            messages,
+            channels,
+            events,
+            reader_task,
+        }
     }
 
     pub async fn write_all(&mut self, data: &[u8]) -> Result<()> {
-        self.stream.write_all(data).await?;
-        self.stream.flush().await?;
-        Ok(())
+        write_encoded(&self.writer, self.encode_base64, data).await
     }
 
-    pub async fn read_response(&mut self) -> Result<Vec<u8>> {
-        self.buffer.clear();
-        let mut temp_buf = [0; 4096];
-
-        loop {
-            match self.stream.read(&mut temp_buf).await {
-                Ok(0) => {
-                    if self.buffer.is_empty() {
-                        return Err(VdkError::Protocol("Connection closed by peer".into()));
-                    }
-                    break;
-                }
-                Ok(n) => {
-                    self.buffer.extend_from_slice(&temp_buf[..n]);
-                    if self.is_response_complete() {
-                        break;
-                    }
-                }
-                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    break;
-                }
-                Err(e) => return Err(e.into()),
-            }
+    /// Returns a handle for sending interleaved frames to the server.
+    ///
+    /// Frames are written on the same stream as RTSP requests; the handle
+    /// serializes access with the connection.
+    pub fn interleaved_writer(&self) -> InterleavedWriter {
+        InterleavedWriter {
+            writer: self.writer.clone(),
+            encode_base64: self.encode_base64,
         }
+    }
+
+    pub async fn read_response(&mut self) -> Result<Vec<u8>> {
+        let mut response = self
+            .messages
+            .recv()
+            .await
+            .ok_or_else(|| VdkError::Protocol("Connection closed by peer".into()))?;
 
         // Find the start of the RTSP response
-        if let Some(pos) = self.buffer.windows(8).position(|window| window == b"RTSP/1.0") {
+        if let Some(pos) = response.windows(8).position(|window| window == b"RTSP/1.0") {
             if pos > 0 {
                 // There was some garbage data before the RTSP response, remove it
-                self.buffer.drain(0..pos);
+                response.drain(0..pos);
             }
         } else {
             return Err(VdkError::Protocol("Invalid RTSP response format: missing RTSP/1.0".into()));
         }
-        
-        // Return the cleaned response
-        Ok(self.buffer.clone())
+
+        Ok(response)
     }
 
-    fn is_response_complete(&self) -> bool {
-        if self.buffer.len() < 4 {
-            return false;
+    /// Forwards interleaved frames received on `channel` to `sender`.
+    pub fn register_channel(&self, channel: u8, sender: mpsc::Sender<Vec<u8>>) {
+        if let Ok(mut channels) = self.channels.lock() {
+            channels.insert(channel, sender);
         }
+    }
 
-        // Look for double CRLF which marks end of headers
-        for i in 0..self.buffer.len() - 3 {
-            if &self.buffer[i..i + 4] == b"\r\n\r\n" {
-                // If we have Content-Length, verify we have the full body
-                if let Some(content_length) = self.get_content_length() {
-                    return self.buffer.len() >= i + 4 + content_length;
-                }
-                return true;
+    /// Reports requests received from the server to `sender`.
+    ///
+    /// Events are dropped when `sender` is full so that an application not
+    /// consuming them never stalls media delivery.
+    pub fn set_event_sender(&self, sender: mpsc::Sender<RTSPEvent>) {
+        if let Ok(mut events) = self.events.lock() {
+            *events = Some(sender);
+        }
+    }
+}
+
+/// Sends interleaved (`$`-framed) data on an RTSP connection.
+#[derive(Clone)]
+pub struct InterleavedWriter {
+    writer: SharedWriter,
+    encode_base64: bool,
+}
+
+impl std::fmt::Debug for InterleavedWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InterleavedWriter")
+            .field("encode_base64", &self.encode_base64)
+            .finish_non_exhaustive()
+    }
+}
+
+impl InterleavedWriter {
+    /// Sends `data` on interleaved `channel`.
+    pub async fn send(&self, channel: u8, data: &[u8]) -> Result<()> {
+        let length = u16::try_from(data.len())
+            .map_err(|_| VdkError::Protocol("Interleaved frame too large".into()))?;
+        let mut frame = Vec::with_capacity(4 + data.len());
+        frame.push(b'$');
+        frame.push(channel);
+        frame.extend_from_slice(&length.to_be_bytes());
+        frame.extend_from_slice(data);
+        write_encoded(&self.writer, self.encode_base64, &frame).await
+    }
+}
+
+async fn write_encoded(writer: &SharedWriter, encode_base64: bool, data: &[u8]) -> Result<()> {
+    let mut writer = writer.lock().await;
+    if encode_base64 {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(data);
+        writer.write_all(encoded.as_bytes()).await?;
+    } else {
+        writer.write_all(data).await?;
+    }
+    writer.flush().await?;
+    Ok(())
+}
+
+impl Drop for RTSPConnection {
+    fn drop(&mut self) {
+        // Dropping the writer closes the outgoing side, the reader task owns
+        // the incoming side and has to be stopped explicitly
+        self.reader_task.abort();
+    }
+}
+
+/// Answers requests the server sends on the control connection.
+struct ServerRequestHandler {
+    writer: SharedWriter,
+    encode_base64: bool,
+    events: EventSender,
+}
+
+impl ServerRequestHandler {
+    async fn handle(&self, request: ServerRequest) -> Result<()> {
+        debug!("Received {} request from server", request.method);
+        write_encoded(
+            &self.writer,
+            self.encode_base64,
+            request.response().as_bytes(),
+        )
+        .await?;
+
+        let sender = self.events.lock().ok().and_then(|events| events.clone());
+        if let Some(sender) = sender {
+            if sender.try_send(request.into_event()).is_err() {
+                debug!("Dropping RTSP event, receiver is full or closed");
             }
         }
-        false
+        Ok(())
     }
+}
 
-    fn get_content_length(&self) -> Option<usize> {
-        let content = std::str::from_utf8(&self.buffer).ok()?;
-        for line in content.lines() {
-            if line.to_lowercase().starts_with("content-length:") {
-                return line[15..].trim().parse().ok();
+async fn read_loop(
+    mut reader: BoxedReader,
+    mut buffer: Vec<u8>,
+    messages: mpsc::Sender<Vec<u8>>,
+    channels: ChannelMap,
+    requests: ServerRequestHandler,
+) {
+    let mut temp_buf = [0; 4096];
+
+    loop {
+        while let Some(frame) = next_frame(&mut buffer) {
+            match frame {
+                Frame::Message(message) => {
+                    // Anything that is not a request is treated as a response,
+                    // read_response() deals with leading garbage
+                    let request = (!message.starts_with(b"RTSP/"))
+                        .then(|| ServerRequest::parse(&message))
+                        .flatten();
+                    if let Some(request) = request {
+                        if let Err(e) = requests.handle(request).await {
+                            warn!("Failed to answer server request: {}", e);
+                            return;
+                        }
+                        continue;
+                    }
+
+                    if messages.send(message).await.is_err() {
+                        return;
+                    }
+                }
+                Frame::Interleaved(channel, data) => {
+                    let sender = channels
+                        .lock()
+                        .ok()
+                        .and_then(|channels| channels.get(&channel).cloned());
+                    match sender {
+                        Some(sender) => {
+                            if sender.send(data).await.is_err() {
+                                if let Ok(mut channels) = channels.lock() {
+                                    channels.remove(&channel);
+                                }
+                            }
+                        }
+                        None => debug!("Dropping interleaved frame on channel {}", channel),
+                    }
+                }
+            }
+        }
+
+        match reader.read(&mut temp_buf).await {
+            Ok(0) => {
+                debug!("RTSP connection closed by peer");
+                return;
+            }
+            Ok(n) => buffer.extend_from_slice(&temp_buf[..n]),
+            Err(e) => {
+                warn!("RTSP connection read error: {}", e);
+                return;
             }
         }
-        None
     }
 }
 
-impl Drop for RTSPConnection {
-    fn drop(&mut self) {
-        // Best effort to close the connection gracefully
-        let _ = self.stream.shutdown();
+/// Removes the next complete frame from the front of `buffer`.
+fn next_frame(buffer: &mut Vec<u8>) -> Option<Frame> {
+    // Skip stray line breaks between messages
+    let skip = buffer
+        .iter()
+        .take_while(|&&b| b == b'\r' || b == b'\n')
+        .count();
+    buffer.drain(..skip);
+
+    if buffer.first() == Some(&b'$') {
+        if buffer.len() < 4 {
+            return None;
+        }
+        let channel = buffer[1];
+        let length = u16::from_be_bytes([buffer[2], buffer[3]]) as usize;
+        if buffer.len() < 4 + length {
+            return None;
+        }
+        let data = buffer[4..4 + length].to_vec();
+        buffer.drain(..4 + length);
+        return Some(Frame::Interleaved(channel, data));
+    }
+
+    // Look for double CRLF which marks end of headers
+    let header_end = buffer.windows(4).position(|w| w == b"\r\n\r\n")? + 4;
+    let content_length = get_content_length(&buffer[..header_end]).unwrap_or(0);
+    if buffer.len() < header_end + content_length {
+        return None;
+    }
+    Some(Frame::Message(
+        buffer.drain(..header_end + content_length).collect(),
+    ))
+}
+
+fn get_content_length(headers: &[u8]) -> Option<usize> {
+    let content = std::str::from_utf8(headers).ok()?;
+    for line in content.lines() {
+        if line.to_lowercase().starts_with("content-length:") {
+            return line[15..].trim().parse().ok();
+        }
+    }
+    None
+}
+
+/// Reads an HTTP response header block, returning it together with any bytes
+/// that were received after it.
+async fn read_http_headers(stream: &mut TcpStream) -> Result<(String, Vec<u8>)> {
+    let mut buffer = Vec::with_capacity(1024);
+    let mut temp_buf = [0; 1024];
+
+    loop {
+        if let Some(pos) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            let headers = String::from_utf8_lossy(&buffer[..pos]).into_owned();
+            return Ok((headers, buffer[pos + 4..].to_vec()));
+        }
+
+        let n = stream.read(&mut temp_buf).await?;
+        if n == 0 {
+            return Err(VdkError::Protocol("Connection closed by peer".into()));
+        }
+        buffer.extend_from_slice(&temp_buf[..n]);
+    }
+}
+
+/// Generates a session cookie that is unique per tunnel.
+fn session_cookie() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let mut hasher = Md5::new();
+    hasher.update(nanos.to_be_bytes());
+    hasher.update(std::process::id().to_be_bytes());
+    format!("{:x}", hasher.finalize())[..22].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_frame() {
+        let mut buffer = b"RTSP/1.0 200 OK\r\nCSeq: 1\r\nContent-Length: 4\r\n\r\nbody".to_vec();
+        buffer.extend_from_slice(&[b'$', 0, 0, 3, 1, 2, 3]);
+        buffer.extend_from_slice(b"\r\nRTSP/1.0 200 OK\r\n");
+
+        assert_eq!(
+            next_frame(&mut buffer),
+            Some(Frame::Message(
+                b"RTSP/1.0 200 OK\r\nCSeq: 1\r\nContent-Length: 4\r\n\r\nbody".to_vec()
+            ))
+        );
+        assert_eq!(
+            next_frame(&mut buffer),
+            Some(Frame::Interleaved(0, vec![1, 2, 3]))
+        );
+        // Incomplete message stays buffered
+        assert_eq!(next_frame(&mut buffer), None);
+        assert_eq!(buffer, b"RTSP/1.0 200 OK\r\n");
+    }
+
+    #[test]
+    fn test_session_cookie() {
+        let cookie = session_cookie();
+        assert_eq!(cookie.len(), 22);
+        assert!(cookie.chars().all(|c| c.is_ascii_hexdigit()));
     }
 }
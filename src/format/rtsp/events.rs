@@ -0,0 +1,204 @@
+/// Methods a client answers with 200 OK when sent by the server
+const SUPPORTED_METHODS: &[&str] = &[
+    "ANNOUNCE",
+    "GET_PARAMETER",
+    "OPTIONS",
+    "REDIRECT",
+    "SET_PARAMETER",
+];
+
+/// Session events reported by [`RTSPClient`](super::RTSPClient).
+///
+/// Server-to-client requests are answered by the client before the event is
+/// delivered, so the application only has to react to them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RTSPEvent {
+    /// The server announced an updated session description (ANNOUNCE)
+    Announce {
+        /// Request URL
+        url: String,
+        /// New SDP session description
+        sdp: String,
+    },
+    /// The server changed session parameters (SET_PARAMETER)
+    SetParameter {
+        /// Request URL
+        url: String,
+        /// Content type of the parameters, if given
+        content_type: Option<String>,
+        /// Parameter body
+        body: String,
+    },
+    /// The server asked the client to continue elsewhere (REDIRECT)
+    ///
+    /// The current session stays valid until the application reconnects to
+    /// `location`.
+    Redirect {
+        /// Request URL
+        url: String,
+        /// Target URL from the `Location` header
+        location: Option<String>,
+        /// Range from which the new server continues, if given
+        range: Option<String>,
+    },
+    /// Any other request sent by the server (e.g. OPTIONS keep-alive)
+    Request {
+        /// Request method
+        method: String,
+        /// Request URL
+        url: String,
+    },
+    /// A 3xx response was followed to a new URL
+    Redirected {
+        /// URL that was redirected
+        from: String,
+        /// URL the client continued with
+        to: String,
+    },
+    /// The session was set up again after a 454 Session Not Found
+    SessionReestablished {
+        /// New session identifier
+        session: String,
+    },
+}
+
+/// An RTSP request sent from the server to the client.
+#[derive(Debug, Clone)]
+pub(crate) struct ServerRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl ServerRequest {
+    /// Parses a complete RTSP request message.
+    pub fn parse(message: &[u8]) -> Option<Self> {
+        let header_end = message.windows(4).position(|w| w == b"\r\n\r\n")?;
+        let head = std::str::from_utf8(&message[..header_end]).ok()?;
+        let mut lines = head.lines();
+
+        let mut request_line = lines.next()?.split_whitespace();
+        let method = request_line.next()?.to_string();
+        let url = request_line.next()?.to_string();
+        if !request_line.next()?.starts_with("RTSP/") {
+            return None;
+        }
+
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect();
+
+        Some(Self {
+            method,
+            url,
+            headers,
+            body: message[header_end + 4..].to_vec(),
+        })
+    }
+
+    /// Returns the value of a header, matched case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Builds the response, echoing CSeq and Session.
+    pub fn response(&self) -> String {
+        let status = if SUPPORTED_METHODS.contains(&self.method.as_str()) {
+            "200 OK"
+        } else {
+            "501 Not Implemented"
+        };
+
+        let mut response = format!("RTSP/1.0 {}\r\n", status);
+        if let Some(cseq) = self.header("CSeq") {
+            response.push_str(&format!("CSeq: {}\r\n", cseq));
+        }
+        if let Some(session) = self.header("Session") {
+            response.push_str(&format!("Session: {}\r\n", session));
+        }
+        response.push_str("User-Agent: vdkio/1.0\r\n");
+        if self.method == "OPTIONS" {
+            response.push_str(&format!("Public: {}\r\n", SUPPORTED_METHODS.join(", ")));
+        }
+        response.push_str("\r\n");
+        response
+    }
+
+    /// Converts the request into the event reported to the application.
+    pub fn into_event(self) -> RTSPEvent {
+        let body = String::from_utf8_lossy(&self.body).into_owned();
+        match self.method.as_str() {
+            "ANNOUNCE" => RTSPEvent::Announce {
+                url: self.url,
+                sdp: body,
+            },
+            "SET_PARAMETER" => RTSPEvent::SetParameter {
+                content_type: self.header("Content-Type").map(String::from),
+                url: self.url,
+                body,
+            },
+            "REDIRECT" => RTSPEvent::Redirect {
+                location: self.header("Location").map(String::from),
+                range: self.header("Range").map(String::from),
+                url: self.url,
+            },
+            _ => RTSPEvent::Request {
+                method: self.method,
+                url: self.url,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_request_response_and_event() {
+        let message = b"REDIRECT rtsp://nvr/stream RTSP/1.0\r\n\
+                        CSeq: 7\r\n\
+                        Session: abc\r\n\
+                        Location: rtsp://nvr2/stream\r\n\r\n";
+        let request = ServerRequest::parse(message).unwrap();
+        assert_eq!(
+            request.response(),
+            "RTSP/1.0 200 OK\r\nCSeq: 7\r\nSession: abc\r\nUser-Agent: vdkio/1.0\r\n\r\n"
+        );
+        assert_eq!(
+            request.into_event(),
+            RTSPEvent::Redirect {
+                url: "rtsp://nvr/stream".into(),
+                location: Some("rtsp://nvr2/stream".into()),
+                range: None,
+            }
+        );
+
+        let message = b"RECORD rtsp://nvr/stream RTSP/1.0\r\ncseq: 3\r\n\r\n";
+        let request = ServerRequest::parse(message).unwrap();
+        assert!(request
+            .response()
+            .starts_with("RTSP/1.0 501 Not Implemented\r\nCSeq: 3\r\n"));
+    }
+
+    #[test]
+    fn test_server_request_body() {
+        let message = b"ANNOUNCE rtsp://nvr/stream RTSP/1.0\r\nCSeq: 1\r\n\
+                        Content-Length: 5\r\n\r\nv=0\r\n";
+        let event = ServerRequest::parse(message).unwrap().into_event();
+        assert_eq!(
+            event,
+            RTSPEvent::Announce {
+                url: "rtsp://nvr/stream".into(),
+                sdp: "v=0\r\n".into(),
+            }
+        );
+
+        assert!(ServerRequest::parse(b"RTSP/1.0 200 OK\r\nCSeq: 1\r\n\r\n").is_none());
+    }
+}
@@ -57,13 +57,21 @@
 //! # }
 //! ```
 
+mod backchannel;
 mod client;
 mod connection;
+mod events;
+pub(crate) mod multicast;
 mod stream;
+mod tls;
 mod transport;
 
+pub use backchannel::{BackchannelCodec, BackchannelSink, BACKCHANNEL_REQUIRE};
 pub use client::{RTSPClient, RTSPSetupOptions};
+pub use events::RTSPEvent;
+pub use multicast::SdpSession;
 pub use stream::{MediaStream, StreamStatistics};
+pub use tls::TlsConfig;
 pub use transport::{CastType, TransportInfo};
 
 use thiserror::Error;
@@ -133,11 +141,14 @@ pub(crate) fn parse_sdp_media(media: &str) -> Result<MediaDescription, RTSPError
         protocol: parts[2].to_string(),
         format: parts[3].to_string(),
         attributes: std::collections::HashMap::new(),
+        connection: None,
     };
 
     // Parse additional attributes
     for line in lines {
-        if line.starts_with("a=") {
+        if let Some(value) = line.strip_prefix("c=") {
+            description.connection = ConnectionInfo::parse(value);
+        } else if line.starts_with("a=") {
             if let Some(attr_str) = line.strip_prefix("a=") {
                 if let Some((name, value)) = attr_str.split_once(':') {
                     description.set_attribute(name, value);
@@ -151,6 +162,125 @@ pub(crate) fn parse_sdp_media(media: &str) -> Result<MediaDescription, RTSPError
     Ok(description)
 }
 
+/// Parses a complete SDP session description.
+///
+/// Returns the session-level attributes and the media descriptions. A
+/// session-level `c=` line applies to every media section that does not carry
+/// its own connection line (RFC 4566, section 5.7).
+pub(crate) fn parse_sdp(
+    sdp: &str,
+) -> (std::collections::HashMap<String, String>, Vec<MediaDescription>) {
+    let mut media_descriptions: Vec<MediaDescription> = Vec::new();
+    let mut global_attrs = std::collections::HashMap::new();
+    let mut global_connection: Option<ConnectionInfo> = None;
+    let mut current_media: Option<MediaDescription> = None;
+
+    for line in sdp.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(value) = line.strip_prefix("m=") {
+            if let Some(media) = current_media.take() {
+                media_descriptions.push(media);
+            }
+
+            if let Ok(media) = parse_sdp_media(value) {
+                current_media = Some(media);
+            }
+        } else if let Some(value) = line.strip_prefix("c=") {
+            let connection = ConnectionInfo::parse(value);
+            match current_media {
+                Some(ref mut media) => media.connection = connection,
+                None => global_connection = connection,
+            }
+        } else if let Some(attr_str) = line.strip_prefix("a=") {
+            let (name, value) = attr_str.split_once(':').unwrap_or((attr_str, ""));
+
+            if let Some(ref mut media) = current_media {
+                media.set_attribute(name, value);
+            } else {
+                global_attrs.insert(name.to_string(), value.to_string());
+            }
+        }
+    }
+
+    if let Some(media) = current_media {
+        media_descriptions.push(media);
+    }
+
+    for media in &mut media_descriptions {
+        if media.connection.is_none() {
+            media.connection = global_connection.clone();
+        }
+    }
+
+    (global_attrs, media_descriptions)
+}
+
+/// Connection data from an SDP `c=` line.
+///
+/// ```text
+/// c=<nettype> <addrtype> <connection-address>[/<ttl>][/<number of addresses>]
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionInfo {
+    /// Network type (always "IN" for Internet)
+    pub network_type: String,
+    /// Address type ("IP4" or "IP6")
+    pub address_type: String,
+    /// Unicast or multicast address
+    pub address: String,
+    /// Multicast time-to-live (IPv4 multicast only)
+    pub ttl: Option<u8>,
+    /// Number of contiguous multicast addresses
+    pub address_count: Option<u32>,
+}
+
+impl ConnectionInfo {
+    /// Parses the value of an SDP `c=` line.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use vdkio::format::rtsp::ConnectionInfo;
+    ///
+    /// let c = ConnectionInfo::parse("IN IP4 239.1.1.1/32").unwrap();
+    /// assert_eq!(c.address, "239.1.1.1");
+    /// assert_eq!(c.ttl, Some(32));
+    /// assert!(c.is_multicast());
+    /// ```
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.split_whitespace();
+        let network_type = parts.next()?.to_string();
+        let address_type = parts.next()?.to_string();
+        let mut address_parts = parts.next()?.split('/');
+        let address = address_parts.next()?.to_string();
+
+        let (ttl, address_count) = if address_type == "IP6" {
+            // IPv6 has no TTL field, only the optional address count
+            (None, address_parts.next().and_then(|n| n.parse().ok()))
+        } else {
+            (
+                address_parts.next().and_then(|t| t.parse().ok()),
+                address_parts.next().and_then(|n| n.parse().ok()),
+            )
+        };
+
+        Some(Self {
+            network_type,
+            address_type,
+            address,
+            ttl,
+            address_count,
+        })
+    }
+
+    /// Returns true if the connection address is a multicast group.
+    pub fn is_multicast(&self) -> bool {
+        self.address
+            .parse::<std::net::IpAddr>()
+            .map(|addr| addr.is_multicast())
+            .unwrap_or(false)
+    }
+}
+
 /// Represents a media description in an SDP message
 #[derive(Debug, Clone)]
 pub struct MediaDescription {
@@ -164,6 +294,8 @@ pub struct MediaDescription {
     pub format: String,
     /// Additional attributes for the media description
     pub attributes: std::collections::HashMap<String, String>,
+    /// Connection data from the media-level (or inherited session-level) `c=` line
+    pub connection: Option<ConnectionInfo>,
 }
 
 impl MediaDescription {
@@ -181,12 +313,142 @@ impl MediaDescription {
     pub fn remove_attribute(&mut self, name: &str) -> Option<String> {
         self.attributes.remove(name)
     }
+
+    /// Returns the media direction announced by the server.
+    ///
+    /// Defaults to [`MediaDirection::SendRecv`] when no direction attribute
+    /// is present (RFC 4566, section 6).
+    pub fn direction(&self) -> MediaDirection {
+        [
+            MediaDirection::SendOnly,
+            MediaDirection::RecvOnly,
+            MediaDirection::Inactive,
+            MediaDirection::SendRecv,
+        ]
+        .into_iter()
+        .find(|direction| self.attributes.contains_key(direction.as_str()))
+        .unwrap_or(MediaDirection::SendRecv)
+    }
+
+    /// Returns the RTP payload type and encoding of the first format.
+    ///
+    /// Static payload types such as PCMU (0) and PCMA (8) do not need an
+    /// `a=rtpmap` attribute and are resolved from RFC 3551.
+    pub fn rtpmap(&self) -> Option<RtpMap> {
+        let payload_type: u8 = self.format.parse().ok()?;
+
+        if let Some(rtpmap) = self
+            .get_attribute("rtpmap")
+            .and_then(|value| RtpMap::parse(value))
+            .filter(|rtpmap| rtpmap.payload_type == payload_type)
+        {
+            return Some(rtpmap);
+        }
+
+        let (encoding, clock_rate) = match payload_type {
+            0 => ("PCMU", 8000),
+            8 => ("PCMA", 8000),
+            _ => return None,
+        };
+        Some(RtpMap {
+            payload_type,
+            encoding: encoding.to_string(),
+            clock_rate,
+            channels: Some(1),
+        })
+    }
+}
+
+/// Media direction attributes (`a=sendonly`, `a=recvonly`, ...).
+///
+/// Directions are expressed from the point of view of the party that wrote
+/// the SDP. ONVIF backchannel sections are marked `sendonly`: the client
+/// sends media to the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaDirection {
+    /// Media flows in both directions
+    SendRecv,
+    /// Media is only sent toward the server (backchannel)
+    SendOnly,
+    /// Media is only received from the server
+    RecvOnly,
+    /// No media flows
+    Inactive,
+}
+
+impl MediaDirection {
+    /// Returns the SDP attribute name of this direction.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MediaDirection::SendRecv => "sendrecv",
+            MediaDirection::SendOnly => "sendonly",
+            MediaDirection::RecvOnly => "recvonly",
+            MediaDirection::Inactive => "inactive",
+        }
+    }
+}
+
+/// RTP payload mapping from an SDP `a=rtpmap` attribute.
+///
+/// ```text
+/// a=rtpmap:<payload type> <encoding name>/<clock rate>[/<channels>]
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RtpMap {
+    /// RTP payload type
+    pub payload_type: u8,
+    /// Encoding name (e.g. "PCMU", "MPEG4-GENERIC")
+    pub encoding: String,
+    /// RTP clock rate in Hz
+    pub clock_rate: u32,
+    /// Number of audio channels
+    pub channels: Option<u8>,
+}
+
+impl RtpMap {
+    /// Parses the value of an `a=rtpmap` attribute.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use vdkio::format::rtsp::RtpMap;
+    ///
+    /// let rtpmap = RtpMap::parse("97 MPEG4-GENERIC/16000/1").unwrap();
+    /// assert_eq!(rtpmap.payload_type, 97);
+    /// assert_eq!(rtpmap.clock_rate, 16000);
+    /// ```
+    pub fn parse(value: &str) -> Option<Self> {
+        let (payload_type, encoding) = value.trim().split_once(' ')?;
+        let mut parts = encoding.trim().split('/');
+
+        Some(Self {
+            payload_type: payload_type.parse().ok()?,
+            encoding: parts.next()?.to_string(),
+            clock_rate: parts.next()?.parse().ok()?,
+            channels: parts.next().and_then(|c| c.parse().ok()),
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_media_direction_and_rtpmap() {
+        let media = "audio 0 RTP/AVP 0\na=control:trackID=2\na=sendonly";
+        let desc = parse_sdp_media(media).unwrap();
+        assert_eq!(desc.direction(), MediaDirection::SendOnly);
+        let rtpmap = desc.rtpmap().unwrap();
+        assert_eq!(rtpmap.encoding, "PCMU");
+        assert_eq!(rtpmap.clock_rate, 8000);
+
+        let media = "audio 0 RTP/AVP 97\na=rtpmap:97 MPEG4-GENERIC/16000/1";
+        let desc = parse_sdp_media(media).unwrap();
+        assert_eq!(desc.direction(), MediaDirection::SendRecv);
+        assert_eq!(desc.rtpmap().unwrap().channels, Some(1));
+    }
+
     #[test]
     fn test_sdp_media_parse() {
         let media = "video 0 RTP/AVP 96\na=control:trackID=0\na=rtpmap:96 H264/90000";
@@ -207,6 +469,7 @@ mod tests {
             protocol: "RTP/AVP".to_string(),
             format: "96".to_string(),
             attributes: std::collections::HashMap::new(),
+            connection: None,
         };
 
         // Set and get attribute
@@ -221,4 +484,37 @@ mod tests {
         assert_eq!(desc.remove_attribute("control").unwrap(), "trackID=1");
         assert!(desc.get_attribute("control").is_none());
     }
+
+    #[test]
+    fn test_parse_sdp_session_connection() {
+        let sdp = "v=0\r\n\
+                   s=Multicast\r\n\
+                   c=IN IP4 239.10.0.1/16\r\n\
+                   a=control:*\r\n\
+                   m=video 5004 RTP/AVP 96\r\n\
+                   a=rtpmap:96 H264/90000\r\n\
+                   m=audio 5006 RTP/AVP 0\r\n\
+                   c=IN IP4 239.10.0.2/8\r\n";
+        let (attrs, media) = parse_sdp(sdp);
+        assert_eq!(attrs.get("control").unwrap(), "*");
+        assert_eq!(media.len(), 2);
+
+        let video = media[0].connection.as_ref().unwrap();
+        assert_eq!(video.address, "239.10.0.1");
+        assert_eq!(video.ttl, Some(16));
+
+        let audio = media[1].connection.as_ref().unwrap();
+        assert_eq!(audio.address, "239.10.0.2");
+        assert_eq!(audio.ttl, Some(8));
+    }
+
+    #[test]
+    fn test_connection_info_ipv6() {
+        let c = ConnectionInfo::parse("IN IP6 FF15::101/3").unwrap();
+        assert_eq!(c.address, "FF15::101");
+        assert_eq!(c.ttl, None);
+        assert_eq!(c.address_count, Some(3));
+        assert!(c.is_multicast());
+        assert!(!ConnectionInfo::parse("IN IP4 10.0.0.1").unwrap().is_multicast());
+    }
 }
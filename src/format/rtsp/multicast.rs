@@ -0,0 +1,233 @@
+use super::{parse_sdp, stream::MediaStream, transport::TransportInfo, MediaDescription};
+use crate::{Result, VdkError};
+use log::{debug, warn};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+
+/// Binds a UDP socket on `port` and joins the multicast `group`.
+///
+/// When `source` is given the socket joins the group source-specifically
+/// (IGMPv3 SSM), otherwise it performs an any-source join. The socket is bound
+/// with `SO_REUSEADDR` so several receivers on one host can share the group.
+pub(crate) fn bind_multicast(
+    group: IpAddr,
+    port: u16,
+    source: Option<IpAddr>,
+    ttl: Option<u8>,
+) -> Result<UdpSocket> {
+    let domain = if group.is_ipv4() {
+        Domain::IPV4
+    } else {
+        Domain::IPV6
+    };
+    let socket = Socket::new(domain, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+
+    match group {
+        IpAddr::V4(group) => {
+            socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)).into())?;
+            match source {
+                Some(IpAddr::V4(source)) => {
+                    socket.join_ssm_v4(&source, &group, &Ipv4Addr::UNSPECIFIED)?
+                }
+                Some(IpAddr::V6(_)) => {
+                    return Err(VdkError::Protocol(
+                        "IPv6 source address for IPv4 multicast group".into(),
+                    ))
+                }
+                None => socket.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)?,
+            }
+            if let Some(ttl) = ttl {
+                socket.set_multicast_ttl_v4(ttl as u32)?;
+            }
+        }
+        IpAddr::V6(group) => {
+            socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)).into())?;
+            if source.is_some() {
+                warn!("Source-specific multicast is not supported for IPv6, joining any-source");
+            }
+            socket.join_multicast_v6(&group, 0)?;
+        }
+    }
+
+    debug!("Joined multicast group {} on port {}", group, port);
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+/// Returns the sender address from an RFC 4570 `a=source-filter` attribute.
+///
+/// Only inclusive filters are honoured:
+///
+/// ```text
+/// a=source-filter: incl IN IP4 232.3.4.5 192.0.2.10
+/// ```
+pub(crate) fn source_filter(attributes: &HashMap<String, String>) -> Option<String> {
+    let filter = attributes.get("source-filter")?;
+    let parts: Vec<&str> = filter.split_whitespace().collect();
+    match parts.as_slice() {
+        ["incl", _, _, _, source, ..] => Some(source.to_string()),
+        _ => None,
+    }
+}
+
+/// A media session described by a standalone SDP file.
+///
+/// Multicast distribution often announces streams with an SDP file (via SAP,
+/// HTTP or a file share) instead of an RTSP server. `SdpSession` parses such a
+/// description and receives the media directly, honouring the `c=` group and
+/// TTL as well as `a=source-filter` for source-specific multicast.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use vdkio::format::rtsp::SdpSession;
+/// use tokio::sync::mpsc;
+///
+/// # async fn example() -> vdkio::Result<()> {
+/// let sdp = "v=0\r\nc=IN IP4 239.1.1.1/32\r\nm=video 5004 RTP/AVP 96\r\n";
+/// let session = SdpSession::parse(sdp)?;
+///
+/// let (tx, mut rx) = mpsc::channel(100);
+/// let _streams = session.open(tx).await?;
+/// while let Some(rtp) = rx.recv().await {
+///     println!("Received {} bytes", rtp.len());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SdpSession {
+    /// Session-level attributes
+    pub attributes: HashMap<String, String>,
+    /// Media sections with their resolved connection data
+    pub media: Vec<MediaDescription>,
+}
+
+impl SdpSession {
+    /// Parses an SDP session description.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the description has no media sections.
+    pub fn parse(sdp: &str) -> Result<Self> {
+        let (attributes, media) = parse_sdp(sdp);
+        if media.is_empty() {
+            return Err(VdkError::Protocol("No media sections found in SDP".into()));
+        }
+        Ok(Self { attributes, media })
+    }
+
+    /// Builds the transport used to receive a media section.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the media section has no connection data.
+    pub fn transport_for(&self, media: &MediaDescription) -> Result<TransportInfo> {
+        let connection = media
+            .connection
+            .as_ref()
+            .ok_or_else(|| VdkError::Protocol("No connection data for media".into()))?;
+        let ports = (media.port, media.port.wrapping_add(1));
+
+        if connection.is_multicast() {
+            let mut transport =
+                TransportInfo::new_multicast(Some(&connection.address), Some(ports));
+            transport.ttl = connection.ttl;
+            transport.source =
+                source_filter(&media.attributes).or_else(|| source_filter(&self.attributes));
+            Ok(transport)
+        } else {
+            Ok(TransportInfo::new_rtp_avp(ports))
+        }
+    }
+
+    /// Binds sockets for every media section and starts receiving RTP.
+    ///
+    /// Received RTP packets are forwarded to `packet_sender`. The returned
+    /// streams own the RTCP sockets and statistics; keep them alive for the
+    /// duration of the session.
+    pub async fn open(&self, packet_sender: mpsc::Sender<Vec<u8>>) -> Result<Vec<MediaStream>> {
+        let mut streams = Vec::with_capacity(self.media.len());
+
+        for media in &self.media {
+            let control = media
+                .get_attribute("control")
+                .map(String::as_str)
+                .unwrap_or("*");
+            let mut stream = MediaStream::new(
+                &media.media_type,
+                control,
+                self.transport_for(media)?,
+                packet_sender.clone(),
+            );
+            stream.setup_transport().await?;
+            stream.start_receiving();
+            streams.push(stream);
+        }
+
+        Ok(streams)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_source_filter() {
+        let mut attrs = HashMap::new();
+        attrs.insert(
+            "source-filter".to_string(),
+            " incl IN IP4 232.3.4.5 192.0.2.10".to_string(),
+        );
+        assert_eq!(source_filter(&attrs).as_deref(), Some("192.0.2.10"));
+
+        attrs.insert(
+            "source-filter".to_string(),
+            " excl IN IP4 232.3.4.5 192.0.2.10".to_string(),
+        );
+        assert!(source_filter(&attrs).is_none());
+    }
+
+    #[test]
+    fn test_sdp_session_multicast_transport() {
+        let sdp = "v=0\r\n\
+                   c=IN IP4 232.3.4.5/64\r\n\
+                   a=source-filter: incl IN IP4 232.3.4.5 192.0.2.10\r\n\
+                   m=video 5004 RTP/AVP 96\r\n";
+        let session = SdpSession::parse(sdp).unwrap();
+        let transport = session.transport_for(&session.media[0]).unwrap();
+
+        assert!(transport.is_multicast());
+        assert_eq!(transport.destination.as_deref(), Some("232.3.4.5"));
+        assert_eq!(transport.source.as_deref(), Some("192.0.2.10"));
+        assert_eq!(transport.ttl, Some(64));
+        assert_eq!(transport.receive_ports(), Some((5004, Some(5005))));
+    }
+
+    #[tokio::test]
+    async fn test_sdp_session_unicast_ingest() {
+        let sdp = "v=0\r\nc=IN IP4 127.0.0.1\r\nm=video 47004 RTP/AVP 96\r\n";
+        let session = SdpSession::parse(sdp).unwrap();
+
+        let (tx, mut rx) = mpsc::channel(10);
+        let _streams = session.open(tx).await.unwrap();
+
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        sender
+            .send_to(&[0x80, 0x60, 0, 1], "127.0.0.1:47004")
+            .await
+            .unwrap();
+
+        let received = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, vec![0x80, 0x60, 0, 1]);
+    }
+}
@@ -1,10 +1,17 @@
+use super::multicast::bind_multicast;
 use super::transport::TransportInfo;
 use crate::format::rtcp::RTCPPacket;
 use crate::format::rtp::JitterBuffer;
-use crate::Result;
+use crate::{Result, VdkError};
+use log::{debug, error, warn};
+use std::net::IpAddr;
 use std::sync::Arc;
 use tokio::net::UdpSocket;
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Default size for packet receive buffers
+const RECEIVE_BUFFER_SIZE: usize = 8192;
 
 /// Statistics for an RTSP media stream
 #[derive(Debug)]
@@ -134,6 +141,11 @@ impl MediaStream {
             server_port_rtcp: None,
             ssrc: self.transport.ssrc,
             mode: Some("PLAY".to_string()),
+            destination: None,
+            source: None,
+            port_rtp: None,
+            port_rtcp: None,
+            ttl: None,
             extra_params,
         };
         self
@@ -142,13 +154,40 @@ impl MediaStream {
     /// Sets up UDP sockets for RTP/RTCP transport
     ///
     /// This method binds UDP sockets for receiving RTP and RTCP packets
-    /// when using UDP transport mode.
+    /// when using UDP transport mode. Multicast transports join the
+    /// `destination` group on the negotiated ports, source-specifically when
+    /// a `source` is given.
     ///
     /// # Errors
     ///
-    /// Returns an error if socket binding fails
+    /// Returns an error if socket binding or the multicast join fails
     pub async fn setup_transport(&mut self) -> Result<()> {
-        if self.transport.protocol != "RTP/AVP/TCP" {
+        if self.transport.is_multicast() {
+            let group: IpAddr = self
+                .transport
+                .destination
+                .as_deref()
+                .ok_or_else(|| VdkError::Protocol("Multicast transport without destination".into()))?
+                .parse()
+                .map_err(|e| VdkError::Protocol(format!("Invalid multicast group: {}", e)))?;
+            let source = match self.transport.source.as_deref() {
+                Some(source) => Some(source.parse::<IpAddr>().map_err(|e| {
+                    VdkError::Protocol(format!("Invalid multicast source: {}", e))
+                })?),
+                None => None,
+            };
+            let (rtp_port, rtcp_port) = self
+                .transport
+                .receive_ports()
+                .ok_or_else(|| VdkError::Protocol("Multicast transport without port".into()))?;
+
+            let ttl = self.transport.ttl;
+            self.rtp_socket = Some(Arc::new(bind_multicast(group, rtp_port, source, ttl)?));
+            if let Some(rtcp_port) = rtcp_port {
+                self.rtcp_socket =
+                    Some(Arc::new(bind_multicast(group, rtcp_port, source, ttl)?));
+            }
+        } else if self.transport.protocol != "RTP/AVP/TCP" {
             if let Some(rtp_port) = self.transport.client_port_rtp {
                 // Create RTP socket
                 let rtp_socket = UdpSocket::bind(format!("0.0.0.0:{}", rtp_port)).await?;
@@ -164,12 +203,70 @@ impl MediaStream {
         Ok(())
     }
 
+    /// Starts forwarding received RTP packets to the packet sender.
+    ///
+    /// Takes ownership of the RTP socket and spawns a task that forwards every
+    /// datagram until the socket fails or the receiver is dropped. Returns
+    /// `None` if no RTP socket is bound (e.g. TCP interleaved transport).
+    pub fn start_receiving(&mut self) -> Option<JoinHandle<()>> {
+        let socket = self.rtp_socket.take()?;
+        let packet_tx = self.packet_sender.clone();
+
+        Some(tokio::spawn(async move {
+            let mut buffer = vec![0u8; RECEIVE_BUFFER_SIZE];
+            loop {
+                match socket.recv_from(&mut buffer).await {
+                    Ok((len, _addr)) => {
+                        if let Err(e) = packet_tx.send(buffer[..len].to_vec()).await {
+                            error!("Failed to send packet: {}", e);
+                            break;
+                        }
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
+                        warn!("Socket timeout, attempting reconnect");
+                        tokio::task::yield_now().await;
+                        continue;
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {
+                        debug!("Socket interrupted, continuing");
+                        tokio::task::yield_now().await;
+                        continue;
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        tokio::task::yield_now().await;
+                        continue;
+                    }
+                    Err(e) => {
+                        error!("Socket error: {}", e);
+                        break;
+                    }
+                }
+            }
+        }))
+    }
+
     /// Generates the transport header string for RTSP SETUP requests
     pub fn get_transport_str(&self) -> String {
-        let mut transport = format!("{};unicast", self.transport.protocol);
+        let cast = if self.transport.is_multicast() {
+            "multicast"
+        } else {
+            "unicast"
+        };
+        let mut transport = format!("{};{}", self.transport.protocol, cast);
 
-        // For UDP mode, include port info
-        if self.transport.protocol == "RTP/AVP" {
+        // For multicast, pass on any requested group, ports and TTL
+        if self.transport.is_multicast() {
+            if let Some(destination) = &self.transport.destination {
+                transport.push_str(&format!(";destination={}", destination));
+            }
+            if let (Some(rtp), Some(rtcp)) = (self.transport.port_rtp, self.transport.port_rtcp) {
+                transport.push_str(&format!(";port={}-{}", rtp, rtcp));
+            }
+            if let Some(ttl) = self.transport.ttl {
+                transport.push_str(&format!(";ttl={}", ttl));
+            }
+        } else if self.transport.protocol == "RTP/AVP" {
+            // For UDP mode, include port info
             if let (Some(rtp), Some(rtcp)) = (
                 self.transport.client_port_rtp,
                 self.transport.client_port_rtcp,
@@ -277,6 +374,11 @@ mod tests {
             server_port_rtcp: None,
             ssrc: Some(0x12345678),
             mode: None,
+            destination: None,
+            source: None,
+            port_rtp: None,
+            port_rtcp: None,
+            ttl: None,
             extra_params: Default::default(),
         };
 
@@ -306,6 +408,33 @@ mod tests {
         assert!(transport_str.contains("mode=PLAY"));
     }
 
+    #[test]
+    fn test_multicast_transport_str_generation() {
+        let stream = MediaStream::new(
+            "video",
+            "trackID=1",
+            TransportInfo::new_multicast(Some("239.1.1.1"), Some((5004, 5005))),
+            mpsc::channel(1).0,
+        );
+
+        let transport_str = stream.get_transport_str();
+        assert!(transport_str.starts_with("RTP/AVP;multicast"));
+        assert!(transport_str.contains("destination=239.1.1.1"));
+        assert!(transport_str.contains("port=5004-5005"));
+        assert!(!transport_str.contains("client_port"));
+    }
+
+    #[tokio::test]
+    async fn test_multicast_setup_requires_destination() {
+        let mut stream = MediaStream::new(
+            "video",
+            "trackID=1",
+            TransportInfo::new_multicast(None, Some((5004, 5005))),
+            mpsc::channel(1).0,
+        );
+        assert!(stream.setup_transport().await.is_err());
+    }
+
     #[test]
     fn test_statistics_update() {
         let transport = TransportInfo {
@@ -317,6 +446,11 @@ mod tests {
             server_port_rtcp: None,
             ssrc: None,
             mode: None,
+            destination: None,
+            source: None,
+            port_rtp: None,
+            port_rtcp: None,
+            ttl: None,
             extra_params: Default::default(),
         };
 
@@ -0,0 +1,387 @@
+use crate::{Result, VdkError};
+use base64::Engine as _;
+
+/// TLS settings for RTSPS connections.
+///
+/// By default the server certificate is verified against the bundled web PKI
+/// roots. Cameras usually ship self-signed certificates, which can be trusted
+/// by adding their CA as a root, by pinning the certificate fingerprint or, as
+/// a last resort, by disabling verification.
+///
+/// The configuration is always available, establishing TLS connections
+/// requires the `tls` cargo feature.
+///
+/// # Example
+///
+/// ```rust
+/// use vdkio::format::rtsp::{RTSPSetupOptions, TlsConfig};
+///
+/// let tls = TlsConfig::new().with_pinned_certificate([0u8; 32]);
+/// let options = RTSPSetupOptions::new().with_tls(tls);
+/// ```
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// Trust the bundled web PKI root certificates
+    pub use_default_roots: bool,
+    /// Additional trusted root certificates (DER encoded)
+    pub root_certificates: Vec<Vec<u8>>,
+    /// SHA-256 fingerprints of server certificates that are accepted as-is
+    pub pinned_certificates: Vec<[u8; 32]>,
+    /// Accept any server certificate without verification
+    pub insecure: bool,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TlsConfig {
+    /// Creates a configuration that verifies against the default roots.
+    pub fn new() -> Self {
+        Self {
+            use_default_roots: true,
+            root_certificates: Vec::new(),
+            pinned_certificates: Vec::new(),
+            insecure: false,
+        }
+    }
+
+    /// Enables or disables the bundled web PKI roots.
+    pub fn with_default_roots(mut self, enable: bool) -> Self {
+        self.use_default_roots = enable;
+        self
+    }
+
+    /// Adds a DER encoded root certificate.
+    pub fn with_root_certificate(mut self, der: Vec<u8>) -> Self {
+        self.root_certificates.push(der);
+        self
+    }
+
+    /// Adds all certificates found in a PEM bundle as roots.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bundle contains no certificate or a
+    /// certificate block is not valid base64.
+    pub fn with_root_certificates_pem(mut self, pem: &str) -> Result<Self> {
+        let certificates = parse_pem_certificates(pem)?;
+        if certificates.is_empty() {
+            return Err(VdkError::InvalidData(
+                "No certificate found in PEM data".into(),
+            ));
+        }
+        self.root_certificates.extend(certificates);
+        Ok(self)
+    }
+
+    /// Pins a server certificate by its SHA-256 fingerprint.
+    ///
+    /// A server presenting a pinned certificate is trusted without chain
+    /// validation. Once any pin is configured, certificates that do not match
+    /// a pin are rejected.
+    pub fn with_pinned_certificate(mut self, sha256: [u8; 32]) -> Self {
+        self.pinned_certificates.push(sha256);
+        self
+    }
+
+    /// Disables certificate verification.
+    ///
+    /// Traffic is still encrypted but the server is not authenticated, so
+    /// only use this for cameras on a trusted network.
+    pub fn with_insecure(mut self, insecure: bool) -> Self {
+        self.insecure = insecure;
+        self
+    }
+}
+
+/// Extracts the DER contents of all `CERTIFICATE` blocks of a PEM bundle.
+fn parse_pem_certificates(pem: &str) -> Result<Vec<Vec<u8>>> {
+    let mut certificates = Vec::new();
+    let mut block: Option<String> = None;
+
+    for line in pem.lines().map(str::trim) {
+        match line {
+            "-----BEGIN CERTIFICATE-----" => block = Some(String::new()),
+            "-----END CERTIFICATE-----" => {
+                if let Some(data) = block.take() {
+                    let der = base64::engine::general_purpose::STANDARD
+                        .decode(data)
+                        .map_err(|e| VdkError::InvalidData(format!("Invalid PEM data: {}", e)))?;
+                    certificates.push(der);
+                }
+            }
+            _ => {
+                if let Some(data) = block.as_mut() {
+                    data.push_str(line);
+                }
+            }
+        }
+    }
+
+    Ok(certificates)
+}
+
+#[cfg(feature = "tls")]
+pub(crate) use connector::client_config;
+
+#[cfg(feature = "tls")]
+mod connector {
+    use super::TlsConfig;
+    use crate::{Result, VdkError};
+    use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+    use rustls::client::WebPkiServerVerifier;
+    use rustls::crypto::WebPkiSupportedAlgorithms;
+    use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+    use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+    use std::sync::Arc;
+
+    /// Builds a rustls client configuration from a [`TlsConfig`].
+    pub(crate) fn client_config(config: &TlsConfig) -> Result<Arc<ClientConfig>> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+        let chain = if config.insecure {
+            None
+        } else {
+            let mut roots = RootCertStore::empty();
+            if config.use_default_roots {
+                roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+            }
+            for der in &config.root_certificates {
+                roots
+                    .add(CertificateDer::from(der.clone()))
+                    .map_err(|e| VdkError::Protocol(format!("Invalid root certificate: {}", e)))?;
+            }
+
+            if roots.is_empty() {
+                if config.pinned_certificates.is_empty() {
+                    return Err(VdkError::Protocol(
+                        "TLS configuration has no trusted roots or pinned certificates".into(),
+                    ));
+                }
+                None
+            } else {
+                Some(
+                    WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                        .build()
+                        .map_err(|e| VdkError::Protocol(format!("Invalid TLS roots: {}", e)))?,
+                )
+            }
+        };
+
+        let verifier = CertificateVerifier {
+            chain,
+            pins: config.pinned_certificates.clone(),
+            insecure: config.insecure,
+            algorithms: provider.signature_verification_algorithms,
+        };
+
+        let client_config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(|e| VdkError::Protocol(format!("TLS setup failed: {}", e)))?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth();
+
+        Ok(Arc::new(client_config))
+    }
+
+    /// Verifies server certificates against pins, roots or nothing at all.
+    #[derive(Debug)]
+    struct CertificateVerifier {
+        chain: Option<Arc<WebPkiServerVerifier>>,
+        pins: Vec<[u8; 32]>,
+        insecure: bool,
+        algorithms: WebPkiSupportedAlgorithms,
+    }
+
+    impl ServerCertVerifier for CertificateVerifier {
+        fn verify_server_cert(
+            &self,
+            end_entity: &CertificateDer<'_>,
+            intermediates: &[CertificateDer<'_>],
+            server_name: &ServerName<'_>,
+            ocsp_response: &[u8],
+            now: UnixTime,
+        ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+            if self.insecure {
+                return Ok(ServerCertVerified::assertion());
+            }
+
+            if !self.pins.is_empty() {
+                let digest = ring::digest::digest(&ring::digest::SHA256, end_entity.as_ref());
+                return if self.pins.iter().any(|pin| pin[..] == *digest.as_ref()) {
+                    Ok(ServerCertVerified::assertion())
+                } else {
+                    Err(rustls::Error::General(
+                        "Server certificate does not match any pinned fingerprint".into(),
+                    ))
+                };
+            }
+
+            match &self.chain {
+                Some(chain) => chain.verify_server_cert(
+                    end_entity,
+                    intermediates,
+                    server_name,
+                    ocsp_response,
+                    now,
+                ),
+                None => Err(rustls::Error::General("No trusted roots configured".into())),
+            }
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+            rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+            rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.algorithms.supported_schemes()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pem_certificates() {
+        let pem = "junk\n\
+                   -----BEGIN CERTIFICATE-----\n\
+                   AQID\n\
+                   BA==\n\
+                   -----END CERTIFICATE-----\n\
+                   -----BEGIN CERTIFICATE-----\n\
+                   BQY=\n\
+                   -----END CERTIFICATE-----\n";
+        let certificates = parse_pem_certificates(pem).unwrap();
+        assert_eq!(certificates, vec![vec![1, 2, 3, 4], vec![5, 6]]);
+
+        assert!(TlsConfig::new().with_root_certificates_pem("junk").is_err());
+    }
+
+    #[cfg(feature = "tls")]
+    mod connection {
+        use super::super::super::connection::RTSPConnection;
+        use super::super::*;
+        use rcgen::CertifiedKey;
+        use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+        use std::sync::Arc;
+        use std::time::Duration;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+        use tokio::sync::mpsc;
+        use tokio_rustls::TlsAcceptor;
+
+        /// Starts a TLS echo server with a fresh self-signed certificate.
+        async fn echo_server() -> (u16, CertifiedKey) {
+            let certified = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+            let cert = CertificateDer::from(certified.cert.der().to_vec());
+            let key =
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()));
+
+            let server_config = rustls::ServerConfig::builder_with_provider(Arc::new(
+                rustls::crypto::ring::default_provider(),
+            ))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)
+            .unwrap();
+            let acceptor = TlsAcceptor::from(Arc::new(server_config));
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            tokio::spawn(async move {
+                while let Ok((tcp, _)) = listener.accept().await {
+                    let acceptor = acceptor.clone();
+                    tokio::spawn(async move {
+                        let Ok(mut tls) = acceptor.accept(tcp).await else {
+                            return;
+                        };
+                        let mut buf = [0u8; 1024];
+                        while let Ok(n) = tls.read(&mut buf).await {
+                            if n == 0 || tls.write_all(&buf[..n]).await.is_err() {
+                                break;
+                            }
+                        }
+                    });
+                }
+            });
+
+            (port, certified)
+        }
+
+        async fn exchange(port: u16, config: &TlsConfig) -> crate::Result<()> {
+            let mut conn = RTSPConnection::connect_tls("localhost", port, config).await?;
+            let (tx, mut rx) = mpsc::channel(4);
+            conn.register_channel(0, tx);
+
+            let mut data = b"RTSP/1.0 200 OK\r\nCSeq: 1\r\n\r\n".to_vec();
+            data.extend_from_slice(&[b'$', 0, 0, 2, 0x80, 0x60]);
+            conn.write_all(&data).await?;
+
+            let response = conn.read_response().await?;
+            assert_eq!(response, b"RTSP/1.0 200 OK\r\nCSeq: 1\r\n\r\n");
+            let packet = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(packet, vec![0x80, 0x60]);
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_tls_custom_root() {
+            let (port, certified) = echo_server().await;
+            let config = TlsConfig::new()
+                .with_default_roots(false)
+                .with_root_certificates_pem(&certified.cert.pem())
+                .unwrap();
+            exchange(port, &config).await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_tls_pinned_certificate() {
+            let (port, certified) = echo_server().await;
+            let digest = ring::digest::digest(&ring::digest::SHA256, certified.cert.der());
+            let mut pin = [0u8; 32];
+            pin.copy_from_slice(digest.as_ref());
+
+            exchange(port, &TlsConfig::new().with_pinned_certificate(pin))
+                .await
+                .unwrap();
+            assert!(
+                exchange(port, &TlsConfig::new().with_pinned_certificate([0u8; 32]))
+                    .await
+                    .is_err()
+            );
+        }
+
+        #[tokio::test]
+        async fn test_tls_insecure_and_untrusted() {
+            let (port, _) = echo_server().await;
+            assert!(exchange(port, &TlsConfig::new()).await.is_err());
+            exchange(port, &TlsConfig::new().with_insecure(true))
+                .await
+                .unwrap();
+        }
+    }
+}
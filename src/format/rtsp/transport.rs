@@ -23,6 +23,16 @@ pub struct TransportInfo {
     pub ssrc: Option<u32>,
     /// Transport mode (e.g., "PLAY", "RECORD")
     pub mode: Option<String>,
+    /// Multicast group (or unicast destination) the media is sent to
+    pub destination: Option<String>,
+    /// Sender address for source-specific multicast
+    pub source: Option<String>,
+    /// Multicast RTP port (`port=` parameter)
+    pub port_rtp: Option<u16>,
+    /// Multicast RTCP port (`port=` parameter)
+    pub port_rtcp: Option<u16>,
+    /// Multicast time-to-live
+    pub ttl: Option<u8>,
     /// Additional transport parameters not covered by other fields
     pub extra_params: HashMap<String, Option<String>>,
 }
@@ -66,10 +76,78 @@ impl TransportInfo {
             server_port_rtcp: None,
             ssrc: None,
             mode: None,
+            destination: None,
+            source: None,
+            port_rtp: None,
+            port_rtcp: None,
+            ttl: None,
             extra_params: HashMap::new(),
         }
     }
 
+    /// Creates a new multicast RTP/AVP transport request.
+    ///
+    /// The server chooses the group and ports unless they are given here; they
+    /// are filled in from the SETUP reply.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Optional multicast group to request
+    /// * `ports` - Optional (RTP port, RTCP port) pair to request
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use vdkio::format::rtsp::{CastType, TransportInfo};
+    ///
+    /// let transport = TransportInfo::new_multicast(Some("239.0.0.1"), Some((5004, 5005)));
+    /// assert_eq!(transport.cast_type, CastType::Multicast);
+    /// assert_eq!(transport.port_rtp, Some(5004));
+    /// ```
+    pub fn new_multicast(destination: Option<&str>, ports: Option<(u16, u16)>) -> Self {
+        let mut info = Self::new_rtp_avp((0, 0));
+        info.cast_type = CastType::Multicast;
+        info.client_port_rtp = None;
+        info.client_port_rtcp = None;
+        info.destination = destination.map(String::from);
+        info.port_rtp = ports.map(|p| p.0);
+        info.port_rtcp = ports.map(|p| p.1);
+        info
+    }
+
+    /// Returns true if this transport describes multicast delivery.
+    pub fn is_multicast(&self) -> bool {
+        self.cast_type == CastType::Multicast
+    }
+
+    /// Returns the (RTP, RTCP) port pair media is received on.
+    ///
+    /// Multicast transports use the `port=` parameter, unicast transports use
+    /// `client_port=`.
+    pub fn receive_ports(&self) -> Option<(u16, Option<u16>)> {
+        if self.is_multicast() {
+            self.port_rtp
+                .or(self.client_port_rtp)
+                .map(|rtp| (rtp, self.port_rtcp.or(self.client_port_rtcp)))
+        } else {
+            self.client_port_rtp.map(|rtp| (rtp, self.client_port_rtcp))
+        }
+    }
+
+    /// Returns the (RTP, RTCP) channel pair of an interleaved transport.
+    ///
+    /// A single channel (`interleaved=4`) implies RTCP on the next channel.
+    pub fn interleaved_channels(&self) -> Option<(u8, u8)> {
+        let value = self.extra_params.get("interleaved")?.as_deref()?;
+        match value.split_once('-') {
+            Some((rtp, rtcp)) => Some((rtp.trim().parse().ok()?, rtcp.trim().parse().ok()?)),
+            None => {
+                let rtp: u8 = value.trim().parse().ok()?;
+                Some((rtp, rtp.wrapping_add(1)))
+            }
+        }
+    }
+
     /// Parses a transport header string into a TransportInfo object.
     ///
     /// This method parses transport specifications according to RFC 2326,
@@ -102,6 +180,11 @@ impl TransportInfo {
             server_port_rtcp: None,
             ssrc: None,
             mode: None,
+            destination: None,
+            source: None,
+            port_rtp: None,
+            port_rtcp: None,
+            ttl: None,
             extra_params: HashMap::new(),
         };
 
@@ -137,9 +220,26 @@ impl TransportInfo {
                             info.ssrc = Some(ssrc);
                         }
                     }
+                    "port" => {
+                        if let Some((rtp, rtcp)) = value.split_once('-') {
+                            info.port_rtp = rtp.parse().ok();
+                            info.port_rtcp = rtcp.parse().ok();
+                        } else {
+                            info.port_rtp = value.parse().ok();
+                        }
+                    }
                     "mode" => {
                         info.mode = Some(value.to_string());
                     }
+                    "destination" => {
+                        info.destination = Some(value.to_string());
+                    }
+                    "source" => {
+                        info.source = Some(value.to_string());
+                    }
+                    "ttl" => {
+                        info.ttl = value.parse().ok();
+                    }
                     _ => {
                         info.extra_params
                             .insert(key.to_string(), Some(value.to_string()));
@@ -183,6 +283,22 @@ impl TransportInfo {
             .to_string(),
         );
 
+        if let Some(ref destination) = self.destination {
+            parts.push(format!("destination={}", destination));
+        }
+
+        if let Some(ref source) = self.source {
+            parts.push(format!("source={}", source));
+        }
+
+        if let Some(ttl) = self.ttl {
+            parts.push(format!("ttl={}", ttl));
+        }
+
+        if let (Some(rtp), Some(rtcp)) = (self.port_rtp, self.port_rtcp) {
+            parts.push(format!("port={}-{}", rtp, rtcp));
+        }
+
         if let (Some(rtp), Some(rtcp)) = (self.client_port_rtp, self.client_port_rtcp) {
             parts.push(format!("client_port={}-{}", rtp, rtcp));
         }
@@ -251,10 +367,40 @@ mod tests {
 
     #[test]
     fn test_transport_parse_multicast() {
-        let transport = "RTP/AVP;multicast;port=5000-5001;ttl=32";
+        let transport = "RTP/AVP;multicast;destination=239.1.2.3;port=5000-5001;ttl=32";
         let info = TransportInfo::parse(transport).unwrap();
         assert_eq!(info.cast_type, CastType::Multicast);
-        assert!(info.extra_params.contains_key("ttl"));
+        assert_eq!(info.destination.as_deref(), Some("239.1.2.3"));
+        assert_eq!(info.port_rtp, Some(5000));
+        assert_eq!(info.port_rtcp, Some(5001));
+        assert_eq!(info.ttl, Some(32));
+        assert_eq!(info.receive_ports(), Some((5000, Some(5001))));
+    }
+
+    #[test]
+    fn test_multicast_to_string_roundtrip() {
+        let mut transport = TransportInfo::new_multicast(Some("232.1.1.1"), Some((6000, 6001)));
+        transport.source = Some("10.0.0.5".to_string());
+        transport.ttl = Some(16);
+
+        let info = TransportInfo::parse(&transport.to_string()).unwrap();
+        assert!(info.is_multicast());
+        assert_eq!(info.destination.as_deref(), Some("232.1.1.1"));
+        assert_eq!(info.source.as_deref(), Some("10.0.0.5"));
+        assert_eq!(info.ttl, Some(16));
+        assert_eq!(info.receive_ports(), Some((6000, Some(6001))));
+    }
+
+    #[test]
+    fn test_interleaved_channels() {
+        let info = TransportInfo::parse("RTP/AVP/TCP;unicast;interleaved=2-3").unwrap();
+        assert_eq!(info.interleaved_channels(), Some((2, 3)));
+
+        let info = TransportInfo::parse("RTP/AVP/TCP;unicast;interleaved=4").unwrap();
+        assert_eq!(info.interleaved_channels(), Some((4, 5)));
+
+        let info = TransportInfo::parse("RTP/AVP;unicast;client_port=5000-5001").unwrap();
+        assert_eq!(info.interleaved_channels(), None);
     }
 
     #[test]
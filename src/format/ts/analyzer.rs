@@ -0,0 +1,1044 @@
+use super::demuxer::SectionBuffer;
+use super::parser::TSPacketParser;
+use super::pes::PESHeader;
+use super::si::{PID_EIT, PID_NIT, PID_SDT, PID_TDT, TABLE_ID_TOT};
+use super::types::*;
+use crate::error::Result;
+use crate::utils::crc::Crc32Mpeg2;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Consecutive sync bytes needed to acquire sync
+const SYNC_ACQUIRE_PACKETS: usize = 5;
+
+/// Consecutive corrupted sync bytes after which sync is lost
+const SYNC_LOSS_PACKETS: u32 = 2;
+
+/// Maximum interval between two sections of a PAT or PMT
+const PSI_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Maximum interval between two PCRs of a PID
+const PCR_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Maximum deviation of a PCR from its value at the transport rate
+const PCR_ACCURACY: Duration = Duration::from_nanos(500);
+
+/// Maximum interval between two PTS of an audio or video PID
+const PTS_INTERVAL: Duration = Duration::from_millis(700);
+
+/// Default time after which a PID referenced by a PMT is reported missing
+const DEFAULT_PID_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Range of the 27 MHz program clock reference
+const PCR_WRAP: u64 = (1 << 33) * 300;
+
+/// CAT PID, whose sections are CRC checked
+const PID_CAT: u16 = 0x0001;
+
+/// Converts a number of 27 MHz clock ticks to a duration.
+fn ticks_to_time(ticks: u64) -> Duration {
+    Duration::from_nanos(ticks * 1000 / 27)
+}
+
+/// Transport Stream checks of ETSI TR 101 290.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum TR101290Check {
+    /// 1.1: Sync lost after corrupted sync bytes
+    TSSyncLoss,
+    /// 1.2: Sync byte other than 0x47 while in sync
+    SyncByte,
+    /// 1.3: PAT missing for 0.5 s, wrong table ID or scrambled on PID 0
+    PAT,
+    /// 1.4: Lost, reordered or repeatedly duplicated packets
+    ContinuityCount,
+    /// 1.5: PMT missing for 0.5 s or scrambled
+    PMT,
+    /// 1.6: A PID referenced by a PMT does not occur
+    PID,
+    /// 2.1: Transport error indicator set
+    Transport,
+    /// 2.2: CRC mismatch of a PSI or SI section
+    CRC,
+    /// 2.3a: More than 100 ms between two PCRs
+    PCRRepetition,
+    /// 2.3b: PCR jump without the discontinuity indicator
+    PCRDiscontinuityIndicator,
+    /// 2.4: PCR off by more than 500 ns from the transport rate
+    PCRAccuracy,
+    /// 2.5: More than 700 ms between two PTS
+    PTS,
+}
+
+impl TR101290Check {
+    /// Returns the TR 101 290 priority of the check, 1 or 2.
+    pub fn priority(&self) -> u8 {
+        match self {
+            Self::TSSyncLoss
+            | Self::SyncByte
+            | Self::PAT
+            | Self::ContinuityCount
+            | Self::PMT
+            | Self::PID => 1,
+            _ => 2,
+        }
+    }
+}
+
+/// A failed TR 101 290 check.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TR101290Error {
+    /// The failed check
+    pub check: TR101290Check,
+    /// PID the error was found on, if it concerns a single PID
+    pub pid: Option<u16>,
+    /// Byte offset of the packet in the input
+    pub offset: u64,
+    /// Stream time by PCR since the first PCR, if known
+    pub time: Option<Duration>,
+    /// Description of the error
+    pub message: String,
+}
+
+/// Statistics of a single PID.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TSPIDReport {
+    /// The PID
+    pub pid: u16,
+    /// Stream type from the PMT, for elementary streams
+    pub stream_type: Option<u8>,
+    /// Number of TS packets
+    pub packets: u64,
+    /// Average bitrate in bits per second
+    pub bitrate: Option<u64>,
+    /// Number of PCRs
+    pub pcrs: u64,
+    /// Largest interval between two PCRs
+    pub max_pcr_interval: Option<Duration>,
+    /// Largest deviation of a PCR from its value at the transport rate
+    pub pcr_jitter: Option<Duration>,
+    /// Number of errors by check
+    pub errors: BTreeMap<TR101290Check, u64>,
+}
+
+/// Summary of a [`TSAnalyzer`] run.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TSAnalysis {
+    /// Number of bytes analyzed
+    pub bytes: u64,
+    /// Number of TS packets
+    pub packets: u64,
+    /// Stream time covered by PCRs
+    pub duration: Option<Duration>,
+    /// Average transport rate in bits per second
+    pub bitrate: Option<u64>,
+    /// Number of errors by check
+    pub errors: BTreeMap<TR101290Check, u64>,
+    /// Statistics of every PID seen or referenced, by PID
+    pub pids: Vec<TSPIDReport>,
+}
+
+impl TSAnalysis {
+    /// Returns the number of errors of the given priority.
+    pub fn error_count(&self, priority: u8) -> u64 {
+        self.errors
+            .iter()
+            .filter(|(check, _)| check.priority() == priority)
+            .map(|(_, count)| count)
+            .sum()
+    }
+}
+
+/// Stream time derived from the PCRs of a reference PID.
+///
+/// Between PCRs, time advances with the byte position at the last
+/// measured transport rate.
+#[derive(Debug)]
+struct Clock {
+    pid: u16,
+    last_pcr: u64,
+    last_offset: u64,
+    /// 27 MHz ticks from the first to the last PCR
+    ticks: u64,
+    /// Transport rate in bytes per tick
+    rate: Option<f64>,
+}
+
+impl Clock {
+    /// Returns the stream time at a byte offset.
+    fn now(&self, offset: u64) -> Duration {
+        ticks_to_time(self.ticks_at(offset))
+    }
+
+    fn ticks_at(&self, offset: u64) -> u64 {
+        let bytes = offset.saturating_sub(self.last_offset) as f64;
+        match self.rate {
+            Some(rate) if rate > 0.0 => self.ticks + (bytes / rate).round() as u64,
+            _ => self.ticks,
+        }
+    }
+
+    fn update(&mut self, pcr: u64, offset: u64, discontinuity: bool) {
+        let delta = (pcr + PCR_WRAP - self.last_pcr) % PCR_WRAP;
+        if discontinuity || delta == 0 || delta > PCR_HZ {
+            // Continue from the byte position over the jump
+            self.ticks = self.ticks_at(offset);
+        } else {
+            let bytes = offset - self.last_offset;
+            self.rate = Some(bytes as f64 / delta as f64);
+            self.ticks += delta;
+        }
+        self.last_pcr = pcr;
+        self.last_offset = offset;
+    }
+}
+
+/// State of a single PID.
+#[derive(Debug, Default)]
+struct PIDState {
+    packets: u64,
+    stream_type: Option<u8>,
+    /// Last continuity counter and how often it was repeated
+    continuity: Option<(u8, u8)>,
+    /// Last PCR and the byte offset of its packet
+    last_pcr: Option<(u64, u64)>,
+    /// Transport rate between the last two PCRs, in bytes per 27 MHz tick
+    pcr_rate: Option<f64>,
+    /// Stream time of the last PCR
+    last_pcr_time: Option<Duration>,
+    pcrs: u64,
+    max_pcr_interval: Option<Duration>,
+    pcr_jitter: Option<Duration>,
+    /// Stream time the PID was last seen, for PID errors
+    last_seen: Option<Duration>,
+    /// Stream time of the last PTS, for PTS errors of audio and video
+    last_pts: Option<Duration>,
+    errors: BTreeMap<TR101290Check, u64>,
+}
+
+/// A program announced in the PAT.
+#[derive(Debug)]
+struct AnalyzerProgram {
+    pmt_pid: u16,
+    /// Stream time of the last PMT section
+    last_pmt: Option<Duration>,
+    /// Elementary PIDs of the last PMT
+    pids: Vec<u16>,
+}
+
+/// Transport Stream analyzer for ETSI TR 101 290 Priority 1 and 2 checks.
+///
+/// The analyzer consumes a TS byte stream in arbitrary chunks and reports
+/// every failed check as a [`TR101290Error`], along with per-PID bitrates
+/// and PCR jitter. Timing checks use the stream time of the first PID
+/// carrying PCRs, so they only start once a PCR has been seen. PCR
+/// accuracy is measured against a constant transport rate, which VBR
+/// streams such as the default [`TSMuxer`](super::TSMuxer) output fail.
+///
+/// # Example
+///
+/// ```no_run
+/// use vdkio::format::ts::TSAnalyzer;
+///
+/// # async fn example() -> vdkio::Result<()> {
+/// let file = tokio::fs::File::open("feed.ts").await?;
+/// let mut analyzer = TSAnalyzer::new();
+/// analyzer.read_from(file).await?;
+/// for error in analyzer.take_errors() {
+///     println!("{:?} on {:?}: {}", error.check, error.pid, error.message);
+/// }
+/// let report = analyzer.report();
+/// println!("{} priority 1 errors", report.error_count(1));
+/// # Ok(())
+/// # }
+/// ```
+pub struct TSAnalyzer {
+    parser: TSPacketParser,
+    crc: Crc32Mpeg2,
+    buffer: Vec<u8>,
+    /// Byte offset of the start of the buffer
+    offset: u64,
+    synced: bool,
+    /// Consecutive corrupted sync bytes while in sync
+    bad_syncs: u32,
+    packets: u64,
+    pid_timeout: Duration,
+    clock: Option<Clock>,
+    pids: BTreeMap<u16, PIDState>,
+    sections: HashMap<u16, SectionBuffer>,
+    /// Stream time of the last PAT section
+    last_pat: Option<Duration>,
+    programs: BTreeMap<u16, AnalyzerProgram>,
+    errors: VecDeque<TR101290Error>,
+    counts: BTreeMap<TR101290Check, u64>,
+}
+
+impl Default for TSAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TSAnalyzer {
+    /// Creates a new analyzer.
+    pub fn new() -> Self {
+        Self {
+            parser: TSPacketParser::new(),
+            crc: Crc32Mpeg2::new(),
+            buffer: Vec::new(),
+            offset: 0,
+            synced: false,
+            bad_syncs: 0,
+            packets: 0,
+            pid_timeout: DEFAULT_PID_TIMEOUT,
+            clock: None,
+            pids: BTreeMap::new(),
+            sections: HashMap::new(),
+            last_pat: None,
+            programs: BTreeMap::new(),
+            errors: VecDeque::new(),
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the time after which a PID referenced by a PMT is reported
+    /// missing (5 s by default).
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Longest allowed gap between two packets of a PID
+    pub fn with_pid_timeout(mut self, timeout: Duration) -> Self {
+        self.pid_timeout = timeout;
+        self
+    }
+
+    /// Analyzes the next chunk of the Transport Stream.
+    ///
+    /// Chunks don't need to be aligned to TS packets.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Bytes following the previously analyzed ones
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+
+        let mut pos = 0;
+        loop {
+            if self.synced {
+                if self.buffer.len() - pos < TS_PACKET_SIZE {
+                    break;
+                }
+                let packet = self.buffer[pos..pos + TS_PACKET_SIZE].to_vec();
+                let offset = self.offset + pos as u64;
+                pos += TS_PACKET_SIZE;
+                if packet[0] == TS_SYNC_BYTE {
+                    self.bad_syncs = 0;
+                    self.analyze_packet(&packet, offset);
+                    continue;
+                }
+
+                self.bad_syncs += 1;
+                self.report_error(
+                    TR101290Check::SyncByte,
+                    None,
+                    offset,
+                    format!("Sync byte 0x{:02x}", packet[0]),
+                );
+                if self.bad_syncs >= SYNC_LOSS_PACKETS {
+                    self.synced = false;
+                    self.report_error(
+                        TR101290Check::TSSyncLoss,
+                        None,
+                        offset,
+                        format!("{} corrupted sync bytes in a row", self.bad_syncs),
+                    );
+                }
+            } else {
+                let needed = TS_PACKET_SIZE * (SYNC_ACQUIRE_PACKETS - 1) + 1;
+                let end = self.buffer.len().saturating_sub(needed - 1);
+                let found = (pos..end).find(|&start| {
+                    (0..SYNC_ACQUIRE_PACKETS)
+                        .all(|i| self.buffer[start + i * TS_PACKET_SIZE] == TS_SYNC_BYTE)
+                });
+                match found {
+                    Some(start) => {
+                        pos = start;
+                        self.synced = true;
+                        self.bad_syncs = 0;
+                    }
+                    None => {
+                        pos = pos.max(end);
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.buffer.drain(..pos);
+        self.offset += pos as u64;
+    }
+
+    /// Reads and analyzes a Transport Stream until its end.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Source of the Transport Stream
+    pub async fn read_from<R: AsyncRead + Unpin>(&mut self, mut reader: R) -> Result<()> {
+        let mut chunk = vec![0u8; TS_PACKET_SIZE * 64];
+        loop {
+            let n = reader.read(&mut chunk).await?;
+            if n == 0 {
+                return Ok(());
+            }
+            self.push(&chunk[..n]);
+        }
+    }
+
+    /// Takes the errors found since the last call.
+    pub fn take_errors(&mut self) -> Vec<TR101290Error> {
+        self.errors.drain(..).collect()
+    }
+
+    /// Returns the statistics of the stream analyzed so far.
+    pub fn report(&self) -> TSAnalysis {
+        let duration = self.clock.as_ref().map(|clock| ticks_to_time(clock.ticks));
+        let bitrate = |packets: u64| {
+            duration
+                .filter(|duration| !duration.is_zero())
+                .map(|duration| {
+                    (packets * TS_PACKET_SIZE as u64 * 8) as f64 / duration.as_secs_f64()
+                })
+                .map(|bitrate| bitrate.round() as u64)
+        };
+
+        TSAnalysis {
+            bytes: self.offset + self.buffer.len() as u64,
+            packets: self.packets,
+            duration,
+            bitrate: bitrate(self.packets),
+            errors: self.counts.clone(),
+            pids: self
+                .pids
+                .iter()
+                .map(|(&pid, state)| TSPIDReport {
+                    pid,
+                    stream_type: state.stream_type,
+                    packets: state.packets,
+                    bitrate: bitrate(state.packets),
+                    pcrs: state.pcrs,
+                    max_pcr_interval: state.max_pcr_interval,
+                    pcr_jitter: state.pcr_jitter,
+                    errors: state.errors.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Records a failed check.
+    fn report_error(
+        &mut self,
+        check: TR101290Check,
+        pid: Option<u16>,
+        offset: u64,
+        message: String,
+    ) {
+        *self.counts.entry(check).or_default() += 1;
+        if let Some(pid) = pid {
+            *self
+                .pids
+                .entry(pid)
+                .or_default()
+                .errors
+                .entry(check)
+                .or_default() += 1;
+        }
+        let time = self.now(offset);
+        self.errors.push_back(TR101290Error {
+            check,
+            pid,
+            offset,
+            time,
+            message,
+        });
+    }
+
+    /// Returns the stream time at a byte offset since the first PCR.
+    fn now(&self, offset: u64) -> Option<Duration> {
+        self.clock.as_ref().map(|clock| clock.now(offset))
+    }
+
+    /// Runs the checks of a single packet with a valid sync byte.
+    fn analyze_packet(&mut self, data: &[u8], offset: u64) {
+        let Ok(header) = self.parser.parse_header(data) else {
+            return;
+        };
+        let pid = header.pid;
+        self.packets += 1;
+        self.pids.entry(pid).or_default().packets += 1;
+
+        if header.transport_error {
+            // The rest of the packet can't be trusted
+            self.report_error(
+                TR101290Check::Transport,
+                Some(pid),
+                offset,
+                "Transport error indicator set".into(),
+            );
+            return;
+        }
+
+        let adaptation = self
+            .parser
+            .parse_adaptation_field(data, TS_HEADER_SIZE)
+            .ok()
+            .flatten();
+        let discontinuity = adaptation.as_ref().is_some_and(|field| field.discontinuity);
+        if let Some(pcr) = adaptation.as_ref().and_then(|field| field.pcr) {
+            self.check_pcr(pid, pcr, offset, discontinuity);
+        }
+
+        let now = self.now(offset);
+        if let Some(now) = now {
+            self.check_timeouts(now, offset);
+        }
+        self.pids.entry(pid).or_default().last_seen = now;
+
+        if pid == PID_NULL {
+            return;
+        }
+        self.check_continuity(&header, discontinuity, offset);
+
+        let is_pmt = self.programs.values().any(|program| program.pmt_pid == pid);
+        if header.scrambling_control != 0 && (pid == PID_PAT || is_pmt) {
+            let check = if pid == PID_PAT {
+                TR101290Check::PAT
+            } else {
+                TR101290Check::PMT
+            };
+            self.report_error(check, Some(pid), offset, "Scrambled PSI packet".into());
+            return;
+        }
+
+        if !header.contains_payload {
+            return;
+        }
+        let start = match &adaptation {
+            Some(field) => TS_HEADER_SIZE + 1 + field.length,
+            // An adaptation field of length 0 still has its length byte
+            None if header.adaptation_field_exists => TS_HEADER_SIZE + 1,
+            None => TS_HEADER_SIZE,
+        };
+        let Some(payload) = data.get(start..) else {
+            return;
+        };
+
+        if pid == PID_PAT
+            || is_pmt
+            || matches!(pid, PID_CAT | PID_NIT | PID_SDT | PID_EIT | PID_TDT)
+        {
+            let sections = self
+                .sections
+                .entry(pid)
+                .or_default()
+                .push(payload, header.payload_unit_start);
+            for section in sections {
+                self.check_section(pid, &section, offset, now);
+            }
+        } else if header.payload_unit_start && self.is_av_pid(pid) {
+            if let Ok(pes) = PESHeader::parse(payload) {
+                if pes.pts.is_some() {
+                    self.pids.entry(pid).or_default().last_pts = now;
+                }
+            }
+        }
+    }
+
+    /// Checks the PCR of a packet, and advances the clock on the reference PID.
+    fn check_pcr(&mut self, pid: u16, pcr: u64, offset: u64, discontinuity: bool) {
+        match &mut self.clock {
+            Some(clock) if clock.pid == pid => clock.update(pcr, offset, discontinuity),
+            Some(_) => {}
+            None => {
+                self.clock = Some(Clock {
+                    pid,
+                    last_pcr: pcr,
+                    last_offset: offset,
+                    ticks: 0,
+                    rate: None,
+                });
+            }
+        }
+        let now = self.now(offset);
+
+        let state = self.pids.entry(pid).or_default();
+        state.pcrs += 1;
+        let previous = state.last_pcr.replace((pcr, offset));
+        let previous_time = std::mem::replace(&mut state.last_pcr_time, now);
+        let Some((last_pcr, last_offset)) = previous.filter(|_| !discontinuity) else {
+            state.pcr_rate = None;
+            return;
+        };
+
+        let mut errors = Vec::new();
+        if let (Some(now), Some(previous_time)) = (now, previous_time) {
+            let interval = now.saturating_sub(previous_time);
+            state.max_pcr_interval = state.max_pcr_interval.max(Some(interval));
+            if interval > PCR_INTERVAL {
+                errors.push((
+                    TR101290Check::PCRRepetition,
+                    format!("{} ms between PCRs", interval.as_millis()),
+                ));
+            }
+        }
+
+        let delta = (pcr + PCR_WRAP - last_pcr) % PCR_WRAP;
+        let elapsed = ticks_to_time(delta);
+        let bytes = (offset - last_offset) as f64;
+        if delta == 0 || delta > PCR_WRAP / 2 || elapsed > PCR_INTERVAL {
+            errors.push((
+                TR101290Check::PCRDiscontinuityIndicator,
+                format!(
+                    "PCR jumped by {} ms without discontinuity indicator",
+                    ticks_to_time(delta.min(PCR_WRAP - delta)).as_millis()
+                ),
+            ));
+            state.pcr_rate = None;
+        } else {
+            // Compare with the PCR expected at the rate of the previous interval
+            if let Some(rate) = state.pcr_rate {
+                let expected = bytes / rate;
+                let deviation =
+                    Duration::from_secs_f64((delta as f64 - expected).abs() / PCR_HZ as f64);
+                state.pcr_jitter = state.pcr_jitter.max(Some(deviation));
+                if deviation > PCR_ACCURACY {
+                    errors.push((
+                        TR101290Check::PCRAccuracy,
+                        format!("PCR off by {} ns", deviation.as_nanos()),
+                    ));
+                }
+            }
+            state.pcr_rate = Some(bytes / delta as f64);
+        }
+
+        for (check, message) in errors {
+            self.report_error(check, Some(pid), offset, message);
+        }
+    }
+
+    /// Checks the continuity counter of a packet against the previous one
+    /// on its PID.
+    ///
+    /// A packet may be sent twice, more duplicates are errors.
+    fn check_continuity(&mut self, header: &TSHeader, discontinuity: bool, offset: u64) {
+        let state = self.pids.entry(header.pid).or_default();
+        let counter = header.continuity_counter;
+        let previous = state.continuity.replace((counter, 0));
+        let Some((previous, duplicates)) = previous.filter(|_| !discontinuity) else {
+            return;
+        };
+
+        let message = if !header.contains_payload {
+            // Packets without payload do not increment the counter
+            if counter == previous {
+                state.continuity = Some((counter, duplicates));
+                return;
+            }
+            format!("Counter changed from {} without payload", previous)
+        } else if counter == (previous + 1) & 0x0F {
+            return;
+        } else if counter == previous {
+            if duplicates == 0 {
+                state.continuity = Some((counter, 1));
+                return;
+            }
+            format!("Packet with counter {} repeated more than once", counter)
+        } else {
+            format!(
+                "Expected counter {}, got {}",
+                (previous + 1) & 0x0F,
+                counter
+            )
+        };
+        self.report_error(
+            TR101290Check::ContinuityCount,
+            Some(header.pid),
+            offset,
+            message,
+        );
+    }
+
+    /// Checks the CRC and table ID of a PSI or SI section, and follows PAT
+    /// and PMT changes.
+    fn check_section(&mut self, pid: u16, section: &[u8], offset: u64, now: Option<Duration>) {
+        let table_id = section[0];
+        let has_crc = section[1] & 0x80 != 0 || table_id == TABLE_ID_TOT;
+        // The CRC over a section including its CRC_32 field is zero
+        if has_crc && self.crc.calculate(section) != 0 {
+            self.report_error(
+                TR101290Check::CRC,
+                Some(pid),
+                offset,
+                format!("CRC mismatch in section with table ID 0x{:02x}", table_id),
+            );
+            return;
+        }
+
+        if pid == PID_PAT {
+            if table_id != TABLE_ID_PAT {
+                self.report_error(
+                    TR101290Check::PAT,
+                    Some(pid),
+                    offset,
+                    format!("Table ID 0x{:02x} on the PAT PID", table_id),
+                );
+                return;
+            }
+            self.last_pat = now;
+            if let Ok(pat) = self.parser.parse_pat(section, 0, 0) {
+                self.handle_pat(pat, now);
+            }
+            return;
+        }
+
+        if table_id != TABLE_ID_PMT {
+            return;
+        }
+        let Ok(pmt) = self.parser.parse_pmt(section, 0, 0) else {
+            return;
+        };
+        let Some(program) = self
+            .programs
+            .get_mut(&pmt.program_number)
+            .filter(|program| program.pmt_pid == pid)
+        else {
+            return;
+        };
+        program.last_pmt = now;
+        program.pids = pmt
+            .elementary_stream_infos
+            .iter()
+            .map(|info| info.elementary_pid)
+            .collect();
+        for info in &pmt.elementary_stream_infos {
+            let state = self.pids.entry(info.elementary_pid).or_default();
+            state.stream_type = Some(info.stream_type);
+            // Timeouts of new PIDs start with the PMT announcing them
+            state.last_seen = state.last_seen.or(now);
+            state.last_pts = state.last_pts.or(now);
+        }
+    }
+
+    /// Follows the programs of a PAT.
+    fn handle_pat(&mut self, pat: PAT, now: Option<Duration>) {
+        let programs: BTreeSet<u16> = pat
+            .entries
+            .iter()
+            .filter(|entry| entry.program_number != 0)
+            .map(|entry| entry.program_number)
+            .collect();
+        self.programs
+            .retain(|program_number, _| programs.contains(program_number));
+
+        for entry in pat.entries.iter().filter(|entry| entry.program_number != 0) {
+            let program = self
+                .programs
+                .entry(entry.program_number)
+                .or_insert_with(|| AnalyzerProgram {
+                    pmt_pid: entry.program_map_pid,
+                    last_pmt: now,
+                    pids: Vec::new(),
+                });
+            if program.pmt_pid != entry.program_map_pid {
+                program.pmt_pid = entry.program_map_pid;
+                program.last_pmt = now;
+                program.pids.clear();
+            }
+        }
+    }
+
+    /// Returns true for audio and video PIDs, whose PTS repetition is checked.
+    ///
+    /// Audio in private data streams is not recognised.
+    fn is_av_pid(&self, pid: u16) -> bool {
+        self.pids.get(&pid).is_some_and(|state| {
+            matches!(
+                state.stream_type,
+                Some(
+                    STREAM_TYPE_H264
+                        | STREAM_TYPE_H265
+                        | STREAM_TYPE_MPEG1_VIDEO
+                        | STREAM_TYPE_MPEG2_VIDEO
+                        | STREAM_TYPE_AAC
+                        | STREAM_TYPE_MPEG1_AUDIO
+                        | STREAM_TYPE_MPEG2_AUDIO
+                        | STREAM_TYPE_AC3
+                        | STREAM_TYPE_EAC3
+                )
+            )
+        })
+    }
+
+    /// Reports PAT, PMT, PID and PTS repetition errors that are due.
+    ///
+    /// Timers without an occurrence run from the first PCR. Each timer
+    /// restarts when reported, so a missing table is reported once per
+    /// interval.
+    fn check_timeouts(&mut self, now: Duration, offset: u64) {
+        let mut errors = Vec::new();
+        let elapsed = |last: Option<Duration>| now.saturating_sub(last.unwrap_or_default());
+
+        if elapsed(self.last_pat) > PSI_INTERVAL {
+            errors.push((
+                TR101290Check::PAT,
+                Some(PID_PAT),
+                "No PAT for 0.5 s".to_string(),
+            ));
+            self.last_pat = Some(now);
+        }
+
+        let mut referenced = BTreeSet::new();
+        for program in self.programs.values_mut() {
+            if elapsed(program.last_pmt) > PSI_INTERVAL {
+                errors.push((
+                    TR101290Check::PMT,
+                    Some(program.pmt_pid),
+                    "No PMT for 0.5 s".to_string(),
+                ));
+                program.last_pmt = Some(now);
+            }
+            referenced.extend(program.pids.iter().copied());
+        }
+
+        let av_pids: Vec<u16> = referenced
+            .iter()
+            .copied()
+            .filter(|&pid| self.is_av_pid(pid))
+            .collect();
+        for pid in referenced {
+            let state = self.pids.entry(pid).or_default();
+            if elapsed(state.last_seen) > self.pid_timeout {
+                errors.push((
+                    TR101290Check::PID,
+                    Some(pid),
+                    format!("PID missing for {} s", self.pid_timeout.as_secs_f64()),
+                ));
+                state.last_seen = Some(now);
+            }
+            if av_pids.contains(&pid) && elapsed(state.last_pts) > PTS_INTERVAL {
+                errors.push((
+                    TR101290Check::PTS,
+                    Some(pid),
+                    "No PTS for 0.7 s".to_string(),
+                ));
+                state.last_pts = Some(now);
+            }
+        }
+
+        for (check, pid, message) in errors {
+            self.report_error(check, pid, offset, message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::av::{self, Packet};
+    use crate::format::ts::test_util::TestCodec;
+    use crate::format::ts::TSMuxer;
+    use crate::format::Muxer as FormatMuxer;
+    use std::io::Cursor;
+
+    /// Muxes 4 s of video at 25 fps and splits it into TS packets.
+    async fn muxed_packets() -> Vec<Vec<u8>> {
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()));
+        let streams = vec![TestCodec::new(av::CodecType::H264).boxed()];
+        muxer.write_header(&streams).await.unwrap();
+        for i in 0..100 {
+            let mut data = vec![0, 0, 0, 1, if i % 25 == 0 { 0x65 } else { 0x41 }];
+            data.resize(300, 0x88);
+            let packet = Packet::new(data)
+                .with_stream_index(0)
+                .with_dts(i * 40_000_000);
+            muxer.write_packet(&packet).await.unwrap();
+        }
+        muxer.flush().await.unwrap();
+        let output = muxer.into_inner().into_inner();
+        output
+            .chunks(TS_PACKET_SIZE)
+            .map(|packet| packet.to_vec())
+            .collect()
+    }
+
+    fn pid(packet: &[u8]) -> u16 {
+        (((packet[1] & 0x1F) as u16) << 8) | packet[2] as u16
+    }
+
+    fn analyze(packets: &[Vec<u8>]) -> (TSAnalysis, Vec<TR101290Error>) {
+        let data = packets.concat();
+        let mut analyzer = TSAnalyzer::new();
+        // Chunks across packet boundaries
+        for chunk in data.chunks(1000) {
+            analyzer.push(chunk);
+        }
+        (analyzer.report(), analyzer.take_errors())
+    }
+
+    #[tokio::test]
+    async fn test_ts_analyzer_clean_stream() {
+        let packets = muxed_packets().await;
+        let (report, errors) = analyze(&packets);
+
+        assert_eq!(report.packets, packets.len() as u64);
+        assert_eq!(report.error_count(1), 0, "{:?}", errors);
+        for check in [
+            TR101290Check::Transport,
+            TR101290Check::CRC,
+            TR101290Check::PCRRepetition,
+            TR101290Check::PCRDiscontinuityIndicator,
+            TR101290Check::PTS,
+        ] {
+            assert!(!report.errors.contains_key(&check), "{:?}", errors);
+        }
+
+        let duration = report.duration.unwrap();
+        assert!(duration > Duration::from_millis(3800));
+        let video = report.pids.iter().find(|pid| pid.pid == 0x100).unwrap();
+        assert_eq!(video.stream_type, Some(STREAM_TYPE_H264));
+        assert!(video.pcrs >= 90);
+        assert!(video.max_pcr_interval <= Some(PCR_INTERVAL));
+        let bitrate = video.bitrate.unwrap();
+        let expected = video.packets * 188 * 8 * 1000 / duration.as_millis() as u64;
+        assert!(bitrate.abs_diff(expected) <= 1);
+    }
+
+    #[tokio::test]
+    async fn test_ts_analyzer_broken_stream() {
+        let mut packets = muxed_packets().await;
+
+        // A lost video packet
+        let lost = packets
+            .iter()
+            .rposition(|packet| pid(packet) == 0x100)
+            .unwrap()
+            - 20;
+        packets.remove(lost);
+        // A corrupted PAT section
+        let pat = packets
+            .iter()
+            .position(|packet| pid(packet) == PID_PAT)
+            .unwrap();
+        packets[pat][10] ^= 0xFF;
+        // A packet marked as damaged by the demodulator
+        packets[50][1] |= 0x80;
+        // Two corrupted sync bytes in a row lose sync
+        packets[100][0] = 0x00;
+        packets[101][0] = 0x00;
+        // No PAT for the last second
+        let end = packets.len() * 3 / 4;
+        let mut index = 0;
+        packets.retain(|packet| {
+            index += 1;
+            index < end || pid(packet) != PID_PAT
+        });
+
+        let (report, errors) = analyze(&packets);
+        let count = |check| report.errors.get(&check).copied().unwrap_or(0);
+        assert_eq!(count(TR101290Check::SyncByte), 2);
+        assert_eq!(count(TR101290Check::TSSyncLoss), 1);
+        assert_eq!(count(TR101290Check::Transport), 1);
+        assert_eq!(count(TR101290Check::CRC), 1);
+        assert!(count(TR101290Check::PAT) >= 1);
+        assert!(count(TR101290Check::ContinuityCount) >= 2);
+        assert!(!report.errors.contains_key(&TR101290Check::PMT));
+
+        let crc = errors
+            .iter()
+            .find(|error| error.check == TR101290Check::CRC)
+            .unwrap();
+        assert_eq!(crc.pid, Some(PID_PAT));
+        assert_eq!(crc.offset, (pat * TS_PACKET_SIZE) as u64);
+        let lost_error = errors
+            .iter()
+            .find(|error| error.check == TR101290Check::ContinuityCount && error.time.is_some())
+            .unwrap();
+        assert_eq!(lost_error.pid, Some(0x100));
+        assert_eq!(lost_error.check.priority(), 1);
+    }
+
+    /// Builds a packet on PID 0x100 with an optional PCR.
+    fn pcr_packet(counter: u8, pcr: Option<u64>) -> Vec<u8> {
+        let mut packet = vec![TS_SYNC_BYTE, 0x01, 0x00, 0x10 | (counter & 0x0F)];
+        if let Some(pcr) = pcr {
+            let (base, extension) = (pcr / 300, pcr % 300);
+            packet[3] |= 0x20;
+            packet.extend_from_slice(&[
+                7,
+                0x10,
+                (base >> 25) as u8,
+                (base >> 17) as u8,
+                (base >> 9) as u8,
+                (base >> 1) as u8,
+                ((base & 1) << 7) as u8 | 0x7E | (extension >> 8) as u8,
+                extension as u8,
+            ]);
+        }
+        packet.resize(TS_PACKET_SIZE, 0xFF);
+        packet
+    }
+
+    #[test]
+    fn test_ts_analyzer_pcr_checks() {
+        // Constant rate, 10 packets and a PCR every 10 ms
+        let mut packets = Vec::new();
+        let mut counter = 0u8;
+        for interval in 0..100u64 {
+            for i in 0..10 {
+                let mut pcr = (i == 0).then_some(interval * 270_000);
+                if interval == 50 {
+                    // 100 ticks of jitter, 3.7 us
+                    pcr = pcr.map(|pcr| pcr + 100);
+                }
+                if (80..91).contains(&interval) {
+                    pcr = None;
+                }
+                packets.push(pcr_packet(counter, pcr));
+                counter = counter.wrapping_add(1);
+            }
+        }
+
+        let (report, errors) = analyze(&packets);
+        let accuracy: Vec<_> = errors
+            .iter()
+            .filter(|error| error.check == TR101290Check::PCRAccuracy)
+            .collect();
+        assert!(!accuracy.is_empty());
+        assert!(accuracy
+            .iter()
+            .all(|error| (50..53).contains(&(error.offset / 1880))));
+
+        let pcr_pid = report.pids.iter().find(|pid| pid.pid == 0x100).unwrap();
+        let jitter = pcr_pid.pcr_jitter.unwrap();
+        assert!(jitter >= Duration::from_nanos(3_700), "{:?}", jitter);
+        assert_eq!(pcr_pid.max_pcr_interval, Some(Duration::from_millis(120)));
+
+        let gap: Vec<_> = errors
+            .iter()
+            .filter(|error| error.offset == 91 * 1880)
+            .map(|error| error.check)
+            .collect();
+        assert_eq!(
+            gap,
+            vec![
+                TR101290Check::PCRRepetition,
+                TR101290Check::PCRDiscontinuityIndicator
+            ]
+        );
+        assert_eq!(report.duration, Some(Duration::from_millis(990)));
+        assert_eq!(report.bitrate, Some(1_519_192));
+    }
+}
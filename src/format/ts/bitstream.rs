@@ -0,0 +1,497 @@
+use super::types::{TELETEXT_PES_HEADER_DATA_LENGTH, TS_PACKET_SIZE};
+use crate::av::CodecType;
+use crate::error::{Result, VdkError};
+
+/// Annex B start code written before every NAL unit
+const START_CODE: [u8; 4] = [0x00, 0x00, 0x00, 0x01];
+
+/// H.264 access unit delimiter, primary_pic_type 7 (any slice type)
+const H264_AUD: [u8; 2] = [0x09, 0xF0];
+
+/// H.265 access unit delimiter, pic_type 2 (any slice type)
+const H265_AUD: [u8; 3] = [0x46, 0x01, 0x50];
+
+/// NAL unit length size used by MP4-like sources without a configuration record
+const DEFAULT_NAL_LENGTH_SIZE: usize = 4;
+
+/// Prepares H.264/H.265 access units for carriage in MPEG-TS.
+///
+/// Transport streams carry video in Annex B byte stream format, and HLS
+/// players expect every access unit to start with an Access Unit Delimiter
+/// and every keyframe to carry its parameter sets. The preparer:
+/// - converts AVCC/HVCC length-prefixed NAL units to Annex B
+/// - inserts an AUD when the access unit does not start with one
+/// - prepends SPS/PPS (plus VPS for H.265) on keyframes that lack them
+///
+/// Parameter sets are taken from the codec's extra data, which may be either
+/// Annex B or an AVC/HEVC decoder configuration record.
+#[derive(Debug, Clone)]
+pub struct AnnexBPreparer {
+    codec_type: CodecType,
+    nal_length_size: Option<usize>,
+    parameter_sets: Vec<Vec<u8>>,
+}
+
+impl AnnexBPreparer {
+    /// Creates a preparer for an H.264 or H.265 stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `codec_type` - Video codec of the stream
+    /// * `extra_data` - Codec extra data (Annex B parameter sets or a decoder
+    ///   configuration record), if any
+    ///
+    /// # Returns
+    ///
+    /// The preparer, or an error for non-video codecs or malformed extra data
+    pub fn new(codec_type: CodecType, extra_data: Option<&[u8]>) -> Result<Self> {
+        if !matches!(codec_type, CodecType::H264 | CodecType::H265) {
+            return Err(VdkError::Codec(format!(
+                "Annex B preparation not supported for {:?}",
+                codec_type
+            )));
+        }
+
+        let mut preparer = Self {
+            codec_type,
+            nal_length_size: None,
+            parameter_sets: Vec::new(),
+        };
+
+        match extra_data {
+            Some(data) if has_start_code(data) => {
+                preparer.parameter_sets = split_annexb(data)
+                    .into_iter()
+                    .filter(|nal| preparer.is_parameter_set(nal))
+                    .map(|nal| nal.to_vec())
+                    .collect();
+            }
+            Some(data) if !data.is_empty() => {
+                let (nal_length_size, parameter_sets) = match codec_type {
+                    CodecType::H264 => parse_avcc_record(data)?,
+                    _ => parse_hvcc_record(data)?,
+                };
+                preparer.nal_length_size = Some(nal_length_size);
+                preparer.parameter_sets = parameter_sets;
+            }
+            _ => {}
+        }
+
+        Ok(preparer)
+    }
+
+    /// Converts an access unit to Annex B, ready for a PES payload.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Access unit in Annex B or length-prefixed format
+    /// * `is_key` - Whether the access unit is a keyframe
+    ///
+    /// # Returns
+    ///
+    /// The access unit in Annex B format with AUD and, for keyframes,
+    /// parameter sets
+    pub fn prepare(&self, data: &[u8], is_key: bool) -> Result<Vec<u8>> {
+        let mut nal_units = self.split(data)?;
+        let is_key = is_key || nal_units.iter().any(|nal| self.is_irap(nal));
+
+        if !nal_units.first().is_some_and(|nal| self.is_aud(nal)) {
+            nal_units.insert(0, self.aud());
+        }
+        // Parameter sets go right after the AUD
+        if is_key && !nal_units.iter().any(|nal| self.is_sps(nal)) {
+            let parameter_sets = self.parameter_sets.iter().map(Vec::as_slice);
+            nal_units.splice(1..1, parameter_sets);
+        }
+
+        let size = nal_units
+            .iter()
+            .map(|nal| START_CODE.len() + nal.len())
+            .sum();
+        let mut output = Vec::with_capacity(size);
+        for nal in nal_units {
+            output.extend_from_slice(&START_CODE);
+            output.extend_from_slice(nal);
+        }
+
+        Ok(output)
+    }
+
+    /// Splits an access unit into NAL units, detecting its format.
+    fn split<'a>(&self, data: &'a [u8]) -> Result<Vec<&'a [u8]>> {
+        // A configuration record means the source is length-prefixed
+        if let Some(size) = self.nal_length_size {
+            if let Some(nal_units) = split_length_prefixed(data, size) {
+                return Ok(nal_units);
+            }
+        }
+        if has_start_code(data) {
+            return Ok(split_annexb(data));
+        }
+        split_length_prefixed(data, DEFAULT_NAL_LENGTH_SIZE).ok_or_else(|| {
+            VdkError::Codec(format!(
+                "{:?} access unit is neither Annex B nor length-prefixed",
+                self.codec_type
+            ))
+        })
+    }
+
+    fn nal_type(&self, nal: &[u8]) -> u8 {
+        match self.codec_type {
+            CodecType::H264 => nal[0] & 0x1F,
+            _ => (nal[0] >> 1) & 0x3F,
+        }
+    }
+
+    fn aud(&self) -> &'static [u8] {
+        match self.codec_type {
+            CodecType::H264 => &H264_AUD,
+            _ => &H265_AUD,
+        }
+    }
+
+    fn is_aud(&self, nal: &[u8]) -> bool {
+        match self.codec_type {
+            CodecType::H264 => self.nal_type(nal) == 9,
+            _ => self.nal_type(nal) == 35,
+        }
+    }
+
+    fn is_sps(&self, nal: &[u8]) -> bool {
+        match self.codec_type {
+            CodecType::H264 => self.nal_type(nal) == 7,
+            _ => self.nal_type(nal) == 33,
+        }
+    }
+
+    fn is_parameter_set(&self, nal: &[u8]) -> bool {
+        match self.codec_type {
+            CodecType::H264 => matches!(self.nal_type(nal), 7 | 8),
+            _ => matches!(self.nal_type(nal), 32..=34),
+        }
+    }
+
+    /// Returns true for IDR slices (H.264) or IRAP pictures (H.265).
+    fn is_irap(&self, nal: &[u8]) -> bool {
+        match self.codec_type {
+            CodecType::H264 => self.nal_type(nal) == 5,
+            _ => matches!(self.nal_type(nal), 16..=21),
+        }
+    }
+}
+
+/// Prefix of the control header before every Opus access unit in MPEG-TS
+const OPUS_CONTROL_PREFIX: u16 = 0x7FE0;
+
+/// Wraps an Opus packet in the control header of its TS access unit.
+///
+/// The header has no trim flags and no extension, only the size of the
+/// packet.
+pub(crate) fn opus_access_unit(packet: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(packet.len() + packet.len() / 255 + 3);
+    data.extend_from_slice(&OPUS_CONTROL_PREFIX.to_be_bytes());
+    data.resize(data.len() + packet.len() / 255, 0xFF);
+    data.push((packet.len() % 255) as u8);
+    data.extend_from_slice(packet);
+    data
+}
+
+/// Splits TS Opus access units into Opus packets, without control headers.
+///
+/// Stops at the first malformed access unit.
+pub(crate) fn split_opus_access_units(data: &[u8]) -> Vec<&[u8]> {
+    let mut packets = Vec::new();
+    let mut pos = 0;
+    while let Some(header) = data.get(pos..pos + 2) {
+        if u16::from_be_bytes([header[0], header[1]]) & 0xFFE0 != OPUS_CONTROL_PREFIX {
+            break;
+        }
+        let (start_trim, end_trim, extension) = (
+            header[1] & 0x10 != 0,
+            header[1] & 0x08 != 0,
+            header[1] & 0x04 != 0,
+        );
+        pos += 2;
+
+        let mut size = 0;
+        loop {
+            let Some(&byte) = data.get(pos) else {
+                return packets;
+            };
+            pos += 1;
+            size += byte as usize;
+            if byte != 0xFF {
+                break;
+            }
+        }
+        pos += 2 * (start_trim as usize + end_trim as usize);
+        if extension {
+            pos += 1 + data.get(pos).map_or(0, |&length| length as usize);
+        }
+
+        match data.get(pos..pos + size) {
+            Some(packet) => packets.push(packet),
+            None => break,
+        }
+        pos += size;
+    }
+    packets
+}
+
+/// Size of a teletext data unit, including data_unit_id and data_unit_length
+const TELETEXT_DATA_UNIT_SIZE: usize = 46;
+
+/// Size of a teletext PES header (ETSI EN 300 472)
+const TELETEXT_PES_HEADER_SIZE: usize = 9 + TELETEXT_PES_HEADER_DATA_LENGTH as usize;
+
+/// Pads a teletext PES payload with stuffing data units, so its PES packet
+/// fills whole TS packets as EN 300 472 requires.
+///
+/// Payloads that are not a data_identifier followed by whole data units are
+/// returned unchanged.
+pub(crate) fn teletext_pes_payload(data: &[u8]) -> Vec<u8> {
+    let mut payload = data.to_vec();
+    if payload.len() % TELETEXT_DATA_UNIT_SIZE != 1 {
+        return payload;
+    }
+    // Bytes short of whole TS packets, a multiple of the data unit size
+    let packet_payload = TS_PACKET_SIZE - 4;
+    let remainder = (TELETEXT_PES_HEADER_SIZE + payload.len()) % packet_payload;
+    let missing = (packet_payload - remainder) % packet_payload;
+    for _ in 0..missing / TELETEXT_DATA_UNIT_SIZE {
+        // Stuffing data_unit_id and the length of the unit's data
+        payload.push(0xFF);
+        payload.push(TELETEXT_DATA_UNIT_SIZE as u8 - 2);
+        payload.resize(payload.len() + TELETEXT_DATA_UNIT_SIZE - 2, 0xFF);
+    }
+    payload
+}
+
+/// Returns true if the data starts with a 3- or 4-byte start code.
+fn has_start_code(data: &[u8]) -> bool {
+    data.starts_with(&[0, 0, 1]) || data.starts_with(&[0, 0, 0, 1])
+}
+
+/// Splits Annex B data into NAL units, without start codes.
+pub(crate) fn split_annexb(data: &[u8]) -> Vec<&[u8]> {
+    let mut nal_units = Vec::new();
+    let mut start = None;
+    let mut i = 0;
+
+    while i + 3 <= data.len() {
+        if data[i..i + 3] == [0, 0, 1] {
+            if let Some(start) = start {
+                push_nal(&mut nal_units, &data[start..i]);
+            }
+            i += 3;
+            start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    if let Some(start) = start {
+        push_nal(&mut nal_units, &data[start..]);
+    }
+
+    nal_units
+}
+
+/// Adds a NAL unit, dropping the zero bytes of a following 4-byte start code.
+fn push_nal<'a>(nal_units: &mut Vec<&'a [u8]>, nal: &'a [u8]) {
+    let end = nal.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+    if end > 0 {
+        nal_units.push(&nal[..end]);
+    }
+}
+
+/// Splits length-prefixed data into NAL units.
+///
+/// Returns `None` if the lengths do not exactly cover the data.
+fn split_length_prefixed(data: &[u8], size: usize) -> Option<Vec<&[u8]>> {
+    let mut nal_units = Vec::new();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let header = data.get(pos..pos + size)?;
+        let length = header
+            .iter()
+            .fold(0usize, |length, &b| (length << 8) | b as usize);
+        pos += size;
+        if length == 0 {
+            return None;
+        }
+        nal_units.push(data.get(pos..pos + length)?);
+        pos += length;
+    }
+
+    (!nal_units.is_empty()).then_some(nal_units)
+}
+
+/// Reads `count` 16-bit length-prefixed NAL units from a configuration record.
+fn read_nal_array(data: &[u8], pos: &mut usize, count: usize) -> Result<Vec<Vec<u8>>> {
+    let mut nal_units = Vec::with_capacity(count);
+    for _ in 0..count {
+        let length = data
+            .get(*pos..*pos + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+            .ok_or_else(|| VdkError::Codec("Decoder configuration record too short".into()))?;
+        *pos += 2;
+        let nal = data
+            .get(*pos..*pos + length)
+            .ok_or_else(|| VdkError::Codec("Decoder configuration record too short".into()))?;
+        nal_units.push(nal.to_vec());
+        *pos += length;
+    }
+    Ok(nal_units)
+}
+
+/// Parses an AVCDecoderConfigurationRecord (ISO/IEC 14496-15, 5.3.3.1).
+fn parse_avcc_record(data: &[u8]) -> Result<(usize, Vec<Vec<u8>>)> {
+    if data.len() < 7 || data[0] != 1 {
+        return Err(VdkError::Codec(
+            "Invalid AVC decoder configuration record".into(),
+        ));
+    }
+    let nal_length_size = (data[4] & 0x03) as usize + 1;
+
+    let mut pos = 6;
+    let mut parameter_sets = read_nal_array(data, &mut pos, (data[5] & 0x1F) as usize)?;
+    let pps_count = *data
+        .get(pos)
+        .ok_or_else(|| VdkError::Codec("Decoder configuration record too short".into()))?;
+    pos += 1;
+    parameter_sets.extend(read_nal_array(data, &mut pos, pps_count as usize)?);
+
+    Ok((nal_length_size, parameter_sets))
+}
+
+/// Parses an HEVCDecoderConfigurationRecord (ISO/IEC 14496-15, 8.3.3.1).
+fn parse_hvcc_record(data: &[u8]) -> Result<(usize, Vec<Vec<u8>>)> {
+    if data.len() < 23 || data[0] != 1 {
+        return Err(VdkError::Codec(
+            "Invalid HEVC decoder configuration record".into(),
+        ));
+    }
+    let nal_length_size = (data[21] & 0x03) as usize + 1;
+
+    let mut pos = 23;
+    let mut parameter_sets = Vec::new();
+    for _ in 0..data[22] {
+        let header = data
+            .get(pos..pos + 3)
+            .ok_or_else(|| VdkError::Codec("Decoder configuration record too short".into()))?;
+        let nal_type = header[0] & 0x3F;
+        let count = u16::from_be_bytes([header[1], header[2]]) as usize;
+        pos += 3;
+
+        let nal_units = read_nal_array(data, &mut pos, count)?;
+        // Only VPS, SPS and PPS; SEI arrays are not repeated
+        if matches!(nal_type, 32..=34) {
+            parameter_sets.extend(nal_units);
+        }
+    }
+
+    Ok((nal_length_size, parameter_sets))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_avcc_to_annexb_with_aud_and_parameter_sets() {
+        let record = [
+            0x01, 0x42, 0x00, 0x1E, 0xFF, // Version, profile, level, 4-byte lengths
+            0xE1, 0x00, 0x03, 0x67, 0x42, 0x1E, // One SPS
+            0x01, 0x00, 0x02, 0x68, 0xCE, // One PPS
+        ];
+        let preparer = AnnexBPreparer::new(CodecType::H264, Some(&record)).unwrap();
+
+        let idr = [0x00, 0x00, 0x00, 0x03, 0x65, 0x88, 0x84];
+        assert_eq!(
+            preparer.prepare(&idr, true).unwrap(),
+            [
+                0, 0, 0, 1, 0x09, 0xF0, //
+                0, 0, 0, 1, 0x67, 0x42, 0x1E, //
+                0, 0, 0, 1, 0x68, 0xCE, //
+                0, 0, 0, 1, 0x65, 0x88, 0x84,
+            ]
+        );
+
+        // Non-IDR frames only get the AUD
+        let slice = [0x00, 0x00, 0x00, 0x02, 0x41, 0x9A];
+        assert_eq!(
+            preparer.prepare(&slice, false).unwrap(),
+            [0, 0, 0, 1, 0x09, 0xF0, 0, 0, 0, 1, 0x41, 0x9A]
+        );
+    }
+
+    #[test]
+    fn test_annexb_input_is_kept() {
+        let extra_data = [0, 0, 0, 1, 0x67, 0x42, 0, 0, 1, 0x68, 0xCE];
+        let preparer = AnnexBPreparer::new(CodecType::H264, Some(&extra_data)).unwrap();
+
+        // Existing AUD and parameter sets are not duplicated
+        let access_unit = [
+            0, 0, 0, 1, 0x09, 0x10, //
+            0, 0, 1, 0x67, 0x42, //
+            0, 0, 1, 0x68, 0xCE, //
+            0, 0, 1, 0x65, 0x88, 0x00,
+        ];
+        assert_eq!(
+            preparer.prepare(&access_unit, true).unwrap(),
+            [
+                0, 0, 0, 1, 0x09, 0x10, //
+                0, 0, 0, 1, 0x67, 0x42, //
+                0, 0, 0, 1, 0x68, 0xCE, //
+                0, 0, 0, 1, 0x65, 0x88,
+            ]
+        );
+
+        assert!(preparer.prepare(&[0x11; 16], false).is_err());
+    }
+
+    #[test]
+    fn test_hvcc_parameter_sets() {
+        let mut record = vec![0x01];
+        record.extend_from_slice(&[0; 20]);
+        record.push(0x03); // 4-byte lengths
+        record.push(3); // VPS, SPS and PPS arrays
+        for (nal_type, nal) in [(32, [0x40, 0x01]), (33, [0x42, 0x01]), (34, [0x44, 0x01])] {
+            record.extend_from_slice(&[0x80 | nal_type, 0x00, 0x01, 0x00, 0x02]);
+            record.extend_from_slice(&nal);
+        }
+        let preparer = AnnexBPreparer::new(CodecType::H265, Some(&record)).unwrap();
+
+        // IDR_W_RADL without the key flag is still detected as a keyframe
+        let idr = [0x00, 0x00, 0x00, 0x03, 0x26, 0x01, 0xAF];
+        assert_eq!(
+            preparer.prepare(&idr, false).unwrap(),
+            [
+                0, 0, 0, 1, 0x46, 0x01, 0x50, //
+                0, 0, 0, 1, 0x40, 0x01, //
+                0, 0, 0, 1, 0x42, 0x01, //
+                0, 0, 0, 1, 0x44, 0x01, //
+                0, 0, 0, 1, 0x26, 0x01, 0xAF,
+            ]
+        );
+
+        assert!(AnnexBPreparer::new(CodecType::AAC, None).is_err());
+    }
+
+    #[test]
+    fn test_opus_access_units() {
+        let short = vec![0xF8; 10];
+        let long = vec![0xFC; 600];
+        let mut data = opus_access_unit(&short);
+        assert_eq!(&data[..3], &[0x7F, 0xE0, 10]);
+        data.extend(opus_access_unit(&long));
+        assert_eq!(&data[13..18], &[0x7F, 0xE0, 0xFF, 0xFF, 90]);
+        // Trims and an extension in front of a third packet
+        data.extend_from_slice(&[0x7F, 0xFC, 2, 0, 10, 0, 20, 1, 0xAA, 0xF8, 0xF8]);
+
+        let packets = split_opus_access_units(&data);
+        assert_eq!(packets, vec![&short[..], &long[..], &[0xF8, 0xF8][..]]);
+        assert!(split_opus_access_units(&[0xF8, 0xFF]).is_empty());
+    }
+}
@@ -1,37 +1,496 @@
+use super::bitstream::{split_annexb, split_opus_access_units};
+use super::index::TSIndex;
 use super::parser::TSPacketParser;
+use super::pes::PESHeader;
+use super::scte35::{SpliceInfoSection, TABLE_ID_SCTE35};
+use super::si::{SITable, PID_EIT, PID_NIT, PID_SDT, PID_TDT, TABLE_ID_TOT};
 use super::types::*;
-use crate::av::{self, CodecData, CodecType, Packet};
-use crate::error::Result;
+use crate::av::{self, CodecData, CodecType, Packet, Timebase, TimestampUnwrapper};
+use crate::codec::aac::AACParser;
+use crate::codec::ac3::AC3FrameHeader;
+use crate::codec::captions::extract_cc_data;
+use crate::codec::h264::SPSInfo;
+use crate::codec::h265::H265Parser;
+use crate::codec::mpeg2::{self, SequenceHeader};
+use crate::codec::mpeg_audio::MPEGAudioHeader;
+use crate::codec::opus::{self, OPUS_SAMPLE_RATE};
+use crate::error::{Result, VdkError};
 use crate::format::Demuxer as FormatDemuxer;
 use crate::utils::crc::Crc32Mpeg2;
 use bytes::Bytes;
-use std::collections::HashMap;
-use tokio::io::{AsyncRead, AsyncReadExt};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io::SeekFrom;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+
+/// Number of consecutive sync bytes required to lock onto the packet grid
+const SYNC_LOCK_PACKETS: usize = 3;
+
+/// Packet sizes detected on the input, in order of preference
+const PACKET_SIZES: [usize; 3] = [TS_PACKET_SIZE, M2TS_PACKET_SIZE, RS_PACKET_SIZE];
+
+/// Bytes read ahead to lock onto the packet grid
+const SYNC_LOOKAHEAD: usize = RS_PACKET_SIZE * SYNC_LOCK_PACKETS;
+
+/// Maximum number of TS packets read while probing codec parameters
+const MAX_PROBE_PACKETS: usize = 20_000;
 
 /// MPEG Transport Stream demuxer.
 ///
 /// This demuxer extracts elementary streams from MPEG-TS container format.
 /// It supports:
-/// - PAT/PMT parsing for stream information
+/// - PAT/PMT parsing for stream information, with multiple programs (MPTS)
 /// - PES packet extraction and reassembly
-/// - PCR timing recovery
+/// - PTS/DTS in nanoseconds, extended across the 33-bit wraparound
+/// - Keyframe detection and packet durations
+/// - Resynchronisation after corrupt or missing bytes
+/// - 188-byte TS, 192-byte M2TS and 204-byte packets, detected from the input
+/// - Continuity counter checks and CRC-validated PSI sections
+/// - H.264, H.265, MPEG-2 video, AAC, MPEG audio, AC-3, E-AC-3 and Opus
+/// - DVB subtitles and teletext, passed through with their languages
+/// - CEA-608/708 closed captions from video SEI, see
+///   [`TSDemuxer::with_closed_captions`]
+/// - Codec probing of resolution, profile/level, sample rate and channels
+/// - DVB SI tables (NIT, SDT, EIT present/following, TDT/TOT) as events
+/// - SCTE-35 splice information as timed events
+///
+/// [`streams`](FormatDemuxer::streams) reads ahead until every stream has
+/// its parameter sets (H.264/H.265) or an ADTS header (AAC). The packets
+/// read meanwhile are buffered and returned by
+/// [`read_packet`](FormatDemuxer::read_packet) afterwards.
+///
+/// Streams of other types are returned as [`CodecType::PrivateData`], with
+/// their PES payloads passed through unchanged. Their extra data holds the
+/// stream type followed by the ES descriptors of the PMT, so
+/// [`TSMuxer`](super::TSMuxer) can write them back.
+///
+/// Stream indices are assigned in the order streams appear in the PMTs, so
+/// the streams of a program have consecutive indices. [`TSDemuxer::programs`]
+/// maps programs to their stream indices, and
+/// [`TSDemuxer::with_program_filter`] limits demuxing to some programs.
+///
+/// Packets are returned one PES packet late per stream, since a packet's
+/// duration is only known once the next DTS arrives. A PES packet hit by
+/// packet loss is dropped, and the next packet of that stream has
+/// [`Packet::is_discontinuity`] set.
+///
+/// Inputs that implement [`AsyncSeek`] can seek to keyframes with
+/// [`TSDemuxer::seek`], using a [`TSIndex`].
+///
+/// Information that is not part of a stream, such as DVB SI tables, is
+/// queued as [`TSEvent`]s in stream order and collected with
+/// [`TSDemuxer::take_events`].
+///
+/// Every packet carries the input offset of the TS packet its PES starts
+/// in, see [`Packet::position`]. With M2TS input, it also carries that TS
+/// packet's arrival timestamp, see [`Packet::arrival_timestamp`]. The parity
+/// bytes of 204-byte packets are skipped without error correction.
 pub struct TSDemuxer<R: AsyncRead + Unpin + Send> {
     reader: R,
     parser: TSPacketParser,
     streams: HashMap<u16, StreamInfo>,
-    pmt_pid: Option<u16>,
+    pat_received: bool,
+    programs: BTreeMap<u16, ProgramInfo>,
+    program_filter: Option<Vec<u16>>,
+    ready: VecDeque<Packet>,
+    buffer: Vec<u8>,
+    synced: bool,
+    /// Size of the packets on the input, once detected
+    packet_size: Option<usize>,
+    /// Arrival timestamp of the TS packet being processed
+    arrival_timestamp: Option<u32>,
+    /// Input offset of the first buffered byte
+    input_position: u64,
+    /// Input offset of the TS packet being processed
+    packet_position: u64,
+    continuity_counters: HashMap<u16, u8>,
+    sections: HashMap<u16, SectionBuffer>,
+    si_sections: HashMap<(u16, u8, u16, u8), Vec<u8>>,
+    scte35_pids: HashMap<u16, u16>,
+    /// Whether closed captions are extracted from H.264/H.265 streams
+    closed_captions: bool,
+    /// Random access points of a seekable input
+    index: Option<TSIndex>,
+    events: VecDeque<TSEvent>,
+    crc: Crc32Mpeg2,
+    statistics: TSStatistics,
+}
+
+/// Out-of-band information found while demuxing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TSEvent {
+    /// A DVB SI table that is new or differs from its last transmission
+    ServiceInformation(SITable),
+    /// An SCTE-35 splice_info_section
+    Splice {
+        /// Program whose PMT lists the SCTE-35 PID
+        program_number: u16,
+        /// Splice time in nanoseconds on the timeline of the program's
+        /// packets, `None` for immediate splices and commands without time
+        pts: Option<i64>,
+        /// The splice information
+        section: SpliceInfoSection,
+    },
+}
+
+/// A program of the Transport Stream, as described by its PMT.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TSProgram {
+    /// Program number from the PAT
+    pub program_number: u16,
+    /// PID carrying the program's PMT
+    pub pmt_pid: u16,
+    /// PID carrying the program's PCR
+    pub pcr_pid: u16,
+    /// Indices of the program's streams, as returned by `streams`
+    pub stream_indices: Vec<usize>,
+}
+
+/// A program listed in the PAT.
+#[derive(Debug)]
+struct ProgramInfo {
+    /// PID carrying the program's PMT
+    pmt_pid: u16,
+    /// Latest PMT of the program
     pmt: Option<PMT>,
 }
 
+/// Error counters of a [`TSDemuxer`], for judging stream health.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TSStatistics {
+    /// Total number of TS packets read
+    pub packets: u64,
+    /// Number of times the packet grid was lost
+    pub sync_losses: u32,
+    /// Bytes skipped while hunting for sync
+    pub skipped_bytes: u64,
+    /// Packets with the transport error indicator set
+    pub transport_errors: u32,
+    /// Continuity counter discontinuities, i.e. lost packets
+    pub continuity_errors: u32,
+    /// PSI sections dropped for a CRC mismatch
+    pub crc_errors: u32,
+    /// PSI sections with a valid CRC dropped because they are malformed
+    pub section_errors: u32,
+}
+
+/// Result of a continuity counter check.
+enum Continuity {
+    Ok,
+    /// Retransmitted packet, to be ignored
+    Duplicate,
+    /// Packets were lost before this one
+    Lost,
+}
+
+/// Reassembles PSI sections spanning several TS packets.
+#[derive(Debug, Default)]
+pub(super) struct SectionBuffer {
+    /// Section data collected so far, if a section has started
+    data: Option<Vec<u8>>,
+}
+
+impl SectionBuffer {
+    /// Adds a TS packet payload and returns the completed sections.
+    pub(super) fn push(&mut self, payload: &[u8], unit_start: bool) -> Vec<Vec<u8>> {
+        let mut sections = Vec::new();
+        if unit_start {
+            let Some((&pointer, rest)) = payload.split_first() else {
+                return sections;
+            };
+            let pointer = (pointer as usize).min(rest.len());
+
+            // Bytes before the pointer finish the previous section
+            if let Some(data) = &mut self.data {
+                data.extend_from_slice(&rest[..pointer]);
+                self.take_sections(&mut sections);
+            }
+            self.data = Some(rest[pointer..].to_vec());
+        } else if let Some(data) = &mut self.data {
+            data.extend_from_slice(payload);
+        }
+
+        self.take_sections(&mut sections);
+        sections
+    }
+
+    /// Moves complete sections out of the buffer.
+    fn take_sections(&mut self, sections: &mut Vec<Vec<u8>>) {
+        while let Some(data) = &mut self.data {
+            // Stuffing after the last section of the packet
+            if matches!(data.first(), None | Some(0xFF)) {
+                self.data = None;
+                break;
+            }
+            if data.len() < 3 {
+                break;
+            }
+            let len = 3 + ((((data[1] & 0x0F) as usize) << 8) | data[2] as usize);
+            if data.len() < len {
+                break;
+            }
+            let rest = data.split_off(len);
+            sections.push(std::mem::replace(data, rest));
+        }
+    }
+}
+
 /// Information about individual elementary streams.
 #[derive(Debug)]
 struct StreamInfo {
     /// Index in the stream list
     stream_index: usize,
+    /// Program the stream belongs to
+    program_number: u16,
     /// Stream configuration data (codec info, etc.)
     config: Option<StreamCodecData>,
     /// Incomplete PES packet being assembled
     pes_buffer: Option<PESBuilder>,
+    /// Wraparound tracking for PTS/DTS
+    timestamps: TimestampUnwrapper,
+    /// Last completed packet, waiting for the next DTS to get its duration
+    pending: Option<Packet>,
+    /// Duration of the last packet, used for the final one
+    last_duration: Option<Duration>,
+    /// Whether data was lost since the last PES packet started
+    discontinuity: bool,
+    /// Parameter set NAL units found while probing, by NAL unit type
+    parameter_sets: BTreeMap<u8, Vec<u8>>,
+    /// Whether the codec parameters are known
+    probed: bool,
+    /// Index of the stream of closed captions extracted from this video
+    caption_index: Option<usize>,
+}
+
+impl StreamInfo {
+    fn new(stream_index: usize, program_number: u16, config: StreamCodecData) -> Self {
+        Self {
+            stream_index,
+            program_number,
+            config: Some(config),
+            pes_buffer: None,
+            timestamps: TimestampUnwrapper::new(PTS_BITS),
+            pending: None,
+            last_duration: None,
+            discontinuity: false,
+            parameter_sets: BTreeMap::new(),
+            probed: false,
+            caption_index: None,
+        }
+    }
+
+    /// Fills in the codec parameters from an access unit.
+    ///
+    /// Video streams are probed once SPS and PPS (and VPS for H.265) have
+    /// been seen, and their extra data holds these parameter sets in Annex B
+    /// format. AAC streams are probed by their first ADTS header, and their
+    /// extra data holds the AudioSpecificConfig.
+    fn probe(&mut self, data: &[u8]) {
+        let Some(config) = &mut self.config else {
+            return;
+        };
+        match config.codec_type {
+            CodecType::H264 | CodecType::H265 => {
+                let h264 = config.codec_type == CodecType::H264;
+                // SPS and PPS, plus VPS for H.265
+                let (required, sps_type): (&[u8], u8) = if h264 {
+                    (&[7, 8], 7)
+                } else {
+                    (&[32, 33, 34], 33)
+                };
+                for nal in split_annexb(data) {
+                    let nal_type = if h264 {
+                        nal[0] & 0x1F
+                    } else {
+                        (nal[0] >> 1) & 0x3F
+                    };
+                    if required.contains(&nal_type) {
+                        self.parameter_sets.insert(nal_type, nal.to_vec());
+                    }
+                }
+                if !required
+                    .iter()
+                    .all(|nal_type| self.parameter_sets.contains_key(nal_type))
+                {
+                    return;
+                }
+
+                let sps = &self.parameter_sets[&sps_type];
+                if h264 {
+                    let Ok(info) = SPSInfo::parse(sps) else {
+                        return;
+                    };
+                    config.width = Some(info.width);
+                    config.height = Some(info.height);
+                    config.profile = Some(info.profile_idc);
+                    config.level = Some(info.level_idc);
+                } else {
+                    let mut parser = H265Parser::new();
+                    let Some(info) = parser.parse_nalu(sps).ok().and(parser.sps()) else {
+                        return;
+                    };
+                    config.width = Some(info.width());
+                    config.height = Some(info.height());
+                    config.profile = Some(info.profile_tier_level.profile_idc);
+                    config.level = Some(info.profile_tier_level.level_idc);
+                }
+
+                let mut extra_data = Vec::new();
+                for nal in self.parameter_sets.values() {
+                    extra_data.extend_from_slice(&[0, 0, 0, 1]);
+                    extra_data.extend_from_slice(nal);
+                }
+                config.extra_data = Some(extra_data);
+            }
+            CodecType::MPEG2Video => {
+                let Some(sequence) = SequenceHeader::find(data) else {
+                    return;
+                };
+                let Ok(header) = SequenceHeader::parse(sequence) else {
+                    return;
+                };
+                config.width = Some(header.width);
+                config.height = Some(header.height);
+                config.extra_data = Some(sequence.to_vec());
+            }
+            CodecType::MPEGAudio => {
+                let Ok(header) = MPEGAudioHeader::parse(data) else {
+                    return;
+                };
+                config.sample_rate = Some(header.sample_rate);
+                config.channels = Some(header.channels);
+                config.profile = Some(header.layer);
+            }
+            CodecType::AC3 | CodecType::EAC3 => {
+                let Ok(header) = AC3FrameHeader::parse(data) else {
+                    return;
+                };
+                config.sample_rate = Some(header.sample_rate);
+                config.channels = Some(header.channels());
+            }
+            CodecType::AAC => {
+                let mut parser = AACParser::new();
+                if parser.parse_frame(data).is_err() {
+                    return;
+                }
+                let Some(aac) = parser.config() else {
+                    return;
+                };
+                config.sample_rate = aac.sample_rate();
+                config.channels = Some(aac.channel_configuration);
+                config.profile = Some(aac.profile as u8 + 1);
+                config.extra_data = Some(aac.audio_specific_config().to_vec());
+            }
+            _ => {}
+        }
+        self.probed = true;
+    }
+
+    /// Turns a finished PES packet into media packets.
+    ///
+    /// Returns the previously finished packet, now that its duration is known.
+    /// Timed metadata, subtitles and private data are sparse and returned
+    /// right away, without a duration. A PES packet with several Opus access
+    /// units becomes one packet per access unit. Closed captions of a video
+    /// access unit are returned right away as well, ahead of their video.
+    fn complete(&mut self, mut pes: PESBuilder) -> Vec<Packet> {
+        let mut data = pes.take_data();
+        if let Some(size) = pes.size {
+            data.truncate(size);
+        }
+        if !self.probed {
+            self.probe(&data);
+        }
+
+        let codec_type = self.config.as_ref().map(|config| config.codec_type);
+        let mut packets: Vec<Packet> = self
+            .caption_index
+            .and_then(|caption_index| {
+                extract_captions(
+                    caption_index,
+                    codec_type == Some(CodecType::H265),
+                    &pes,
+                    &data,
+                )
+            })
+            .into_iter()
+            .collect();
+        if codec_type == Some(CodecType::OPUS) {
+            let mut offset = 0;
+            for unit in split_opus_access_units(&data) {
+                let mut unit_pes = PESBuilder {
+                    pts: pes.pts.map(|pts| pts + offset),
+                    dts: pes.dts.map(|dts| dts + offset),
+                    data: unit.to_vec(),
+                    ..pes
+                };
+                // Only the first access unit follows lost data
+                pes.discontinuity = false;
+                offset += opus::packet_duration(unit).map_or(0, |d| d.as_nanos() as i64);
+                packets.extend(self.queue(&mut unit_pes));
+            }
+            return packets;
+        }
+        pes.data = data;
+        packets.extend(self.queue(&mut pes));
+        packets
+    }
+
+    /// Queues a finished access unit, returning the previous one.
+    fn queue(&mut self, pes: &mut PESBuilder) -> Option<Packet> {
+        let data = pes.take_data();
+
+        let is_key = pes.random_access
+            || self
+                .config
+                .as_ref()
+                .is_some_and(|config| is_keyframe(config.codec_type, &data));
+        let mut packet = Packet::new(Bytes::from(data))
+            .with_stream_index(self.stream_index)
+            .with_key_flag(is_key)
+            .with_discontinuity(pes.discontinuity);
+        packet.arrival_timestamp = pes.arrival_timestamp;
+        packet.position = Some(pes.position);
+        if let Some(pts) = pes.pts {
+            packet = packet.with_pts(pts);
+        }
+        // Without a DTS, decoding time equals presentation time
+        if let Some(dts) = pes.dts.or(pes.pts) {
+            packet = packet.with_dts(dts);
+        }
+
+        if self
+            .config
+            .as_ref()
+            .is_some_and(|config| is_sparse(config.codec_type))
+        {
+            return Some(packet);
+        }
+
+        let next_dts = packet.dts;
+        let mut previous = self.pending.replace(packet)?;
+        if let (Some(dts), Some(next_dts)) = (previous.dts, next_dts) {
+            if next_dts > dts {
+                let duration = Duration::from_nanos((next_dts - dts) as u64);
+                previous.duration = Some(duration);
+                self.last_duration = Some(duration);
+            }
+        }
+        Some(previous)
+    }
+
+    /// Returns the buffered packets at the end of the input.
+    fn flush(&mut self) -> Vec<Packet> {
+        let mut packets = Vec::new();
+        if let Some(pes) = self.pes_buffer.take().filter(|pes| !pes.data.is_empty()) {
+            packets.extend(self.complete(pes));
+        }
+        if let Some(mut packet) = self.pending.take() {
+            packet.duration = self.last_duration;
+            packets.push(packet);
+        }
+        packets
+    }
 }
 
 /// Codec information for a stream.
@@ -45,6 +504,32 @@ struct StreamCodecData {
     height: Option<u32>,
     /// Codec-specific extra data
     extra_data: Option<Vec<u8>>,
+    /// Sample rate in Hz for audio codecs
+    sample_rate: Option<u32>,
+    /// Number of channels for audio codecs
+    channels: Option<u8>,
+    /// Codec profile
+    profile: Option<u8>,
+    /// Codec level
+    level: Option<u8>,
+    /// ISO 639-2 language code
+    language: Option<String>,
+}
+
+impl StreamCodecData {
+    fn new(codec_type: CodecType) -> Self {
+        Self {
+            codec_type,
+            width: None,
+            height: None,
+            extra_data: None,
+            sample_rate: None,
+            channels: None,
+            profile: None,
+            level: None,
+            language: None,
+        }
+    }
 }
 
 impl CodecData for StreamCodecData {
@@ -60,6 +545,21 @@ impl CodecData for StreamCodecData {
     fn extra_data(&self) -> Option<&[u8]> {
         self.extra_data.as_deref()
     }
+    fn sample_rate(&self) -> Option<u32> {
+        self.sample_rate
+    }
+    fn channels(&self) -> Option<u8> {
+        self.channels
+    }
+    fn profile(&self) -> Option<u8> {
+        self.profile
+    }
+    fn level(&self) -> Option<u8> {
+        self.level
+    }
+    fn language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
 }
 
 // StreamCodecData implements Clone and CodecData, so it gets CodecDataExt through
@@ -68,8 +568,18 @@ impl CodecData for StreamCodecData {
 /// Helper for assembling PES packets from TS packets.
 #[derive(Debug)]
 struct PESBuilder {
-    /// PTS from PES header
+    /// PTS from PES header, in nanoseconds
     pts: Option<i64>,
+    /// DTS from PES header, in nanoseconds
+    dts: Option<i64>,
+    /// Random access indicator of the first TS packet
+    random_access: bool,
+    /// Arrival timestamp of the first TS packet, for M2TS input
+    arrival_timestamp: Option<u32>,
+    /// Input offset of the first TS packet
+    position: u64,
+    /// Whether data was lost before this packet
+    discontinuity: bool,
     /// Size of complete PES packet
     size: Option<usize>,
     /// Accumulated data
@@ -80,6 +590,11 @@ impl PESBuilder {
     fn new() -> Self {
         Self {
             pts: None,
+            dts: None,
+            random_access: false,
+            arrival_timestamp: None,
+            position: 0,
+            discontinuity: false,
             size: None,
             data: Vec::new(),
         }
@@ -95,12 +610,20 @@ impl PESBuilder {
         self.pts = Some(pts);
     }
 
+    /// Sets DTS value from PES header.
+    fn set_dts(&mut self, dts: i64) {
+        self.dts = Some(dts);
+    }
+
     /// Sets expected packet size from PES header.
     fn set_size(&mut self, size: usize) {
         self.size = Some(size);
     }
 
     /// Returns whether packet is complete.
+    ///
+    /// Packets without a size (`PES_packet_length` 0) complete at the start
+    /// of the next PES packet.
     fn is_complete(&self) -> bool {
         if let Some(size) = self.size {
             self.data.len() >= size
@@ -115,19 +638,120 @@ impl PESBuilder {
     }
 }
 
-/// Parse PTS value from PES header.
-fn parse_pts(data: &[u8]) -> Option<i64> {
-    if data.len() < 14 || (data[7] & 0x80) == 0 {
+/// Returns the offset of the sync byte in a packet of the given size.
+fn sync_byte_offset(packet_size: usize) -> usize {
+    // M2TS packets start with the TP_extra_header
+    if packet_size == M2TS_PACKET_SIZE {
+        4
+    } else {
+        0
+    }
+}
+
+/// Returns the codec of an elementary stream listed in a PMT.
+///
+/// Private data streams (stream type 0x06) are identified by their
+/// descriptors. Unknown streams are [`CodecType::PrivateData`].
+fn stream_codec_type(info: &ElementaryStreamInfo) -> CodecType {
+    let has_tag = |tag| info.descriptors.iter().any(|d| d.tag == tag);
+    let format = info
+        .descriptors
+        .iter()
+        .find_map(Descriptor::format_identifier);
+    match info.stream_type {
+        STREAM_TYPE_H264 => CodecType::H264,
+        STREAM_TYPE_H265 => CodecType::H265,
+        STREAM_TYPE_MPEG1_VIDEO | STREAM_TYPE_MPEG2_VIDEO => CodecType::MPEG2Video,
+        STREAM_TYPE_AAC => CodecType::AAC,
+        STREAM_TYPE_MPEG1_AUDIO | STREAM_TYPE_MPEG2_AUDIO => CodecType::MPEGAudio,
+        STREAM_TYPE_AC3 => CodecType::AC3,
+        STREAM_TYPE_EAC3 => CodecType::EAC3,
+        // Metadata in PES packets, as long as it is ID3
+        STREAM_TYPE_METADATA
+            if info
+                .descriptors
+                .iter()
+                .filter(|descriptor| descriptor.tag == DESCRIPTOR_METADATA)
+                .all(Descriptor::is_id3_metadata) =>
+        {
+            CodecType::ID3
+        }
+        STREAM_TYPE_PRIVATE_DATA if has_tag(DESCRIPTOR_SUBTITLING) => CodecType::DVBSubtitle,
+        STREAM_TYPE_PRIVATE_DATA if has_tag(DESCRIPTOR_TELETEXT) => CodecType::Teletext,
+        STREAM_TYPE_PRIVATE_DATA if has_tag(DESCRIPTOR_AC3) => CodecType::AC3,
+        STREAM_TYPE_PRIVATE_DATA if has_tag(DESCRIPTOR_ENHANCED_AC3) => CodecType::EAC3,
+        STREAM_TYPE_PRIVATE_DATA => match format {
+            Some(FORMAT_IDENTIFIER_AC3) => CodecType::AC3,
+            Some(FORMAT_IDENTIFIER_EAC3) => CodecType::EAC3,
+            Some(FORMAT_IDENTIFIER_OPUS) => CodecType::OPUS,
+            _ => CodecType::PrivateData,
+        },
+        _ => CodecType::PrivateData,
+    }
+}
+
+/// Returns the closed captions of a video access unit as a packet of the
+/// caption stream, or `None` if the access unit has no captions.
+///
+/// The packet carries the PTS of its picture. With B-frames, caption packets
+/// are in decoding order and have to be sorted by PTS before decoding.
+fn extract_captions(
+    caption_index: usize,
+    hevc: bool,
+    pes: &PESBuilder,
+    data: &[u8],
+) -> Option<Packet> {
+    let cc_data: Vec<u8> = split_annexb(data)
+        .into_iter()
+        .flat_map(|nal| extract_cc_data(nal, hevc))
+        .collect();
+    if cc_data.is_empty() {
         return None;
     }
+    let mut packet = Packet::new(Bytes::from(cc_data))
+        .with_stream_index(caption_index)
+        .with_key_flag(true)
+        .with_discontinuity(pes.discontinuity);
+    packet.arrival_timestamp = pes.arrival_timestamp;
+    packet.position = Some(pes.position);
+    if let Some(pts) = pes.pts.or(pes.dts) {
+        packet = packet.with_pts(pts).with_dts(pts);
+    }
+    Some(packet)
+}
 
-    let pts = ((data[9] as i64 & 0x0E) << 29)
-        | ((data[10] as i64) << 22)
-        | ((data[11] as i64 & 0xFE) << 14)
-        | ((data[12] as i64) << 7)
-        | ((data[13] as i64 & 0xFE) >> 1);
+/// Returns true for streams without regular access units, whose packets are
+/// returned without a duration as soon as they are complete.
+pub(super) fn is_sparse(codec_type: CodecType) -> bool {
+    matches!(
+        codec_type,
+        CodecType::ID3
+            | CodecType::DVBSubtitle
+            | CodecType::Teletext
+            | CodecType::ClosedCaptions
+            | CodecType::PrivateData
+    )
+}
+
+/// Width of PTS/DTS values in bits
+const PTS_BITS: u32 = 33;
+
+/// Converts 90 kHz timestamp units to nanoseconds.
+fn pts_to_nanos(ticks: i64) -> i64 {
+    ticks * 100_000 / 9
+}
 
-    Some(pts)
+/// Returns true if the access unit contains an IDR (H.264) or IRAP (H.265)
+/// picture. Audio frames are always decodable on their own.
+fn is_keyframe(codec_type: CodecType, data: &[u8]) -> bool {
+    match codec_type {
+        CodecType::H264 => split_annexb(data).iter().any(|nal| nal[0] & 0x1F == 5),
+        CodecType::H265 => split_annexb(data)
+            .iter()
+            .any(|nal| matches!((nal[0] >> 1) & 0x3F, 16..=21)),
+        CodecType::MPEG2Video => mpeg2::is_intra_picture(data),
+        _ => true,
+    }
 }
 
 impl<R: AsyncRead + Unpin + Send> TSDemuxer<R> {
@@ -137,216 +761,741 @@ impl<R: AsyncRead + Unpin + Send> TSDemuxer<R> {
             reader,
             parser: TSPacketParser::new(),
             streams: HashMap::new(),
-            pmt_pid: None,
-            pmt: None,
+            pat_received: false,
+            programs: BTreeMap::new(),
+            program_filter: None,
+            ready: VecDeque::new(),
+            buffer: Vec::new(),
+            synced: false,
+            packet_size: None,
+            arrival_timestamp: None,
+            input_position: 0,
+            packet_position: 0,
+            continuity_counters: HashMap::new(),
+            sections: HashMap::new(),
+            si_sections: HashMap::new(),
+            scte35_pids: HashMap::new(),
+            closed_captions: false,
+            index: None,
+            events: VecDeque::new(),
+            crc: Crc32Mpeg2::new(),
+            statistics: TSStatistics::default(),
         }
     }
 
-    /// Reads a complete TS packet.
-    async fn read_packet_data(&mut self) -> Result<Vec<u8>> {
-        let mut packet = vec![0u8; TS_PACKET_SIZE];
-        self.reader.read_exact(&mut packet).await?;
-        Ok(packet)
+    /// Limits demuxing to the given programs.
+    ///
+    /// Streams of other programs are ignored. By default, all programs
+    /// listed in the PAT are demuxed.
+    ///
+    /// # Arguments
+    ///
+    /// * `programs` - Program numbers to demux
+    pub fn with_program_filter(mut self, programs: &[u16]) -> Self {
+        self.program_filter = Some(programs.to_vec());
+        self
     }
-}
 
-#[async_trait::async_trait]
-impl<R: AsyncRead + Unpin + Send> FormatDemuxer for TSDemuxer<R> {
-    async fn read_packet(&mut self) -> Result<Packet> {
+    /// Extracts CEA-608/708 closed captions from the SEI of H.264 and H.265
+    /// streams.
+    ///
+    /// Every H.264 and H.265 stream is followed by a stream of
+    /// [`CodecType::ClosedCaptions`], whether or not the video carries
+    /// captions. Its packets hold the cc_data triplets of one access unit and
+    /// the PTS of its picture, see [`crate::codec::captions`].
+    pub fn with_closed_captions(mut self) -> Self {
+        self.closed_captions = true;
+        self
+    }
+
+    /// Returns the programs whose PMT has been received, by program number.
+    pub fn programs(&self) -> Vec<TSProgram> {
+        self.programs
+            .iter()
+            .filter_map(|(&program_number, program)| {
+                let pmt = program.pmt.as_ref()?;
+                let mut stream_indices: Vec<usize> = self
+                    .streams
+                    .values()
+                    .filter(|stream| stream.program_number == program_number)
+                    .flat_map(|stream| [Some(stream.stream_index), stream.caption_index])
+                    .flatten()
+                    .collect();
+                stream_indices.sort_unstable();
+                Some(TSProgram {
+                    program_number,
+                    pmt_pid: program.pmt_pid,
+                    pcr_pid: pmt.pcr_pid,
+                    stream_indices,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the events found since the last call, in stream order.
+    ///
+    /// Events are found while reading ahead, so they can precede the
+    /// packets returned by [`read_packet`](FormatDemuxer::read_packet).
+    pub fn take_events(&mut self) -> Vec<TSEvent> {
+        self.events.drain(..).collect()
+    }
+
+    /// Returns the error counters collected so far.
+    pub fn statistics(&self) -> &TSStatistics {
+        &self.statistics
+    }
+
+    /// Returns the size of the packets on the input, once detected.
+    ///
+    /// One of [`TS_PACKET_SIZE`], [`M2TS_PACKET_SIZE`] and [`RS_PACKET_SIZE`].
+    pub fn packet_size(&self) -> Option<usize> {
+        self.packet_size
+    }
+
+    /// Returns the `count` lowest stream indices not used by a stream or
+    /// caption stream, so indices freed by PMT updates are reused.
+    fn unused_stream_indices(&self, count: usize) -> Vec<usize> {
+        let used: HashSet<usize> = self
+            .streams
+            .values()
+            .flat_map(|stream| [Some(stream.stream_index), stream.caption_index])
+            .flatten()
+            .collect();
+        (0..)
+            .filter(|index| !used.contains(index))
+            .take(count)
+            .collect()
+    }
+
+    /// Drops the buffered input and incomplete packets after the input moved
+    /// to `position`, expecting timestamps near `dts` (in nanoseconds).
+    fn reset_input(&mut self, position: u64, dts: i64) {
+        self.buffer.clear();
+        self.input_position = position;
+        self.ready.clear();
+        self.continuity_counters.clear();
+        self.sections.clear();
+        for stream in self.streams.values_mut() {
+            stream.pes_buffer = None;
+            stream.pending = None;
+            stream.discontinuity = false;
+            stream
+                .timestamps
+                .reset(Some(Timebase::MPEG.from_nanos(dts)));
+        }
+    }
+
+    /// Queues the packets still buffered at the end of the input, e.g. the
+    /// last unbounded video PES.
+    fn flush_streams(&mut self) {
+        for stream in self.streams.values_mut() {
+            self.ready.extend(stream.flush());
+        }
+    }
+
+    /// Reads the input until `len` bytes are buffered or the input ends.
+    async fn fill_buffer(&mut self, len: usize) -> Result<()> {
+        let mut chunk = [0u8; SYNC_LOOKAHEAD];
+        while self.buffer.len() < len {
+            let n = self.reader.read(&mut chunk).await?;
+            if n == 0 {
+                break;
+            }
+            self.buffer.extend_from_slice(&chunk[..n]);
+        }
+        Ok(())
+    }
+
+    /// Returns the offset and size of the first packet followed by sync
+    /// bytes at the start of [`SYNC_LOCK_PACKETS`] consecutive packets.
+    ///
+    /// Packet sizes are tried in the order of [`PACKET_SIZES`] until one is
+    /// detected, only that size is used afterwards. Near the end of the
+    /// input, fewer packets are required.
+    fn find_sync(&self, at_end: bool) -> Option<(usize, usize)> {
+        let len = self.buffer.len();
+        let sizes = match &self.packet_size {
+            Some(size) => std::slice::from_ref(size),
+            None => &PACKET_SIZES[..],
+        };
+        (0..len).find_map(|offset| {
+            sizes
+                .iter()
+                .copied()
+                .find(|&size| {
+                    if offset + size > len {
+                        return false;
+                    }
+                    let mut checked = 0;
+                    for pos in (offset + sync_byte_offset(size)..len)
+                        .step_by(size)
+                        .take(SYNC_LOCK_PACKETS)
+                    {
+                        if self.buffer[pos] != TS_SYNC_BYTE {
+                            return false;
+                        }
+                        checked += 1;
+                    }
+                    at_end || checked == SYNC_LOCK_PACKETS
+                })
+                .map(|size| (offset, size))
+        })
+    }
+
+    /// Reads a complete TS packet, hunting for the packet grid after
+    /// corruption.
+    ///
+    /// The arrival timestamp of M2TS packets is kept for
+    /// [`TSDemuxer::process_packet`], other packet sizes are reduced to the
+    /// TS packet.
+    async fn read_packet_data(&mut self) -> Result<Vec<u8>> {
         loop {
-            let data = self.read_packet_data().await?;
-            let header = self.parser.parse_header(&data)?;
+            self.fill_buffer(SYNC_LOOKAHEAD).await?;
+            let at_end = self.buffer.len() < SYNC_LOOKAHEAD;
+            let size = self.packet_size.unwrap_or(TS_PACKET_SIZE);
+            if self.buffer.len() < size {
+                return Err(VdkError::Io(std::io::ErrorKind::UnexpectedEof.into()));
+            }
 
-            // Skip packets with transport errors
-            if header.transport_error {
-                continue;
+            let sync = sync_byte_offset(size);
+            if self.synced && self.buffer[sync] == TS_SYNC_BYTE {
+                let data: Vec<u8> = self.buffer.drain(..size).collect();
+                self.packet_position = self.input_position;
+                self.input_position += size as u64;
+                self.arrival_timestamp = (size == M2TS_PACKET_SIZE).then(|| {
+                    u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+                        & ARRIVAL_TIMESTAMP_MASK
+                });
+                return Ok(data[sync..sync + TS_PACKET_SIZE].to_vec());
+            }
+            if self.synced {
+                self.synced = false;
+                self.statistics.sync_losses += 1;
             }
 
-            // Parse adaptation field if present
-            let mut payload_offset = TS_HEADER_SIZE;
-            if header.adaptation_field_exists {
-                if let Some(adaptation) = self.parser.parse_adaptation_field(&data, payload_offset)? {
-                    payload_offset += adaptation.length + 1;
+            match self.find_sync(at_end) {
+                Some((offset, size)) => {
+                    self.buffer.drain(..offset);
+                    self.input_position += offset as u64;
+                    self.statistics.skipped_bytes += offset as u64;
+                    self.packet_size = Some(size);
+                    self.synced = true;
+                }
+                None => {
+                    // Keep the bytes that may still start a locked packet
+                    let keep = if at_end {
+                        0
+                    } else {
+                        self.packet_size.unwrap_or(RS_PACKET_SIZE) * (SYNC_LOCK_PACKETS - 1)
+                    };
+                    let skipped = self.buffer.len() - keep;
+                    self.buffer.drain(..skipped);
+                    self.input_position += skipped as u64;
+                    self.statistics.skipped_bytes += skipped as u64;
                 }
             }
+        }
+    }
 
-            // Handle payload
-            if header.contains_payload {
-                match header.pid {
-                    PID_PAT if header.payload_unit_start => {
-                        // Parse PAT to get PMT PID
-                        let table_offset = payload_offset + data[payload_offset] as usize + 1;
-                        let pat = self.parser.parse_pat(&data[table_offset..], 0, 0)?;
-                        
-                        // Use first program's PMT
-                        if let Some(entry) = pat.entries.first() {
-                            self.pmt_pid = Some(entry.program_map_pid);
-                        }
-                    }
+    /// Checks the continuity counter of a packet against the previous one
+    /// on its PID.
+    fn check_continuity(&mut self, header: &TSHeader, discontinuity: bool) -> Continuity {
+        let previous = self
+            .continuity_counters
+            .insert(header.pid, header.continuity_counter);
+        let previous = match previous {
+            Some(previous) if !discontinuity => previous,
+            _ => return Continuity::Ok,
+        };
 
-                    pmt_pid if Some(pmt_pid) == self.pmt_pid && header.payload_unit_start => {
-                        // Parse PMT to get elementary stream info
-                        let table_offset = payload_offset + data[payload_offset] as usize + 1;
-                        let pmt = self.parser.parse_pmt(&data[table_offset..], 0, 0)?;
-                        
-                        // Create stream entries
-                        for (i, info) in pmt.elementary_stream_infos.iter().enumerate() {
-                            let codec_type = match info.stream_type {
-                                STREAM_TYPE_H264 => CodecType::H264,
-                                STREAM_TYPE_H265 => CodecType::H265,
-                                STREAM_TYPE_AAC => CodecType::AAC,
-                                _ => continue,
-                            };
-
-                            let stream = StreamInfo {
-                                stream_index: i,
-                                config: Some(StreamCodecData {
-                                    codec_type,
-                                    width: None,
-                                    height: None,
-                                    extra_data: None,
-                                }),
-                                pes_buffer: None,
-                            };
-                            self.streams.insert(info.elementary_pid, stream);
-                        }
-                        self.pmt = Some(pmt);
-                    }
+        // Packets without payload do not increment the counter
+        let expected = if header.contains_payload {
+            (previous + 1) & 0x0F
+        } else {
+            previous
+        };
+        if header.continuity_counter == expected {
+            Continuity::Ok
+        } else if header.contains_payload && header.continuity_counter == previous {
+            Continuity::Duplicate
+        } else {
+            self.statistics.continuity_errors += 1;
+            Continuity::Lost
+        }
+    }
 
-                    elementary_pid if self.streams.contains_key(&elementary_pid) => {
-                        let stream = self.streams.get_mut(&elementary_pid).unwrap();
-                        let payload = &data[payload_offset..];
-
-                        // Start new PES packet or add to existing
-                        if header.payload_unit_start {
-                            // Handle completed PES packet
-                            if let Some(pes) = &mut stream.pes_buffer {
-                                if !pes.data.is_empty() {
-                                    let packet_data = pes.take_data();
-                                    return Ok(Packet::new(Bytes::from(packet_data))
-                                        .with_pts(pes.pts.unwrap_or(0))
-                                        .with_stream_index(stream.stream_index));
-                                }
-                            }
-
-                            // Parse PES header
-                            if payload.len() >= 6 {
-                                let packet_len = ((payload[4] as usize) << 8) | (payload[5] as usize);
-                                let pts = parse_pts(payload);
-
-                                // Create new PES packet
-                                let mut pes = PESBuilder::new();
-                                if let Some(pts) = pts {
-                                    // Convert from 90kHz to ns
-                                    pes.set_pts((pts as f64 * 1_000_000_000.0 / 90_000.0) as i64);
-                                }
-                                pes.set_size(packet_len);
-                                stream.pes_buffer = Some(pes);
-                            }
-                        }
+    /// Adds PSI payload for a PID and returns its completed sections with
+    /// a valid CRC, or without CRC for short-form sections such as the TDT.
+    fn read_sections(&mut self, pid: u16, payload: &[u8], unit_start: bool) -> Vec<Vec<u8>> {
+        let sections = self
+            .sections
+            .entry(pid)
+            .or_default()
+            .push(payload, unit_start);
+        sections
+            .into_iter()
+            .filter(|section| {
+                let has_crc =
+                    section[1] & 0x80 != 0 || matches!(section[0], TABLE_ID_TOT | TABLE_ID_SCTE35);
+                // The CRC over a section including its CRC_32 field is zero
+                let valid = !has_crc || self.crc.calculate(section) == 0;
+                if !valid {
+                    self.statistics.crc_errors += 1;
+                }
+                valid
+            })
+            .collect()
+    }
+
+    /// Queues an event for an SI section of a new or changed table.
+    ///
+    /// SI tables are repeated continuously, so a section equal to the last
+    /// one with the same table ID, extension and section number is skipped.
+    fn handle_si_section(&mut self, pid: u16, section: Vec<u8>) {
+        let key = if section[1] & 0x80 != 0 && section.len() >= 8 {
+            let extension = u16::from_be_bytes([section[3], section[4]]);
+            (pid, section[0], extension, section[6])
+        } else {
+            (pid, section[0], 0, 0)
+        };
+        if self.si_sections.get(&key) == Some(&section) {
+            return;
+        }
+        match SITable::parse(&section, &self.crc) {
+            Ok(Some(table)) => {
+                self.events.push_back(TSEvent::ServiceInformation(table));
+                self.si_sections.insert(key, section);
+            }
+            Ok(None) => {}
+            Err(_) => self.statistics.section_errors += 1,
+        }
+    }
+
+    /// Queues an event for an SCTE-35 section received on `pid`.
+    ///
+    /// The splice time is extended like the timestamps of the program's PCR
+    /// stream, so it can be compared with packet timestamps.
+    fn handle_splice(&mut self, pid: u16, section: &[u8]) {
+        let Ok(splice) = SpliceInfoSection::parse(section, &self.crc) else {
+            self.statistics.section_errors += 1;
+            return;
+        };
+        let program_number = self.scte35_pids[&pid];
+        let pcr_stream = self
+            .programs
+            .get(&program_number)
+            .and_then(|program| program.pmt.as_ref())
+            .and_then(|pmt| self.streams.get(&pmt.pcr_pid));
+        let pts = splice.splice_pts().map(|pts| {
+            let ticks = match pcr_stream {
+                Some(stream) => stream.timestamps.nearest(pts),
+                None => pts as i64,
+            };
+            pts_to_nanos(ticks)
+        });
+        self.events.push_back(TSEvent::Splice {
+            program_number,
+            pts,
+            section: splice,
+        });
+    }
 
-                        // Add payload to PES packet
-                        if let Some(pes) = &mut stream.pes_buffer {
-                            pes.push_data(payload);
-
-                            // Return completed packets
-                            if pes.is_complete() {
-                                let packet_data = pes.take_data();
-                                return Ok(Packet::new(Bytes::from(packet_data))
-                                    .with_pts(pes.pts.unwrap_or(0))
-                                    .with_stream_index(stream.stream_index));
-                            }
+    /// Updates the programs from a PAT.
+    fn handle_pat(&mut self, pat: PAT) {
+        let listed = |program_number: u16| {
+            pat.entries
+                .iter()
+                .find(|entry| entry.program_number == program_number)
+        };
+        self.programs
+            .retain(|&program_number, _| listed(program_number).is_some());
+
+        // Program number 0 points to the network information table
+        for entry in pat.entries.iter().filter(|entry| entry.program_number != 0) {
+            let selected = match &self.program_filter {
+                Some(filter) => filter.contains(&entry.program_number),
+                None => true,
+            };
+            if !selected {
+                continue;
+            }
+            let program = self
+                .programs
+                .entry(entry.program_number)
+                .or_insert(ProgramInfo {
+                    pmt_pid: entry.program_map_pid,
+                    pmt: None,
+                });
+            if program.pmt_pid != entry.program_map_pid {
+                program.pmt_pid = entry.program_map_pid;
+                program.pmt = None;
+            }
+        }
+        self.pat_received = true;
+    }
+
+    /// Creates stream entries from a PMT received on `pmt_pid`.
+    fn handle_pmt(&mut self, pmt_pid: u16, pmt: PMT) {
+        let program_number = pmt.program_number;
+        match self.programs.get(&program_number) {
+            Some(program) if program.pmt_pid == pmt_pid => {}
+            // Another program sharing the PID, or one that is filtered out
+            _ => return,
+        }
+
+        // Streams that left the program free their indices
+        let pids: HashSet<u16> = pmt
+            .elementary_stream_infos
+            .iter()
+            .map(|info| info.elementary_pid)
+            .collect();
+        self.streams
+            .retain(|pid, stream| stream.program_number != program_number || pids.contains(pid));
+        self.scte35_pids
+            .retain(|pid, program| *program != program_number || pids.contains(pid));
+
+        for info in &pmt.elementary_stream_infos {
+            if info.stream_type == STREAM_TYPE_SCTE35 {
+                self.scte35_pids.insert(info.elementary_pid, program_number);
+                continue;
+            }
+            let codec_type = stream_codec_type(info);
+
+            // A repeated PMT keeps the state of known streams
+            let known = self.streams.get(&info.elementary_pid).is_some_and(|known| {
+                known.config.as_ref().map(|c| c.codec_type) == Some(codec_type)
+            });
+            if !known {
+                let mut config = StreamCodecData::new(codec_type);
+                config.language = info.descriptors.iter().find_map(Descriptor::language);
+                match codec_type {
+                    // Opus has no headers in band
+                    CodecType::OPUS => {
+                        config.sample_rate = Some(OPUS_SAMPLE_RATE);
+                        config.channels =
+                            info.descriptors.iter().find_map(Descriptor::opus_channels);
+                    }
+                    CodecType::PrivateData => {
+                        let mut extra_data = vec![info.stream_type];
+                        for descriptor in &info.descriptors {
+                            extra_data.push(descriptor.tag);
+                            extra_data.push(descriptor.data.len() as u8);
+                            extra_data.extend_from_slice(&descriptor.data);
                         }
+                        config.extra_data = Some(extra_data);
+                    }
+                    // The pages or services, written back by the muxer
+                    CodecType::DVBSubtitle | CodecType::Teletext => {
+                        let tag = if codec_type == CodecType::DVBSubtitle {
+                            DESCRIPTOR_SUBTITLING
+                        } else {
+                            DESCRIPTOR_TELETEXT
+                        };
+                        config.extra_data = info
+                            .descriptors
+                            .iter()
+                            .find(|descriptor| descriptor.tag == tag)
+                            .map(|descriptor| descriptor.data.clone());
                     }
                     _ => {}
                 }
+                // A stream whose codec changed keeps its index
+                let captions =
+                    self.closed_captions && matches!(codec_type, CodecType::H264 | CodecType::H265);
+                let replaced = self.streams.remove(&info.elementary_pid);
+                let (stream_index, caption_index) = match replaced {
+                    Some(replaced) => {
+                        let caption_index = captions.then(|| {
+                            replaced
+                                .caption_index
+                                .unwrap_or_else(|| self.unused_stream_indices(1)[0])
+                        });
+                        (replaced.stream_index, caption_index)
+                    }
+                    None => {
+                        let indices = self.unused_stream_indices(1 + captions as usize);
+                        (indices[0], indices.get(1).copied())
+                    }
+                };
+                let mut stream = StreamInfo::new(stream_index, program_number, config);
+                // Nothing to probe, and the stream may never carry a PES
+                stream.probed = is_sparse(codec_type) || codec_type == CodecType::OPUS;
+                stream.caption_index = caption_index;
+                self.streams.insert(info.elementary_pid, stream);
             }
         }
+        if let Some(program) = self.programs.get_mut(&program_number) {
+            program.pmt = Some(pmt);
+        }
     }
 
-    async fn streams(&mut self) -> Result<Vec<Box<dyn av::CodecDataExt>>> {
-        // Read packets until we have stream information
-        while self.pmt.is_none() {
-            let data = self.read_packet_data().await?;
-            let header = self.parser.parse_header(&data)?;
+    /// Processes one TS packet, queueing any completed media packets.
+    fn process_packet(&mut self, data: &[u8]) -> Result<()> {
+        let header = self.parser.parse_header(data)?;
+        self.statistics.packets += 1;
 
-            // Skip packets with transport errors
-            if header.transport_error {
-                continue;
+        // Skip packets with transport errors
+        if header.transport_error {
+            self.statistics.transport_errors += 1;
+            return Ok(());
+        }
+        if header.pid == PID_NULL {
+            return Ok(());
+        }
+
+        // Parse adaptation field if present
+        let mut payload_offset = TS_HEADER_SIZE;
+        let mut adaptation = None;
+        if header.adaptation_field_exists {
+            // A zero-length adaptation field still occupies its length byte
+            payload_offset += data[payload_offset] as usize + 1;
+            if payload_offset > TS_PACKET_SIZE {
+                return Ok(());
             }
+            adaptation = self
+                .parser
+                .parse_adaptation_field(data, TS_HEADER_SIZE)
+                .ok()
+                .flatten();
+        }
+        let discontinuity = adaptation.as_ref().is_some_and(|field| field.discontinuity);
+        let random_access = adaptation.as_ref().is_some_and(|field| field.random_access);
+
+        let lost = match self.check_continuity(&header, discontinuity) {
+            Continuity::Ok => false,
+            Continuity::Duplicate => return Ok(()),
+            Continuity::Lost => true,
+        };
+        if !header.contains_payload {
+            return Ok(());
+        }
+        let payload = &data[payload_offset..];
 
-            // Parse adaptation field if present
-            let mut payload_offset = TS_HEADER_SIZE;
-            if header.adaptation_field_exists {
-                if let Some(adaptation) = self.parser.parse_adaptation_field(&data, payload_offset)? {
-                    payload_offset += adaptation.length + 1;
+        match header.pid {
+            PID_PAT => {
+                if lost {
+                    self.sections.remove(&PID_PAT);
+                }
+                for section in self.read_sections(PID_PAT, payload, header.payload_unit_start) {
+                    if section[0] != TABLE_ID_PAT {
+                        continue;
+                    }
+                    match self.parser.parse_pat(&section, 0, 0) {
+                        Ok(pat) => self.handle_pat(pat),
+                        Err(_) => self.statistics.section_errors += 1,
+                    }
                 }
             }
 
-            // Handle payload
-            if header.contains_payload {
-                match header.pid {
-                    PID_PAT if header.payload_unit_start => {
-                        // Parse PAT to get PMT PID
-                        let table_offset = payload_offset + data[payload_offset] as usize + 1;
-                        let pat = self.parser.parse_pat(&data[table_offset..], 0, 0)?;
-                        
-                        // Use first program's PMT
-                        if let Some(entry) = pat.entries.first() {
-                            self.pmt_pid = Some(entry.program_map_pid);
-                        }
+            pmt_pid if self.programs.values().any(|p| p.pmt_pid == pmt_pid) => {
+                if lost {
+                    self.sections.remove(&pmt_pid);
+                }
+                for section in self.read_sections(pmt_pid, payload, header.payload_unit_start) {
+                    if section[0] != TABLE_ID_PMT {
+                        continue;
+                    }
+                    match self.parser.parse_pmt(&section, 0, 0) {
+                        Ok(pmt) => self.handle_pmt(pmt_pid, pmt),
+                        Err(_) => self.statistics.section_errors += 1,
                     }
+                }
+            }
+
+            PID_NIT | PID_SDT | PID_EIT | PID_TDT => {
+                if lost {
+                    self.sections.remove(&header.pid);
+                }
+                for section in self.read_sections(header.pid, payload, header.payload_unit_start) {
+                    self.handle_si_section(header.pid, section);
+                }
+            }
+
+            scte35_pid if self.scte35_pids.contains_key(&scte35_pid) => {
+                if lost {
+                    self.sections.remove(&scte35_pid);
+                }
+                for section in self.read_sections(scte35_pid, payload, header.payload_unit_start) {
+                    self.handle_splice(scte35_pid, &section);
+                }
+            }
+
+            elementary_pid if self.streams.contains_key(&elementary_pid) => {
+                let stream = self.streams.get_mut(&elementary_pid).unwrap();
+                if lost {
+                    // The partial PES is corrupt, the next one is flagged
+                    stream.pes_buffer = None;
+                    stream.discontinuity = true;
+                }
+
+                // Start new PES packet or add to existing
+                let mut finished = None;
+                if header.payload_unit_start {
+                    // The previous PES packet ends where the next one starts
+                    finished = stream.pes_buffer.take().filter(|pes| !pes.data.is_empty());
+
+                    if let Ok(pes_header) = PESHeader::parse(payload) {
+                        let mut pes = PESBuilder::new();
+                        pes.random_access = random_access;
+                        pes.arrival_timestamp = self.arrival_timestamp;
+                        pes.position = self.packet_position;
+                        pes.discontinuity = std::mem::take(&mut stream.discontinuity);
+                        if let Some(dts) = pes_header.dts {
+                            pes.set_dts(pts_to_nanos(stream.timestamps.unwrap(dts)));
+                        }
+                        if let Some(pts) = pes_header.pts {
+                            pes.set_pts(pts_to_nanos(stream.timestamps.unwrap(pts)));
+                        }
 
-                    pmt_pid if Some(pmt_pid) == self.pmt_pid && header.payload_unit_start => {
-                        // Parse PMT to get elementary stream info
-                        let table_offset = payload_offset + data[payload_offset] as usize + 1;
-                        let pmt = self.parser.parse_pmt(&data[table_offset..], 0, 0)?;
-                        
-                        // Create stream entries
-                        for (i, info) in pmt.elementary_stream_infos.iter().enumerate() {
-                            let codec_type = match info.stream_type {
-                                STREAM_TYPE_H264 => CodecType::H264,
-                                STREAM_TYPE_H265 => CodecType::H265,
-                                STREAM_TYPE_AAC => CodecType::AAC,
-                                _ => continue,
-                            };
-
-                            let stream = StreamInfo {
-                                stream_index: i,
-                                config: Some(StreamCodecData {
-                                    codec_type,
-                                    width: None,
-                                    height: None,
-                                    extra_data: None,
-                                }),
-                                pes_buffer: None,
-                            };
-                            self.streams.insert(info.elementary_pid, stream);
+                        // The payload excludes the PES header
+                        let header_len = pes_header.header_len();
+                        if pes_header.packet_length > 0 {
+                            // PES_packet_length counts the bytes after itself
+                            let size = 6 + pes_header.packet_length as usize;
+                            pes.set_size(size.saturating_sub(header_len));
                         }
-                        self.pmt = Some(pmt);
-                        break;
+                        pes.push_data(&payload[header_len.min(payload.len())..]);
+                        stream.pes_buffer = Some(pes);
                     }
-                    _ => {}
+                } else if let Some(pes) = &mut stream.pes_buffer {
+                    pes.push_data(payload);
+                }
+
+                if let Some(pes) = finished {
+                    self.ready.extend(stream.complete(pes));
+                }
+                if stream
+                    .pes_buffer
+                    .as_ref()
+                    .is_some_and(PESBuilder::is_complete)
+                {
+                    if let Some(pes) = stream.pes_buffer.take() {
+                        self.ready.extend(stream.complete(pes));
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin + Send> TSDemuxer<R> {
+    /// Sets the index used for seeking, e.g. one built and kept earlier.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Index of this input, see [`TSIndex::build`]
+    pub fn with_index(mut self, index: TSIndex) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    /// Returns the index used for seeking, once built or set.
+    pub fn index(&self) -> Option<&TSIndex> {
+        self.index.as_ref()
+    }
+
+    /// Moves to the last keyframe presented at or before `time`.
+    ///
+    /// Without an index, the first seek builds one by reading the whole
+    /// input. Packets buffered before the seek are dropped, and reading
+    /// continues with the PES packets starting at the keyframe, so packets
+    /// of other streams may precede it slightly.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - Presentation time on the timeline of the packets
+    ///
+    /// # Returns
+    ///
+    /// The presentation time of the keyframe, or an error if the input has
+    /// no keyframes or fails to seek
+    pub async fn seek(&mut self, time: Duration) -> Result<Duration> {
+        if self.index.is_none() {
+            self.reader.seek(SeekFrom::Start(0)).await?;
+            let mut scanner = TSDemuxer::new(&mut self.reader);
+            scanner.program_filter = self.program_filter.clone();
+            self.index = Some(TSIndex::scan(&mut scanner).await?);
+        }
+        let entry = self
+            .index
+            .as_ref()
+            .and_then(|index| index.keyframe_at(time))
+            .copied()
+            .ok_or_else(|| VdkError::InvalidData("No keyframe to seek to".into()))?;
+
+        self.reader.seek(SeekFrom::Start(entry.position)).await?;
+        self.reset_input(entry.position, entry.dts);
+        Ok(Duration::from_nanos(entry.pts.max(0) as u64))
+    }
+}
+
+#[async_trait::async_trait]
+impl<R: AsyncRead + Unpin + Send> FormatDemuxer for TSDemuxer<R> {
+    async fn read_packet(&mut self) -> Result<Packet> {
+        loop {
+            if let Some(packet) = self.ready.pop_front() {
+                return Ok(packet);
+            }
+
+            let data = match self.read_packet_data().await {
+                Ok(data) => data,
+                Err(VdkError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    self.flush_streams();
+                    match self.ready.pop_front() {
+                        Some(packet) => return Ok(packet),
+                        None => return Err(VdkError::Io(e)),
+                    }
+                }
+                Err(e) => return Err(e),
+            };
+            self.process_packet(&data)?;
+        }
+    }
+
+    async fn streams(&mut self) -> Result<Vec<Box<dyn av::CodecDataExt>>> {
+        // Read packets until we have the PMTs of all programs
+        while !self.pat_received || self.programs.values().any(|p| p.pmt.is_none()) {
+            let data = self.read_packet_data().await?;
+            self.process_packet(&data)?;
+        }
+
+        // Probe codec parameters, keeping the packets for read_packet
+        let mut probe_packets = 0;
+        while probe_packets < MAX_PROBE_PACKETS && self.streams.values().any(|s| !s.probed) {
+            match self.read_packet_data().await {
+                Ok(data) => self.process_packet(&data)?,
+                Err(VdkError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    self.flush_streams();
+                    break;
                 }
+                Err(e) => return Err(e),
             }
+            probe_packets += 1;
         }
 
-        // Return stream configurations
-        let mut configs = Vec::new();
+        // Return stream configurations in stream index order
+        let mut configs: Vec<(usize, Box<dyn av::CodecDataExt>)> = Vec::new();
         for stream in self.streams.values() {
             if let Some(config) = &stream.config {
-                // Create a Box<dyn CodecDataExt> directly
-                let ext_config: Box<dyn av::CodecDataExt> = Box::new(config.clone());
-                configs.push(ext_config);
+                configs.push((stream.stream_index, Box::new(config.clone())));
+            }
+            if let Some(caption_index) = stream.caption_index {
+                let config = StreamCodecData::new(CodecType::ClosedCaptions);
+                configs.push((caption_index, Box::new(config)));
             }
         }
-        Ok(configs)
+        configs.sort_by_key(|(index, _)| *index);
+        Ok(configs.into_iter().map(|(_, config)| config).collect())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::format::ts::test_util::TestCodec;
     use std::io::Cursor;
     use tokio::runtime::Runtime;
 
@@ -358,7 +1507,7 @@ mod tests {
         pat_packet[3] = 0x10; // No adaptation field
         pat_packet[4] = 0x00; // Pointer field
         pat_packet[5] = 0x00; // Table ID (PAT)
-        
+
         // Section length (13 bytes)
         let section_length = 13;
         pat_packet[6] = 0xB0; // Section syntax indicator + length MSB
@@ -456,4 +1605,845 @@ mod tests {
             assert_eq!(streams[0].codec_type(), CodecType::H264);
         });
     }
+
+    #[test]
+    fn test_ts_demuxer_pmt_update() {
+        let pmt_packet = |version: u8, counter: u8, streams: &[(u8, u16)]| {
+            let mut pmt = PMT::new();
+            pmt.program_number = 1;
+            pmt.pcr_pid = 0x100;
+            pmt.elementary_stream_infos = streams
+                .iter()
+                .map(|&(stream_type, elementary_pid)| ElementaryStreamInfo {
+                    stream_type,
+                    elementary_pid,
+                    descriptors: Vec::new(),
+                })
+                .collect();
+            let mut body = bytes::BytesMut::new();
+            pmt.write_to(&mut body).unwrap();
+            let mut section = vec![TABLE_ID_PMT];
+            section.extend_from_slice(&(0xB000u16 | (body.len() + 9) as u16).to_be_bytes());
+            section.extend_from_slice(&[0x00, 0x01, 0xC1 | version << 1, 0, 0]);
+            section.extend_from_slice(&body);
+            let crc = Crc32Mpeg2::new().calculate(&section);
+            section.extend_from_slice(&crc.to_be_bytes());
+            let mut packet = vec![0x47, 0x40, 0x20, 0x10 | counter, 0];
+            packet.extend_from_slice(&section);
+            packet.resize(TS_PACKET_SIZE, 0xFF);
+            packet
+        };
+        let indices = |demuxer: &TSDemuxer<Cursor<Vec<u8>>>| {
+            let mut indices: Vec<(u16, usize, Option<usize>)> = demuxer
+                .streams
+                .iter()
+                .map(|(&pid, stream)| (pid, stream.stream_index, stream.caption_index))
+                .collect();
+            indices.sort_unstable();
+            indices
+        };
+
+        let mut demuxer = TSDemuxer::new(Cursor::new(Vec::new())).with_closed_captions();
+        demuxer.process_packet(&create_pat_packet()).unwrap();
+        let version_0 = [(STREAM_TYPE_H264, 0x100), (STREAM_TYPE_AAC, 0x101)];
+        demuxer
+            .process_packet(&pmt_packet(0, 0, &version_0))
+            .unwrap();
+        assert_eq!(indices(&demuxer), [(0x100, 0, Some(1)), (0x101, 2, None)]);
+
+        // The video changes codec and keeps its indices, the audio moves to
+        // another PID and takes the index its old PID freed
+        let version_1 = [(STREAM_TYPE_H265, 0x100), (STREAM_TYPE_AC3, 0x102)];
+        demuxer
+            .process_packet(&pmt_packet(1, 1, &version_1))
+            .unwrap();
+        assert_eq!(indices(&demuxer), [(0x100, 0, Some(1)), (0x102, 2, None)]);
+        let codec = |pid| demuxer.streams[&pid].config.as_ref().unwrap().codec_type;
+        assert_eq!(codec(0x100), CodecType::H265);
+        assert_eq!(codec(0x102), CodecType::AC3);
+        assert_eq!(demuxer.programs()[0].stream_indices, [0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_ts_demuxer_timestamps_and_keyframes() {
+        use crate::format::ts::TSMuxer;
+        use crate::format::Muxer as FormatMuxer;
+
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()));
+        muxer
+            .write_header(&[TestCodec::new(CodecType::H264).boxed()])
+            .await
+            .unwrap();
+
+        // 40 ms frames across the 33-bit wraparound, which is just after the second
+        let base = 8_589_930_984i64 * 100_000 / 9;
+        let ms = 1_000_000i64;
+        let frames = [
+            (0x65, true),  // IDR flagged as key: random access indicator
+            (0x41, false), // Non-IDR
+            (0x65, false), // IDR without the flag: found by NAL type
+            (0x41, false),
+        ];
+        for (i, (nal_type, is_key)) in frames.iter().enumerate() {
+            let dts = base + i as i64 * 40 * ms;
+            let packet = Packet::new(vec![0, 0, 0, 1, 0x09, 0xF0, 0, 0, 0, 1, *nal_type, 0x88])
+                .with_stream_index(0)
+                .with_dts(dts)
+                .with_pts(dts + 80 * ms)
+                .with_key_flag(*is_key);
+            muxer.write_packet(&packet).await.unwrap();
+        }
+        muxer.flush().await.unwrap();
+        let output = muxer.into_inner().into_inner();
+
+        let mut demuxer = TSDemuxer::new(Cursor::new(output));
+        demuxer.streams().await.unwrap();
+        for (i, (nal_type, _)) in frames.iter().enumerate() {
+            let packet = demuxer.read_packet().await.unwrap();
+            let dts = base + i as i64 * 40 * ms;
+            assert_eq!(packet.dts, Some(dts));
+            assert_eq!(packet.pts, Some(dts + 80 * ms));
+            assert_eq!(packet.is_key, *nal_type == 0x65);
+            assert_eq!(packet.duration, Some(Duration::from_millis(40)));
+            assert_eq!(
+                &packet.data[..],
+                &[0, 0, 0, 1, 0x09, 0xF0, 0, 0, 0, 1, *nal_type, 0x88]
+            );
+        }
+        assert!(demuxer.read_packet().await.is_err());
+    }
+
+    /// Muxes `count` 40 ms video frames of `size` bytes.
+    async fn mux_frames(count: usize, size: usize) -> Vec<u8> {
+        use crate::format::ts::TSMuxer;
+        use crate::format::Muxer as FormatMuxer;
+
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()));
+        muxer
+            .write_header(&[TestCodec::new(CodecType::H264).boxed()])
+            .await
+            .unwrap();
+        for i in 0..count {
+            let mut data = vec![0, 0, 0, 1, 0x09, 0xF0, 0, 0, 0, 1, 0x41];
+            data.resize(size, i as u8 + 1);
+            let packet = Packet::new(data).with_dts(i as i64 * 40_000_000);
+            muxer.write_packet(&packet).await.unwrap();
+        }
+        muxer.flush().await.unwrap();
+        muxer.into_inner().into_inner()
+    }
+
+    async fn demux_all(data: Vec<u8>) -> (Vec<Packet>, TSStatistics) {
+        let mut demuxer = TSDemuxer::new(Cursor::new(data));
+        demuxer.streams().await.unwrap();
+        let mut packets = Vec::new();
+        while let Ok(packet) = demuxer.read_packet().await {
+            packets.push(packet);
+        }
+        (packets, demuxer.statistics().clone())
+    }
+
+    #[tokio::test]
+    async fn test_ts_demuxer_resync() {
+        let mut data = mux_frames(10, 400).await;
+
+        // Garbage between two packets, and a corrupted sync byte further on
+        let garbage_at = 12 * TS_PACKET_SIZE;
+        data.splice(garbage_at..garbage_at, [0x00; 37]);
+        data[37 + 20 * TS_PACKET_SIZE] = 0x46;
+
+        let (packets, statistics) = demux_all(data).await;
+        assert_eq!(statistics.sync_losses, 2);
+        assert_eq!(statistics.skipped_bytes, 37 + TS_PACKET_SIZE as u64);
+        assert_eq!(statistics.continuity_errors, 1);
+
+        // Only the frame with the corrupted packet is lost
+        assert_eq!(packets.len(), 9);
+        let lost = packets.iter().position(|p| p.is_discontinuity).unwrap();
+        assert_eq!(
+            packets[lost - 1].dts.unwrap() + 80_000_000,
+            packets[lost].dts.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ts_demuxer_continuity_errors() {
+        let data = mux_frames(10, 400).await;
+        let pid = |p: &[u8]| ((p[1] as u16 & 0x1F) << 8) | p[2] as u16;
+
+        // Drop the second TS packet of the sixth frame
+        let mut frame = 0;
+        let mut packets: Vec<&[u8]> = Vec::new();
+        for packet in data.chunks(TS_PACKET_SIZE) {
+            if pid(packet) == 0x100 && packet[1] & 0x40 != 0 {
+                frame += 1;
+            }
+            if frame == 6 && pid(packet) == 0x100 && packet[1] & 0x40 == 0 {
+                frame += 1;
+                continue;
+            }
+            packets.push(packet);
+        }
+        // Duplicates of a packet are ignored
+        let duplicate = packets[3].to_vec();
+        packets.insert(3, &duplicate);
+
+        let (packets, statistics) = demux_all(packets.concat()).await;
+        assert_eq!(statistics.continuity_errors, 1);
+        assert_eq!(packets.len(), 9);
+        let flagged: Vec<i64> = packets
+            .iter()
+            .filter(|p| p.is_discontinuity)
+            .map(|p| p.dts.unwrap())
+            .collect();
+        assert_eq!(flagged, vec![6 * 40_000_000]);
+    }
+
+    #[tokio::test]
+    async fn test_ts_demuxer_packet_sizes() {
+        let data = mux_frames(10, 1000).await;
+        let (expected, _) = demux_all(data.clone()).await;
+        assert_eq!(expected.len(), 10);
+        assert!(expected.iter().all(|p| p.arrival_timestamp.is_none()));
+
+        // M2TS with an arrival timestamp of 1000 ticks per packet, after
+        // some garbage, and 204-byte packets with parity
+        let mut m2ts = vec![0x47, 0x47, 0x00, 0x12, 0x47];
+        let mut rs = Vec::new();
+        for (i, packet) in data.chunks(TS_PACKET_SIZE).enumerate() {
+            let arrival = 0xC000_0000 | (i as u32 * 1000);
+            m2ts.extend_from_slice(&arrival.to_be_bytes());
+            m2ts.extend_from_slice(packet);
+            rs.extend_from_slice(packet);
+            rs.extend_from_slice(&[0x47; RS_PACKET_SIZE - TS_PACKET_SIZE]);
+        }
+
+        for (input, size) in [(m2ts, M2TS_PACKET_SIZE), (rs, RS_PACKET_SIZE)] {
+            let mut demuxer = TSDemuxer::new(Cursor::new(input));
+            demuxer.streams().await.unwrap();
+            assert_eq!(demuxer.packet_size(), Some(size));
+            let mut packets = Vec::new();
+            while let Ok(packet) = demuxer.read_packet().await {
+                packets.push(packet);
+            }
+            assert_eq!(packets.len(), expected.len());
+            for (packet, expected) in packets.iter().zip(&expected) {
+                assert_eq!(packet.data, expected.data);
+                assert_eq!(packet.dts, expected.dts);
+            }
+
+            let arrivals: Vec<Option<u32>> = packets.iter().map(|p| p.arrival_timestamp).collect();
+            if size == M2TS_PACKET_SIZE {
+                assert_eq!(demuxer.statistics().skipped_bytes, 5);
+                // The copy permission bits are not part of the timestamp
+                assert!(arrivals.iter().all(|a| a.is_some_and(|a| a % 1000 == 0)));
+                assert!(arrivals.windows(2).all(|pair| pair[0] < pair[1]));
+            } else {
+                assert!(arrivals.iter().all(Option::is_none));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ts_demuxer_psi_crc() {
+        // A corrupted PAT is dropped and the next one is used
+        let mut data = create_pat_packet();
+        data[15] ^= 0x01;
+        let mut pat = create_pat_packet();
+        pat[3] = 0x11;
+        data.extend_from_slice(&pat);
+        data.extend_from_slice(&create_pmt_packet());
+
+        let mut demuxer = TSDemuxer::new(Cursor::new(data));
+        assert_eq!(demuxer.streams().await.unwrap().len(), 1);
+        assert_eq!(demuxer.statistics().crc_errors, 1);
+        assert_eq!(demuxer.statistics().continuity_errors, 0);
+    }
+
+    #[tokio::test]
+    async fn test_ts_demuxer_malformed_psi() {
+        // A PMT with a valid CRC whose ES_info_length runs past the section
+        // is dropped, and demuxing goes on with the next one
+        let mut data = create_pat_packet();
+        let mut pmt = create_pmt_packet();
+        pmt[21] = 0x20;
+        let crc = Crc32Mpeg2::new().calculate(&pmt[5..22]);
+        pmt[22..26].copy_from_slice(&crc.to_be_bytes());
+        data.extend_from_slice(&pmt);
+        let mut pmt = create_pmt_packet();
+        pmt[3] = 0x11;
+        data.extend_from_slice(&pmt);
+
+        let mut demuxer = TSDemuxer::new(Cursor::new(data));
+        assert_eq!(demuxer.streams().await.unwrap().len(), 1);
+        assert_eq!(demuxer.statistics().section_errors, 1);
+        assert_eq!(demuxer.statistics().crc_errors, 0);
+    }
+
+    #[test]
+    fn test_section_buffer() {
+        let section = |table_id: u8, len: usize| {
+            let mut section = vec![table_id, 0xB0, len as u8];
+            section.resize(3 + len, 0xAA);
+            section
+        };
+        let first = section(0x02, 200);
+        let second = section(0x02, 20);
+        let mut buffer = SectionBuffer::default();
+
+        // A section spanning two packets, followed by a second one
+        let mut payload = vec![0];
+        payload.extend_from_slice(&first[..150]);
+        assert!(buffer.push(&payload, true).is_empty());
+
+        let mut payload = first[150..].to_vec();
+        payload.extend_from_slice(&[0xFF; 10]);
+        assert_eq!(buffer.push(&payload, false), vec![first.clone()]);
+
+        // The pointer field skips the end of a section that was never started
+        let mut payload = vec![3, 0xAA, 0xAA, 0xAA];
+        payload.extend_from_slice(&second);
+        payload.extend_from_slice(&second);
+        payload.push(0xFF);
+        assert_eq!(buffer.push(&payload, true), vec![second.clone(), second]);
+        assert!(buffer.data.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ts_demuxer_codec_probing() {
+        use crate::format::ts::TSMuxer;
+        use crate::format::Muxer as FormatMuxer;
+
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()));
+        muxer
+            .write_header(&[
+                TestCodec::new(CodecType::H264).boxed(),
+                TestCodec::new(CodecType::AAC).boxed(),
+            ])
+            .await
+            .unwrap();
+
+        // Baseline 1280x720 SPS and a PPS, first sent with the second frame
+        let sps = [0x67, 66, 0xC0, 30, 0xDA, 0x01, 0x40, 0x16, 0xE4];
+        let pps = [0x68, 0xCE, 0x38, 0x80];
+        // AAC-LC, 48 kHz, stereo, 15-byte frame
+        let adts = [0xFF, 0xF1, 0x4C, 0x80, 0x01, 0xFF, 0xFC];
+
+        let ms = 1_000_000i64;
+        for i in 0..4i64 {
+            let mut video = vec![0, 0, 0, 1];
+            if i == 1 {
+                video.extend_from_slice(&sps);
+                video.extend_from_slice(&[0, 0, 0, 1]);
+                video.extend_from_slice(&pps);
+                video.extend_from_slice(&[0, 0, 0, 1, 0x65, 0x88]);
+            } else {
+                video.extend_from_slice(&[0x41, 0x9A]);
+            }
+            let packet = Packet::new(video)
+                .with_stream_index(0)
+                .with_dts(i * 40 * ms)
+                .with_key_flag(i == 1);
+            muxer.write_packet(&packet).await.unwrap();
+
+            let mut audio = adts.to_vec();
+            audio.resize(15, i as u8);
+            let packet = Packet::new(audio)
+                .with_stream_index(1)
+                .with_dts(i * 40 * ms);
+            muxer.write_packet(&packet).await.unwrap();
+        }
+        muxer.flush().await.unwrap();
+        let output = muxer.into_inner().into_inner();
+
+        let mut demuxer = TSDemuxer::new(Cursor::new(output));
+        let streams = demuxer.streams().await.unwrap();
+        assert_eq!(streams.len(), 2);
+
+        let video = &streams[0];
+        assert_eq!((video.width(), video.height()), (Some(1280), Some(720)));
+        assert_eq!((video.profile(), video.level()), (Some(66), Some(30)));
+        let mut extra_data = vec![0, 0, 0, 1];
+        extra_data.extend_from_slice(&sps);
+        extra_data.extend_from_slice(&[0, 0, 0, 1]);
+        extra_data.extend_from_slice(&pps);
+        assert_eq!(video.extra_data(), Some(&extra_data[..]));
+
+        let audio = &streams[1];
+        assert_eq!(audio.sample_rate(), Some(48000));
+        assert_eq!(audio.channels(), Some(2));
+        assert_eq!(audio.profile(), Some(2));
+        assert_eq!(audio.extra_data(), Some(&[0x11, 0x90][..]));
+
+        // The packets read while probing are replayed from the start
+        let mut video_dts = Vec::new();
+        let mut audio_count = 0;
+        while let Ok(packet) = demuxer.read_packet().await {
+            match packet.stream_index {
+                0 => video_dts.push(packet.dts.unwrap()),
+                _ => audio_count += 1,
+            }
+        }
+        assert_eq!(video_dts, vec![0, 40 * ms, 80 * ms, 120 * ms]);
+        assert_eq!(audio_count, 4);
+    }
+
+    #[tokio::test]
+    async fn test_ts_demuxer_program_filter() {
+        use crate::format::ts::TSMuxer;
+        use crate::format::Muxer as FormatMuxer;
+
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()))
+            .with_program(1, 0x1000, &[0])
+            .with_program(7, 0x1001, &[1]);
+        muxer
+            .write_header(&[
+                TestCodec::new(CodecType::H264).boxed(),
+                TestCodec::new(CodecType::AAC).boxed(),
+            ])
+            .await
+            .unwrap();
+        for i in 0..3i64 {
+            let packet = Packet::new(vec![0, 0, 0, 1, 0x41, 0x9A])
+                .with_stream_index(0)
+                .with_dts(i * 40_000_000);
+            muxer.write_packet(&packet).await.unwrap();
+            let packet = Packet::new(vec![0xAA; 20])
+                .with_stream_index(1)
+                .with_dts(i * 40_000_000);
+            muxer.write_packet(&packet).await.unwrap();
+        }
+        muxer.flush().await.unwrap();
+        let output = muxer.into_inner().into_inner();
+
+        // Program 7 alone, its stream indices start at 0
+        let mut demuxer = TSDemuxer::new(Cursor::new(output)).with_program_filter(&[7]);
+        let streams = demuxer.streams().await.unwrap();
+        assert_eq!(streams.len(), 1);
+        assert_eq!(streams[0].codec_type(), CodecType::AAC);
+        let programs = demuxer.programs();
+        assert_eq!(programs.len(), 1);
+        assert_eq!(programs[0].program_number, 7);
+        assert_eq!(programs[0].stream_indices, vec![0]);
+
+        let mut packets = Vec::new();
+        while let Ok(packet) = demuxer.read_packet().await {
+            packets.push(packet);
+        }
+        assert_eq!(packets.len(), 3);
+        assert!(packets
+            .iter()
+            .all(|p| p.stream_index == 0 && p.data[..] == [0xAA; 20]));
+    }
+
+    #[tokio::test]
+    async fn test_ts_demuxer_multi_packet_pat() {
+        use crate::format::ts::TSMuxer;
+        use crate::format::Muxer as FormatMuxer;
+
+        // 60 programs of one audio stream each, too many for one TS packet
+        let streams: Vec<_> = (0..60)
+            .map(|_| TestCodec::new(CodecType::AAC).boxed())
+            .collect();
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()));
+        for i in 0..60u16 {
+            muxer = muxer.with_program(i + 1, 0x1000 + i, &[i as usize]);
+        }
+        muxer.write_header(&streams).await.unwrap();
+        for i in 0..60 {
+            let packet = Packet::new(vec![0xAA; 20]).with_stream_index(i).with_dts(0);
+            muxer.write_packet(&packet).await.unwrap();
+        }
+        muxer.flush().await.unwrap();
+        let output = muxer.into_inner().into_inner();
+        assert_eq!(output.len() % TS_PACKET_SIZE, 0);
+        assert!(output
+            .chunks(TS_PACKET_SIZE)
+            .all(|packet| packet[0] == 0x47));
+
+        let mut demuxer = TSDemuxer::new(Cursor::new(output));
+        assert_eq!(demuxer.streams().await.unwrap().len(), 60);
+        let programs = demuxer.programs();
+        assert_eq!(programs.len(), 60);
+        assert_eq!(programs[59].program_number, 60);
+        let mut count = 0;
+        while demuxer.read_packet().await.is_ok() {
+            count += 1;
+        }
+        assert_eq!(count, 60);
+
+        // A PAT longer than a section can hold is refused
+        let streams: Vec<_> = (0..300)
+            .map(|_| TestCodec::new(CodecType::AAC).boxed())
+            .collect();
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()));
+        for i in 0..300u16 {
+            muxer = muxer.with_program(i + 1, 0x1000 + i, &[i as usize]);
+        }
+        let error = muxer.write_header(&streams).await.unwrap_err();
+        assert!(error.to_string().contains("PAT"));
+    }
+
+    #[tokio::test]
+    async fn test_ts_demuxer_audio_video_and_private_streams() {
+        use crate::format::ts::TSMuxer;
+        use crate::format::Muxer as FormatMuxer;
+
+        // A registration descriptor on a user private stream type
+        let private_info = vec![0x90, 0x05, 4, b'T', b'E', b'S', b'T'];
+        let streams = vec![
+            TestCodec::new(CodecType::MPEG2Video).boxed(),
+            TestCodec::new(CodecType::MPEGAudio).boxed(),
+            TestCodec::new(CodecType::AC3).boxed(),
+            TestCodec::new(CodecType::EAC3).boxed(),
+            TestCodec::new(CodecType::OPUS).with_channels(2).boxed(),
+            TestCodec::new(CodecType::PrivateData)
+                .with_extra_data(&private_info)
+                .boxed(),
+        ];
+
+        let with_padding = |header: &[u8], len: usize| {
+            let mut data = header.to_vec();
+            data.resize(len, 0x55);
+            data
+        };
+        let mut mpeg2 = vec![
+            0, 0, 1, 0xB3, 0x2D, 0x01, 0xE0, 0x24, 0xFF, 0xFF, 0xE0, 0x18,
+        ];
+        mpeg2.extend_from_slice(&[0, 0, 1, 0x00, 0x00, 0x0F, 0xFF, 0xF8, 0, 0, 1, 0x01]);
+        let frames = [
+            with_padding(&mpeg2, 3000),
+            with_padding(&[0xFF, 0xFB, 0x90, 0x64], 417),
+            with_padding(&[0x0B, 0x77, 0x00, 0x00, 0x04, 0x40, 0xE1, 0x40], 768),
+            with_padding(&[0x0B, 0x77, 0x00, 0xFF, 0x74, 0x80, 0x00, 0x00], 512),
+            with_padding(&[0xF8], 160),
+            with_padding(&[0x12, 0x34], 100),
+        ];
+
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()));
+        muxer.write_header(&streams).await.unwrap();
+        let ms = 1_000_000i64;
+        for i in 0..4 {
+            for (index, frame) in frames.iter().enumerate() {
+                let packet = Packet::new(frame.clone())
+                    .with_stream_index(index)
+                    .with_pts((1000 + i * 20) * ms);
+                muxer.write_packet(&packet).await.unwrap();
+            }
+        }
+        muxer.flush().await.unwrap();
+        let output = muxer.into_inner().into_inner();
+
+        let mut demuxer = TSDemuxer::new(Cursor::new(output));
+        let configs = demuxer.streams().await.unwrap();
+        let types: Vec<CodecType> = configs.iter().map(|c| c.codec_type()).collect();
+        assert_eq!(
+            types,
+            streams.iter().map(|c| c.codec_type()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            (configs[0].width(), configs[0].height()),
+            (Some(720), Some(480))
+        );
+        assert_eq!(
+            (configs[1].sample_rate(), configs[1].channels()),
+            (Some(44100), Some(2))
+        );
+        assert_eq!(
+            (configs[2].sample_rate(), configs[2].channels()),
+            (Some(48000), Some(6))
+        );
+        assert_eq!(
+            (configs[3].sample_rate(), configs[3].channels()),
+            (Some(44100), Some(2))
+        );
+        assert_eq!(
+            (configs[4].sample_rate(), configs[4].channels()),
+            (Some(48000), Some(2))
+        );
+        assert_eq!(configs[5].extra_data(), Some(&private_info[..]));
+
+        let mut packets = Vec::new();
+        while let Ok(packet) = demuxer.read_packet().await {
+            packets.push(packet);
+        }
+        for (index, frame) in frames.iter().enumerate() {
+            let stream: Vec<&Packet> = packets.iter().filter(|p| p.stream_index == index).collect();
+            assert_eq!(stream.len(), 4, "stream {}", index);
+            // Opus loses its control header, nothing else changes
+            assert!(
+                stream.iter().all(|p| p.data == frame[..]),
+                "stream {}",
+                index
+            );
+        }
+        // Intra pictures are keyframes
+        assert!(packets
+            .iter()
+            .filter(|p| p.stream_index == 0)
+            .all(|p| p.is_key));
+    }
+
+    #[tokio::test]
+    async fn test_ts_demuxer_subtitles_and_captions() {
+        use crate::format::ts::TSMuxer;
+        use crate::format::Muxer as FormatMuxer;
+
+        let teletext_page = vec![b'f', b'r', b'a', 0x12, 0x88];
+        let streams = vec![
+            TestCodec::new(CodecType::H264).boxed(),
+            TestCodec::new(CodecType::DVBSubtitle)
+                .with_language("deu")
+                .boxed(),
+            TestCodec::new(CodecType::Teletext)
+                .with_extra_data(&teletext_page)
+                .boxed(),
+            TestCodec::new(CodecType::AC3).with_language("eng").boxed(),
+        ];
+
+        // Two CEA-608 byte pairs per frame in a GA94 SEI
+        let cc_data = |i: u8| vec![0xFC, 0x94, i + 1, 0xFD, 0x80, 0x80];
+        let sei = |i: u8| {
+            let mut nal = vec![0, 0, 0, 1, 0x06, 0x04, 17, 0xB5, 0x00, 0x31];
+            nal.extend_from_slice(b"GA94");
+            nal.extend_from_slice(&[0x03, 0x42, 0xFF]);
+            nal.extend_from_slice(&cc_data(i));
+            nal.extend_from_slice(&[0xFF, 0x80]);
+            nal
+        };
+        let sps = [0x67, 66, 0xC0, 30, 0xDA, 0x01, 0x40, 0x16, 0xE4];
+        let pps = [0x68, 0xCE, 0x38, 0x80];
+        let subtitle = vec![
+            0x20, 0x00, 0x0F, 0x10, 0x00, 0x01, 0x00, 0x02, 0x55, 0x66, 0xFF,
+        ];
+        // data_identifier and two EBU teletext data units
+        let mut teletext = vec![0x10];
+        for _ in 0..2 {
+            teletext.extend_from_slice(&[0x03, 0x2C]);
+            teletext.extend_from_slice(&[0x55; 44]);
+        }
+        let ac3 = {
+            let mut frame = vec![0x0B, 0x77, 0x00, 0x00, 0x04, 0x40, 0xE1, 0x40];
+            frame.resize(768, 0x55);
+            frame
+        };
+
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()));
+        muxer.write_header(&streams).await.unwrap();
+        let ms = 1_000_000i64;
+        for i in 0..4u8 {
+            let pts = (1000 + i as i64 * 40) * ms;
+            let mut video = Vec::new();
+            if i == 0 {
+                for nal in [&sps[..], &pps[..]] {
+                    video.extend_from_slice(&[0, 0, 0, 1]);
+                    video.extend_from_slice(nal);
+                }
+            }
+            video.extend_from_slice(&sei(i));
+            video.extend_from_slice(&[0, 0, 0, 1, if i == 0 { 0x65 } else { 0x41 }, 0x88]);
+            let frames = [video, subtitle.clone(), teletext.clone(), ac3.clone()];
+            for (index, frame) in frames.into_iter().enumerate() {
+                let packet = Packet::new(frame)
+                    .with_stream_index(index)
+                    .with_pts(pts)
+                    .with_key_flag(i == 0);
+                muxer.write_packet(&packet).await.unwrap();
+            }
+        }
+        muxer.flush().await.unwrap();
+        let output = muxer.into_inner().into_inner();
+
+        // Teletext PES packets fill whole TS packets, with a 45-byte header
+        let teletext_pes: Vec<&[u8]> = output
+            .chunks(TS_PACKET_SIZE)
+            .filter(|packet| packet[1] & 0x40 != 0 && packet[2] == 0x02 && packet[1] & 0x1F == 1)
+            .collect();
+        assert_eq!(teletext_pes.len(), 4);
+        for packet in teletext_pes {
+            assert_eq!(packet[3] & 0x30, 0x10, "no adaptation field");
+            assert_eq!(u16::from_be_bytes([packet[8], packet[9]]), 184 - 6);
+            assert_eq!(packet[12], TELETEXT_PES_HEADER_DATA_LENGTH);
+            assert_eq!(packet[4 + 45], 0x10);
+        }
+
+        let mut demuxer = TSDemuxer::new(Cursor::new(output.clone()));
+        let configs = demuxer.streams().await.unwrap();
+        assert_eq!(configs.len(), 4);
+
+        let mut demuxer = TSDemuxer::new(Cursor::new(output)).with_closed_captions();
+        let configs = demuxer.streams().await.unwrap();
+        let types: Vec<CodecType> = configs.iter().map(|c| c.codec_type()).collect();
+        assert_eq!(
+            types,
+            [
+                CodecType::H264,
+                CodecType::ClosedCaptions,
+                CodecType::DVBSubtitle,
+                CodecType::Teletext,
+                CodecType::AC3,
+            ]
+        );
+        assert_eq!(demuxer.programs()[0].stream_indices, [0, 1, 2, 3, 4]);
+        let languages: Vec<Option<&str>> = configs.iter().map(|c| c.language()).collect();
+        assert_eq!(
+            languages,
+            [None, None, Some("deu"), Some("fra"), Some("eng")]
+        );
+        assert_eq!(
+            configs[2].extra_data(),
+            Some(&Descriptor::dvb_subtitling(*b"deu").data[..])
+        );
+        assert_eq!(configs[3].extra_data(), Some(&teletext_page[..]));
+
+        let mut packets = Vec::new();
+        while let Ok(packet) = demuxer.read_packet().await {
+            packets.push(packet);
+        }
+        let stream = |index| -> Vec<&Packet> {
+            packets.iter().filter(|p| p.stream_index == index).collect()
+        };
+        let captions = stream(1);
+        assert_eq!(captions.len(), 4);
+        for (i, (captions, video)) in captions.iter().zip(stream(0)).enumerate() {
+            assert_eq!(captions.data, cc_data(i as u8)[..]);
+            assert_eq!(captions.pts, video.pts);
+        }
+        assert!(stream(2).iter().all(|p| p.data == subtitle[..]));
+        // Stuffing data units complete the teletext PES packets
+        let teletext_packets = stream(3);
+        assert_eq!(teletext_packets.len(), 4);
+        for packet in teletext_packets {
+            assert_eq!(packet.data.len(), 184 - 45);
+            assert_eq!(packet.data[..teletext.len()], teletext[..]);
+            assert_eq!(
+                packet.data[teletext.len()..teletext.len() + 2],
+                [0xFF, 0x2C]
+            );
+        }
+        assert_eq!(stream(4).len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_ts_demuxer_multi_packet_pmt() {
+        use crate::format::ts::TSMuxer;
+        use crate::format::Muxer as FormatMuxer;
+
+        let audio = TestCodec::new(CodecType::AC3).with_language("eng");
+        let mut streams = vec![TestCodec::new(CodecType::H264).boxed()];
+        streams.extend((0..12).map(|_| audio.clone().boxed()));
+
+        let sps = [0x67, 66, 0xC0, 30, 0xDA, 0x01, 0x40, 0x16, 0xE4];
+        let pps = [0x68, 0xCE, 0x38, 0x80];
+        let mut video = Vec::new();
+        for nal in [&sps[..], &pps[..], &[0x65, 0x88][..]] {
+            video.extend_from_slice(&[0, 0, 0, 1]);
+            video.extend_from_slice(nal);
+        }
+        let mut ac3 = vec![0x0B, 0x77, 0x00, 0x00, 0x04, 0x40, 0xE1, 0x40];
+        ac3.resize(768, 0x55);
+
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()));
+        muxer.write_header(&streams).await.unwrap();
+        for index in 0..streams.len() {
+            let data = if index == 0 {
+                video.clone()
+            } else {
+                ac3.clone()
+            };
+            let packet = Packet::new(data)
+                .with_stream_index(index)
+                .with_pts(1_000_000_000)
+                .with_key_flag(true);
+            muxer.write_packet(&packet).await.unwrap();
+        }
+        muxer.flush().await.unwrap();
+        let output = muxer.into_inner().into_inner();
+
+        // The PMT continues in a second TS packet
+        assert_eq!(output.len() % TS_PACKET_SIZE, 0);
+        let pmt_packets: Vec<&[u8]> = output
+            .chunks(TS_PACKET_SIZE)
+            .filter(|packet| u16::from_be_bytes([packet[1] & 0x1F, packet[2]]) == PID_PMT)
+            .take(2)
+            .collect();
+        assert_ne!(pmt_packets[0][1] & 0x40, 0);
+        assert_eq!(pmt_packets[1][1] & 0x40, 0);
+        assert_eq!(pmt_packets[1][3] & 0x0F, 1);
+
+        let mut demuxer = TSDemuxer::new(Cursor::new(output));
+        let configs = demuxer.streams().await.unwrap();
+        assert_eq!(configs.len(), 13);
+        assert!(configs[1..]
+            .iter()
+            .all(|c| c.codec_type() == CodecType::AC3 && c.language() == Some("eng")));
+        let mut count = 0;
+        while demuxer.read_packet().await.is_ok() {
+            count += 1;
+        }
+        assert_eq!(count, 13);
+
+        // A PMT longer than a section can hold is refused
+        let streams: Vec<_> = (0..100).map(|_| audio.clone().boxed()).collect();
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()));
+        assert!(muxer.write_header(&streams).await.is_err());
+    }
+
+    #[test]
+    fn test_stream_codec_type() {
+        let info = |stream_type, descriptors| ElementaryStreamInfo {
+            stream_type,
+            elementary_pid: 0x100,
+            descriptors,
+        };
+        let descriptor = |tag, data: &[u8]| Descriptor {
+            tag,
+            data: data.to_vec(),
+        };
+
+        // DVB signalling of AC-3, E-AC-3 and Opus in private data streams
+        let cases = [
+            (
+                info(0x06, vec![descriptor(DESCRIPTOR_AC3, &[0])]),
+                CodecType::AC3,
+            ),
+            (
+                info(0x06, vec![descriptor(DESCRIPTOR_ENHANCED_AC3, &[0])]),
+                CodecType::EAC3,
+            ),
+            (
+                info(0x06, vec![Descriptor::registration(FORMAT_IDENTIFIER_OPUS)]),
+                CodecType::OPUS,
+            ),
+            (
+                info(0x06, vec![Descriptor::dvb_subtitling(*b"eng")]),
+                CodecType::DVBSubtitle,
+            ),
+            (
+                info(0x06, vec![Descriptor::teletext_subtitles(*b"eng")]),
+                CodecType::Teletext,
+            ),
+            (info(0x06, Vec::new()), CodecType::PrivateData),
+            (info(0x04, Vec::new()), CodecType::MPEGAudio),
+            (info(0x01, Vec::new()), CodecType::MPEG2Video),
+            (info(0xEA, Vec::new()), CodecType::PrivateData),
+        ];
+        for (info, codec_type) in cases {
+            assert_eq!(stream_codec_type(&info), codec_type);
+        }
+        assert_eq!(Descriptor::opus_audio(2).opus_channels(), Some(2));
+        assert_eq!(
+            descriptor(DESCRIPTOR_EXTENSION, &[0x80, 0x81]).opus_channels(),
+            None
+        );
+        assert_eq!(
+            Descriptor::iso_639_language(*b"SPA").language().as_deref(),
+            Some("spa")
+        );
+        assert_eq!(
+            descriptor(DESCRIPTOR_ISO_639_LANGUAGE, &[0; 4]).language(),
+            None
+        );
+        assert_eq!(Descriptor::registration(*b"engl").language(), None);
+    }
 }
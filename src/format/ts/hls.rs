@@ -1,4 +1,6 @@
+use super::scte35::SpliceInfoSection;
 use crate::error::{Result, VdkError};
+use chrono::{DateTime, SecondsFormat, Utc};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tokio::fs::File;
@@ -23,6 +25,57 @@ pub struct HLSVariant {
     pub codecs: String,
 }
 
+/// SCTE-35 signalling at the start of a segment.
+///
+/// A cue starting a break is written as `#EXT-X-DATERANGE` with the
+/// `SCTE35-OUT` attribute and as `#EXT-X-CUE-OUT`, a cue ending it as
+/// `#EXT-X-CUE-IN`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HLSCue {
+    /// `true` if the cue starts a break, `false` if it ends one
+    pub out: bool,
+    /// Splice or segmentation event ID
+    pub event_id: u32,
+    /// Planned duration of the break
+    pub duration: Option<Duration>,
+    /// The splice_info_section as transmitted
+    pub scte35: Vec<u8>,
+}
+
+impl HLSCue {
+    /// Creates the cue for a splice_info_section.
+    ///
+    /// # Arguments
+    ///
+    /// * `splice` - The parsed splice information
+    /// * `section` - The serialized section, for the `SCTE35-OUT` attribute
+    ///
+    /// # Returns
+    ///
+    /// The cue, or `None` if the section neither starts nor ends a break
+    pub fn from_splice(splice: &SpliceInfoSection, section: &[u8]) -> Option<Self> {
+        Some(Self {
+            out: splice.is_out()?,
+            event_id: splice.event_id()?,
+            duration: splice.duration(),
+            scte35: section.to_vec(),
+        })
+    }
+}
+
+/// Kind of an HLS media playlist (`#EXT-X-PLAYLIST-TYPE`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HLSPlaylistType {
+    /// Sliding window of the latest segments, without a playlist type
+    #[default]
+    Live,
+    /// Segments are only appended, so clients can seek back to the start
+    Event,
+    /// Recording that keeps all segments, written as an event playlist until
+    /// it ends, since VOD playlists must not change
+    VOD,
+}
+
 /// Represents a media segment in an HLS playlist.
 ///
 /// Each segment contains a portion of the media stream and has associated
@@ -37,6 +90,10 @@ pub struct HLSSegment {
     pub sequence_number: u32,
     /// Optional byte range for partial segments
     pub byte_range: Option<(u64, u64)>,
+    /// Wall-clock time of the first sample (`#EXT-X-PROGRAM-DATE-TIME`)
+    pub program_date_time: Option<DateTime<Utc>>,
+    /// SCTE-35 cues taking effect at the start of the segment
+    pub cues: Vec<HLSCue>,
 }
 
 /// Represents an HLS media playlist (*.m3u8).
@@ -55,6 +112,8 @@ pub struct HLSPlaylist {
     pub segments: Vec<HLSSegment>,
     /// Indicates if the playlist is complete
     pub is_endlist: bool,
+    /// Whether old segments are removed or kept
+    pub playlist_type: HLSPlaylistType,
     /// Optional variant information for master playlists
     pub variant: Option<HLSVariant>,
 }
@@ -72,6 +131,7 @@ impl HLSPlaylist {
             media_sequence: 0,
             segments: Vec::new(),
             is_endlist: false,
+            playlist_type: HLSPlaylistType::Live,
             variant: None,
         }
     }
@@ -144,8 +204,33 @@ impl HLSPlaylist {
             .write_all(format!("#EXT-X-MEDIA-SEQUENCE:{}\n", self.media_sequence).as_bytes())
             .await?;
 
+        match self.playlist_type {
+            HLSPlaylistType::Live => {}
+            HLSPlaylistType::VOD if self.is_endlist => {
+                writer.write_all(b"#EXT-X-PLAYLIST-TYPE:VOD\n").await?;
+            }
+            HLSPlaylistType::Event | HLSPlaylistType::VOD => {
+                writer.write_all(b"#EXT-X-PLAYLIST-TYPE:EVENT\n").await?;
+            }
+        }
+
         // Write segments
         for segment in &self.segments {
+            if let Some(date) = segment.program_date_time {
+                writer
+                    .write_all(
+                        format!(
+                            "#EXT-X-PROGRAM-DATE-TIME:{}\n",
+                            date.to_rfc3339_opts(SecondsFormat::Millis, true)
+                        )
+                        .as_bytes(),
+                    )
+                    .await?;
+            }
+            for cue in &segment.cues {
+                writer.write_all(cue_tags(cue, segment).as_bytes()).await?;
+            }
+
             writer
                 .write_all(format!("#EXTINF:{:.3},\n", segment.duration.as_secs_f64()).as_bytes())
                 .await?;
@@ -169,6 +254,36 @@ impl HLSPlaylist {
     }
 }
 
+/// Formats the playlist tags of an SCTE-35 cue at the start of `segment`.
+fn cue_tags(cue: &HLSCue, segment: &HLSSegment) -> String {
+    if !cue.out {
+        return "#EXT-X-CUE-IN\n".to_string();
+    }
+
+    let mut tags = String::new();
+    // DATERANGE requires a START-DATE
+    if let Some(date) = segment.program_date_time {
+        tags.push_str(&format!(
+            "#EXT-X-DATERANGE:ID=\"splice-{}\",START-DATE=\"{}\"",
+            cue.event_id,
+            date.to_rfc3339_opts(SecondsFormat::Millis, true)
+        ));
+        if let Some(duration) = cue.duration {
+            tags.push_str(&format!(",PLANNED-DURATION={:.3}", duration.as_secs_f64()));
+        }
+        tags.push_str(",SCTE35-OUT=0x");
+        for byte in &cue.scte35 {
+            tags.push_str(&format!("{:02X}", byte));
+        }
+        tags.push('\n');
+    }
+    match cue.duration {
+        Some(duration) => tags.push_str(&format!("#EXT-X-CUE-OUT:{:.3}\n", duration.as_secs_f64())),
+        None => tags.push_str("#EXT-X-CUE-OUT\n"),
+    }
+    tags
+}
+
 /// Represents an HLS master playlist containing multiple variant streams.
 ///
 /// The master playlist allows clients to choose the most appropriate quality
@@ -259,6 +374,12 @@ pub struct HLSSegmenter {
     current_segment: Option<(PathBuf, Duration, u64)>,
     /// Current variant stream configuration
     variant: Option<HLSVariant>,
+    /// SCTE-35 cues for the current segment
+    cues: Vec<HLSCue>,
+    /// Configured wall-clock time of the first segment
+    start_date: Option<DateTime<Utc>>,
+    /// Media time and wall-clock time of the same instant, once known
+    date_anchor: Option<(Duration, DateTime<Utc>)>,
 }
 
 impl HLSSegmenter {
@@ -277,6 +398,9 @@ impl HLSSegmenter {
             master_playlist: HLSMasterPlaylist::new(),
             current_segment: None,
             variant: None,
+            cues: Vec::new(),
+            start_date: None,
+            date_anchor: None,
         }
     }
 
@@ -291,7 +415,7 @@ impl HLSSegmenter {
         self
     }
 
-    /// Sets the maximum number of segments to keep in the playlist.
+    /// Sets the maximum number of segments to keep in a live playlist.
     ///
     /// # Arguments
     ///
@@ -301,6 +425,17 @@ impl HLSSegmenter {
         self
     }
 
+    /// Sets the playlist type. Event and VOD playlists keep all segments.
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_type` - The playlist type, [`HLSPlaylistType::Live`] by
+    ///   default
+    pub fn with_playlist_type(mut self, playlist_type: HLSPlaylistType) -> Self {
+        self.playlist.playlist_type = playlist_type;
+        self
+    }
+
     /// Adds a variant stream configuration.
     ///
     /// # Arguments
@@ -313,6 +448,48 @@ impl HLSSegmenter {
         self
     }
 
+    /// Sets all variants of the master playlist, for variants written to the
+    /// same directory by separate segmenters.
+    ///
+    /// # Arguments
+    ///
+    /// * `variants` - The variant streams, including this segmenter's own
+    pub fn with_master_variants(mut self, variants: Vec<HLSVariant>) -> Self {
+        self.master_playlist.variants = variants;
+        self
+    }
+
+    /// Sets the wall-clock time of the first segment.
+    ///
+    /// Segments then carry `#EXT-X-PROGRAM-DATE-TIME`. Without it, the
+    /// system clock at the first SCTE-35 cue is used, since
+    /// `#EXT-X-DATERANGE` requires program date times.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - Wall-clock time of the start of the first segment
+    pub fn with_program_date_time(mut self, date: DateTime<Utc>) -> Self {
+        self.start_date = Some(date);
+        self
+    }
+
+    /// Attaches an SCTE-35 cue to the start of the current segment.
+    ///
+    /// The segment should start at the splice point, see
+    /// [`TSMuxer::write_splice`](super::TSMuxer::write_splice).
+    ///
+    /// # Arguments
+    ///
+    /// * `cue` - The cue to signal in the playlist
+    pub fn add_cue(&mut self, cue: HLSCue) {
+        if self.date_anchor.is_none() {
+            if let Some((_, start_time, _)) = &self.current_segment {
+                self.date_anchor = Some((*start_time, Utc::now()));
+            }
+        }
+        self.cues.push(cue);
+    }
+
     /// Starts a new segment at the specified timestamp.
     ///
     /// # Arguments
@@ -333,6 +510,10 @@ impl HLSSegmenter {
 
         let file = File::create(&path).await?;
         self.current_segment = Some((path, timestamp, 0));
+        self.cues.clear();
+        if self.date_anchor.is_none() {
+            self.date_anchor = self.start_date.map(|date| (timestamp, date));
+        }
         Ok(file)
     }
 
@@ -343,24 +524,35 @@ impl HLSSegmenter {
     /// * `end_timestamp` - The ending timestamp for the segment
     pub async fn finish_segment(&mut self, end_timestamp: Duration) -> Result<()> {
         if let Some((path, start_time, _bytes_written)) = self.current_segment.take() {
-            let duration = end_timestamp - start_time;
+            let duration = end_timestamp.saturating_sub(start_time);
             let filename = path
                 .file_name()
                 .and_then(|n| n.to_str())
                 .ok_or_else(|| VdkError::InvalidData("Invalid segment filename".into()))?
                 .to_string();
 
+            let program_date_time = self.date_anchor.map(|(anchor, date)| {
+                if start_time >= anchor {
+                    date + (start_time - anchor)
+                } else {
+                    date - (anchor - start_time)
+                }
+            });
             let segment = HLSSegment {
                 filename,
                 duration,
                 sequence_number: self.sequence_number,
                 byte_range: None,
+                program_date_time,
+                cues: std::mem::take(&mut self.cues),
             };
 
             self.playlist.segments.push(segment);
 
-            // Remove old segments if we exceed max_segments
-            while self.playlist.segments.len() > self.max_segments {
+            // Remove old segments of live playlists if we exceed max_segments
+            while self.playlist.playlist_type == HLSPlaylistType::Live
+                && self.playlist.segments.len() > self.max_segments
+            {
                 if let Some(old_segment) = self.playlist.segments.first() {
                     let old_path = self.output_dir.join(&old_segment.filename);
                     tokio::fs::remove_file(old_path).await?;
@@ -375,6 +567,51 @@ impl HLSSegmenter {
         Ok(())
     }
 
+    /// Marks the media playlist as complete (`#EXT-X-ENDLIST`).
+    pub fn end_playlist(&mut self) {
+        self.playlist.is_endlist = true;
+    }
+
+    /// Returns the file name of the media playlist.
+    ///
+    /// This is `<variant>.m3u8` when a variant is configured, matching the
+    /// master playlist entry, and `playlist.m3u8` otherwise.
+    pub fn playlist_filename(&self) -> String {
+        match &self.variant {
+            Some(variant) => format!("{}.m3u8", variant.name),
+            None => "playlist.m3u8".to_string(),
+        }
+    }
+
+    /// Rewrites the playlist files in the output directory.
+    ///
+    /// The media playlist is written to a temporary file and renamed over the
+    /// old one, so clients polling a live stream never read a partial
+    /// playlist. With a variant configured, `master.m3u8` is written the same
+    /// way.
+    pub async fn write_playlist_files(&self) -> Result<()> {
+        let mut playlist = Vec::new();
+        self.write_playlist(&mut playlist).await?;
+        self.replace_file(&self.playlist_filename(), &playlist)
+            .await?;
+
+        if self.variant.is_some() {
+            let mut master = Vec::new();
+            self.write_master_playlist(&mut master).await?;
+            self.replace_file("master.m3u8", &master).await?;
+        }
+        Ok(())
+    }
+
+    /// Atomically replaces a file in the output directory.
+    async fn replace_file(&self, filename: &str, content: &[u8]) -> Result<()> {
+        let path = self.output_dir.join(filename);
+        let temp_path = self.output_dir.join(format!(".{}.tmp", filename));
+        tokio::fs::write(&temp_path, content).await?;
+        tokio::fs::rename(&temp_path, &path).await?;
+        Ok(())
+    }
+
     /// Writes the current media playlist to the provided writer.
     pub async fn write_playlist<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> Result<()> {
         self.playlist.write_to(writer).await
@@ -392,7 +629,11 @@ impl HLSSegmenter {
     /// * `current_time` - The current timestamp to check against
     pub fn should_start_new_segment(&self, current_time: Duration) -> bool {
         if let Some((_, start_time, _)) = &self.current_segment {
-            current_time - *start_time >= self.segment_duration
+            // Time going backwards is a discontinuity and starts a new segment
+            match current_time.checked_sub(*start_time) {
+                Some(elapsed) => elapsed >= self.segment_duration,
+                None => true,
+            }
         } else {
             true
         }
@@ -495,4 +736,59 @@ mod tests {
             assert!(segmenter.master_playlist.variants.len() == 1);
         });
     }
+
+    #[test]
+    fn test_segmenter_playlist_types() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let dir = std::env::temp_dir().join(format!("vdkio_hls_vod_{}", std::process::id()));
+            tokio::fs::create_dir_all(&dir).await.unwrap();
+            let variant = HLSVariant {
+                name: "high".to_string(),
+                bandwidth: 2_000_000,
+                resolution: None,
+                codecs: "avc1.64001f".to_string(),
+            };
+            let mut segmenter = HLSSegmenter::new(&dir)
+                .with_max_segments(2)
+                .with_playlist_type(HLSPlaylistType::VOD)
+                .with_variant(variant);
+
+            for i in 0..3 {
+                let start_time = Duration::from_secs(i * 2);
+                let _file = segmenter.start_segment(start_time).await.unwrap();
+                segmenter
+                    .finish_segment(start_time + Duration::from_secs(2))
+                    .await
+                    .unwrap();
+                segmenter.write_playlist_files().await.unwrap();
+            }
+
+            // All segments are kept, and the playlist only grows until it ends
+            let playlist = tokio::fs::read_to_string(dir.join("high.m3u8"))
+                .await
+                .unwrap();
+            assert_eq!(playlist.matches("#EXTINF:").count(), 3);
+            assert!(playlist.contains("#EXT-X-MEDIA-SEQUENCE:0\n"));
+            assert!(playlist.contains("#EXT-X-PLAYLIST-TYPE:EVENT\n"));
+            assert!(!playlist.contains("#EXT-X-ENDLIST"));
+            assert!(dir.join("high_0.ts").exists());
+
+            segmenter.end_playlist();
+            segmenter.write_playlist_files().await.unwrap();
+            let playlist = tokio::fs::read_to_string(dir.join("high.m3u8"))
+                .await
+                .unwrap();
+            assert!(playlist.contains("#EXT-X-PLAYLIST-TYPE:VOD\n"));
+            assert!(playlist.ends_with("high_2.ts\n#EXT-X-ENDLIST\n"));
+
+            // The master playlist is a separate file
+            let master = tokio::fs::read_to_string(dir.join("master.m3u8"))
+                .await
+                .unwrap();
+            assert!(master.ends_with("high.m3u8\n"));
+            assert!(!master.contains("#EXTINF"));
+            tokio::fs::remove_dir_all(&dir).await.unwrap();
+        });
+    }
 }
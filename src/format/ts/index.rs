@@ -0,0 +1,183 @@
+use super::demuxer::TSDemuxer;
+use crate::av::CodecType;
+use crate::error::{Result, VdkError};
+use crate::format::Demuxer as FormatDemuxer;
+use std::time::Duration;
+use tokio::io::AsyncRead;
+
+/// Minimum spacing of the entries of inputs without video, in nanoseconds
+const AUDIO_ENTRY_INTERVAL: i64 = 500_000_000;
+
+/// A random access point of a Transport Stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TSIndexEntry {
+    /// Input offset of the TS packet the keyframe's PES packet starts in
+    pub position: u64,
+    /// Presentation time of the keyframe in nanoseconds
+    pub pts: i64,
+    /// Decoding time of the keyframe in nanoseconds
+    pub dts: i64,
+}
+
+/// Random access points of a Transport Stream file.
+///
+/// The entries are the keyframes of the first video stream. Inputs without
+/// video get an entry every 500 ms on their first audio stream instead.
+/// Times are in nanoseconds on the timeline of [`Packet::pts`], as returned
+/// by [`TSDemuxer`].
+///
+/// [`Packet::pts`]: crate::av::Packet::pts
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TSIndex {
+    /// Stream whose access units are indexed
+    stream_index: Option<usize>,
+    /// Random access points in input order
+    entries: Vec<TSIndexEntry>,
+    /// Earliest presentation time of any packet
+    start_time: Option<i64>,
+    /// Latest presentation end time of any packet
+    end_time: Option<i64>,
+}
+
+impl TSIndex {
+    /// Builds the index of a Transport Stream by demuxing all of it.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The Transport Stream, read from its start
+    ///
+    /// # Returns
+    ///
+    /// The index, or an error if the input has no PAT/PMT or fails to read
+    pub async fn build<R: AsyncRead + Unpin + Send>(reader: R) -> Result<Self> {
+        Self::scan(&mut TSDemuxer::new(reader)).await
+    }
+
+    /// Builds the index from the packets of a demuxer, up to the end of its
+    /// input.
+    pub(super) async fn scan<R: AsyncRead + Unpin + Send>(
+        demuxer: &mut TSDemuxer<R>,
+    ) -> Result<Self> {
+        let streams = demuxer.streams().await?;
+        let find = |types: &[CodecType]| {
+            streams
+                .iter()
+                .position(|stream| types.contains(&stream.codec_type()))
+        };
+        let has_video = find(&[CodecType::H264, CodecType::H265, CodecType::MPEG2Video]);
+        let mut index = Self {
+            stream_index: has_video.or(find(&[
+                CodecType::AAC,
+                CodecType::OPUS,
+                CodecType::AC3,
+                CodecType::EAC3,
+                CodecType::MPEGAudio,
+            ])),
+            ..Default::default()
+        };
+
+        loop {
+            let packet = match demuxer.read_packet().await {
+                Ok(packet) => packet,
+                Err(VdkError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            };
+            let Some(pts) = packet.pts else {
+                continue;
+            };
+            index.start_time = Some(index.start_time.map_or(pts, |start| start.min(pts)));
+            let end = pts + packet.duration.map_or(0, |d| d.as_nanos() as i64);
+            index.end_time = Some(index.end_time.map_or(end, |last| last.max(end)));
+
+            if Some(packet.stream_index) != index.stream_index || !packet.is_key {
+                continue;
+            }
+            let Some(position) = packet.position else {
+                continue;
+            };
+            let due = match index.entries.last() {
+                Some(last) => has_video.is_some() || pts - last.pts >= AUDIO_ENTRY_INTERVAL,
+                None => true,
+            };
+            if due {
+                index.entries.push(TSIndexEntry {
+                    position,
+                    pts,
+                    dts: packet.dts.unwrap_or(pts),
+                });
+            }
+        }
+        Ok(index)
+    }
+
+    /// Returns the stream whose keyframes are indexed.
+    pub fn stream_index(&self) -> Option<usize> {
+        self.stream_index
+    }
+
+    /// Returns the random access points in input order.
+    pub fn entries(&self) -> &[TSIndexEntry] {
+        &self.entries
+    }
+
+    /// Returns the earliest presentation time of the input.
+    pub fn start_time(&self) -> Option<Duration> {
+        self.start_time
+            .map(|start| Duration::from_nanos(start.max(0) as u64))
+    }
+
+    /// Returns the time from the earliest presentation time to the end of
+    /// the last packet.
+    pub fn duration(&self) -> Option<Duration> {
+        let (start, end) = (self.start_time?, self.end_time?);
+        Some(Duration::from_nanos((end - start).max(0) as u64))
+    }
+
+    /// Returns the last random access point presented at or before `time`.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - Presentation time on the timeline of the packets
+    ///
+    /// # Returns
+    ///
+    /// The entry, the first one for times before it, or `None` if the index
+    /// is empty
+    pub fn keyframe_at(&self, time: Duration) -> Option<&TSIndexEntry> {
+        let time = time.as_nanos() as i64;
+        self.entries
+            .iter()
+            .filter(|entry| entry.pts <= time)
+            .max_by_key(|entry| entry.pts)
+            .or_else(|| self.entries.first())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyframe_at() {
+        let entry = |position, seconds: i64| TSIndexEntry {
+            position,
+            pts: seconds * 1_000_000_000,
+            dts: seconds * 1_000_000_000 - 40_000_000,
+        };
+        let index = TSIndex {
+            stream_index: Some(0),
+            entries: vec![entry(0, 10), entry(18800, 12), entry(37600, 14)],
+            start_time: Some(9_960_000_000),
+            end_time: Some(16_000_000_000),
+        };
+
+        let at = |seconds: f64| index.keyframe_at(Duration::from_secs_f64(seconds));
+        assert_eq!(at(5.0), Some(&index.entries[0]));
+        assert_eq!(at(12.0), Some(&index.entries[1]));
+        assert_eq!(at(13.9), Some(&index.entries[1]));
+        assert_eq!(at(20.0), Some(&index.entries[2]));
+        assert_eq!(index.start_time(), Some(Duration::from_millis(9960)));
+        assert_eq!(index.duration(), Some(Duration::from_millis(6040)));
+        assert_eq!(TSIndex::default().keyframe_at(Duration::ZERO), None);
+    }
+}
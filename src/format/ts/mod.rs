@@ -4,9 +4,19 @@
 //! including support for:
 //!
 //! - TS packet parsing and generation
+//! - M2TS (192-byte) packets with arrival timestamps, and 204-byte packets
 //! - Program Specific Information (PSI) tables
+//! - DVB Service Information (SI) tables
 //! - Packetized Elementary Stream (PES) handling
+//! - H.264, H.265, MPEG-2 video, AAC, MPEG audio, AC-3, E-AC-3 and Opus,
+//!   with other streams passed through as private data
+//! - DVB subtitle and teletext passthrough with ISO 639 languages, and
+//!   CEA-608/708 closed captions extracted from H.264/H.265 SEI
+//! - ID3 timed metadata streams
 //! - HLS segmentation and playlist generation
+//! - Keyframe indexing, seeking and lossless trimming of TS files
+//! - ETSI TR 101 290 stream analysis
+//! - Streaming over UDP and RTP, unicast and multicast
 //!
 //! ## Core Features
 //!
@@ -62,12 +72,21 @@
 //! # }
 //! ```
 
+/// ETSI TR 101 290 stream analysis
+pub mod analyzer;
+
+/// H.264/H.265 bitstream preparation for muxing
+pub mod bitstream;
+
 /// TS demuxer implementation for extracting elementary streams
 pub mod demuxer;
 
 /// HLS-specific functionality including segmentation and playlist generation
 pub mod hls;
 
+/// Keyframe index of TS files, for seeking
+pub mod index;
+
 /// TS muxer implementation for creating MPEG-TS packets
 pub mod muxer;
 
@@ -77,14 +96,35 @@ pub mod parser;
 /// PES packet handling and management
 pub mod pes;
 
+/// SCTE-35 splice information
+pub mod scte35;
+
+/// DVB Service Information tables (SDT, NIT, EIT, TDT/TOT)
+pub mod si;
+
+/// Lossless cutting of TS files
+pub mod trim;
+
 /// Core TS types and constants
 pub mod types;
 
+/// TS over UDP and RTP, unicast and multicast
+pub mod udp;
+
+#[cfg(test)]
+mod test_util;
+
 // Re-export commonly used types and constants
-pub use demuxer::TSDemuxer;
-pub use hls::{HLSPlaylist, HLSSegment, HLSSegmenter, HLSVariant};
+pub use analyzer::{TR101290Check, TR101290Error, TSAnalysis, TSAnalyzer, TSPIDReport};
+pub use bitstream::AnnexBPreparer;
+pub use demuxer::{TSDemuxer, TSEvent, TSProgram, TSStatistics};
+pub use hls::{HLSPlaylist, HLSPlaylistType, HLSSegment, HLSSegmenter, HLSVariant};
+pub use index::{TSIndex, TSIndexEntry};
 pub use muxer::TSMuxer;
 pub use pes::{PESHeader, PESPacket};
+pub use scte35::{SpliceCommand, SpliceInfoSection};
+pub use si::{SITable, EIT, NIT, SDT, TDT, TOT};
+pub use udp::{UDPSink, UDPSource};
 pub use types::{
     TSHeader,
     PID_PAT,
@@ -92,5 +132,13 @@ pub use types::{
     STREAM_TYPE_AAC,
     STREAM_TYPE_H264,
     STREAM_TYPE_H265,
+    STREAM_TYPE_MPEG2_VIDEO,
+    STREAM_TYPE_MPEG1_AUDIO,
+    STREAM_TYPE_MPEG2_AUDIO,
+    STREAM_TYPE_AC3,
+    STREAM_TYPE_EAC3,
+    STREAM_TYPE_PRIVATE_DATA,
     TS_PACKET_SIZE,
+    M2TS_PACKET_SIZE,
+    RS_PACKET_SIZE,
 };
@@ -1,17 +1,51 @@
-use super::hls::HLSSegmenter;
+use super::bitstream::{opus_access_unit, teletext_pes_payload, AnnexBPreparer};
+use super::hls::{HLSCue, HLSSegmenter};
+use super::parser::TSPacketParser;
+use super::pes::PESPacket;
+use super::scte35::{SpliceInfoSection, DESCRIPTOR_REGISTRATION, SCTE35_IDENTIFIER};
+use super::si::{SITable, PID_NIT};
 use super::types::*;
 use crate::av::{self, CodecDataExt, Packet};
 use crate::error::{Result, VdkError};
 use crate::format::Muxer as FormatMuxer;
 use crate::utils::crc::Crc32Mpeg2;
 use bytes::{BufMut, BytesMut};
+use std::collections::{BTreeMap, VecDeque};
 use std::time::Duration;
 use tokio::fs::File;
 use tokio::io::{self, AsyncWrite, AsyncWriteExt};
 
-#[allow(dead_code)]
 const PCR_INTERVAL: Duration = Duration::from_millis(40); // ~25 PCR updates per second
 
+/// Default delay of the PCR behind the decoding time stamps
+const DEFAULT_PCR_OFFSET: Duration = Duration::from_millis(100);
+
+/// Default repetition interval of PAT and PMT
+const DEFAULT_PSI_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Size of the PCR field in the adaptation field
+const PCR_SIZE: usize = 6;
+
+/// Offset in a TS packet of the byte carrying the last bit of the PCR base,
+/// the byte whose arrival time the PCR encodes
+const PCR_BYTE_OFFSET: u64 = 10;
+
+/// Jump of the decoding time stamps that restarts the CBR transport clock
+const CBR_JUMP_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// T-STD buffer size of AAC and MPEG audio streams (ISO/IEC 13818-1, 2.4.2.6)
+const AUDIO_BUFFER_SIZE: usize = 3584;
+
+/// T-STD buffer size of AC-3 streams (ATSC A/52, Annex A)
+const AC3_BUFFER_SIZE: usize = 2592;
+
+/// VBV buffer size of MPEG-2 video, Main Profile at High Level
+const MPEG2_VIDEO_BUFFER_SIZE: usize = 9_781_248 / 8;
+
+/// Maximum section_length of a PAT or PMT (ISO/IEC 13818-1, 2.4.4.3 and
+/// 2.4.4.9)
+const MAX_SECTION_LENGTH: usize = 1021;
+
 /// Codec information specific to Transport Stream format.
 #[derive(Debug, Clone)]
 struct TSCodecData {
@@ -40,26 +74,195 @@ impl av::CodecData for TSCodecData {
     }
 }
 
+/// Elementary stream buffer of the T-STD model for one stream.
+///
+/// Access units enter the buffer when they are sent and leave it at their
+/// decoding time.
+#[derive(Debug, Default)]
+struct TSTDBuffer {
+    /// Buffer size in bytes, unchecked when unknown
+    size: Option<usize>,
+    fullness: usize,
+    /// Decoding time and size of the access units in the buffer
+    removals: VecDeque<(Duration, usize)>,
+}
+
+impl TSTDBuffer {
+    /// Removes the access units decoded up to `now`.
+    fn drain(&mut self, now: Duration) {
+        while let Some(&(dts, size)) = self.removals.front() {
+            if dts > now {
+                break;
+            }
+            self.fullness -= size;
+            self.removals.pop_front();
+        }
+    }
+
+    /// Returns true if an access unit of `size` bytes fits in the buffer.
+    fn fits(&self, size: usize) -> bool {
+        match self.size {
+            Some(limit) => self.fullness + size <= limit,
+            None => true,
+        }
+    }
+
+    /// Adds an access unit decoded at `dts`.
+    fn push(&mut self, dts: Duration, size: usize) {
+        if self.size.is_some() {
+            self.fullness += size;
+            self.removals.push_back((dts, size));
+        }
+    }
+
+    /// Empties the buffer, e.g. after a discontinuity.
+    fn clear(&mut self) {
+        self.fullness = 0;
+        self.removals.clear();
+    }
+}
+
+/// Returns the default T-STD buffer size of a stream in bytes.
+///
+/// H.264 and H.265 buffers hold the maximum CPB size of the stream's level,
+/// 4.1 when the level is unknown, MPEG-2 video buffers the VBV size of Main
+/// Profile at High Level. AAC and MPEG audio buffers hold 3584 bytes, AC-3
+/// buffers 2592 bytes. Other streams are unchecked.
+fn default_buffer_size(codec: &dyn CodecDataExt) -> Option<usize> {
+    // Maximum CPB sizes in kbit, with the NAL factor of the codec
+    let (max_cpb, nal_factor) = match codec.codec_type() {
+        av::CodecType::H264 => {
+            let max_cpb = match codec.level().unwrap_or(41) {
+                0..=10 => 175,
+                11 => 500,
+                12 => 1000,
+                13 | 20 => 2000,
+                21 | 22 => 4000,
+                30 => 10000,
+                31 => 14000,
+                32 => 20000,
+                40 => 25000,
+                41 | 42 => 62500,
+                50 => 135000,
+                _ => 240000,
+            };
+            (max_cpb, 1200)
+        }
+        av::CodecType::H265 => {
+            // Main tier
+            let max_cpb = match codec.level().unwrap_or(123) {
+                0..=30 => 350,
+                31..=60 => 1500,
+                61..=63 => 3000,
+                64..=90 => 6000,
+                91..=93 => 10000,
+                94..=120 => 12000,
+                121..=123 => 20000,
+                124..=150 => 25000,
+                151..=153 => 40000,
+                154..=180 => 60000,
+                181..=183 => 120000,
+                _ => 240000,
+            };
+            (max_cpb, 1100)
+        }
+        av::CodecType::MPEG2Video => return Some(MPEG2_VIDEO_BUFFER_SIZE),
+        av::CodecType::AAC | av::CodecType::MPEGAudio => return Some(AUDIO_BUFFER_SIZE),
+        av::CodecType::AC3 => return Some(AC3_BUFFER_SIZE),
+        _ => return None,
+    };
+    Some(max_cpb * nal_factor / 8)
+}
+
+/// A program of the multiplex, described by its own PMT.
+#[derive(Debug)]
+struct Program {
+    /// PID carrying the program's PMT
+    pmt_pid: u16,
+    /// Indices of the streams belonging to the program
+    streams: Vec<usize>,
+    /// Program Map Table, filled in by `write_header`
+    pmt: PMT,
+    /// Stream whose PID carries the program's PCR
+    pcr_stream: usize,
+}
+
+impl Program {
+    fn new(program_number: u16, pmt_pid: u16, streams: &[usize]) -> Self {
+        let mut pmt = PMT::new();
+        pmt.program_number = program_number;
+        Self {
+            pmt_pid,
+            streams: streams.to_vec(),
+            pmt,
+            pcr_stream: 0,
+        }
+    }
+}
+
+/// A DVB SI table repeated by the muxer.
+#[derive(Debug)]
+struct SIEntry {
+    table: SITable,
+    /// Time between two transmissions
+    interval: Duration,
+    /// PCR of the last transmission
+    last_sent: Option<Duration>,
+    /// PCR of the first transmission, TDT and TOT times advance from there
+    origin: Option<Duration>,
+}
+
 /// MPEG Transport Stream muxer.
 ///
 /// Implements multiplexing of multiple elementary streams (video, audio)
 /// into a single MPEG-TS bitstream. Supports:
-/// - Multiple program streams
+/// - Multiple programs (MPTS), each with its own PMT, PCR PID and streams
+/// - PES packetization, converting nanosecond packet timestamps to 90 kHz PTS/DTS
+/// - Annex B conversion of H.264/H.265 with AUD and parameter sets
 /// - Program Association Table (PAT) generation
 /// - Program Map Table (PMT) generation
+/// - PCR insertion at least every 40 ms and periodic PAT/PMT repetition
+/// - DVB SI tables (NIT, SDT, EIT, TDT/TOT) at configurable repetition rates
+/// - SCTE-35 splice information, signalled in HLS playlists
+/// - ID3 timed metadata streams
+/// - Constant bitrate output with null packet stuffing
+/// - 192-byte M2TS packets with arrival timestamps
 /// - Optional HLS segmentation
 pub struct TSMuxer<W: AsyncWrite + Unpin + Send> {
     stream_writer: io::BufWriter<W>,
     streams: Vec<Box<dyn CodecDataExt>>,
+    preparers: Vec<Option<AnnexBPreparer>>,
     continuity_counters: Vec<u8>,
     current_pcr: Duration,
     last_pcr: Option<Duration>,
-    last_pcr_write: Duration,
+    pcr_offset: Duration,
     pat: PAT,
-    pmt: PMT,
+    programs: Vec<Program>,
+    psi_interval: Duration,
+    last_psi: Option<Duration>,
+    si_tables: Vec<SIEntry>,
+    section_continuity_counters: BTreeMap<u16, u8>,
+    scte35_pid: Option<u16>,
+    splices: VecDeque<(Option<Duration>, HLSCue)>,
     crc: Crc32Mpeg2,
     hls_segmenter: Option<HLSSegmenter>,
+    segment_writer: Option<io::BufWriter<File>>,
+    segment_end: Duration,
+    video_stream: Option<usize>,
+    /// PTS of the last video packet, for timed metadata without timestamps
+    last_video_pts: Option<i64>,
     stream_discontinuity: bool,
+    /// Constant mux rate in bits per second
+    mux_rate: Option<u64>,
+    /// TS bytes written so far
+    bytes_written: u64,
+    /// Transport time and byte position the CBR clock counts from
+    clock_base: Option<(Duration, u64)>,
+    /// T-STD buffer sizes configured per stream index
+    buffer_sizes: BTreeMap<usize, usize>,
+    buffers: Vec<TSTDBuffer>,
+    /// Whether packets are prefixed with a TP_extra_header
+    m2ts: bool,
 }
 
 impl<W: AsyncWrite + Unpin + Send> TSMuxer<W> {
@@ -72,20 +275,44 @@ impl<W: AsyncWrite + Unpin + Send> TSMuxer<W> {
         Self {
             stream_writer: io::BufWriter::new(writer),
             streams: Vec::new(),
+            preparers: Vec::new(),
             continuity_counters: Vec::new(),
             current_pcr: Duration::ZERO,
             last_pcr: None,
-            last_pcr_write: Duration::ZERO,
+            pcr_offset: DEFAULT_PCR_OFFSET,
             pat: PAT::new(),
-            pmt: PMT::new(),
+            programs: Vec::new(),
+            psi_interval: DEFAULT_PSI_INTERVAL,
+            last_psi: None,
+            si_tables: Vec::new(),
+            section_continuity_counters: BTreeMap::new(),
+            scte35_pid: None,
+            splices: VecDeque::new(),
             crc: Crc32Mpeg2::new(),
             hls_segmenter: None,
+            segment_writer: None,
+            segment_end: Duration::ZERO,
+            video_stream: None,
+            last_video_pts: None,
             stream_discontinuity: false,
+            mux_rate: None,
+            bytes_written: 0,
+            clock_base: None,
+            buffer_sizes: BTreeMap::new(),
+            buffers: Vec::new(),
+            m2ts: false,
         }
     }
 
     /// Configures HLS segmentation for this muxer.
     ///
+    /// The Transport Stream is then written to segment files instead of the
+    /// writer passed to [`TSMuxer::new`]. A new segment starts at the first
+    /// video keyframe after the segment duration (at any packet for audio-only
+    /// streams), and the media playlist is rewritten after every segment.
+    /// Packets before the first keyframe are dropped, since a segment has to
+    /// be decodable on its own.
+    ///
     /// # Arguments
     ///
     /// * `segmenter` - The HLS segmenter configuration
@@ -94,308 +321,2039 @@ impl<W: AsyncWrite + Unpin + Send> TSMuxer<W> {
         self
     }
 
-    /// Marks the stream as discontinuous, affecting PCR and segment timing.
-    pub fn mark_discontinuity(&mut self) {
-        self.stream_discontinuity = true;
+    /// Adds a program to the multiplex.
+    ///
+    /// Without programs, all streams form program 1 with its PMT on
+    /// [`PID_PMT`]. Once a program is added, every stream passed to
+    /// `write_header` must belong to one. Each program carries its PCR on
+    /// its first video stream, or its first stream without video.
+    ///
+    /// # Arguments
+    ///
+    /// * `program_number` - Program number listed in the PAT, not 0
+    /// * `pmt_pid` - PID carrying the program's PMT
+    /// * `streams` - Indices of the program's streams, as passed to `write_header`
+    pub fn with_program(mut self, program_number: u16, pmt_pid: u16, streams: &[usize]) -> Self {
+        self.programs
+            .push(Program::new(program_number, pmt_pid, streams));
+        self
     }
 
-    /// Resets the Program Clock Reference timing.
-    pub fn reset_pcr(&mut self) {
-        self.current_pcr = Duration::ZERO;
-        self.last_pcr = None;
-        self.last_pcr_write = Duration::ZERO;
+    /// Sets how far the PCR runs behind the decoding time stamps.
+    ///
+    /// The PCR is derived as `DTS - offset`, giving decoders that much
+    /// buffering time. Defaults to 100 ms.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Delay of the PCR relative to DTS
+    pub fn with_pcr_offset(mut self, offset: Duration) -> Self {
+        self.pcr_offset = offset;
+        self
     }
 
-    /// Gets the PID for a stream index.
-    fn get_stream_pid(&self, index: usize) -> u16 {
-        0x100 + (index as u16)
+    /// Sets how often PAT and PMT are repeated.
+    ///
+    /// The tables are also repeated before every video keyframe, so players
+    /// joining late can start decoding. Defaults to 100 ms.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Maximum time between two PAT/PMT pairs
+    pub fn with_psi_interval(mut self, interval: Duration) -> Self {
+        self.psi_interval = interval;
+        self
     }
 
-    /// Gets and increments the continuity counter for a stream.
-    fn get_next_continuity_counter(&mut self, stream_index: usize) -> u8 {
-        let counter = &mut self.continuity_counters[stream_index];
-        *counter = (*counter + 1) & 0x0F;
-        *counter
+    /// Enables constant bitrate output at a fixed mux rate.
+    ///
+    /// The multiplex is padded with null packets ([`PID_NULL`]) so every PCR
+    /// matches the byte position it is sent at. Access units are sent no
+    /// earlier than `DTS - offset` (see [`TSMuxer::with_pcr_offset`]) and
+    /// held back while they would overflow the stream's T-STD buffer (see
+    /// [`TSMuxer::with_buffer_size`]). PCRs, PAT/PMT and SI tables keep
+    /// their repetition rates while stuffing. Jumps of the time stamps by
+    /// more than a second restart the clock with a discontinuity.
+    ///
+    /// Writing a packet fails when it reaches the decoder after its DTS,
+    /// i.e. when the rate is too low for the input, or when an access unit
+    /// is larger than its stream's buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `bits_per_second` - Transport rate of the multiplex, including all overhead
+    pub fn with_mux_rate(mut self, bits_per_second: u64) -> Self {
+        self.mux_rate = Some(bits_per_second).filter(|&rate| rate > 0);
+        self
     }
 
-    /// Updates the Program Clock Reference with new timing information.
-    fn update_pcr(&mut self, packet_time: Option<Duration>) {
-        if let Some(time) = packet_time {
-            if let Some(last_pcr) = self.last_pcr {
-                if time < last_pcr {
-                    self.mark_discontinuity();
-                }
-            }
-            self.current_pcr = time;
-        }
+    /// Writes 192-byte M2TS (BDAV) packets instead of 188-byte TS packets.
+    ///
+    /// Every TS packet is prefixed with a TP_extra_header holding its
+    /// arrival timestamp on the 27 MHz PCR clock: the transport time in CBR
+    /// mode, the current PCR otherwise. The mux rate only counts the TS
+    /// packets.
+    pub fn with_m2ts(mut self) -> Self {
+        self.m2ts = true;
+        self
     }
-}
 
-#[async_trait::async_trait]
-impl<W: AsyncWrite + Unpin + Send> FormatMuxer for TSMuxer<W> {
-    /// Writes the initial Transport Stream headers including PAT and PMT.
-    async fn write_header(&mut self, streams: &[Box<dyn CodecDataExt>]) -> Result<()> {
-        // Initialize PAT
-        self.pat.entries.clear();
-        self.pat.entries.push(PATEntry {
-            program_number: 1,
-            network_pid: 0,
-            program_map_pid: PID_PMT,
+    /// Sets the T-STD elementary stream buffer size of a stream.
+    ///
+    /// Only used in CBR mode, see [`TSMuxer::with_mux_rate`]. Defaults to the
+    /// maximum CPB size of the level for H.264 and H.265 and to 3584 bytes
+    /// for AAC. Other streams are not checked by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the stream, as passed to `write_header`
+    /// * `bytes` - Size of the decoder's buffer for the stream
+    pub fn with_buffer_size(mut self, stream_index: usize, bytes: usize) -> Self {
+        self.buffer_sizes.insert(stream_index, bytes);
+        self
+    }
+
+    /// Adds a DVB SI table, repeated while writing packets.
+    ///
+    /// The time of a TDT or TOT is the time of its first transmission and
+    /// advances with the PCR from there. With a NIT, the PAT lists the
+    /// network PID as program 0. Typical intervals are 2 s for SDT and EIT
+    /// present/following, 10 s for NIT and 30 s for TDT/TOT.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The table to insert
+    /// * `interval` - Time between two transmissions of the table
+    pub fn with_si_table(mut self, table: SITable, interval: Duration) -> Self {
+        self.si_tables.push(SIEntry {
+            table,
+            interval,
+            last_sent: None,
+            origin: None,
         });
+        self
+    }
 
-        // Set PCR PID to first stream's PID if available
-        if !streams.is_empty() {
-            self.pmt.pcr_pid = self.get_stream_pid(0);
-        }
+    /// Replaces an SI table added with [`TSMuxer::with_si_table`].
+    ///
+    /// The table replaces the one with the same table ID, table ID extension
+    /// and section number, e.g. the EIT present section of a service when
+    /// the next event starts, and is sent with the next packet. Remember to
+    /// increment the table's version.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The updated table
+    ///
+    /// # Returns
+    ///
+    /// An error if no such table was added
+    pub fn update_si_table(&mut self, table: SITable) -> Result<()> {
+        let entry = self
+            .si_tables
+            .iter_mut()
+            .find(|entry| entry.table.key() == table.key())
+            .ok_or_else(|| VdkError::InvalidData("SI table was not added to the muxer".into()))?;
+        entry.table = table;
+        entry.last_sent = None;
+        entry.origin = None;
+        Ok(())
+    }
 
-        // Initialize streams
-        for codec in streams {
-            let stream_type = match codec.codec_type() {
-                av::CodecType::H264 => STREAM_TYPE_H264,
-                av::CodecType::H265 => STREAM_TYPE_H265,
-                av::CodecType::AAC => STREAM_TYPE_AAC,
-                _ => return Err(VdkError::InvalidData("Unsupported codec type".to_string())),
-            };
+    /// Enables SCTE-35 splice information on a PID.
+    ///
+    /// The PID is listed in the PMT of every program with stream type
+    /// [`STREAM_TYPE_SCTE35`] and a `CUEI` registration descriptor. Splice
+    /// information is then written with [`TSMuxer::write_splice`].
+    ///
+    /// # Arguments
+    ///
+    /// * `pid` - PID carrying the splice_info_sections
+    pub fn with_scte35(mut self, pid: u16) -> Self {
+        self.scte35_pid = Some(pid);
+        self
+    }
 
-            let elementary_pid = 0x100 + (self.streams.len() as u16);
-            self.pmt.elementary_stream_infos.push(ElementaryStreamInfo {
-                stream_type,
-                elementary_pid,
-                descriptors: Vec::new(),
-            });
+    /// Writes an SCTE-35 splice_info_section.
+    ///
+    /// The section is written right away, ahead of the splice point as
+    /// SCTE 35 requires. With HLS, a section that starts or ends a break
+    /// also ends the current segment at the splice point: the first video
+    /// packet (any packet without video) whose PTS reaches the splice
+    /// time, or the next one for immediate splices. The new segment carries
+    /// the cue, see [`HLSCue`].
+    ///
+    /// # Arguments
+    ///
+    /// * `splice` - The splice information
+    ///
+    /// # Returns
+    ///
+    /// An error if SCTE-35 was not enabled with [`TSMuxer::with_scte35`]
+    pub async fn write_splice(&mut self, splice: &SpliceInfoSection) -> Result<()> {
+        let pid = self
+            .scte35_pid
+            .ok_or_else(|| VdkError::InvalidData("SCTE-35 is not enabled on this muxer".into()))?;
+        let section = splice.to_bytes(&self.crc)?;
 
-            self.continuity_counters.push(0);
-            self.streams.push(Box::new(TSCodecData {
-                codec_type: codec.codec_type(),
-                width: codec.width(),
-                height: codec.height(),
-                extra_data: codec.extra_data().map(|d| d.to_vec()),
-            }));
+        if self.hls_segmenter.is_some() {
+            if let Some(cue) = HLSCue::from_splice(splice, &section) {
+                // Splice times are PTS values near the current DTS
+                let reference = self.current_pcr + self.pcr_offset;
+                let time = splice
+                    .splice_pts()
+                    .map(|pts| unwrap_splice_pts(pts, reference));
+                self.splices.push_back((time, cue));
+            }
+            if self.segment_writer.is_none() {
+                return Ok(());
+            }
         }
 
-        // Write PAT
-        let mut pat_buf = BytesMut::with_capacity(TS_PACKET_SIZE);
-
-        // PAT header
-        let header = TSHeader {
-            sync_byte: 0x47,
-            transport_error: false,
-            payload_unit_start: true,
-            transport_priority: false,
-            pid: PID_PAT,
-            scrambling_control: 0,
-            adaptation_field_exists: false,
-            contains_payload: true,
-            continuity_counter: 0,
-        };
-        header.write_to(&mut pat_buf)?;
-
-        // Write PAT content
-        pat_buf.put_u8(0); // Pointer field
-        pat_buf.put_u8(TABLE_ID_PAT);
-
-        let mut section = BytesMut::new();
-        self.pat.write_to(&mut section)?;
+        self.write_section(pid, &section).await?;
+        self.flush().await
+    }
 
-        let section_length = section.len() + 5 + 4;
-        pat_buf.put_u16((0xB000 | section_length as u16) & 0x3FF);
-        pat_buf.put_u16(1); // Transport stream ID
-        pat_buf.put_u8(0xC1); // Version 0, current
+    /// Returns true when the next splice is due at a packet presented at `time`.
+    fn splice_due(&self, time: Option<Duration>) -> bool {
+        match self.splices.front() {
+            Some((None, _)) => true,
+            Some((Some(splice), _)) => time.is_some_and(|time| time >= *splice),
+            None => false,
+        }
+    }
 
-        pat_buf.put_u8(0); // Section number
-        pat_buf.put_u8(0); // Last section number
+    /// Marks the stream as discontinuous, affecting PCR and segment timing.
+    ///
+    /// The next PCR carries the discontinuity indicator and PAT/PMT are
+    /// repeated with it.
+    pub fn mark_discontinuity(&mut self) {
+        self.stream_discontinuity = true;
+    }
 
-        pat_buf.extend_from_slice(&section);
+    /// Consumes the muxer, returning the underlying writer.
+    ///
+    /// Call [`FormatMuxer::flush`] or [`FormatMuxer::write_trailer`] first,
+    /// buffered data is discarded otherwise.
+    pub fn into_inner(self) -> W {
+        self.stream_writer.into_inner()
+    }
 
-        // Calculate and write CRC
-        let crc = self.crc.calculate(&pat_buf[5..5 + section_length - 4].to_vec());
-        pat_buf.put_u32(crc);
+    /// Resets the Program Clock Reference timing.
+    pub fn reset_pcr(&mut self) {
+        self.current_pcr = Duration::ZERO;
+        self.last_pcr = None;
+        self.clock_base = None;
+    }
 
-        // Stuffing
-        while pat_buf.len() < TS_PACKET_SIZE {
-            pat_buf.put_u8(0xFF);
+    /// Gets the PES stream_id for a stream index.
+    fn get_stream_id(&self, index: usize) -> Result<u8> {
+        let stream = self
+            .streams
+            .get(index)
+            .ok_or_else(|| VdkError::InvalidData(format!("Unknown stream index {}", index)))?;
+        match stream.codec_type() {
+            av::CodecType::H264 => Ok(STREAM_ID_H264),
+            av::CodecType::H265 => Ok(STREAM_ID_H265),
+            av::CodecType::MPEG2Video => Ok(STREAM_ID_MPEG2_VIDEO),
+            av::CodecType::AAC => Ok(STREAM_ID_AAC),
+            av::CodecType::MPEGAudio => Ok(STREAM_ID_MPEG_AUDIO),
+            av::CodecType::AC3
+            | av::CodecType::EAC3
+            | av::CodecType::OPUS
+            | av::CodecType::ID3
+            | av::CodecType::DVBSubtitle
+            | av::CodecType::Teletext
+            | av::CodecType::PrivateData => Ok(STREAM_ID_PRIVATE_1),
+            av::CodecType::ClosedCaptions => Err(closed_captions_error()),
         }
+    }
 
-        self.stream_writer.write_all(&pat_buf).await?;
+    /// Wraps an access unit in a PES packet.
+    ///
+    /// Packet timestamps are in nanoseconds. DTS is only written when it
+    /// differs from PTS. Video PES packets are unbounded
+    /// (`PES_packet_length` 0), audio PES packets carry their length.
+    /// A `header_data_length` beyond the timestamps is filled with stuffing.
+    fn build_pes(stream_id: u8, header_data_length: u8, packet: &Packet) -> Result<PESPacket> {
+        let mut pes = PESPacket::new(stream_id, packet.data.to_vec());
+        pes.header.data_alignment = true;
+        pes.header.header_data_length = header_data_length;
 
-        // Write PMT
-        let mut pmt_buf = BytesMut::with_capacity(TS_PACKET_SIZE);
+        if let Some(pts) = packet.pts.or(packet.dts) {
+            pes = pes.with_pts(nanos_to_duration(pts));
+        }
+        if let (Some(pts), Some(dts)) = (packet.pts, packet.dts) {
+            if dts != pts {
+                pes = pes.with_dts(nanos_to_duration(dts));
+            }
+        }
 
-        // PMT header
-        let header = TSHeader {
-            sync_byte: 0x47,
-            transport_error: false,
-            payload_unit_start: true,
-            transport_priority: false,
-            pid: PID_PMT,
-            scrambling_control: 0,
-            adaptation_field_exists: false,
-            contains_payload: true,
-            continuity_counter: 0,
-        };
-        header.write_to(&mut pmt_buf)?;
+        if !is_video_stream_id(stream_id) {
+            // Everything after the PES_packet_length field
+            pes.header.packet_length = u16::try_from(pes.len() - 6).map_err(|_| {
+                VdkError::InvalidData("Access unit too large for a bounded PES packet".into())
+            })?;
+        }
 
-        // Write PMT content
-        pmt_buf.put_u8(0); // Pointer field
-        pmt_buf.put_u8(TABLE_ID_PMT);
+        Ok(pes)
+    }
 
-        let mut section = BytesMut::new();
-        self.pmt.write_to(&mut section)?;
+    /// Splits a serialized PES packet into TS packets on the stream's PID.
+    ///
+    /// The first TS packet carries the PCR and random access indicator when
+    /// requested. Every TS packet is full except the last one, which is
+    /// padded with adaptation field stuffing.
+    async fn write_pes(
+        &mut self,
+        stream_index: usize,
+        pes: &[u8],
+        pcr: Option<Duration>,
+        random_access: bool,
+    ) -> Result<()> {
+        let pid = self.get_stream_pid(stream_index);
+        let pcr = pcr.map(|pcr| self.next_packet_pcr(pcr));
+        let mut offset = 0;
 
-        let section_length = section.len() + 4 + 4; // +4 for program number and version, +4 for CRC
-        pmt_buf.put_u16((0xB000 | section_length as u16) & 0x3FF);
-        pmt_buf.put_u16(1); // Program number
-        pmt_buf.put_u8(0xC1); // Version 0, current
+        while offset < pes.len() {
+            let first = offset == 0;
+            let adaptation = AdaptationFieldData {
+                pcr: pcr.filter(|_| first),
+                random_access: random_access && first,
+                discontinuity: first && pcr.is_some() && self.stream_discontinuity,
+            };
+            let payload_size = (pes.len() - offset).min(adaptation.max_payload_size());
+            let continuity_counter = self.get_next_continuity_counter(stream_index);
 
-        pmt_buf.put_u8(0); // Section number
-        pmt_buf.put_u8(0); // Last section number
+            let ts_packet = build_ts_packet(
+                pid,
+                first,
+                continuity_counter,
+                &adaptation,
+                Some(&pes[offset..offset + payload_size]),
+            )?;
+            self.write_ts(&ts_packet).await?;
+            offset += payload_size;
+        }
 
-        pmt_buf.extend_from_slice(&section);
+        Ok(())
+    }
 
-        // Calculate and write CRC
-        let crc = self.crc.calculate(&pmt_buf[5..5 + section_length - 4].to_vec());
-        pmt_buf.put_u32(crc);
+    /// Writes a TS packet carrying only a PCR on a stream's PID.
+    async fn write_pcr_packet(&mut self, stream_index: usize, pcr: Duration) -> Result<()> {
+        let adaptation = AdaptationFieldData {
+            pcr: Some(self.next_packet_pcr(pcr)),
+            random_access: false,
+            discontinuity: self.stream_discontinuity,
+        };
+        // Packets without payload do not advance the continuity counter
+        let continuity_counter = self.continuity_counters[stream_index];
+        let ts_packet = build_ts_packet(
+            self.get_stream_pid(stream_index),
+            false,
+            continuity_counter,
+            &adaptation,
+            None,
+        )?;
+        self.write_ts(&ts_packet).await?;
+        Ok(())
+    }
 
-        // Stuffing
-        while pmt_buf.len() < TS_PACKET_SIZE {
-            pmt_buf.put_u8(0xFF);
+    /// Writes the PAT, and the PMT of every program, each in as many TS
+    /// packets as it needs.
+    async fn write_psi(&mut self) -> Result<()> {
+        let pat = self.build_pat_section()?;
+        self.write_section(PID_PAT, &pat).await?;
+        for program in 0..self.programs.len() {
+            let pmt = self.build_pmt_section(program)?;
+            self.write_section(self.programs[program].pmt_pid, &pmt)
+                .await?;
         }
-
-        self.stream_writer.write_all(&pmt_buf).await?;
-        self.stream_writer.flush().await?;
-
+        self.last_psi = Some(self.current_pcr);
         Ok(())
     }
 
-    /// Writes a media packet as one or more TS packets.
-    async fn write_packet(&mut self, packet: &Packet) -> Result<()> {
-        // Split packet data into TS packets
-        let payload = &packet.data;
-        let mut offset = 0;
-        
-        while offset < payload.len() {
-            let mut ts_packet = BytesMut::with_capacity(TS_PACKET_SIZE);
-                
-            // Calculate sizes
-            let header_size = 4;
-            let adaptation_field_size = if offset == 0 { 1 } else { 0 }; // Only first packet has adaptation field
-            let max_payload_size = TS_PACKET_SIZE - header_size - adaptation_field_size;
-            let payload_size = std::cmp::min(max_payload_size, payload.len() - offset);
-            let stuffing_size = TS_PACKET_SIZE - header_size - adaptation_field_size - payload_size;
-
-            // Write TS header
-            let header = TSHeader {
-                sync_byte: 0x47,
-                transport_error: false,
-                payload_unit_start: offset == 0, // Only first packet has payload_unit_start
-                transport_priority: false,
-                pid: self.get_stream_pid(packet.stream_index),
-                scrambling_control: 0,
-                adaptation_field_exists: adaptation_field_size > 0 || stuffing_size > 0,
-                contains_payload: true,
-                continuity_counter: self.get_next_continuity_counter(packet.stream_index),
+    /// Writes the SI tables that are due at the current PCR.
+    async fn write_si(&mut self) -> Result<()> {
+        let now = self.current_pcr;
+        let mut sections = Vec::new();
+        for entry in &mut self.si_tables {
+            let due = match entry.last_sent {
+                Some(last) => now < last || now - last >= entry.interval,
+                None => true,
             };
-            header.write_to(&mut ts_packet)?;
-
-            // Write adaptation field if needed
-            if header.adaptation_field_exists {
-                ts_packet.put_u8((adaptation_field_size + stuffing_size) as u8); // Adaptation field length
-                if adaptation_field_size > 0 {
-                    ts_packet.put_u8(0); // No flags
-                }
-                // Add stuffing
-                for _ in 0..stuffing_size {
-                    ts_packet.put_u8(0xFF);
-                }
+            if !due {
+                continue;
             }
-                
-            // Write payload
-            ts_packet.extend_from_slice(&payload[offset..offset + payload_size]);
-                
-            // Write packet
-            self.stream_writer.write_all(&ts_packet).await?;
-            offset += payload_size;
+            let origin = *entry.origin.get_or_insert(now);
+            let table = entry.table.advanced(now.saturating_sub(origin));
+            sections.push((table.pid(), table.to_section(&self.crc)?));
+            entry.last_sent = Some(now);
         }
-            
-        // Update PCR if needed
-        if let Some(pts) = packet.pts {
-            self.update_pcr(Some(Duration::from_millis(pts as u64)));
+
+        for (pid, section) in sections {
+            self.write_section(pid, &section).await?;
         }
-            
-        self.stream_writer.flush().await?;
         Ok(())
     }
 
-    /// Finalizes the Transport Stream and writes any pending data.
-    async fn write_trailer(&mut self) -> Result<()> {
-        if let Some(segmenter) = &mut self.hls_segmenter {
-            let current_time = self.current_pcr;
-            segmenter.finish_segment(current_time).await?;
-
-            let playlist_path = segmenter.get_output_dir().join("playlist.m3u8");
-            let playlist_file = File::create(playlist_path).await?;
-            let mut playlist_writer = io::BufWriter::new(playlist_file);
+    /// Writes a section on a PID, spread over as many TS packets as needed.
+    ///
+    /// The first packet starts with a zero pointer field, the last one is
+    /// padded with 0xFF bytes.
+    async fn write_section(&mut self, pid: u16, section: &[u8]) -> Result<()> {
+        let mut payload = Vec::with_capacity(section.len() + 1);
+        payload.push(0); // Pointer field
+        payload.extend_from_slice(section);
 
-            segmenter.write_playlist(&mut playlist_writer).await?;
-            segmenter.write_master_playlist(&mut playlist_writer).await?;
-            playlist_writer.flush().await?;
+        let adaptation = AdaptationFieldData {
+            pcr: None,
+            random_access: false,
+            discontinuity: false,
+        };
+        let payload_size = adaptation.max_payload_size();
+        for (i, chunk) in payload.chunks(payload_size).enumerate() {
+            let mut chunk = chunk.to_vec();
+            chunk.resize(payload_size, 0xFF);
+            let counter = self.section_continuity_counters.entry(pid).or_insert(0);
+            let ts_packet = build_ts_packet(pid, i == 0, *counter, &adaptation, Some(&chunk))?;
+            *counter = (*counter + 1) & 0x0F;
+            self.write_ts(&ts_packet).await?;
         }
-
-        self.stream_writer.flush().await?;
-        Ok(())
-    }
-
-    /// Flushes any buffered data to the output.
-    async fn flush(&mut self) -> Result<()> {
-        self.stream_writer.flush().await?;
         Ok(())
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Cursor;
-    use tokio::runtime::Runtime;
 
-    #[derive(Debug, Clone)]
-    struct TestCodec;
+    /// Writes a PCR on the PCR PID of every program.
+    ///
+    /// The PCR of the program whose PCR stream is `pes_stream` is left to
+    /// the PES about to be written on that stream.
+    ///
+    /// # Returns
+    ///
+    /// True if the PES has to carry a PCR
+    async fn write_pcrs(&mut self, pes_stream: Option<usize>) -> Result<bool> {
+        let mut pcr_streams: Vec<usize> = self
+            .programs
+            .iter()
+            .filter(|program| !program.streams.is_empty())
+            .map(|program| program.pcr_stream)
+            .collect();
+        pcr_streams.sort_unstable();
+        pcr_streams.dedup();
 
-    impl av::CodecData for TestCodec {
-        fn codec_type(&self) -> av::CodecType {
-            av::CodecType::H264
+        let mut pes_pcr = false;
+        for pcr_stream in pcr_streams {
+            if Some(pcr_stream) == pes_stream {
+                pes_pcr = true;
+            } else {
+                self.write_pcr_packet(pcr_stream, self.current_pcr).await?;
+            }
         }
-
-        fn width(&self) -> Option<u32> {
-            None
+        if !pes_pcr {
+            self.stream_discontinuity = false;
         }
+        self.last_pcr = Some(self.current_pcr);
+        Ok(pes_pcr)
+    }
 
-        fn height(&self) -> Option<u32> {
-            None
+    /// Writes TS packets to the current HLS segment, or the output writer.
+    ///
+    /// In M2TS mode, `data` is a single TS packet and gets its
+    /// TP_extra_header.
+    async fn write_ts(&mut self, data: &[u8]) -> Result<()> {
+        let extra_header = self.m2ts.then(|| {
+            let arrival = self.transport_time(self.bytes_written).as_nanos() * 27 / 1000;
+            // Copy permission indicator 0, 30-bit arrival_time_stamp
+            ((arrival as u32) & ARRIVAL_TIMESTAMP_MASK).to_be_bytes()
+        });
+        let prefix = extra_header.as_ref().map_or(&[][..], |header| &header[..]);
+        match &mut self.segment_writer {
+            Some(writer) => {
+                writer.write_all(prefix).await?;
+                writer.write_all(data).await?;
+            }
+            None => {
+                self.stream_writer.write_all(prefix).await?;
+                self.stream_writer.write_all(data).await?;
+            }
         }
-
-        fn extra_data(&self) -> Option<&[u8]> {
-            None
+        self.bytes_written += data.len() as u64;
+        if self.mux_rate.is_some() && self.clock_base.is_some() {
+            self.current_pcr = self.transport_time(self.bytes_written);
         }
+        Ok(())
     }
 
-    // CodecDataExt is automatically implemented for TestCodec since it implements
-    // both CodecData and Clone (via #[derive(Clone)])
+    /// Returns the CBR transport time of a byte position.
+    fn transport_time(&self, position: u64) -> Duration {
+        let (Some(rate), Some((time, base))) = (self.mux_rate, self.clock_base) else {
+            return self.current_pcr;
+        };
+        let bits = (position.saturating_sub(base) as u128) * 8;
+        time + Duration::from_nanos((bits * 1_000_000_000 / rate as u128) as u64)
+    }
 
-    #[test]
-    fn test_ts_muxer_basic() {
-        let rt = Runtime::new().unwrap();
-        rt.block_on(async {
-            let buf = Vec::new();
-            let mut muxer = TSMuxer::new(Cursor::new(buf));
+    /// Returns the PCR of the next TS packet.
+    ///
+    /// In CBR mode this is the transport time of the packet's PCR field,
+    /// otherwise `pcr` itself.
+    fn next_packet_pcr(&self, pcr: Duration) -> Duration {
+        match (self.mux_rate, self.clock_base) {
+            (Some(_), Some(_)) => self.transport_time(self.bytes_written + PCR_BYTE_OFFSET),
+            _ => pcr,
+        }
+    }
 
-            let streams = vec![Box::new(TestCodec) as Box<dyn CodecDataExt>];
-            muxer.write_header(&streams).await.unwrap();
+    /// Pads the CBR multiplex up to the transport time `target`.
+    ///
+    /// Null packets fill the gaps between the PCRs, PAT/PMT and SI tables
+    /// that fall due meanwhile.
+    async fn stuff_until(&mut self, target: Duration) -> Result<()> {
+        let adaptation = AdaptationFieldData {
+            pcr: None,
+            random_access: false,
+            discontinuity: false,
+        };
+        let null_packet = build_ts_packet(
+            PID_NULL,
+            false,
+            0,
+            &adaptation,
+            Some(&[0xFF; TS_PACKET_SIZE - TS_HEADER_SIZE]),
+        )?;
 
-            let packet = Packet::new(bytes::Bytes::from(vec![0; 184]))
-                .with_stream_index(0)
-                .with_pts(0);
+        while self.current_pcr < target {
+            if self.pcr_due() {
+                self.write_pcrs(None).await?;
+            } else if self.psi_due() {
+                self.write_psi().await?;
+            } else {
+                let written = self.bytes_written;
+                self.write_si().await?;
+                if self.bytes_written == written {
+                    self.write_ts(&null_packet).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Delays an access unit of the CBR multiplex until it may be sent.
+    ///
+    /// The multiplex is stuffed up to `DTS - offset`, then until the
+    /// stream's T-STD buffer has room for the access unit. The first access
+    /// unit and jumps of the time stamps (re)start the transport clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Stream of the access unit
+    /// * `dts` - Decoding time of the access unit
+    /// * `size` - Size of the access unit in bytes
+    async fn schedule_access_unit(
+        &mut self,
+        stream_index: usize,
+        dts: Duration,
+        size: usize,
+    ) -> Result<()> {
+        let target = dts.saturating_sub(self.pcr_offset);
+        let now = self.current_pcr;
+        let jump = target > now + CBR_JUMP_THRESHOLD || dts + CBR_JUMP_THRESHOLD < now;
+        if self.clock_base.is_none() || self.stream_discontinuity || jump {
+            if self.clock_base.is_some() {
+                self.mark_discontinuity();
+            }
+            self.clock_base = Some((target, self.bytes_written));
+            self.current_pcr = target;
+            for buffer in &mut self.buffers {
+                buffer.clear();
+            }
+        }
+        self.stuff_until(target).await?;
+
+        loop {
+            let now = self.current_pcr;
+            let buffer = &mut self.buffers[stream_index];
+            buffer.drain(now);
+            if buffer.fits(size) {
+                return Ok(());
+            }
+            match buffer.removals.front() {
+                Some(&(removal, _)) => self.stuff_until(removal).await?,
+                None => {
+                    return Err(VdkError::InvalidData(format!(
+                        "Access unit of {} bytes exceeds the T-STD buffer of stream {}",
+                        size, stream_index
+                    )))
+                }
+            }
+        }
+    }
+
+    /// Closes the current HLS segment, if any, and starts a new one.
+    ///
+    /// The finished segment is added to the playlist with its measured
+    /// duration, up to `time`, and the playlist files are rewritten.
+    async fn start_segment(&mut self, time: Duration) -> Result<()> {
+        let segmenter = match &mut self.hls_segmenter {
+            Some(segmenter) => segmenter,
+            None => return Ok(()),
+        };
+
+        if let Some(mut writer) = self.segment_writer.take() {
+            writer.flush().await?;
+            segmenter.finish_segment(time).await?;
+            segmenter.write_playlist_files().await?;
+        }
+
+        let file = segmenter.start_segment(time).await?;
+        self.segment_writer = Some(io::BufWriter::new(file));
+
+        // Every segment starts with PAT/PMT, SI tables and a PCR
+        self.last_psi = None;
+        self.last_pcr = None;
+        for entry in &mut self.si_tables {
+            entry.last_sent = None;
+        }
+        Ok(())
+    }
+
+    /// Returns true when PAT/PMT are due at the current PCR.
+    fn psi_due(&self) -> bool {
+        match self.last_psi {
+            Some(last) => self.current_pcr < last || self.current_pcr - last >= self.psi_interval,
+            None => true,
+        }
+    }
+
+    /// Returns true when a PCR is due at the current PCR.
+    fn pcr_due(&self) -> bool {
+        match self.last_pcr {
+            Some(last) => self.current_pcr < last || self.current_pcr - last >= PCR_INTERVAL,
+            None => true,
+        }
+    }
+
+    /// Builds the PAT section, from table_id to CRC.
+    ///
+    /// # Returns
+    ///
+    /// The section, or an error if its programs do not fit in the 1021
+    /// bytes a PAT section can hold after its length field
+    fn build_pat_section(&self) -> Result<Vec<u8>> {
+        let mut section = BytesMut::new();
+        self.pat.write_to(&mut section)?;
+
+        let section_length = section.len() + 5 + 4; // +5 for transport stream ID, version and section numbers, +4 for CRC
+        if section_length > MAX_SECTION_LENGTH {
+            return Err(VdkError::InvalidData(format!(
+                "PAT is {} bytes long, more than the maximum of {}",
+                section_length, MAX_SECTION_LENGTH
+            )));
+        }
+
+        let mut pat_buf = BytesMut::with_capacity(3 + section_length);
+        pat_buf.put_u8(TABLE_ID_PAT);
+        pat_buf.put_u16(0xB000 | section_length as u16);
+        pat_buf.put_u16(1); // Transport stream ID
+        pat_buf.put_u8(0xC1); // Version 0, current
+
+        pat_buf.put_u8(0); // Section number
+        pat_buf.put_u8(0); // Last section number
+
+        pat_buf.extend_from_slice(&section);
+
+        // CRC covers the section from table_id on
+        let crc = self.crc.calculate(&pat_buf);
+        pat_buf.put_u32(crc);
+        Ok(pat_buf.to_vec())
+    }
+
+    /// Builds the PMT section of a program, from table_id to CRC.
+    ///
+    /// # Returns
+    ///
+    /// The section, or an error if its descriptors do not fit in the 1021
+    /// bytes a PMT section can hold after its length field
+    fn build_pmt_section(&self, program: usize) -> Result<Vec<u8>> {
+        let program = &self.programs[program];
+        let mut section = BytesMut::new();
+        program.pmt.write_to(&mut section)?;
+
+        let section_length = section.len() + 5 + 4; // +5 for program number, version and section numbers, +4 for CRC
+        if section_length > MAX_SECTION_LENGTH {
+            return Err(VdkError::InvalidData(format!(
+                "PMT of program {} is {} bytes long, more than the maximum of {}",
+                program.pmt.program_number, section_length, MAX_SECTION_LENGTH
+            )));
+        }
+
+        let mut pmt_buf = BytesMut::with_capacity(3 + section_length);
+        pmt_buf.put_u8(TABLE_ID_PMT);
+        pmt_buf.put_u16(0xB000 | section_length as u16);
+        pmt_buf.put_u16(program.pmt.program_number);
+        pmt_buf.put_u8(0xC1); // Version 0, current
+
+        pmt_buf.put_u8(0); // Section number
+        pmt_buf.put_u8(0); // Last section number
+
+        pmt_buf.extend_from_slice(&section);
+
+        // CRC covers the section from table_id on
+        let crc = self.crc.calculate(&pmt_buf);
+        pmt_buf.put_u32(crc);
+        Ok(pmt_buf.to_vec())
+    }
+
+    /// Gets the PID for a stream index.
+    fn get_stream_pid(&self, index: usize) -> u16 {
+        0x100 + (index as u16)
+    }
+
+    /// Gets and increments the continuity counter for a stream.
+    fn get_next_continuity_counter(&mut self, stream_index: usize) -> u8 {
+        let counter = &mut self.continuity_counters[stream_index];
+        *counter = (*counter + 1) & 0x0F;
+        *counter
+    }
+
+    /// Updates the Program Clock Reference with new timing information.
+    fn update_pcr(&mut self, packet_time: Option<Duration>) {
+        if let Some(time) = packet_time {
+            if let Some(last_pcr) = self.last_pcr {
+                if time < last_pcr {
+                    self.mark_discontinuity();
+                }
+            }
+            self.current_pcr = time;
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<W: AsyncWrite + Unpin + Send> FormatMuxer for TSMuxer<W> {
+    /// Writes the initial Transport Stream headers including PAT and PMTs.
+    ///
+    /// PAT and PMT are repeated while writing packets, see
+    /// [`TSMuxer::with_psi_interval`].
+    async fn write_header(&mut self, streams: &[Box<dyn CodecDataExt>]) -> Result<()> {
+        // Without configured programs, all streams form a single program
+        if self.programs.is_empty() {
+            let all: Vec<usize> = (0..streams.len()).collect();
+            self.programs.push(Program::new(1, PID_PMT, &all));
+        }
+
+        // Initialize streams
+        let mut stream_infos = Vec::with_capacity(streams.len());
+        for codec in streams {
+            stream_infos.push(stream_info(codec.as_ref())?);
+
+            let preparer = match codec.codec_type() {
+                av::CodecType::H264 | av::CodecType::H265 => {
+                    Some(AnnexBPreparer::new(codec.codec_type(), codec.extra_data())?)
+                }
+                _ => None,
+            };
+            self.preparers.push(preparer);
+
+            self.continuity_counters.push(0);
+            self.buffers.push(TSTDBuffer {
+                size: self
+                    .buffer_sizes
+                    .get(&self.streams.len())
+                    .copied()
+                    .or_else(|| default_buffer_size(codec.as_ref())),
+                ..Default::default()
+            });
+            self.streams.push(Box::new(TSCodecData {
+                codec_type: codec.codec_type(),
+                width: codec.width(),
+                height: codec.height(),
+                extra_data: codec.extra_data().map(|d| d.to_vec()),
+            }));
+        }
+
+        let is_video = |index: &usize| {
+            matches!(
+                streams[*index].codec_type(),
+                av::CodecType::H264 | av::CodecType::H265 | av::CodecType::MPEG2Video
+            )
+        };
+        let is_metadata = |index: &usize| streams[*index].codec_type() == av::CodecType::ID3;
+        let is_sparse = |index: &usize| {
+            matches!(
+                streams[*index].codec_type(),
+                av::CodecType::ID3
+                    | av::CodecType::DVBSubtitle
+                    | av::CodecType::Teletext
+                    | av::CodecType::PrivateData
+            )
+        };
+        if let Some(index) = (0..streams.len()).find(|index| {
+            !self
+                .programs
+                .iter()
+                .any(|program| program.streams.contains(index))
+        }) {
+            return Err(VdkError::InvalidData(format!(
+                "Stream {} is not part of any program",
+                index
+            )));
+        }
+
+        // Initialize PAT and one PMT per program
+        self.pat.entries.clear();
+        if self
+            .si_tables
+            .iter()
+            .any(|entry| matches!(entry.table, SITable::Nit(_)))
+        {
+            self.pat.entries.push(PATEntry {
+                program_number: 0,
+                network_pid: PID_NIT,
+                program_map_pid: 0,
+            });
+        }
+        for program in &mut self.programs {
+            let program_number = program.pmt.program_number;
+            if program_number == 0 {
+                return Err(VdkError::InvalidData(
+                    "Program number 0 is reserved for the network PID".into(),
+                ));
+            }
+            if let Some(index) = program
+                .streams
+                .iter()
+                .find(|&&index| index >= streams.len())
+            {
+                return Err(VdkError::InvalidData(format!(
+                    "Unknown stream index {} in program {}",
+                    index, program_number
+                )));
+            }
+            self.pat.entries.push(PATEntry {
+                program_number,
+                network_pid: 0,
+                program_map_pid: program.pmt_pid,
+            });
+
+            // PCR goes on the first video stream, or the first stream without
+            // video. Sparse metadata and private data only carry it as a last
+            // resort.
+            let pcr_stream = program
+                .streams
+                .iter()
+                .copied()
+                .find(|index| is_video(index))
+                .or(program
+                    .streams
+                    .iter()
+                    .copied()
+                    .find(|index| !is_sparse(index)))
+                .or(program.streams.first().copied());
+            program.pcr_stream = pcr_stream.unwrap_or(0);
+            program.pmt.pcr_pid = pcr_stream.map_or(PID_NULL, |index| 0x100 + index as u16);
+            program.pmt.elementary_stream_infos = program
+                .streams
+                .iter()
+                .map(|&index| ElementaryStreamInfo {
+                    stream_type: stream_infos[index].0,
+                    elementary_pid: 0x100 + index as u16,
+                    descriptors: stream_infos[index].1.clone(),
+                })
+                .collect();
+            program.pmt.program_descriptors.clear();
+            if program.streams.iter().any(is_metadata) {
+                program
+                    .pmt
+                    .program_descriptors
+                    .push(Descriptor::id3_metadata_pointer(program_number));
+            }
+            if let Some(pid) = self.scte35_pid {
+                program.pmt.program_descriptors.push(Descriptor {
+                    tag: DESCRIPTOR_REGISTRATION,
+                    data: SCTE35_IDENTIFIER.to_be_bytes().to_vec(),
+                });
+                program
+                    .pmt
+                    .elementary_stream_infos
+                    .push(ElementaryStreamInfo {
+                        stream_type: STREAM_TYPE_SCTE35,
+                        elementary_pid: pid,
+                        descriptors: Vec::new(),
+                    });
+            }
+        }
+
+        // HLS segments start at keyframes of the first video stream
+        self.video_stream = (0..streams.len()).find(is_video);
+
+        self.last_pcr = None;
+        self.last_psi = None;
+        // With HLS, every segment starts with its own PAT/PMT
+        if self.hls_segmenter.is_none() {
+            self.write_psi().await?;
+        }
+        self.stream_writer.flush().await?;
+
+        Ok(())
+    }
+
+    /// Writes a media packet as one PES packet spread over TS packets,
+    /// preceded by the PSI, SI tables and PCRs that are due.
+    async fn write_packet(&mut self, packet: &Packet) -> Result<()> {
+        let stream_id = self.get_stream_id(packet.stream_index)?;
+        let codec_type = self.streams[packet.stream_index].codec_type();
+        let aligned;
+        let packet =
+            if codec_type == av::CodecType::ID3 && packet.pts.is_none() && packet.dts.is_none() {
+                aligned = Packet {
+                    pts: self.last_video_pts,
+                    ..packet.clone()
+                };
+                &aligned
+            } else if codec_type == av::CodecType::OPUS {
+                aligned = Packet {
+                    data: opus_access_unit(&packet.data).into(),
+                    ..packet.clone()
+                };
+                &aligned
+            } else if codec_type == av::CodecType::Teletext {
+                aligned = Packet {
+                    data: teletext_pes_payload(&packet.data).into(),
+                    ..packet.clone()
+                };
+                &aligned
+            } else {
+                packet
+            };
+        if is_video_stream_id(stream_id) {
+            self.last_video_pts = packet.pts.or(packet.dts);
+        }
+        let keyframe = packet.is_key && is_video_stream_id(stream_id);
+        let time = packet.dts.or(packet.pts).map(nanos_to_duration);
+
+        let header_data_length = match codec_type {
+            av::CodecType::Teletext => TELETEXT_PES_HEADER_DATA_LENGTH,
+            _ => 0,
+        };
+        let mut pes = Self::build_pes(stream_id, header_data_length, packet)?;
+        // Video PES packets are unbounded, so the payload can change size
+        if let Some(preparer) = &self.preparers[packet.stream_index] {
+            pes.payload = preparer.prepare(&packet.data, packet.is_key)?;
+        }
+        if self.hls_segmenter.is_some() {
+            let boundary = match self.video_stream {
+                Some(_) => keyframe,
+                None => true,
+            };
+            // Splices cut exactly at their splice point
+            let splice_point = match self.video_stream {
+                Some(index) => index == packet.stream_index,
+                None => true,
+            };
+            let presentation_time = packet.pts.or(packet.dts).map(nanos_to_duration);
+            let splice = splice_point && self.splice_due(presentation_time);
+            let segment_start = match (&self.hls_segmenter, time) {
+                (Some(_), Some(time)) if splice => Some(time),
+                (Some(segmenter), Some(time)) if boundary => {
+                    Some(time).filter(|time| segmenter.should_start_new_segment(*time))
+                }
+                _ => None,
+            };
+            if let Some(start) = segment_start {
+                self.start_segment(start).await?;
+            }
+            if splice && segment_start.is_some() {
+                while self.splice_due(presentation_time) {
+                    let Some((_, cue)) = self.splices.pop_front() else {
+                        break;
+                    };
+                    if let Some(segmenter) = &mut self.hls_segmenter {
+                        segmenter.add_cue(cue);
+                    }
+                }
+            }
+            if self.segment_writer.is_none() {
+                return Ok(());
+            }
+            if let Some(time) = time {
+                let end = time + packet.duration.unwrap_or_default();
+                self.segment_end = self.segment_end.max(end);
+            }
+        }
+
+        // The PCR follows DTS, delayed by the configured offset. At a
+        // constant rate it follows the byte position instead.
+        if let Some(time) = time {
+            match self.mux_rate {
+                Some(_) => {
+                    self.schedule_access_unit(packet.stream_index, time, pes.payload.len())
+                        .await?
+                }
+                None => self.update_pcr(Some(time.saturating_sub(self.pcr_offset))),
+            }
+        }
+
+        if keyframe || self.stream_discontinuity || self.psi_due() {
+            self.write_psi().await?;
+        }
+        self.write_si().await?;
+
+        let mut pes_pcr = None;
+        if time.is_some()
+            && (self.stream_discontinuity || self.pcr_due())
+            && self.write_pcrs(Some(packet.stream_index)).await?
+        {
+            pes_pcr = Some(self.current_pcr);
+        }
+
+        let mut buf = BytesMut::with_capacity(pes.len());
+        pes.write_to(&mut buf)?;
+        self.write_pes(packet.stream_index, &buf, pes_pcr, keyframe)
+            .await?;
+        if pes_pcr.is_some() {
+            self.stream_discontinuity = false;
+        }
+
+        if let (Some(rate), Some(dts)) = (self.mux_rate, time) {
+            self.buffers[packet.stream_index].push(dts, pes.payload.len());
+            // The last byte has to arrive before the access unit is decoded
+            if self.current_pcr > dts {
+                return Err(VdkError::InvalidData(format!(
+                    "Mux rate of {} bit/s is too low: stream {} arrives {} ms after its DTS",
+                    rate,
+                    packet.stream_index,
+                    (self.current_pcr - dts).as_millis()
+                )));
+            }
+        }
+
+        self.flush().await
+    }
+
+    /// Finalizes the Transport Stream and writes any pending data.
+    async fn write_trailer(&mut self) -> Result<()> {
+        if let Some(segmenter) = &mut self.hls_segmenter {
+            if let Some(mut writer) = self.segment_writer.take() {
+                writer.flush().await?;
+                segmenter.finish_segment(self.segment_end).await?;
+            }
+            segmenter.end_playlist();
+            segmenter.write_playlist_files().await?;
+        }
+
+        self.stream_writer.flush().await?;
+        Ok(())
+    }
+
+    /// Flushes any buffered data to the output.
+    async fn flush(&mut self) -> Result<()> {
+        if let Some(writer) = &mut self.segment_writer {
+            writer.flush().await?;
+        }
+        self.stream_writer.flush().await?;
+        Ok(())
+    }
+}
+
+/// Converts a packet timestamp in nanoseconds, clamping negative values.
+fn nanos_to_duration(nanos: i64) -> Duration {
+    Duration::from_nanos(nanos.max(0) as u64)
+}
+
+/// Converts a 90 kHz splice PTS to the time closest to `reference`.
+fn unwrap_splice_pts(pts: u64, reference: Duration) -> Duration {
+    const WRAP: i128 = 1 << 33;
+    let reference_ticks = (reference.as_nanos() * 9 / 100_000) as i128;
+    let mut delta = (pts as i128 - reference_ticks).rem_euclid(WRAP);
+    if delta >= WRAP / 2 {
+        delta -= WRAP;
+    }
+    let ticks = (reference_ticks + delta).max(0);
+    Duration::from_nanos((ticks * 100_000 / 9) as u64)
+}
+
+/// Returns the PMT stream type and ES descriptors of a stream.
+///
+/// AC-3 and E-AC-3 use the ATSC stream types with a registration
+/// descriptor, Opus the DVB signalling. Private data is written with the
+/// stream type and descriptors in its extra data, as returned by
+/// [`TSDemuxer`](super::TSDemuxer). DVB subtitles and teletext take their
+/// subtitling_descriptor or teletext_descriptor payload from the extra data,
+/// or describe a single service in the stream's language. Streams with a
+/// language get an ISO_639_language_descriptor.
+fn stream_info(codec: &dyn CodecDataExt) -> Result<(u8, Vec<Descriptor>)> {
+    let language = codec
+        .language()
+        .map(|language| {
+            <[u8; 3]>::try_from(language.as_bytes())
+                .ok()
+                .filter(|code| code.iter().all(u8::is_ascii_alphabetic))
+                .ok_or_else(|| {
+                    VdkError::InvalidData(format!("Invalid ISO 639-2 language code: {}", language))
+                })
+        })
+        .transpose()?;
+    let subtitles = |tag, default: fn([u8; 3]) -> Descriptor| {
+        let descriptor = match codec.extra_data() {
+            Some(data) if !data.is_empty() => Descriptor {
+                tag,
+                data: data.to_vec(),
+            },
+            _ => default(language.unwrap_or(*b"und")),
+        };
+        (STREAM_TYPE_PRIVATE_DATA, vec![descriptor])
+    };
+
+    let mut info = match codec.codec_type() {
+        av::CodecType::H264 => (STREAM_TYPE_H264, Vec::new()),
+        av::CodecType::H265 => (STREAM_TYPE_H265, Vec::new()),
+        av::CodecType::MPEG2Video => (STREAM_TYPE_MPEG2_VIDEO, Vec::new()),
+        av::CodecType::AAC => (STREAM_TYPE_AAC, Vec::new()),
+        // MPEG-2 audio adds the lower sample rates
+        av::CodecType::MPEGAudio => match codec.sample_rate() {
+            Some(rate) if rate < 32000 => (STREAM_TYPE_MPEG2_AUDIO, Vec::new()),
+            _ => (STREAM_TYPE_MPEG1_AUDIO, Vec::new()),
+        },
+        av::CodecType::AC3 => (
+            STREAM_TYPE_AC3,
+            vec![Descriptor::registration(FORMAT_IDENTIFIER_AC3)],
+        ),
+        av::CodecType::EAC3 => (
+            STREAM_TYPE_EAC3,
+            vec![Descriptor::registration(FORMAT_IDENTIFIER_EAC3)],
+        ),
+        av::CodecType::OPUS => {
+            let channels = codec.channels().unwrap_or(2);
+            if !(1..=8).contains(&channels) {
+                return Err(VdkError::InvalidData(format!(
+                    "Unsupported number of Opus channels: {}",
+                    channels
+                )));
+            }
+            (
+                STREAM_TYPE_PRIVATE_DATA,
+                vec![
+                    Descriptor::registration(FORMAT_IDENTIFIER_OPUS),
+                    Descriptor::opus_audio(channels),
+                ],
+            )
+        }
+        av::CodecType::ID3 => (STREAM_TYPE_METADATA, vec![Descriptor::id3_metadata()]),
+        av::CodecType::DVBSubtitle => subtitles(DESCRIPTOR_SUBTITLING, Descriptor::dvb_subtitling),
+        av::CodecType::Teletext => subtitles(DESCRIPTOR_TELETEXT, Descriptor::teletext_subtitles),
+        av::CodecType::ClosedCaptions => return Err(closed_captions_error()),
+        av::CodecType::PrivateData => match codec.extra_data() {
+            Some([stream_type, descriptors @ ..]) => (
+                *stream_type,
+                TSPacketParser::new().parse_descriptors(descriptors)?,
+            ),
+            _ => (STREAM_TYPE_PRIVATE_DATA, Vec::new()),
+        },
+    };
+    if let Some(language) = language {
+        if !info
+            .1
+            .iter()
+            .any(|descriptor| descriptor.tag == DESCRIPTOR_ISO_639_LANGUAGE)
+        {
+            info.1.push(Descriptor::iso_639_language(language));
+        }
+    }
+    Ok(info)
+}
+
+/// Returns the error for caption streams, which have no TS stream of their
+/// own.
+fn closed_captions_error() -> VdkError {
+    VdkError::InvalidData("Closed captions are carried in the SEI of their video stream".into())
+}
+
+/// Returns true for video PES stream_ids (0xE0-0xEF).
+fn is_video_stream_id(stream_id: u8) -> bool {
+    stream_id & 0xF0 == 0xE0
+}
+
+/// Optional adaptation field content of a TS packet.
+struct AdaptationFieldData {
+    pcr: Option<Duration>,
+    random_access: bool,
+    discontinuity: bool,
+}
+
+impl AdaptationFieldData {
+    /// Returns true if the adaptation field needs its flags byte.
+    fn has_flags(&self) -> bool {
+        self.pcr.is_some() || self.random_access || self.discontinuity
+    }
+
+    /// Returns the payload space left by this adaptation field.
+    fn max_payload_size(&self) -> usize {
+        let mut size = TS_PACKET_SIZE - TS_HEADER_SIZE;
+        if self.has_flags() {
+            // Length and flags bytes
+            size -= 2;
+        }
+        if self.pcr.is_some() {
+            size -= PCR_SIZE;
+        }
+        size
+    }
+}
+
+/// Builds one TS packet, padding short payloads with adaptation field stuffing.
+fn build_ts_packet(
+    pid: u16,
+    payload_unit_start: bool,
+    continuity_counter: u8,
+    adaptation: &AdaptationFieldData,
+    payload: Option<&[u8]>,
+) -> Result<BytesMut> {
+    let payload_len = payload.map_or(0, |p| p.len());
+    let space = adaptation.max_payload_size();
+    if payload_len > space {
+        return Err(VdkError::InvalidData("TS payload too large".into()));
+    }
+    let stuffing = space - payload_len;
+    let adaptation_field_exists = adaptation.has_flags() || stuffing > 0;
+
+    let mut ts_packet = BytesMut::with_capacity(TS_PACKET_SIZE);
+    let header = TSHeader {
+        sync_byte: 0x47,
+        transport_error: false,
+        payload_unit_start,
+        transport_priority: false,
+        pid,
+        scrambling_control: 0,
+        adaptation_field_exists,
+        contains_payload: payload.is_some(),
+        continuity_counter,
+    };
+    header.write_to(&mut ts_packet)?;
+
+    if adaptation.has_flags() {
+        let field_length = 1 + adaptation.pcr.map_or(0, |_| PCR_SIZE) + stuffing;
+        ts_packet.put_u8(field_length as u8);
+
+        let mut flags = 0u8;
+        if adaptation.discontinuity {
+            flags |= 0x80;
+        }
+        if adaptation.random_access {
+            flags |= 0x40;
+        }
+        if adaptation.pcr.is_some() {
+            flags |= 0x10;
+        }
+        ts_packet.put_u8(flags);
+
+        if let Some(pcr) = adaptation.pcr {
+            // 33-bit base, 6 reserved bits, 9-bit extension
+            ts_packet.put_uint(time_to_pcr(pcr), PCR_SIZE);
+        }
+        ts_packet.put_bytes(0xFF, stuffing);
+    } else if stuffing > 0 {
+        // A single byte is just the zero adaptation_field_length
+        ts_packet.put_u8((stuffing - 1) as u8);
+        if stuffing > 1 {
+            ts_packet.put_u8(0); // No flags
+            ts_packet.put_bytes(0xFF, stuffing - 2);
+        }
+    }
+
+    if let Some(payload) = payload {
+        ts_packet.extend_from_slice(payload);
+    }
+    Ok(ts_packet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::av::CodecType;
+    use crate::format::ts::test_util::TestCodec;
+    use std::io::Cursor;
+    use tokio::runtime::Runtime;
+
+    /// Builds an Annex B access unit starting with an AUD, `len` bytes long.
+    fn access_unit(nal_header: u8, len: usize) -> Vec<u8> {
+        let mut data = vec![0, 0, 0, 1, 0x09, 0xF0, 0, 0, 0, 1, nal_header];
+        data.extend((data.len()..len).map(|i| (1 + i % 255) as u8));
+        data
+    }
+
+    #[test]
+    fn test_ts_muxer_basic() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let buf = Vec::new();
+            let mut muxer = TSMuxer::new(Cursor::new(buf));
+
+            let streams = vec![TestCodec::new(CodecType::H264).boxed()];
+            muxer.write_header(&streams).await.unwrap();
+
+            let packet = Packet::new(access_unit(0x41, 184))
+                .with_stream_index(0)
+                .with_pts(0);
             muxer.write_packet(&packet).await.unwrap();
         });
     }
+
+    #[tokio::test]
+    async fn test_ts_muxer_pes_roundtrip() {
+        use crate::format::ts::TSDemuxer;
+        use crate::format::Demuxer as FormatDemuxer;
+
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()));
+        let streams = vec![
+            TestCodec::new(CodecType::H264).boxed(),
+            TestCodec::new(CodecType::AAC).boxed(),
+        ];
+        muxer.write_header(&streams).await.unwrap();
+
+        let ms = 1_000_000i64;
+        let input = vec![
+            Packet::new(access_unit(0x41, 1000))
+                .with_stream_index(0)
+                .with_pts(80 * ms)
+                .with_dts(40 * ms),
+            Packet::new(vec![0xAA; 300])
+                .with_stream_index(1)
+                .with_pts(40 * ms),
+            // Fills the first TS packet up to one byte, forcing a 1-byte adaptation field
+            Packet::new(access_unit(0x41, 183 - 14))
+                .with_stream_index(0)
+                .with_pts(120 * ms),
+        ];
+        for packet in &input {
+            muxer.write_packet(packet).await.unwrap();
+        }
+        let output = muxer.stream_writer.into_inner().into_inner();
+        assert_eq!(output.len() % TS_PACKET_SIZE, 0);
+
+        // Stuffing only appears in the last TS packet of a PES
+        let video: Vec<&[u8]> = output
+            .chunks(TS_PACKET_SIZE)
+            .filter(|p| p[0] == 0x47 && ((p[1] as u16 & 0x1F) << 8 | p[2] as u16) == 0x100)
+            .collect();
+        assert_eq!(video.len(), 6 + 1);
+        // The first packet carries the initial PCR
+        assert_eq!(&video[0][4..6], &[7, 0x10]);
+        assert_eq!(&video[0][12..16], &[0x00, 0x00, 0x01, STREAM_ID_H264]);
+        assert_eq!(&video[0][16..18], &[0x00, 0x00]); // Unbounded video PES
+        assert_eq!(video[0][19], 0xC0); // PTS and DTS
+        assert_eq!(video[1][3] & 0x20, 0);
+        assert_ne!(video[5][3] & 0x20, 0);
+        assert_eq!(
+            video[5][4] as usize,
+            TS_PACKET_SIZE - 5 - (1000 + 19 - 176 - 4 * 184)
+        );
+        assert_eq!(video[6][3] & 0x20, 0x20);
+        assert_eq!(video[6][4], 0);
+
+        let mut demuxer = TSDemuxer::new(Cursor::new(output));
+        let codecs = demuxer.streams().await.unwrap();
+        assert_eq!(codecs.len(), 2);
+
+        let mut packets = Vec::new();
+        while let Ok(packet) = demuxer.read_packet().await {
+            packets.push(packet);
+        }
+        assert_eq!(packets.len(), input.len());
+
+        for expected in &input {
+            let packet = packets
+                .iter()
+                .find(|p| p.stream_index == expected.stream_index && p.pts == expected.pts)
+                .expect("packet missing after demuxing");
+            assert_eq!(packet.data, expected.data);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ts_muxer_pcr_and_psi_repetition() {
+        use crate::format::ts::parser::TSPacketParser;
+
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()))
+            .with_pcr_offset(Duration::from_millis(50))
+            .with_psi_interval(Duration::from_millis(100));
+        let streams = vec![
+            TestCodec::new(CodecType::AAC).boxed(),
+            TestCodec::new(CodecType::H264).boxed(),
+        ];
+        muxer.write_header(&streams).await.unwrap();
+
+        // Sparse video keyframes every 200 ms, audio every 20 ms
+        let ms = 1_000_000i64;
+        for t in (0..20).map(|i| 1000 + i * 20) {
+            if (t - 1000) % 200 == 0 {
+                let video = Packet::new(access_unit(0x65, 400))
+                    .with_stream_index(1)
+                    .with_dts(t * ms)
+                    .with_key_flag(true);
+                muxer.write_packet(&video).await.unwrap();
+            }
+            let audio = Packet::new(vec![0x22; 50])
+                .with_stream_index(0)
+                .with_pts(t * ms);
+            muxer.write_packet(&audio).await.unwrap();
+        }
+        let output = muxer.stream_writer.into_inner().into_inner();
+
+        let parser = TSPacketParser::new();
+        let mut pcrs = Vec::new();
+        let mut pat_counters = Vec::new();
+        let mut last_pid = None;
+        let mut video_cc = 0;
+        for packet in output.chunks(TS_PACKET_SIZE) {
+            let header = parser.parse_header(packet).unwrap();
+            if header.pid == PID_PAT {
+                pat_counters.push(header.continuity_counter);
+            }
+            if header.pid == 0x101 {
+                if header.payload_unit_start {
+                    // Keyframes are preceded by PAT and PMT
+                    assert_eq!(last_pid, Some(PID_PMT));
+                }
+                if header.contains_payload {
+                    video_cc = header.continuity_counter;
+                } else {
+                    // PCR-only packets repeat the counter
+                    assert_eq!(header.continuity_counter, video_cc);
+                }
+            }
+            if let Some(field) = parser.parse_adaptation_field(packet, 4).unwrap() {
+                if let Some(pcr) = field.pcr {
+                    assert_eq!(header.pid, 0x101);
+                    pcrs.push(pcr);
+                }
+            }
+            last_pid = Some(header.pid);
+        }
+
+        // PCR is DTS - offset, in 27 MHz ticks
+        assert_eq!(pcrs[0], 950 * 27_000);
+        for pair in pcrs.windows(2) {
+            assert!(pair[1] > pair[0] && pair[1] - pair[0] <= 40 * 27_000);
+        }
+        assert!(*pcrs.last().unwrap() > (1380 - 50 - 40) * 27_000);
+
+        // Header, keyframes and the 100 ms interval, counted per PID
+        assert!(pat_counters.len() >= 5);
+        for (i, cc) in pat_counters.iter().enumerate() {
+            assert_eq!(*cc as usize, i % 16);
+        }
+    }
+
+    /// An H.264 stream with its parameter sets in the codec data.
+    fn param_set_codec() -> Box<dyn CodecDataExt> {
+        TestCodec::new(CodecType::H264)
+            .with_extra_data(&[0, 0, 0, 1, 0x67, 0x42, 0, 0, 0, 1, 0x68, 0xCE])
+            .boxed()
+    }
+
+    #[tokio::test]
+    async fn test_ts_muxer_hls_segmentation() {
+        let dir = std::env::temp_dir().join(format!("vdkio_hls_{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+
+        let segmenter = HLSSegmenter::new(&dir)
+            .with_segment_duration(Duration::from_secs(2))
+            .with_max_segments(10);
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new())).with_hls(segmenter);
+        let streams = vec![param_set_codec(), TestCodec::new(CodecType::AAC).boxed()];
+        muxer.write_header(&streams).await.unwrap();
+
+        // Audio before the first keyframe has no segment to go to
+        let audio = Packet::new(vec![0x22; 50]).with_stream_index(1).with_pts(0);
+        muxer.write_packet(&audio).await.unwrap();
+
+        // 25 fps video with a keyframe every 1.5 s, for 7 s
+        let ms = 1_000_000i64;
+        for frame in 0..175 {
+            let t = 100 + frame * 40;
+            let key = frame % 37 == 0;
+            let video = Packet::new(vec![0, 0, 0, 1, if key { 0x65 } else { 0x41 }, 0x88])
+                .with_stream_index(0)
+                .with_dts(t * ms)
+                .with_duration(Duration::from_millis(40))
+                .with_key_flag(key);
+            muxer.write_packet(&video).await.unwrap();
+            let audio = Packet::new(vec![0x22; 50])
+                .with_stream_index(1)
+                .with_pts(t * ms);
+            muxer.write_packet(&audio).await.unwrap();
+
+            if frame == 160 {
+                // Live playlist after the first two cuts
+                let playlist = tokio::fs::read_to_string(dir.join("playlist.m3u8"))
+                    .await
+                    .unwrap();
+                assert_eq!(playlist.matches("#EXTINF:2.960,").count(), 2);
+                assert!(!playlist.contains("#EXT-X-ENDLIST"));
+            }
+        }
+        muxer.write_trailer().await.unwrap();
+        assert!(muxer.stream_writer.into_inner().into_inner().is_empty());
+
+        // Keyframes every 1.48 s from 0.1 s; cuts at the first one after 2 s
+        let playlist = tokio::fs::read_to_string(dir.join("playlist.m3u8"))
+            .await
+            .unwrap();
+        assert!(playlist.contains("#EXTINF:2.960,\nstream_0.ts\n"));
+        assert!(playlist.contains("#EXTINF:2.960,\nstream_1.ts\n"));
+        assert!(playlist.contains("#EXTINF:1.080,\nstream_2.ts\n"));
+        assert!(playlist.ends_with("#EXT-X-ENDLIST\n"));
+        assert!(!dir.join(".playlist.m3u8.tmp").exists());
+
+        for sequence in 0..3 {
+            let segment = tokio::fs::read(dir.join(format!("stream_{}.ts", sequence)))
+                .await
+                .unwrap();
+            assert_eq!(segment.len() % TS_PACKET_SIZE, 0);
+
+            // PAT, PMT, then the keyframe with PCR, AUD and repeated SPS/PPS
+            let pid = |p: &[u8]| (p[1] as u16 & 0x1F) << 8 | p[2] as u16;
+            let packets: Vec<&[u8]> = segment.chunks(TS_PACKET_SIZE).collect();
+            assert_eq!(pid(packets[0]), PID_PAT);
+            assert_eq!(pid(packets[1]), PID_PMT);
+            assert_eq!(pid(packets[2]), 0x100);
+            assert_eq!(packets[2][5], 0x50); // Random access and PCR
+            let payload = &packets[2][5 + packets[2][4] as usize..];
+            let header_len = 9 + payload[8] as usize;
+            assert_eq!(
+                &payload[header_len..header_len + 24],
+                &[
+                    0, 0, 0, 1, 0x09, 0xF0, 0, 0, 0, 1, 0x67, 0x42, 0, 0, 0, 1, 0x68, 0xCE, 0, 0,
+                    0, 1, 0x65, 0x88
+                ]
+            );
+        }
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ts_muxer_multiple_programs() {
+        use crate::format::ts::{TSDemuxer, TSProgram};
+        use crate::format::Demuxer as FormatDemuxer;
+
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()))
+            .with_program(1, 0x1000, &[0, 1])
+            .with_program(7, 0x1001, &[2]);
+        let streams = vec![
+            TestCodec::new(CodecType::H264).boxed(),
+            TestCodec::new(CodecType::AAC).boxed(),
+            TestCodec::new(CodecType::AAC).boxed(),
+        ];
+        muxer.write_header(&streams).await.unwrap();
+
+        let ms = 1_000_000i64;
+        for i in 0..3 {
+            let packet = Packet::new(access_unit(0x41, 300))
+                .with_stream_index(0)
+                .with_dts(i * 40 * ms);
+            muxer.write_packet(&packet).await.unwrap();
+            for stream_index in [1, 2] {
+                let packet = Packet::new(vec![stream_index as u8; 100])
+                    .with_stream_index(stream_index)
+                    .with_dts(i * 40 * ms);
+                muxer.write_packet(&packet).await.unwrap();
+            }
+        }
+        muxer.flush().await.unwrap();
+        let output = muxer.into_inner().into_inner();
+
+        // Both programs get a PCR on their own PCR PID
+        let pcr_pids: Vec<u16> = output
+            .chunks(TS_PACKET_SIZE)
+            .filter(|p| p[3] & 0x20 != 0 && p[4] > 0 && p[5] & 0x10 != 0)
+            .map(|p| (p[1] as u16 & 0x1F) << 8 | p[2] as u16)
+            .collect();
+        assert!(pcr_pids.contains(&0x100));
+        assert!(pcr_pids.contains(&0x102));
+        assert!(!pcr_pids.contains(&0x101));
+
+        let mut demuxer = TSDemuxer::new(Cursor::new(output));
+        assert_eq!(demuxer.streams().await.unwrap().len(), 3);
+        assert_eq!(
+            demuxer.programs(),
+            vec![
+                TSProgram {
+                    program_number: 1,
+                    pmt_pid: 0x1000,
+                    pcr_pid: 0x100,
+                    stream_indices: vec![0, 1],
+                },
+                TSProgram {
+                    program_number: 7,
+                    pmt_pid: 0x1001,
+                    pcr_pid: 0x102,
+                    stream_indices: vec![2],
+                },
+            ]
+        );
+
+        let mut count = 0;
+        while let Ok(packet) = demuxer.read_packet().await {
+            if packet.stream_index > 0 {
+                assert_eq!(packet.data[0] as usize, packet.stream_index);
+            }
+            count += 1;
+        }
+        assert_eq!(count, 9);
+
+        // Streams must be assigned to a program
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new())).with_program(1, 0x1000, &[0]);
+        assert!(muxer.write_header(&streams).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ts_muxer_si_tables() {
+        use crate::format::ts::si::*;
+        use crate::format::ts::{TSDemuxer, TSEvent};
+        use crate::format::Demuxer as FormatDemuxer;
+        use chrono::{TimeZone, Utc};
+
+        let service = ServiceDescriptor {
+            service_type: SERVICE_TYPE_DIGITAL_TV,
+            provider_name: "vdkio".into(),
+            service_name: "Test".into(),
+        };
+        let sdt = SDT {
+            transport_stream_id: 1,
+            original_network_id: 1,
+            version: 0,
+            services: vec![SDTService {
+                service_id: 1,
+                eit_schedule: false,
+                eit_present_following: false,
+                running_status: RUNNING_STATUS_RUNNING,
+                free_ca_mode: false,
+                descriptors: vec![service.to_descriptor().unwrap()],
+            }],
+        };
+        let nit = NIT {
+            network_id: 1,
+            version: 0,
+            descriptors: Vec::new(),
+            transport_streams: Vec::new(),
+        };
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()))
+            .with_si_table(SITable::Sdt(sdt.clone()), Duration::from_millis(500))
+            .with_si_table(SITable::Nit(nit), Duration::from_secs(10))
+            .with_si_table(
+                SITable::Tdt(TDT { utc_time: start }),
+                Duration::from_secs(1),
+            );
+        let streams = vec![TestCodec::new(CodecType::H264).boxed()];
+        muxer.write_header(&streams).await.unwrap();
+
+        let ms = 1_000_000i64;
+        for i in 0..60 {
+            let packet = Packet::new(access_unit(0x41, 300))
+                .with_stream_index(0)
+                .with_dts(i * 40 * ms);
+            muxer.write_packet(&packet).await.unwrap();
+            if i == 30 {
+                let mut sdt = sdt.clone();
+                sdt.version = 1;
+                sdt.services[0].running_status = RUNNING_STATUS_NOT_RUNNING;
+                muxer.update_si_table(SITable::Sdt(sdt)).unwrap();
+            }
+        }
+        muxer.flush().await.unwrap();
+        let output = muxer.into_inner().into_inner();
+
+        // The SDT is repeated every 500 ms on its own PID
+        let sdt_packets = output
+            .chunks(TS_PACKET_SIZE)
+            .filter(|p| (p[1] as u16 & 0x1F) << 8 | p[2] as u16 == PID_SDT)
+            .count();
+        assert_eq!(sdt_packets, 6);
+
+        let mut demuxer = TSDemuxer::new(Cursor::new(output));
+        demuxer.streams().await.unwrap();
+        while demuxer.read_packet().await.is_ok() {}
+        let tables: Vec<SITable> = demuxer
+            .take_events()
+            .into_iter()
+            .filter_map(|event| match event {
+                TSEvent::ServiceInformation(table) => Some(table),
+                _ => None,
+            })
+            .collect();
+
+        // Repetitions are reported once, changes again
+        let sdts: Vec<&SDT> = tables
+            .iter()
+            .filter_map(|table| match table {
+                SITable::Sdt(sdt) => Some(sdt),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sdts.len(), 2);
+        assert_eq!(sdts[0], &sdt);
+        assert_eq!(sdts[0].services[0].service_descriptor(), Some(service));
+        assert_eq!(sdts[1].version, 1);
+        assert!(tables.iter().any(|table| matches!(table, SITable::Nit(_))));
+
+        // The TDT advances with the PCR
+        let times: Vec<_> = tables
+            .iter()
+            .filter_map(|table| match table {
+                SITable::Tdt(tdt) => Some(tdt.utc_time),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(times.len(), 3);
+        assert_eq!(times[0], start);
+        assert_eq!(times[1] - times[0], chrono::Duration::seconds(1));
+        assert!(demuxer.take_events().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ts_muxer_scte35_events() {
+        use crate::format::ts::scte35::*;
+        use crate::format::ts::{TSDemuxer, TSEvent};
+        use crate::format::Demuxer as FormatDemuxer;
+
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new())).with_scte35(0x1F0);
+        let streams = vec![TestCodec::new(CodecType::H264).boxed()];
+        muxer.write_header(&streams).await.unwrap();
+
+        let ms = 1_000_000i64;
+        let splice = SpliceInfoSection::new(SpliceCommand::Insert(
+            SpliceInsert::new(1, true, 90_000).with_break_duration(30 * 90_000, true),
+        ));
+        for i in 0..50 {
+            let packet = Packet::new(access_unit(0x41, 100))
+                .with_stream_index(0)
+                .with_dts(i * 40 * ms);
+            muxer.write_packet(&packet).await.unwrap();
+            if i == 10 {
+                muxer.write_splice(&splice).await.unwrap();
+            }
+        }
+        muxer.flush().await.unwrap();
+        let output = muxer.into_inner().into_inner();
+
+        let mut demuxer = TSDemuxer::new(Cursor::new(output));
+        assert_eq!(demuxer.streams().await.unwrap().len(), 1);
+        while demuxer.read_packet().await.is_ok() {}
+        assert_eq!(
+            demuxer.take_events(),
+            vec![TSEvent::Splice {
+                program_number: 1,
+                pts: Some(1_000_000_000),
+                section: splice,
+            }]
+        );
+
+        // Splices need a PID
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()));
+        muxer.write_header(&streams).await.unwrap();
+        let null = SpliceInfoSection::new(SpliceCommand::Null);
+        assert!(muxer.write_splice(&null).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ts_muxer_id3_metadata() {
+        use crate::codec::id3::{ID3CodecData, ID3Frame, ID3Tag};
+        use crate::format::ts::TSDemuxer;
+        use crate::format::Demuxer as FormatDemuxer;
+
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()));
+        let streams = vec![
+            TestCodec::new(CodecType::H264).boxed(),
+            Box::new(ID3CodecData),
+        ];
+        muxer.write_header(&streams).await.unwrap();
+
+        // The PCR stays on video, the PMT announces the ID3 stream
+        let pmt = &muxer.programs[0].pmt;
+        assert_eq!(pmt.pcr_pid, 0x100);
+        assert_eq!(
+            pmt.program_descriptors,
+            vec![Descriptor::id3_metadata_pointer(1)]
+        );
+        assert_eq!(
+            pmt.elementary_stream_infos[1].stream_type,
+            STREAM_TYPE_METADATA
+        );
+        assert_eq!(
+            pmt.elementary_stream_infos[1].descriptors,
+            vec![Descriptor::id3_metadata()]
+        );
+
+        let ms = 1_000_000i64;
+        let tag = ID3Tag::new()
+            .with_frame(ID3Frame::private("com.example.id", &[1, 2]))
+            .with_frame(ID3Frame::user_text("title", "Live"));
+        for i in 0..10 {
+            let video = Packet::new(access_unit(0x41, 100))
+                .with_stream_index(0)
+                .with_dts(i * 40 * ms);
+            muxer.write_packet(&video).await.unwrap();
+            if i == 5 {
+                // Without timestamps, metadata gets the last video PTS
+                let metadata = Packet::new(tag.to_bytes().unwrap()).with_stream_index(1);
+                muxer.write_packet(&metadata).await.unwrap();
+            }
+        }
+        muxer.flush().await.unwrap();
+        let output = muxer.into_inner().into_inner();
+
+        let mut demuxer = TSDemuxer::new(Cursor::new(output));
+        let codecs = demuxer.streams().await.unwrap();
+        assert_eq!(codecs.len(), 2);
+        assert_eq!(codecs[1].codec_type(), av::CodecType::ID3);
+
+        let mut metadata = Vec::new();
+        while let Ok(packet) = demuxer.read_packet().await {
+            if packet.stream_index == 1 {
+                metadata.push(packet);
+            }
+        }
+        assert_eq!(metadata.len(), 1);
+        assert_eq!(metadata[0].pts, Some(200 * ms));
+        assert_eq!(metadata[0].duration, None);
+        assert_eq!(ID3Tag::parse(&metadata[0].data).unwrap(), tag);
+    }
+
+    #[tokio::test]
+    async fn test_ts_muxer_scte35_hls() {
+        use crate::format::ts::scte35::*;
+        use chrono::{TimeZone, Utc};
+
+        let dir = std::env::temp_dir().join(format!("vdkio_scte35_{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+
+        let segmenter = HLSSegmenter::new(&dir)
+            .with_segment_duration(Duration::from_secs(2))
+            .with_max_segments(10)
+            .with_program_date_time(Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap());
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()))
+            .with_hls(segmenter)
+            .with_scte35(0x1F0);
+        let streams = vec![param_set_codec()];
+        muxer.write_header(&streams).await.unwrap();
+
+        // Keyframes every 1.6 s, a break from 3 s to 5 s, 7 s in total
+        let ms = 1_000_000i64;
+        for frame in 0..175 {
+            let key = frame % 40 == 0;
+            let video = Packet::new(vec![0, 0, 0, 1, if key { 0x65 } else { 0x41 }, 0x88])
+                .with_stream_index(0)
+                .with_dts(frame * 40 * ms)
+                .with_duration(Duration::from_millis(40))
+                .with_key_flag(key);
+            muxer.write_packet(&video).await.unwrap();
+
+            if frame == 25 {
+                let out = SpliceInfoSection::new(SpliceCommand::Insert(
+                    SpliceInsert::new(9, true, 3 * 90_000).with_break_duration(2 * 90_000, true),
+                ));
+                muxer.write_splice(&out).await.unwrap();
+            }
+            if frame == 100 {
+                let back = SpliceInfoSection::new(SpliceCommand::TimeSignal {
+                    pts_time: Some(5 * 90_000),
+                })
+                .with_descriptor(SpliceDescriptor::Segmentation(
+                    SegmentationDescriptor::new(9, SEGMENTATION_BREAK_END, None),
+                ));
+                muxer.write_splice(&back).await.unwrap();
+            }
+        }
+        muxer.write_trailer().await.unwrap();
+
+        // Cuts at the splice points, between keyframe cuts
+        let playlist = tokio::fs::read_to_string(dir.join("playlist.m3u8"))
+            .await
+            .unwrap();
+        assert!(playlist.contains(concat!(
+            "#EXTINF:3.000,\nstream_0.ts\n",
+            "#EXT-X-PROGRAM-DATE-TIME:2024-01-01T12:00:03.000Z\n",
+            "#EXT-X-DATERANGE:ID=\"splice-9\",START-DATE=\"2024-01-01T12:00:03.000Z\",",
+            "PLANNED-DURATION=2.000,SCTE35-OUT=0xFC"
+        )));
+        assert!(playlist.contains(concat!(
+            "\n#EXT-X-CUE-OUT:2.000\n#EXTINF:2.000,\nstream_1.ts\n",
+            "#EXT-X-PROGRAM-DATE-TIME:2024-01-01T12:00:05.000Z\n",
+            "#EXT-X-CUE-IN\n#EXTINF:2.000,\nstream_2.ts\n"
+        )));
+
+        // The splice sections are in the segments
+        let segment = tokio::fs::read(dir.join("stream_0.ts")).await.unwrap();
+        assert!(segment
+            .chunks(TS_PACKET_SIZE)
+            .any(|p| (p[1] as u16 & 0x1F) << 8 | p[2] as u16 == 0x1F0));
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ts_muxer_cbr() {
+        use crate::format::ts::parser::TSPacketParser;
+        use crate::format::ts::{TR101290Check, TSAnalyzer};
+
+        const RATE: u64 = 2_000_000;
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new())).with_mux_rate(RATE);
+        let streams = vec![
+            TestCodec::new(CodecType::H264).boxed(),
+            TestCodec::new(CodecType::AAC).boxed(),
+        ];
+        muxer.write_header(&streams).await.unwrap();
+
+        // 1 Mbit/s of video at 25 fps and 120 kbit/s of audio
+        let ms = 1_000_000i64;
+        for t in (0..100).map(|i| 1000 + i * 20) {
+            if t % 40 == 0 {
+                let video = Packet::new(access_unit(0x65, 5000))
+                    .with_stream_index(0)
+                    .with_dts(t * ms)
+                    .with_key_flag((t - 1000) % 1000 == 0);
+                muxer.write_packet(&video).await.unwrap();
+            }
+            let audio = Packet::new(vec![0x22; 300])
+                .with_stream_index(1)
+                .with_pts(t * ms);
+            muxer.write_packet(&audio).await.unwrap();
+        }
+        let output = muxer.stream_writer.into_inner().into_inner();
+
+        // Two seconds at the mux rate, starting 100 ms before the first DTS
+        let duration = output.len() as u64 * 8 * 1000 / RATE;
+        assert!((1900..=2100).contains(&duration), "{} ms", duration);
+
+        let parser = TSPacketParser::new();
+        let mut nulls = 0;
+        let mut pcrs = Vec::new();
+        for (i, packet) in output.chunks(TS_PACKET_SIZE).enumerate() {
+            let header = parser.parse_header(packet).unwrap();
+            if header.pid == PID_NULL {
+                nulls += 1;
+            }
+            if let Some(field) = parser.parse_adaptation_field(packet, 4).unwrap() {
+                if let Some(pcr) = field.pcr {
+                    pcrs.push((pcr, (i * TS_PACKET_SIZE) as u64));
+                }
+            }
+        }
+        assert!(nulls > 100);
+
+        // Every PCR matches its byte position at the mux rate
+        // The clock starts at the first DTS - offset, after the initial PAT/PMT
+        assert!((900 * 27_000..902 * 27_000).contains(&pcrs[0].0));
+        let first = pcrs[0];
+        for &(pcr, offset) in &pcrs {
+            let expected = first.0 + (offset - first.1) * 8 * 27_000_000 / RATE;
+            assert!(pcr.abs_diff(expected) <= 1, "PCR at {}", offset);
+        }
+
+        let mut analyzer = TSAnalyzer::new();
+        analyzer.push(&output);
+        let analysis = analyzer.report();
+        // Measured between the first and the last PCR, counting all bytes
+        assert!(analysis.bitrate.unwrap().abs_diff(RATE) < RATE / 20);
+        for check in [
+            TR101290Check::PCRAccuracy,
+            TR101290Check::PCRRepetition,
+            TR101290Check::PAT,
+            TR101290Check::ContinuityCount,
+        ] {
+            assert!(!analysis.errors.contains_key(&check), "{:?}", check);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ts_muxer_cbr_limits() {
+        let streams = vec![
+            TestCodec::new(CodecType::H264).boxed(),
+            TestCodec::new(CodecType::AAC).boxed(),
+        ];
+        let ms = 1_000_000i64;
+
+        // 1 Mbit/s of video does not fit in 500 kbit/s
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new())).with_mux_rate(500_000);
+        muxer.write_header(&streams).await.unwrap();
+        let mut result = Ok(());
+        for t in (0..25).map(|i| 1000 + i * 40) {
+            let video = Packet::new(access_unit(0x41, 5000))
+                .with_stream_index(0)
+                .with_dts(t * ms);
+            result = muxer.write_packet(&video).await;
+            if result.is_err() {
+                break;
+            }
+        }
+        assert!(
+            matches!(result, Err(VdkError::InvalidData(message)) if message.contains("too low"))
+        );
+
+        // Audio frames wait for room in the 3584 byte AAC buffer
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new())).with_mux_rate(2_000_000);
+        muxer.write_header(&streams).await.unwrap();
+        for t in (0..5).map(|i| 1000 + i * 20) {
+            let audio = Packet::new(vec![0x22; 1500])
+                .with_stream_index(1)
+                .with_pts(t * ms);
+            muxer.write_packet(&audio).await.unwrap();
+        }
+        // Two frames fit before the first one is decoded at 1000 ms
+        assert!(muxer.current_pcr >= Duration::from_millis(1020));
+        let audio = Packet::new(vec![0x22; 4000])
+            .with_stream_index(1)
+            .with_pts(1100 * ms);
+        assert!(muxer.write_packet(&audio).await.is_err());
+
+        // A configured buffer size replaces the default
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()))
+            .with_mux_rate(2_000_000)
+            .with_buffer_size(0, 1000);
+        muxer.write_header(&streams).await.unwrap();
+        let video = Packet::new(access_unit(0x41, 5000))
+            .with_stream_index(0)
+            .with_dts(1000 * ms);
+        assert!(muxer.write_packet(&video).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ts_muxer_m2ts() {
+        use crate::format::ts::parser::TSPacketParser;
+
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new())).with_m2ts();
+        let streams = vec![TestCodec::new(CodecType::H264).boxed()];
+        muxer.write_header(&streams).await.unwrap();
+        let ms = 1_000_000i64;
+        for i in 0..5 {
+            let packet = Packet::new(access_unit(0x41, 1000))
+                .with_stream_index(0)
+                .with_dts((1000 + i * 40) * ms);
+            muxer.write_packet(&packet).await.unwrap();
+        }
+        let output = muxer.stream_writer.into_inner().into_inner();
+        assert_eq!(output.len() % M2TS_PACKET_SIZE, 0);
+
+        let parser = TSPacketParser::new();
+        let mut arrivals = Vec::new();
+        let mut pcrs = 0;
+        for packet in output.chunks(M2TS_PACKET_SIZE) {
+            let arrival = u32::from_be_bytes([packet[0], packet[1], packet[2], packet[3]]);
+            arrivals.push(arrival);
+            let ts_packet = &packet[4..];
+            assert_eq!(ts_packet[0], TS_SYNC_BYTE);
+            if let Some(field) = parser.parse_adaptation_field(ts_packet, 4).unwrap() {
+                if let Some(pcr) = field.pcr {
+                    // Arrival timestamps run on the PCR clock
+                    assert_eq!(arrival, pcr as u32 & ARRIVAL_TIMESTAMP_MASK);
+                    pcrs += 1;
+                }
+            }
+        }
+        assert!(pcrs >= 5);
+        assert!(arrivals.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
 }
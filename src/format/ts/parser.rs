@@ -201,10 +201,16 @@ impl TSPacketParser {
         let section_length = ((data[1] as usize & 0x0F) << 8) | data[2] as usize;
         let total_length = 3 + section_length;
 
+        // Header fields up to program_info_length, and the CRC
+        if section_length < 13 {
+            return Err(VdkError::InvalidData("PMT section too short".into()));
+        }
+
         if data.len() < total_length {
             return Err(VdkError::InvalidData("PMT data shorter than section length".into()));
         }
 
+        pmt.program_number = ((data[3] as u16) << 8) | data[4] as u16;
         let mut pos = 8;
 
         pmt.pcr_pid = ((data[pos] as u16 & 0x1F) << 8) | data[pos + 1] as u16;
@@ -244,7 +250,8 @@ impl TSPacketParser {
         Ok(pmt)
     }
 
-    fn parse_descriptors(&self, data: &[u8]) -> Result<Vec<Descriptor>> {
+    /// Parses a descriptor loop of a PSI section.
+    pub(crate) fn parse_descriptors(&self, data: &[u8]) -> Result<Vec<Descriptor>> {
         let mut descriptors = Vec::new();
         let mut pos = 0;
 
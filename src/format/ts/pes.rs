@@ -1,5 +1,5 @@
 use super::types::time_to_pts;
-use crate::error::Result;
+use crate::error::{Result, VdkError};
 use bytes::{BufMut, BytesMut};
 use std::time::Duration;
 
@@ -110,6 +110,74 @@ impl PESHeader {
         self
     }
 
+    /// Parses a PES header from the start of a PES packet.
+    ///
+    /// # Arguments
+    /// * `data` - PES packet data starting with the start code prefix.
+    ///
+    /// # Returns
+    /// The parsed header, or an error if the data is too short or invalid.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < 6 {
+            return Err(VdkError::InvalidData("PES header too short".into()));
+        }
+        if data[0..3] != [0x00, 0x00, 0x01] {
+            return Err(VdkError::InvalidData(
+                "Invalid PES start code prefix".into(),
+            ));
+        }
+
+        let mut header = Self::new(data[3]);
+        header.packet_length = u16::from_be_bytes([data[4], data[5]]);
+        if !has_optional_header(header.stream_id) {
+            return Ok(header);
+        }
+
+        if data.len() < 9 {
+            return Err(VdkError::InvalidData("PES header too short".into()));
+        }
+        if data[6] & 0xC0 != 0x80 {
+            return Err(VdkError::InvalidData(
+                "Invalid PES header marker bits".into(),
+            ));
+        }
+        header.scrambling_control = (data[6] >> 4) & 0x03;
+        header.priority = data[6] & 0x08 != 0;
+        header.data_alignment = data[6] & 0x04 != 0;
+        header.copyright = data[6] & 0x02 != 0;
+        header.original = data[6] & 0x01 != 0;
+
+        header.pts_dts_flags = data[7] & 0xC0;
+        header.escr_flag = data[7] & 0x20 != 0;
+        header.es_rate_flag = data[7] & 0x10 != 0;
+        header.dsm_trick_mode_flag = data[7] & 0x08 != 0;
+        header.additional_copy_info_flag = data[7] & 0x04 != 0;
+        header.crc_flag = data[7] & 0x02 != 0;
+        header.extension_flag = data[7] & 0x01 != 0;
+        header.header_data_length = data[8];
+
+        if data.len() < header.header_len() {
+            return Err(VdkError::InvalidData("PES header data too short".into()));
+        }
+        if header.pts_dts_flags & 0x80 != 0 {
+            header.pts = Some(read_timestamp(&data[9..])?);
+        }
+        if header.pts_dts_flags == 0xC0 {
+            header.dts = Some(read_timestamp(&data[14..])?);
+        }
+
+        Ok(header)
+    }
+
+    /// Returns the size of the header in bytes, where the payload starts.
+    pub fn header_len(&self) -> usize {
+        if has_optional_header(self.stream_id) {
+            9 + self.header_data_length as usize
+        } else {
+            6
+        }
+    }
+
     /// Writes the PES header to a BytesMut buffer.
     ///
     /// This method encodes all header fields into the buffer according to MPEG-TS PES syntax.
@@ -131,26 +199,24 @@ impl PESHeader {
         // PES packet length (2 bytes)
         buf.put_u16(self.packet_length);
 
-        // Flags (1 byte)
-        let mut flags = 0u8;
-        flags |= self.scrambling_control << 6;
+        // Flags (1 byte): '10' marker, scrambling, priority, alignment, copyright, original
+        let mut flags = 0x80 | (self.scrambling_control & 0x03) << 4;
         if self.priority {
-            flags |= 0x20;
+            flags |= 0x08;
         }
         if self.data_alignment {
-            flags |= 0x10;
+            flags |= 0x04;
         }
         if self.copyright {
-            flags |= 0x08;
+            flags |= 0x02;
         }
         if self.original {
-            flags |= 0x04;
+            flags |= 0x01;
         }
-        flags |= self.pts_dts_flags;
         buf.put_u8(flags);
 
-        // Additional flags (1 byte)
-        let mut flags2 = 0u8;
+        // Flags (1 byte): PTS/DTS flags followed by the optional field flags
+        let mut flags2 = self.pts_dts_flags & 0xC0;
         if self.escr_flag {
             flags2 |= 0x20;
         }
@@ -171,8 +237,10 @@ impl PESHeader {
         }
         buf.put_u8(flags2);
 
-        // Header data length (1 byte)
-        buf.put_u8(self.header_data_length);
+        // Header data length (1 byte), never shorter than the timestamps it carries
+        let timestamps_len = self.timestamps_len();
+        let header_data_length = self.header_data_length.max(timestamps_len);
+        buf.put_u8(header_data_length);
 
         // Write PTS if present
         if let Some(pts) = self.pts {
@@ -185,8 +253,18 @@ impl PESHeader {
             write_timestamp(buf, 0x10, dts)?;
         }
 
+        // Stuffing for any remaining optional header bytes
+        for _ in timestamps_len..header_data_length {
+            buf.put_u8(0xFF);
+        }
+
         Ok(())
     }
+
+    /// Returns the number of header data bytes used by PTS and DTS.
+    fn timestamps_len(&self) -> u8 {
+        (if self.pts.is_some() { 5 } else { 0 }) + (if self.dts.is_some() { 5 } else { 0 })
+    }
 }
 
 /// Represents a complete Packetized Elementary Stream (PES) packet.
@@ -255,14 +333,13 @@ impl PESPacket {
     /// Returns the total length of the PES packet in bytes.
     ///
     /// This includes the fixed header size, optional PTS/DTS fields,
-    /// and the payload length.
+    /// header stuffing and the payload length.
     ///
     /// # Returns
     /// Length in bytes of the complete PES packet
     pub fn len(&self) -> usize {
         9 + // Fixed PES header size
-        (if self.header.pts.is_some() { 5 } else { 0 }) + // PTS size
-        (if self.header.dts.is_some() { 5 } else { 0 }) + // DTS size
+        self.header.header_data_length.max(self.header.timestamps_len()) as usize + // PTS, DTS and stuffing
         self.payload.len()
     }
 }
@@ -291,6 +368,30 @@ fn write_timestamp(buf: &mut BytesMut, marker: u8, ts: u64) -> Result<()> {
     Ok(())
 }
 
+/// Reads a 33-bit PTS/DTS timestamp with its marker bits.
+fn read_timestamp(data: &[u8]) -> Result<u64> {
+    if data.len() < 5 {
+        return Err(VdkError::InvalidData("PES timestamp too short".into()));
+    }
+
+    Ok((((data[0] as u64) >> 1) & 0x07) << 30
+        | (data[1] as u64) << 22
+        | ((data[2] as u64) >> 1) << 15
+        | (data[3] as u64) << 7
+        | (data[4] as u64) >> 1)
+}
+
+/// Returns true if PES packets of this stream carry the optional header.
+///
+/// Padding, private stream 2, ECM/EMM, DSMCC, H.222.1 type E and the
+/// program stream directory only have the 6-byte fixed header.
+fn has_optional_header(stream_id: u8) -> bool {
+    !matches!(
+        stream_id,
+        0xBC | 0xBE | 0xBF | 0xF0 | 0xF1 | 0xF2 | 0xF8 | 0xFF
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -321,5 +422,56 @@ mod tests {
 
         // Verify stream ID
         assert_eq!(buf[3], 0xe0);
+
+        // '10' marker, PTS only flag and header data length
+        assert_eq!(buf[6], 0x80);
+        assert_eq!(buf[7], 0x80);
+        assert_eq!(buf[8], 5);
+        assert_eq!(buf.len(), 9 + 5 + 10);
+        assert_eq!(buf.len(), packet.len());
+    }
+
+    #[test]
+    fn test_pes_timestamps_encoding() {
+        let mut buf = BytesMut::new();
+        let packet = PESPacket::new(0xe0, vec![])
+            .with_pts(Duration::from_secs(2))
+            .with_dts(Duration::from_secs(1));
+        packet.write_to(&mut buf).unwrap();
+
+        assert_eq!(buf[7], 0xC0);
+        assert_eq!(buf[8], 10);
+        // PTS 180000 with '0011' prefix, DTS 90000 with '0001' prefix
+        assert_eq!(&buf[9..14], &[0x31, 0x00, 0x0B, 0x7E, 0x41]);
+        assert_eq!(&buf[14..19], &[0x11, 0x00, 0x05, 0xBF, 0x21]);
+    }
+
+    #[test]
+    fn test_pes_header_parsing() {
+        let mut buf = BytesMut::new();
+        let mut packet = PESPacket::new(0xc0, vec![0xAB; 4])
+            .with_pts(Duration::from_secs(2))
+            .with_dts(Duration::from_secs(1));
+        packet.header.data_alignment = true;
+        packet.header.header_data_length = 12;
+        packet.write_to(&mut buf).unwrap();
+
+        let header = PESHeader::parse(&buf).unwrap();
+        assert_eq!(header.stream_id, 0xc0);
+        assert!(header.data_alignment);
+        assert_eq!(header.pts, Some(180_000));
+        assert_eq!(header.dts, Some(90_000));
+        assert_eq!(header.header_len(), 9 + 12);
+        assert_eq!(&buf[header.header_len()..], &[0xAB; 4]);
+
+        // The largest 33-bit timestamp survives the marker bits
+        let mut buf = BytesMut::new();
+        let mut packet = PESPacket::new(0xe0, vec![]);
+        packet.header.pts = Some(0x1_FFFF_FFFF);
+        packet.header.pts_dts_flags = 0x80;
+        packet.write_to(&mut buf).unwrap();
+        assert_eq!(PESHeader::parse(&buf).unwrap().pts, Some(0x1_FFFF_FFFF));
+
+        assert!(PESHeader::parse(&[0x00, 0x00, 0x02, 0xe0, 0, 0, 0x80, 0, 0]).is_err());
     }
 }
@@ -0,0 +1,732 @@
+use super::si::SectionReader;
+use crate::error::{Result, VdkError};
+use crate::utils::crc::Crc32Mpeg2;
+use bytes::{BufMut, BytesMut};
+use std::time::Duration;
+
+/// Table ID of the splice_info_section
+pub const TABLE_ID_SCTE35: u8 = 0xFC;
+
+/// Format identifier `CUEI`, used in the PMT registration descriptor and
+/// in splice descriptors
+pub const SCTE35_IDENTIFIER: u32 = 0x4355_4549;
+
+/// Tag of the registration_descriptor announcing SCTE-35 in a PMT
+pub const DESCRIPTOR_REGISTRATION: u8 = 0x05;
+
+/// Tag of the segmentation_descriptor
+pub const SPLICE_DESCRIPTOR_SEGMENTATION: u8 = 0x02;
+
+/// splice_command_type of splice_null
+pub const SPLICE_COMMAND_NULL: u8 = 0x00;
+/// splice_command_type of splice_insert
+pub const SPLICE_COMMAND_INSERT: u8 = 0x05;
+/// splice_command_type of time_signal
+pub const SPLICE_COMMAND_TIME_SIGNAL: u8 = 0x06;
+
+/// Segmentation type of a break start
+pub const SEGMENTATION_BREAK_START: u8 = 0x22;
+/// Segmentation type of a break end
+pub const SEGMENTATION_BREAK_END: u8 = 0x23;
+/// Segmentation type of a provider advertisement start
+pub const SEGMENTATION_PROVIDER_AD_START: u8 = 0x30;
+/// Segmentation type of a provider advertisement end
+pub const SEGMENTATION_PROVIDER_AD_END: u8 = 0x31;
+/// Segmentation type of a provider placement opportunity start
+pub const SEGMENTATION_PROVIDER_PLACEMENT_START: u8 = 0x34;
+/// Segmentation type of a provider placement opportunity end
+pub const SEGMENTATION_PROVIDER_PLACEMENT_END: u8 = 0x35;
+/// Segmentation type of a distributor placement opportunity start
+pub const SEGMENTATION_DISTRIBUTOR_PLACEMENT_START: u8 = 0x36;
+/// Segmentation type of a distributor placement opportunity end
+pub const SEGMENTATION_DISTRIBUTOR_PLACEMENT_END: u8 = 0x37;
+
+/// Range of 33-bit PTS values
+const PTS_WRAP: u64 = 1 << 33;
+
+/// Largest section_length of a splice_info_section
+const MAX_SECTION_LENGTH: usize = 4093;
+
+/// Duration of a break, as signalled by `break_duration()`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BreakDuration {
+    /// Whether the splice back into the network happens automatically
+    pub auto_return: bool,
+    /// Duration in 90 kHz ticks
+    pub duration: u64,
+}
+
+/// A splice_insert command, announcing a splice out of or back into the network.
+///
+/// Only program splice mode is supported, component splices are rejected
+/// when parsing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpliceInsert {
+    /// ID of the splice event
+    pub event_id: u32,
+    /// Whether the command cancels a previously announced event
+    pub cancel: bool,
+    /// `true` for a splice out of the network (ad break start), `false` for
+    /// the return to the network
+    pub out_of_network: bool,
+    /// Whether the splice happens at the next opportunity instead of `pts_time`
+    pub splice_immediate: bool,
+    /// Splice time in 90 kHz ticks, before `pts_adjustment`
+    pub pts_time: Option<u64>,
+    /// Duration of the break
+    pub break_duration: Option<BreakDuration>,
+    /// ID of the program, unique within the network
+    pub unique_program_id: u16,
+    /// Number of the avail within the program
+    pub avail_num: u8,
+    /// Number of avails expected within the program
+    pub avails_expected: u8,
+}
+
+impl SpliceInsert {
+    /// Creates a splice_insert at `pts_time` with the remaining fields zeroed.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_id` - ID of the splice event
+    /// * `out_of_network` - `true` to start a break, `false` to end it
+    /// * `pts_time` - Splice time in 90 kHz ticks
+    pub fn new(event_id: u32, out_of_network: bool, pts_time: u64) -> Self {
+        Self {
+            event_id,
+            cancel: false,
+            out_of_network,
+            splice_immediate: false,
+            pts_time: Some(pts_time),
+            break_duration: None,
+            unique_program_id: 0,
+            avail_num: 0,
+            avails_expected: 0,
+        }
+    }
+
+    /// Sets the duration of the break.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - Break duration in 90 kHz ticks
+    /// * `auto_return` - Whether the return to the network is automatic
+    pub fn with_break_duration(mut self, duration: u64, auto_return: bool) -> Self {
+        self.break_duration = Some(BreakDuration {
+            auto_return,
+            duration,
+        });
+        self
+    }
+}
+
+/// A splice command of a splice_info_section.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpliceCommand {
+    /// splice_null, e.g. a heartbeat
+    Null,
+    /// splice_insert
+    Insert(SpliceInsert),
+    /// time_signal, qualified by segmentation descriptors
+    TimeSignal {
+        /// Signal time in 90 kHz ticks, before `pts_adjustment`
+        pts_time: Option<u64>,
+    },
+    /// Any other command, kept as is
+    Other {
+        /// splice_command_type
+        command_type: u8,
+        /// Command bytes
+        data: Vec<u8>,
+    },
+}
+
+/// A segmentation_descriptor, describing the segment a time_signal starts or ends.
+///
+/// Only program segmentation is supported, component segmentation is
+/// rejected when parsing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentationDescriptor {
+    /// ID of the segmentation event
+    pub event_id: u32,
+    /// Whether the descriptor cancels a previously announced event
+    pub cancel: bool,
+    /// Delivery restriction flags (web_delivery_allowed, no_regional_blackout,
+    /// archive_allowed and device_restrictions) as the low 5 bits, `None`
+    /// when delivery is not restricted
+    pub delivery_restrictions: Option<u8>,
+    /// Duration of the segment in 90 kHz ticks
+    pub duration: Option<u64>,
+    /// Type of `upid`
+    pub upid_type: u8,
+    /// Unique program identifier
+    pub upid: Vec<u8>,
+    /// Segmentation type, e.g. [`SEGMENTATION_PROVIDER_PLACEMENT_START`]
+    pub type_id: u8,
+    /// Number of this segment
+    pub segment_num: u8,
+    /// Number of segments expected
+    pub segments_expected: u8,
+    /// Sub-segment number and count, for placement opportunities
+    pub sub_segment: Option<(u8, u8)>,
+}
+
+impl SegmentationDescriptor {
+    /// Creates a descriptor for a segmentation event without UPID.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_id` - ID of the segmentation event
+    /// * `type_id` - Segmentation type
+    /// * `duration` - Duration of the segment in 90 kHz ticks
+    pub fn new(event_id: u32, type_id: u8, duration: Option<u64>) -> Self {
+        Self {
+            event_id,
+            cancel: false,
+            delivery_restrictions: None,
+            duration,
+            upid_type: 0,
+            upid: Vec::new(),
+            type_id,
+            segment_num: 0,
+            segments_expected: 0,
+            sub_segment: None,
+        }
+    }
+
+    /// Returns `Some(true)` for segmentation types starting a break or an
+    /// advertisement, `Some(false)` for those ending one, and `None` for
+    /// other types such as program or chapter boundaries.
+    pub fn is_out(&self) -> Option<bool> {
+        match self.type_id {
+            // Starts are even, ends odd
+            SEGMENTATION_BREAK_START..=SEGMENTATION_BREAK_END | 0x30..=0x3F => {
+                Some(self.type_id & 1 == 0)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A splice_descriptor following the splice command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpliceDescriptor {
+    /// segmentation_descriptor
+    Segmentation(SegmentationDescriptor),
+    /// Any other descriptor, kept as is
+    Other {
+        /// splice_descriptor_tag
+        tag: u8,
+        /// Format identifier, usually [`SCTE35_IDENTIFIER`]
+        identifier: u32,
+        /// Descriptor bytes after the identifier
+        data: Vec<u8>,
+    },
+}
+
+/// An SCTE-35 splice_info_section.
+///
+/// Splice times are 33-bit PTS values in 90 kHz ticks on the timeline of
+/// the program, after adding `pts_adjustment`. Encrypted sections are not
+/// supported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpliceInfoSection {
+    /// Offset added to every splice time, in 90 kHz ticks
+    pub pts_adjustment: u64,
+    /// Authorization tier (12 bits), 0xFFF when unused
+    pub tier: u16,
+    /// The splice command
+    pub command: SpliceCommand,
+    /// Descriptors qualifying the command
+    pub descriptors: Vec<SpliceDescriptor>,
+}
+
+impl SpliceInfoSection {
+    /// Creates a section carrying `command` without descriptors.
+    pub fn new(command: SpliceCommand) -> Self {
+        Self {
+            pts_adjustment: 0,
+            tier: 0xFFF,
+            command,
+            descriptors: Vec::new(),
+        }
+    }
+
+    /// Adds a descriptor qualifying the command.
+    pub fn with_descriptor(mut self, descriptor: SpliceDescriptor) -> Self {
+        self.descriptors.push(descriptor);
+        self
+    }
+
+    /// Returns the splice time with `pts_adjustment` applied, in 90 kHz
+    /// ticks, or `None` for immediate splices and commands without time.
+    pub fn splice_pts(&self) -> Option<u64> {
+        let pts_time = match &self.command {
+            SpliceCommand::Insert(insert) if !insert.cancel && !insert.splice_immediate => {
+                insert.pts_time
+            }
+            SpliceCommand::TimeSignal { pts_time } => *pts_time,
+            _ => None,
+        }?;
+        Some((pts_time + self.pts_adjustment) % PTS_WRAP)
+    }
+
+    /// Returns `Some(true)` when the section starts a break, `Some(false)`
+    /// when it ends one, and `None` otherwise.
+    ///
+    /// A splice_insert is judged by `out_of_network`, a time_signal by its
+    /// first segmentation descriptor of a break or advertisement type.
+    pub fn is_out(&self) -> Option<bool> {
+        match &self.command {
+            SpliceCommand::Insert(insert) if !insert.cancel => Some(insert.out_of_network),
+            SpliceCommand::TimeSignal { .. } => self
+                .break_descriptor()
+                .and_then(SegmentationDescriptor::is_out),
+            _ => None,
+        }
+    }
+
+    /// Returns the ID of the splice or segmentation event.
+    pub fn event_id(&self) -> Option<u32> {
+        match &self.command {
+            SpliceCommand::Insert(insert) => Some(insert.event_id),
+            SpliceCommand::TimeSignal { .. } => self
+                .break_descriptor()
+                .map(|descriptor| descriptor.event_id),
+            _ => None,
+        }
+    }
+
+    /// Returns the signalled duration of the break.
+    pub fn duration(&self) -> Option<Duration> {
+        let ticks = match &self.command {
+            SpliceCommand::Insert(insert) => insert.break_duration.map(|d| d.duration),
+            SpliceCommand::TimeSignal { .. } => self.break_descriptor()?.duration,
+            _ => None,
+        }?;
+        Some(Duration::from_nanos(ticks * 100_000 / 9))
+    }
+
+    /// Returns the first segmentation descriptor of a break or advertisement.
+    fn break_descriptor(&self) -> Option<&SegmentationDescriptor> {
+        self.descriptors
+            .iter()
+            .find_map(|descriptor| match descriptor {
+                SpliceDescriptor::Segmentation(segmentation)
+                    if !segmentation.cancel && segmentation.is_out().is_some() =>
+                {
+                    Some(segmentation)
+                }
+                _ => None,
+            })
+    }
+
+    /// Serializes the section, from `table_id` up to and including the CRC.
+    ///
+    /// # Arguments
+    ///
+    /// * `crc` - CRC calculator for the `CRC_32` field
+    ///
+    /// # Returns
+    ///
+    /// The section bytes, or an error if a field is out of range
+    pub fn to_bytes(&self, crc: &Crc32Mpeg2) -> Result<Vec<u8>> {
+        let mut command = BytesMut::new();
+        let command_type = match &self.command {
+            SpliceCommand::Null => SPLICE_COMMAND_NULL,
+            SpliceCommand::Insert(insert) => {
+                write_splice_insert(&mut command, insert);
+                SPLICE_COMMAND_INSERT
+            }
+            SpliceCommand::TimeSignal { pts_time } => {
+                write_splice_time(&mut command, *pts_time);
+                SPLICE_COMMAND_TIME_SIGNAL
+            }
+            SpliceCommand::Other { command_type, data } => {
+                command.put_slice(data);
+                *command_type
+            }
+        };
+
+        let mut descriptors = BytesMut::new();
+        for descriptor in &self.descriptors {
+            write_descriptor(&mut descriptors, descriptor)?;
+        }
+
+        // Everything after section_length, including the CRC
+        let section_length = 11 + command.len() + 2 + descriptors.len() + 4;
+        if section_length > MAX_SECTION_LENGTH || command.len() > 0xFFF {
+            return Err(VdkError::InvalidData(
+                "Splice info section too large".into(),
+            ));
+        }
+
+        let mut buf = BytesMut::with_capacity(3 + section_length);
+        buf.put_u8(TABLE_ID_SCTE35);
+        // No section syntax, not private, SAP type not specified
+        buf.put_u16(0x3000 | section_length as u16);
+        buf.put_u8(0); // protocol_version
+                       // Not encrypted, with the 33-bit pts_adjustment
+        buf.put_uint(self.pts_adjustment % PTS_WRAP, 5);
+        buf.put_u8(0xFF); // cw_index
+        buf.put_uint(((self.tier as u64 & 0xFFF) << 12) | command.len() as u64, 3);
+        buf.put_u8(command_type);
+        buf.put_slice(&command);
+        buf.put_u16(descriptors.len() as u16);
+        buf.put_slice(&descriptors);
+        let crc = crc.calculate(&buf);
+        buf.put_u32(crc);
+        Ok(buf.to_vec())
+    }
+
+    /// Parses a splice_info_section and checks its CRC.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Section bytes starting at `table_id`
+    /// * `crc` - CRC calculator for the `CRC_32` field
+    ///
+    /// # Returns
+    ///
+    /// The parsed section, or an error if it is corrupt, encrypted or uses
+    /// component splice mode
+    pub fn parse(data: &[u8], crc: &Crc32Mpeg2) -> Result<Self> {
+        if data.len() < 3 || data[0] != TABLE_ID_SCTE35 {
+            return Err(VdkError::InvalidData("Not a splice info section".into()));
+        }
+        let len = 3 + ((((data[1] & 0x0F) as usize) << 8) | data[2] as usize);
+        let data = data
+            .get(..len)
+            .filter(|data| data.len() >= 20)
+            .ok_or_else(|| VdkError::InvalidData("Splice info section truncated".into()))?;
+        if crc.calculate(data) != 0 {
+            return Err(VdkError::InvalidData(
+                "Splice info section CRC mismatch".into(),
+            ));
+        }
+
+        let mut reader = SectionReader::new(&data[3..data.len() - 4]);
+        reader.u8()?; // protocol_version
+        let adjustment = reader.bytes(5)?;
+        if adjustment[0] & 0x80 != 0 {
+            return Err(VdkError::InvalidData(
+                "Encrypted splice info sections are not supported".into(),
+            ));
+        }
+        let pts_adjustment = read_pts(adjustment);
+        reader.u8()?; // cw_index
+        let tier_and_length = reader.bytes(3)?;
+        let tier = (tier_and_length[0] as u16) << 4 | (tier_and_length[1] as u16) >> 4;
+        let command_length =
+            ((tier_and_length[1] as usize & 0x0F) << 8) | tier_and_length[2] as usize;
+        let command_type = reader.u8()?;
+
+        let command = match command_type {
+            SPLICE_COMMAND_NULL => SpliceCommand::Null,
+            SPLICE_COMMAND_INSERT => SpliceCommand::Insert(read_splice_insert(&mut reader)?),
+            SPLICE_COMMAND_TIME_SIGNAL => SpliceCommand::TimeSignal {
+                pts_time: read_splice_time(&mut reader)?,
+            },
+            // The length is 0xFFF in legacy streams, only for known commands
+            _ => SpliceCommand::Other {
+                command_type,
+                data: reader.bytes(command_length)?.to_vec(),
+            },
+        };
+
+        let descriptor_loop_length = reader.u16()? as usize;
+        let mut descriptors = SectionReader::new(reader.bytes(descriptor_loop_length)?);
+        let mut parsed = Vec::new();
+        while descriptors.remaining() > 0 {
+            let tag = descriptors.u8()?;
+            let len = descriptors.u8()? as usize;
+            let mut descriptor = SectionReader::new(descriptors.bytes(len)?);
+            let identifier = descriptor.u32()?;
+            parsed.push(match tag {
+                SPLICE_DESCRIPTOR_SEGMENTATION if identifier == SCTE35_IDENTIFIER => {
+                    SpliceDescriptor::Segmentation(read_segmentation(&mut descriptor)?)
+                }
+                _ => SpliceDescriptor::Other {
+                    tag,
+                    identifier,
+                    data: descriptor.bytes(descriptor.remaining())?.to_vec(),
+                },
+            });
+        }
+
+        Ok(Self {
+            pts_adjustment,
+            tier,
+            command,
+            descriptors: parsed,
+        })
+    }
+}
+
+/// Reads a 33-bit value from the low bits of 5 bytes.
+fn read_pts(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0u64, |value, &byte| value << 8 | byte as u64)
+        % PTS_WRAP
+}
+
+fn write_splice_time(buf: &mut BytesMut, pts_time: Option<u64>) {
+    match pts_time {
+        // time_specified_flag and 6 reserved bits before the 33-bit time
+        Some(pts) => buf.put_uint(0xFE_0000_0000 | (pts % PTS_WRAP), 5),
+        None => buf.put_u8(0x7F),
+    }
+}
+
+fn read_splice_time(reader: &mut SectionReader) -> Result<Option<u64>> {
+    if reader.peek()? & 0x80 == 0 {
+        reader.u8()?;
+        return Ok(None);
+    }
+    Ok(Some(read_pts(reader.bytes(5)?)))
+}
+
+fn write_splice_insert(buf: &mut BytesMut, insert: &SpliceInsert) {
+    buf.put_u32(insert.event_id);
+    buf.put_u8((insert.cancel as u8) << 7 | 0x7F);
+    if insert.cancel {
+        return;
+    }
+    // Program splice mode, reserved bits set
+    buf.put_u8(
+        (insert.out_of_network as u8) << 7
+            | 0x40
+            | (insert.break_duration.is_some() as u8) << 5
+            | (insert.splice_immediate as u8) << 4
+            | 0x0F,
+    );
+    if !insert.splice_immediate {
+        write_splice_time(buf, insert.pts_time);
+    }
+    if let Some(duration) = insert.break_duration {
+        buf.put_uint(
+            (duration.auto_return as u64) << 39 | 0x7E_0000_0000 | (duration.duration % PTS_WRAP),
+            5,
+        );
+    }
+    buf.put_u16(insert.unique_program_id);
+    buf.put_u8(insert.avail_num);
+    buf.put_u8(insert.avails_expected);
+}
+
+fn read_splice_insert(reader: &mut SectionReader) -> Result<SpliceInsert> {
+    let event_id = reader.u32()?;
+    let cancel = reader.u8()? & 0x80 != 0;
+    let mut insert = SpliceInsert {
+        event_id,
+        cancel,
+        out_of_network: false,
+        splice_immediate: false,
+        pts_time: None,
+        break_duration: None,
+        unique_program_id: 0,
+        avail_num: 0,
+        avails_expected: 0,
+    };
+    if cancel {
+        return Ok(insert);
+    }
+
+    let flags = reader.u8()?;
+    if flags & 0x40 == 0 {
+        return Err(VdkError::InvalidData(
+            "Component splice mode is not supported".into(),
+        ));
+    }
+    insert.out_of_network = flags & 0x80 != 0;
+    insert.splice_immediate = flags & 0x10 != 0;
+    if !insert.splice_immediate {
+        insert.pts_time = read_splice_time(reader)?;
+    }
+    if flags & 0x20 != 0 {
+        let bytes = reader.bytes(5)?;
+        insert.break_duration = Some(BreakDuration {
+            auto_return: bytes[0] & 0x80 != 0,
+            duration: read_pts(bytes),
+        });
+    }
+    insert.unique_program_id = reader.u16()?;
+    insert.avail_num = reader.u8()?;
+    insert.avails_expected = reader.u8()?;
+    Ok(insert)
+}
+
+fn write_descriptor(buf: &mut BytesMut, descriptor: &SpliceDescriptor) -> Result<()> {
+    let mut body = BytesMut::new();
+    let tag = match descriptor {
+        SpliceDescriptor::Segmentation(segmentation) => {
+            body.put_u32(SCTE35_IDENTIFIER);
+            write_segmentation(&mut body, segmentation)?;
+            SPLICE_DESCRIPTOR_SEGMENTATION
+        }
+        SpliceDescriptor::Other {
+            tag,
+            identifier,
+            data,
+        } => {
+            body.put_u32(*identifier);
+            body.put_slice(data);
+            *tag
+        }
+    };
+    let len = u8::try_from(body.len())
+        .map_err(|_| VdkError::InvalidData("Splice descriptor too long".into()))?;
+    buf.put_u8(tag);
+    buf.put_u8(len);
+    buf.put_slice(&body);
+    Ok(())
+}
+
+fn write_segmentation(buf: &mut BytesMut, segmentation: &SegmentationDescriptor) -> Result<()> {
+    buf.put_u32(segmentation.event_id);
+    buf.put_u8((segmentation.cancel as u8) << 7 | 0x7F);
+    if segmentation.cancel {
+        return Ok(());
+    }
+    // Program segmentation mode
+    let restrictions = match segmentation.delivery_restrictions {
+        Some(flags) => flags & 0x1F,
+        None => 0x3F,
+    };
+    buf.put_u8(0x80 | (segmentation.duration.is_some() as u8) << 6 | restrictions);
+    if let Some(duration) = segmentation.duration {
+        buf.put_uint(duration & 0xFF_FFFF_FFFF, 5);
+    }
+    let upid_len = u8::try_from(segmentation.upid.len())
+        .map_err(|_| VdkError::InvalidData("Segmentation UPID too long".into()))?;
+    buf.put_u8(segmentation.upid_type);
+    buf.put_u8(upid_len);
+    buf.put_slice(&segmentation.upid);
+    buf.put_u8(segmentation.type_id);
+    buf.put_u8(segmentation.segment_num);
+    buf.put_u8(segmentation.segments_expected);
+    if let Some((num, expected)) = segmentation.sub_segment {
+        buf.put_u8(num);
+        buf.put_u8(expected);
+    }
+    Ok(())
+}
+
+fn read_segmentation(reader: &mut SectionReader) -> Result<SegmentationDescriptor> {
+    let event_id = reader.u32()?;
+    let cancel = reader.u8()? & 0x80 != 0;
+    let mut segmentation = SegmentationDescriptor::new(event_id, 0, None);
+    segmentation.cancel = cancel;
+    if cancel {
+        return Ok(segmentation);
+    }
+
+    let flags = reader.u8()?;
+    if flags & 0x80 == 0 {
+        return Err(VdkError::InvalidData(
+            "Component segmentation is not supported".into(),
+        ));
+    }
+    if flags & 0x20 == 0 {
+        segmentation.delivery_restrictions = Some(flags & 0x1F);
+    }
+    if flags & 0x40 != 0 {
+        let bytes = reader.bytes(5)?;
+        segmentation.duration = Some(
+            bytes
+                .iter()
+                .fold(0u64, |value, &byte| value << 8 | byte as u64),
+        );
+    }
+    segmentation.upid_type = reader.u8()?;
+    let upid_len = reader.u8()? as usize;
+    segmentation.upid = reader.bytes(upid_len)?.to_vec();
+    segmentation.type_id = reader.u8()?;
+    segmentation.segment_num = reader.u8()?;
+    segmentation.segments_expected = reader.u8()?;
+    // Only present for placement opportunities in recent revisions
+    if reader.remaining() >= 2 {
+        segmentation.sub_segment = Some((reader.u8()?, reader.u8()?));
+    }
+    Ok(segmentation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_time_signal_parsing() {
+        // Placement opportunity start, sample 14.2 of SCTE 35
+        let data = hex(concat!(
+            "FC3034000000000000FFFFF00506FE72BD0050001E021C435545494800008E7F",
+            "CF0001A599B00808000000002CA0A18A3402009AC9D17E"
+        ));
+        let crc = Crc32Mpeg2::new();
+        let section = SpliceInfoSection::parse(&data, &crc).unwrap();
+
+        assert_eq!(
+            section.command,
+            SpliceCommand::TimeSignal {
+                pts_time: Some(0x72BD0050)
+            }
+        );
+        let SpliceDescriptor::Segmentation(segmentation) = &section.descriptors[0] else {
+            panic!("expected a segmentation descriptor");
+        };
+        assert_eq!(segmentation.event_id, 0x4800008E);
+        assert_eq!(segmentation.type_id, SEGMENTATION_PROVIDER_PLACEMENT_START);
+        assert_eq!(segmentation.duration, Some(0x0001A599B0));
+        assert_eq!(segmentation.delivery_restrictions, Some(0x0F));
+        assert_eq!(segmentation.upid, hex("000000002CA0A18A"));
+        assert_eq!(segmentation.segment_num, 2);
+
+        assert_eq!(section.splice_pts(), Some(0x72BD0050));
+        assert_eq!(section.is_out(), Some(true));
+        assert_eq!(section.event_id(), Some(0x4800008E));
+        assert_eq!(section.duration(), Some(Duration::from_millis(307_000)));
+
+        // Serializing gives back the same bytes
+        assert_eq!(section.to_bytes(&crc).unwrap(), data);
+    }
+
+    #[test]
+    fn test_splice_insert_roundtrip() {
+        let crc = Crc32Mpeg2::new();
+        let mut section = SpliceInfoSection::new(SpliceCommand::Insert(
+            SpliceInsert::new(7, true, PTS_WRAP - 90_000).with_break_duration(30 * 90_000, true),
+        ));
+        section.pts_adjustment = 180_000;
+
+        let data = section.to_bytes(&crc).unwrap();
+        assert_eq!(crc.calculate(&data), 0);
+        let parsed = SpliceInfoSection::parse(&data, &crc).unwrap();
+        assert_eq!(parsed, section);
+
+        // The adjustment wraps around
+        assert_eq!(parsed.splice_pts(), Some(90_000));
+        assert_eq!(parsed.duration(), Some(Duration::from_secs(30)));
+
+        let back_in = SpliceInfoSection::new(SpliceCommand::Insert(SpliceInsert {
+            splice_immediate: true,
+            pts_time: None,
+            ..SpliceInsert::new(7, false, 0)
+        }));
+        let parsed = SpliceInfoSection::parse(&back_in.to_bytes(&crc).unwrap(), &crc).unwrap();
+        assert_eq!(parsed, back_in);
+        assert_eq!(parsed.is_out(), Some(false));
+        assert_eq!(parsed.splice_pts(), None);
+
+        let mut corrupt = data;
+        corrupt[15] ^= 0x01;
+        assert!(SpliceInfoSection::parse(&corrupt, &crc).is_err());
+    }
+}
@@ -0,0 +1,1043 @@
+use super::types::Descriptor;
+use crate::error::{Result, VdkError};
+use crate::utils::crc::Crc32Mpeg2;
+use bytes::{BufMut, BytesMut};
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+
+/// PID of the Network Information Table
+pub const PID_NIT: u16 = 0x0010;
+/// PID of the Service Description Table
+pub const PID_SDT: u16 = 0x0011;
+/// PID of the Event Information Table
+pub const PID_EIT: u16 = 0x0012;
+/// PID of the Time and Date Table and the Time Offset Table
+pub const PID_TDT: u16 = 0x0014;
+
+/// Table ID of the NIT describing the actual network
+pub const TABLE_ID_NIT: u8 = 0x40;
+/// Table ID of the SDT describing the actual transport stream
+pub const TABLE_ID_SDT: u8 = 0x42;
+/// Table ID of the EIT present/following of the actual transport stream
+pub const TABLE_ID_EIT_PF: u8 = 0x4E;
+/// Table ID of the Time and Date Table
+pub const TABLE_ID_TDT: u8 = 0x70;
+/// Table ID of the Time Offset Table
+pub const TABLE_ID_TOT: u8 = 0x73;
+
+/// Tag of the network_name_descriptor
+pub const DESCRIPTOR_NETWORK_NAME: u8 = 0x40;
+/// Tag of the service_descriptor
+pub const DESCRIPTOR_SERVICE: u8 = 0x48;
+/// Tag of the short_event_descriptor
+pub const DESCRIPTOR_SHORT_EVENT: u8 = 0x4D;
+
+/// Service type of a digital television service
+pub const SERVICE_TYPE_DIGITAL_TV: u8 = 0x01;
+/// Service type of a digital radio service
+pub const SERVICE_TYPE_DIGITAL_RADIO: u8 = 0x02;
+
+/// Running status of a service or event that is not running
+pub const RUNNING_STATUS_NOT_RUNNING: u8 = 1;
+/// Running status of a service or event that is running
+pub const RUNNING_STATUS_RUNNING: u8 = 4;
+
+/// Largest section_length of an SI section
+const MAX_SECTION_LENGTH: usize = 4093;
+
+/// Modified Julian Date of the Unix epoch
+const MJD_UNIX_EPOCH: i64 = 40587;
+
+/// A long-form section (`section_syntax_indicator` set) protected by a CRC.
+///
+/// This is the container of every SI table except TDT and TOT. Tables build
+/// their payload, the part between `last_section_number` and `CRC_32`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    /// Table ID
+    pub table_id: u8,
+    /// Table ID extension, e.g. the transport stream ID of an SDT
+    pub table_id_extension: u16,
+    /// Version number (5 bits), incremented when the content changes
+    pub version: u8,
+    /// Number of this section
+    pub section_number: u8,
+    /// Number of the last section of the table
+    pub last_section_number: u8,
+    /// Table data after the section header, without CRC
+    pub payload: Vec<u8>,
+}
+
+impl Section {
+    /// Serializes the section, from `table_id` up to and including the CRC.
+    ///
+    /// # Arguments
+    ///
+    /// * `crc` - CRC calculator for the `CRC_32` field
+    ///
+    /// # Returns
+    ///
+    /// The section bytes, or an error if the payload is too large
+    pub fn to_bytes(&self, crc: &Crc32Mpeg2) -> Result<Vec<u8>> {
+        // Extension, version, section numbers and CRC
+        let section_length = 5 + self.payload.len() + 4;
+        if section_length > MAX_SECTION_LENGTH {
+            return Err(VdkError::InvalidData(format!(
+                "Section of table 0x{:02x} too large: {} bytes",
+                self.table_id, section_length
+            )));
+        }
+
+        let mut buf = BytesMut::with_capacity(3 + section_length);
+        buf.put_u8(self.table_id);
+        // section_syntax_indicator, reserved_future_use and reserved bits
+        buf.put_u16(0xF000 | section_length as u16);
+        buf.put_u16(self.table_id_extension);
+        buf.put_u8(0xC1 | (self.version & 0x1F) << 1);
+        buf.put_u8(self.section_number);
+        buf.put_u8(self.last_section_number);
+        buf.put_slice(&self.payload);
+        let crc = crc.calculate(&buf);
+        buf.put_u32(crc);
+        Ok(buf.to_vec())
+    }
+
+    /// Parses a long-form section and checks its CRC.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Section bytes starting at `table_id`
+    /// * `crc` - CRC calculator for the `CRC_32` field
+    ///
+    /// # Returns
+    ///
+    /// The parsed section, or an error if it is truncated, short-form or corrupt
+    pub fn parse(data: &[u8], crc: &Crc32Mpeg2) -> Result<Self> {
+        let data = section_bytes(data)?;
+        if data[1] & 0x80 == 0 {
+            return Err(VdkError::InvalidData("Not a long-form section".into()));
+        }
+        if data.len() < 12 {
+            return Err(VdkError::InvalidData("Section too short".into()));
+        }
+        if crc.calculate(data) != 0 {
+            return Err(VdkError::InvalidData("Section CRC mismatch".into()));
+        }
+
+        Ok(Self {
+            table_id: data[0],
+            table_id_extension: u16::from_be_bytes([data[3], data[4]]),
+            version: (data[5] >> 1) & 0x1F,
+            section_number: data[6],
+            last_section_number: data[7],
+            payload: data[8..data.len() - 4].to_vec(),
+        })
+    }
+}
+
+/// service_descriptor naming a service and its provider.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceDescriptor {
+    /// Service type, e.g. [`SERVICE_TYPE_DIGITAL_TV`]
+    pub service_type: u8,
+    /// Name of the service provider
+    pub provider_name: String,
+    /// Name of the service
+    pub service_name: String,
+}
+
+impl ServiceDescriptor {
+    /// Encodes the descriptor.
+    pub fn to_descriptor(&self) -> Result<Descriptor> {
+        let mut data = vec![self.service_type];
+        put_text(&mut data, &self.provider_name)?;
+        put_text(&mut data, &self.service_name)?;
+        Ok(Descriptor {
+            tag: DESCRIPTOR_SERVICE,
+            data,
+        })
+    }
+
+    /// Decodes a service_descriptor, returning `None` for other or corrupt descriptors.
+    pub fn from_descriptor(descriptor: &Descriptor) -> Option<Self> {
+        if descriptor.tag != DESCRIPTOR_SERVICE {
+            return None;
+        }
+        let mut reader = SectionReader::new(&descriptor.data);
+        Some(Self {
+            service_type: reader.u8().ok()?,
+            provider_name: reader.text().ok()?,
+            service_name: reader.text().ok()?,
+        })
+    }
+}
+
+/// short_event_descriptor giving the name and a description of an event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShortEventDescriptor {
+    /// ISO 639-2 language code, e.g. "eng"
+    pub language: String,
+    /// Name of the event
+    pub event_name: String,
+    /// Short description of the event
+    pub text: String,
+}
+
+impl ShortEventDescriptor {
+    /// Encodes the descriptor.
+    pub fn to_descriptor(&self) -> Result<Descriptor> {
+        let mut data = language_code(&self.language)?.to_vec();
+        put_text(&mut data, &self.event_name)?;
+        put_text(&mut data, &self.text)?;
+        Ok(Descriptor {
+            tag: DESCRIPTOR_SHORT_EVENT,
+            data,
+        })
+    }
+
+    /// Decodes a short_event_descriptor, returning `None` for other or corrupt descriptors.
+    pub fn from_descriptor(descriptor: &Descriptor) -> Option<Self> {
+        if descriptor.tag != DESCRIPTOR_SHORT_EVENT {
+            return None;
+        }
+        let mut reader = SectionReader::new(&descriptor.data);
+        Some(Self {
+            language: String::from_utf8_lossy(reader.bytes(3).ok()?).into_owned(),
+            event_name: reader.text().ok()?,
+            text: reader.text().ok()?,
+        })
+    }
+}
+
+/// network_name_descriptor naming the delivery network.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkNameDescriptor {
+    /// Name of the network
+    pub name: String,
+}
+
+impl NetworkNameDescriptor {
+    /// Encodes the descriptor.
+    pub fn to_descriptor(&self) -> Result<Descriptor> {
+        let data = encode_text(&self.name);
+        if data.len() > 255 {
+            return Err(VdkError::InvalidData("Network name too long".into()));
+        }
+        Ok(Descriptor {
+            tag: DESCRIPTOR_NETWORK_NAME,
+            data,
+        })
+    }
+
+    /// Decodes a network_name_descriptor, returning `None` for other descriptors.
+    pub fn from_descriptor(descriptor: &Descriptor) -> Option<Self> {
+        (descriptor.tag == DESCRIPTOR_NETWORK_NAME).then(|| Self {
+            name: decode_text(&descriptor.data),
+        })
+    }
+}
+
+/// A service entry of the SDT.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SDTService {
+    /// Service ID, equal to the program number in the PAT
+    pub service_id: u16,
+    /// Whether EIT schedule information is broadcast for the service
+    pub eit_schedule: bool,
+    /// Whether EIT present/following information is broadcast for the service
+    pub eit_present_following: bool,
+    /// Running status, e.g. [`RUNNING_STATUS_RUNNING`]
+    pub running_status: u8,
+    /// Whether the service is scrambled
+    pub free_ca_mode: bool,
+    /// Service descriptors, usually including a service_descriptor
+    pub descriptors: Vec<Descriptor>,
+}
+
+impl SDTService {
+    /// Returns the first service_descriptor of the service.
+    pub fn service_descriptor(&self) -> Option<ServiceDescriptor> {
+        self.descriptors
+            .iter()
+            .find_map(ServiceDescriptor::from_descriptor)
+    }
+}
+
+/// Service Description Table of the actual transport stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SDT {
+    /// Transport stream ID, as in the PAT
+    pub transport_stream_id: u16,
+    /// Original network ID
+    pub original_network_id: u16,
+    /// Version number (5 bits)
+    pub version: u8,
+    /// Services of the transport stream
+    pub services: Vec<SDTService>,
+}
+
+impl SDT {
+    /// Serializes the table as a single section.
+    pub fn to_section(&self, crc: &Crc32Mpeg2) -> Result<Vec<u8>> {
+        let mut payload = BytesMut::new();
+        payload.put_u16(self.original_network_id);
+        payload.put_u8(0xFF); // reserved_future_use
+        for service in &self.services {
+            payload.put_u16(service.service_id);
+            payload.put_u8(
+                0xFC | (service.eit_schedule as u8) << 1 | service.eit_present_following as u8,
+            );
+            put_descriptors(
+                &mut payload,
+                (service.running_status & 0x07) << 5 | (service.free_ca_mode as u8) << 4,
+                &service.descriptors,
+            )?;
+        }
+
+        Section {
+            table_id: TABLE_ID_SDT,
+            table_id_extension: self.transport_stream_id,
+            version: self.version,
+            section_number: 0,
+            last_section_number: 0,
+            payload: payload.to_vec(),
+        }
+        .to_bytes(crc)
+    }
+
+    /// Parses an SDT section.
+    pub fn parse(data: &[u8], crc: &Crc32Mpeg2) -> Result<Self> {
+        let section = parse_table(data, crc, TABLE_ID_SDT)?;
+        let mut reader = SectionReader::new(&section.payload);
+        let original_network_id = reader.u16()?;
+        reader.bytes(1)?;
+
+        let mut services = Vec::new();
+        while reader.remaining() > 0 {
+            let service_id = reader.u16()?;
+            let flags = reader.u8()?;
+            let status = reader.peek()?;
+            services.push(SDTService {
+                service_id,
+                eit_schedule: flags & 0x02 != 0,
+                eit_present_following: flags & 0x01 != 0,
+                running_status: status >> 5,
+                free_ca_mode: status & 0x10 != 0,
+                descriptors: reader.descriptors()?,
+            });
+        }
+
+        Ok(Self {
+            transport_stream_id: section.table_id_extension,
+            original_network_id,
+            version: section.version,
+            services,
+        })
+    }
+}
+
+/// A transport stream entry of the NIT.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NITTransportStream {
+    /// Transport stream ID
+    pub transport_stream_id: u16,
+    /// Original network ID
+    pub original_network_id: u16,
+    /// Transport stream descriptors, e.g. delivery system descriptors
+    pub descriptors: Vec<Descriptor>,
+}
+
+/// Network Information Table of the actual network.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NIT {
+    /// Network ID
+    pub network_id: u16,
+    /// Version number (5 bits)
+    pub version: u8,
+    /// Network descriptors, usually including a network_name_descriptor
+    pub descriptors: Vec<Descriptor>,
+    /// Transport streams of the network
+    pub transport_streams: Vec<NITTransportStream>,
+}
+
+impl NIT {
+    /// Returns the name from the network_name_descriptor.
+    pub fn network_name(&self) -> Option<String> {
+        self.descriptors
+            .iter()
+            .find_map(NetworkNameDescriptor::from_descriptor)
+            .map(|descriptor| descriptor.name)
+    }
+
+    /// Serializes the table as a single section.
+    pub fn to_section(&self, crc: &Crc32Mpeg2) -> Result<Vec<u8>> {
+        let mut payload = BytesMut::new();
+        put_descriptors(&mut payload, 0xF0, &self.descriptors)?;
+
+        let mut streams = BytesMut::new();
+        for stream in &self.transport_streams {
+            streams.put_u16(stream.transport_stream_id);
+            streams.put_u16(stream.original_network_id);
+            put_descriptors(&mut streams, 0xF0, &stream.descriptors)?;
+        }
+        payload.put_u16(0xF000 | loop_length(streams.len())?);
+        payload.put_slice(&streams);
+
+        Section {
+            table_id: TABLE_ID_NIT,
+            table_id_extension: self.network_id,
+            version: self.version,
+            section_number: 0,
+            last_section_number: 0,
+            payload: payload.to_vec(),
+        }
+        .to_bytes(crc)
+    }
+
+    /// Parses a NIT section.
+    pub fn parse(data: &[u8], crc: &Crc32Mpeg2) -> Result<Self> {
+        let section = parse_table(data, crc, TABLE_ID_NIT)?;
+        let mut reader = SectionReader::new(&section.payload);
+        let descriptors = reader.descriptors()?;
+
+        let loop_length = (reader.u16()? & 0x0FFF) as usize;
+        let mut streams = SectionReader::new(reader.bytes(loop_length)?);
+        let mut transport_streams = Vec::new();
+        while streams.remaining() > 0 {
+            transport_streams.push(NITTransportStream {
+                transport_stream_id: streams.u16()?,
+                original_network_id: streams.u16()?,
+                descriptors: streams.descriptors()?,
+            });
+        }
+
+        Ok(Self {
+            network_id: section.table_id_extension,
+            version: section.version,
+            descriptors,
+            transport_streams,
+        })
+    }
+}
+
+/// An event of the EIT.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EITEvent {
+    /// Event ID, unique within the service
+    pub event_id: u16,
+    /// Start time of the event
+    pub start_time: DateTime<Utc>,
+    /// Duration of the event, at most 99:59:59
+    pub duration: Duration,
+    /// Running status, e.g. [`RUNNING_STATUS_RUNNING`]
+    pub running_status: u8,
+    /// Whether the event is scrambled
+    pub free_ca_mode: bool,
+    /// Event descriptors, usually including a short_event_descriptor
+    pub descriptors: Vec<Descriptor>,
+}
+
+impl EITEvent {
+    /// Returns the first short_event_descriptor of the event.
+    pub fn short_event(&self) -> Option<ShortEventDescriptor> {
+        self.descriptors
+            .iter()
+            .find_map(ShortEventDescriptor::from_descriptor)
+    }
+}
+
+/// Event Information Table present/following section of the actual
+/// transport stream.
+///
+/// Section 0 carries the present event and section 1 the following one,
+/// so a service's EIT present/following is a pair of these tables.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EIT {
+    /// Service ID the events belong to
+    pub service_id: u16,
+    /// Transport stream ID
+    pub transport_stream_id: u16,
+    /// Original network ID
+    pub original_network_id: u16,
+    /// Version number (5 bits)
+    pub version: u8,
+    /// 0 for the present event, 1 for the following event
+    pub section_number: u8,
+    /// The present or following event, if any
+    pub events: Vec<EITEvent>,
+}
+
+impl EIT {
+    /// Serializes the table as a single section.
+    pub fn to_section(&self, crc: &Crc32Mpeg2) -> Result<Vec<u8>> {
+        let mut payload = BytesMut::new();
+        payload.put_u16(self.transport_stream_id);
+        payload.put_u16(self.original_network_id);
+        payload.put_u8(1); // segment_last_section_number
+        payload.put_u8(TABLE_ID_EIT_PF); // last_table_id
+        for event in &self.events {
+            payload.put_u16(event.event_id);
+            put_utc_time(&mut payload, &event.start_time)?;
+            put_bcd_duration(&mut payload, event.duration)?;
+            put_descriptors(
+                &mut payload,
+                (event.running_status & 0x07) << 5 | (event.free_ca_mode as u8) << 4,
+                &event.descriptors,
+            )?;
+        }
+
+        Section {
+            table_id: TABLE_ID_EIT_PF,
+            table_id_extension: self.service_id,
+            version: self.version,
+            section_number: self.section_number,
+            last_section_number: 1,
+            payload: payload.to_vec(),
+        }
+        .to_bytes(crc)
+    }
+
+    /// Parses an EIT present/following section.
+    pub fn parse(data: &[u8], crc: &Crc32Mpeg2) -> Result<Self> {
+        let section = parse_table(data, crc, TABLE_ID_EIT_PF)?;
+        let mut reader = SectionReader::new(&section.payload);
+        let transport_stream_id = reader.u16()?;
+        let original_network_id = reader.u16()?;
+        reader.bytes(2)?;
+
+        let mut events = Vec::new();
+        while reader.remaining() > 0 {
+            let event_id = reader.u16()?;
+            let start_time = reader.utc_time()?;
+            let duration = reader.bcd_duration()?;
+            let status = reader.peek()?;
+            events.push(EITEvent {
+                event_id,
+                start_time,
+                duration,
+                running_status: status >> 5,
+                free_ca_mode: status & 0x10 != 0,
+                descriptors: reader.descriptors()?,
+            });
+        }
+
+        Ok(Self {
+            service_id: section.table_id_extension,
+            transport_stream_id,
+            original_network_id,
+            version: section.version,
+            section_number: section.section_number,
+            events,
+        })
+    }
+}
+
+/// Time and Date Table carrying the current UTC time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TDT {
+    /// Current time, with a resolution of one second
+    pub utc_time: DateTime<Utc>,
+}
+
+impl TDT {
+    /// Serializes the table. The TDT is a short section without CRC.
+    pub fn to_section(&self) -> Result<Vec<u8>> {
+        let mut buf = BytesMut::with_capacity(8);
+        buf.put_u8(TABLE_ID_TDT);
+        buf.put_u16(0x7000 | 5);
+        put_utc_time(&mut buf, &self.utc_time)?;
+        Ok(buf.to_vec())
+    }
+
+    /// Parses a TDT section.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let data = section_bytes(data)?;
+        if data[0] != TABLE_ID_TDT {
+            return Err(VdkError::InvalidData("Not a TDT section".into()));
+        }
+        let mut reader = SectionReader::new(&data[3..]);
+        Ok(Self {
+            utc_time: reader.utc_time()?,
+        })
+    }
+}
+
+/// Time Offset Table carrying the current UTC time and local time offsets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TOT {
+    /// Current time, with a resolution of one second
+    pub utc_time: DateTime<Utc>,
+    /// Descriptors, usually a local_time_offset_descriptor
+    pub descriptors: Vec<Descriptor>,
+}
+
+impl TOT {
+    /// Serializes the table. The TOT is a short section with a CRC.
+    pub fn to_section(&self, crc: &Crc32Mpeg2) -> Result<Vec<u8>> {
+        let mut body = BytesMut::new();
+        put_utc_time(&mut body, &self.utc_time)?;
+        put_descriptors(&mut body, 0xF0, &self.descriptors)?;
+
+        let mut buf = BytesMut::with_capacity(3 + body.len() + 4);
+        buf.put_u8(TABLE_ID_TOT);
+        buf.put_u16(0x7000 | (body.len() + 4) as u16);
+        buf.put_slice(&body);
+        let crc = crc.calculate(&buf);
+        buf.put_u32(crc);
+        Ok(buf.to_vec())
+    }
+
+    /// Parses a TOT section and checks its CRC.
+    pub fn parse(data: &[u8], crc: &Crc32Mpeg2) -> Result<Self> {
+        let data = section_bytes(data)?;
+        if data[0] != TABLE_ID_TOT {
+            return Err(VdkError::InvalidData("Not a TOT section".into()));
+        }
+        if data.len() < 14 || crc.calculate(data) != 0 {
+            return Err(VdkError::InvalidData("Corrupt TOT section".into()));
+        }
+        let mut reader = SectionReader::new(&data[3..data.len() - 4]);
+        Ok(Self {
+            utc_time: reader.utc_time()?,
+            descriptors: reader.descriptors()?,
+        })
+    }
+}
+
+/// A DVB Service Information table.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SITable {
+    /// Network Information Table
+    Nit(NIT),
+    /// Service Description Table
+    Sdt(SDT),
+    /// Event Information Table present/following section
+    Eit(EIT),
+    /// Time and Date Table
+    Tdt(TDT),
+    /// Time Offset Table
+    Tot(TOT),
+}
+
+impl SITable {
+    /// Returns the PID carrying the table.
+    pub fn pid(&self) -> u16 {
+        match self {
+            SITable::Nit(_) => PID_NIT,
+            SITable::Sdt(_) => PID_SDT,
+            SITable::Eit(_) => PID_EIT,
+            SITable::Tdt(_) | SITable::Tot(_) => PID_TDT,
+        }
+    }
+
+    /// Serializes the table as a single section.
+    pub fn to_section(&self, crc: &Crc32Mpeg2) -> Result<Vec<u8>> {
+        match self {
+            SITable::Nit(nit) => nit.to_section(crc),
+            SITable::Sdt(sdt) => sdt.to_section(crc),
+            SITable::Eit(eit) => eit.to_section(crc),
+            SITable::Tdt(tdt) => tdt.to_section(),
+            SITable::Tot(tot) => tot.to_section(crc),
+        }
+    }
+
+    /// Parses a section of a supported table.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Section bytes starting at `table_id`
+    /// * `crc` - CRC calculator for the `CRC_32` field
+    ///
+    /// # Returns
+    ///
+    /// The table, `None` for other tables such as the SDT of other
+    /// transport streams, or an error if the section is corrupt
+    pub fn parse(data: &[u8], crc: &Crc32Mpeg2) -> Result<Option<Self>> {
+        let table = match data.first() {
+            Some(&TABLE_ID_NIT) => SITable::Nit(NIT::parse(data, crc)?),
+            Some(&TABLE_ID_SDT) => SITable::Sdt(SDT::parse(data, crc)?),
+            Some(&TABLE_ID_EIT_PF) => SITable::Eit(EIT::parse(data, crc)?),
+            Some(&TABLE_ID_TDT) => SITable::Tdt(TDT::parse(data)?),
+            Some(&TABLE_ID_TOT) => SITable::Tot(TOT::parse(data, crc)?),
+            _ => return Ok(None),
+        };
+        Ok(Some(table))
+    }
+
+    /// Returns what identifies the table among repeated and updated
+    /// versions: table ID, table ID extension and section number.
+    pub(crate) fn key(&self) -> (u8, u16, u8) {
+        match self {
+            SITable::Nit(nit) => (TABLE_ID_NIT, nit.network_id, 0),
+            SITable::Sdt(sdt) => (TABLE_ID_SDT, sdt.transport_stream_id, 0),
+            SITable::Eit(eit) => (TABLE_ID_EIT_PF, eit.service_id, eit.section_number),
+            SITable::Tdt(_) => (TABLE_ID_TDT, 0, 0),
+            SITable::Tot(_) => (TABLE_ID_TOT, 0, 0),
+        }
+    }
+
+    /// Returns the table with its time advanced by `elapsed`, for TDT and TOT.
+    pub(crate) fn advanced(&self, elapsed: Duration) -> SITable {
+        let advance = |time: &DateTime<Utc>| {
+            chrono::Duration::from_std(elapsed)
+                .ok()
+                .and_then(|elapsed| time.checked_add_signed(elapsed))
+                .unwrap_or(*time)
+        };
+        match self {
+            SITable::Tdt(tdt) => SITable::Tdt(TDT {
+                utc_time: advance(&tdt.utc_time),
+            }),
+            SITable::Tot(tot) => SITable::Tot(TOT {
+                utc_time: advance(&tot.utc_time),
+                descriptors: tot.descriptors.clone(),
+            }),
+            table => table.clone(),
+        }
+    }
+}
+
+/// Parses a long-form section and checks its table ID.
+fn parse_table(data: &[u8], crc: &Crc32Mpeg2, table_id: u8) -> Result<Section> {
+    let section = Section::parse(data, crc)?;
+    if section.table_id != table_id {
+        return Err(VdkError::InvalidData(format!(
+            "Expected table 0x{:02x}, found 0x{:02x}",
+            table_id, section.table_id
+        )));
+    }
+    Ok(section)
+}
+
+/// Returns the section bytes as given by `section_length`.
+fn section_bytes(data: &[u8]) -> Result<&[u8]> {
+    if data.len() < 3 {
+        return Err(VdkError::InvalidData("Section too short".into()));
+    }
+    let len = 3 + ((((data[1] & 0x0F) as usize) << 8) | data[2] as usize);
+    data.get(..len)
+        .ok_or_else(|| VdkError::InvalidData("Section data shorter than section length".into()))
+}
+
+/// Checks a descriptor loop length against its 12-bit field.
+fn loop_length(len: usize) -> Result<u16> {
+    u16::try_from(len)
+        .ok()
+        .filter(|&len| len <= 0x0FFF)
+        .ok_or_else(|| VdkError::InvalidData("Descriptor loop too long".into()))
+}
+
+/// Writes a descriptor loop with its 12-bit length, after the 4 bits in `high_bits`.
+fn put_descriptors(buf: &mut BytesMut, high_bits: u8, descriptors: &[Descriptor]) -> Result<()> {
+    let len = descriptors.iter().map(|d| 2 + d.data.len()).sum();
+    buf.put_u16((high_bits as u16 & 0xF0) << 8 | loop_length(len)?);
+    for descriptor in descriptors {
+        let len = u8::try_from(descriptor.data.len())
+            .map_err(|_| VdkError::InvalidData("Descriptor too long".into()))?;
+        buf.put_u8(descriptor.tag);
+        buf.put_u8(len);
+        buf.put_slice(&descriptor.data);
+    }
+    Ok(())
+}
+
+/// Writes a time as 16-bit Modified Julian Date and 24-bit BCD UTC.
+fn put_utc_time(buf: &mut BytesMut, time: &DateTime<Utc>) -> Result<()> {
+    let seconds = time.timestamp();
+    let mjd = u16::try_from(seconds.div_euclid(86_400) + MJD_UNIX_EPOCH)
+        .map_err(|_| VdkError::InvalidData("Time out of MJD range".into()))?;
+    let seconds = seconds.rem_euclid(86_400);
+    buf.put_u16(mjd);
+    buf.put_u8(to_bcd((seconds / 3600) as u8));
+    buf.put_u8(to_bcd((seconds / 60 % 60) as u8));
+    buf.put_u8(to_bcd((seconds % 60) as u8));
+    Ok(())
+}
+
+/// Writes a duration as 24-bit BCD hours, minutes and seconds.
+fn put_bcd_duration(buf: &mut BytesMut, duration: Duration) -> Result<()> {
+    let seconds = duration.as_secs();
+    if seconds >= 100 * 3600 {
+        return Err(VdkError::InvalidData("Event duration too long".into()));
+    }
+    buf.put_u8(to_bcd((seconds / 3600) as u8));
+    buf.put_u8(to_bcd((seconds / 60 % 60) as u8));
+    buf.put_u8(to_bcd((seconds % 60) as u8));
+    Ok(())
+}
+
+/// Writes a text field preceded by its 8-bit length.
+fn put_text(data: &mut Vec<u8>, text: &str) -> Result<()> {
+    let text = encode_text(text);
+    let len = u8::try_from(text.len())
+        .map_err(|_| VdkError::InvalidData("Text field too long".into()))?;
+    data.push(len);
+    data.extend_from_slice(&text);
+    Ok(())
+}
+
+/// Encodes a DVB string, ASCII as is and anything else as UTF-8.
+fn encode_text(text: &str) -> Vec<u8> {
+    let mut data = Vec::with_capacity(text.len() + 1);
+    if !text.is_ascii() {
+        // Character table selector for UTF-8
+        data.push(0x15);
+    }
+    data.extend_from_slice(text.as_bytes());
+    data
+}
+
+/// Decodes a DVB string. Other character tables than the default and
+/// UTF-8 are decoded as if they were UTF-8.
+fn decode_text(data: &[u8]) -> String {
+    let data = match data.first() {
+        Some(0x10) => data.get(3..).unwrap_or_default(),
+        Some(0x1F) => data.get(2..).unwrap_or_default(),
+        Some(&selector) if selector < 0x20 => &data[1..],
+        _ => data,
+    };
+    String::from_utf8_lossy(data).into_owned()
+}
+
+/// Checks and converts an ISO 639-2 language code.
+fn language_code(language: &str) -> Result<[u8; 3]> {
+    language
+        .as_bytes()
+        .try_into()
+        .map_err(|_| VdkError::InvalidData(format!("Invalid language code {:?}", language)))
+}
+
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+fn from_bcd(value: u8) -> Result<u8> {
+    if value >> 4 > 9 || value & 0x0F > 9 {
+        return Err(VdkError::InvalidData(format!(
+            "Invalid BCD value 0x{:02x}",
+            value
+        )));
+    }
+    Ok((value >> 4) * 10 + (value & 0x0F))
+}
+
+/// Bounds-checked reader over section data.
+pub(super) struct SectionReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SectionReader<'a> {
+    pub(super) fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub(super) fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    pub(super) fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.remaining() < len {
+            return Err(VdkError::InvalidData("Section data truncated".into()));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub(super) fn peek(&self) -> Result<u8> {
+        self.data
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| VdkError::InvalidData("Section data truncated".into()))
+    }
+
+    pub(super) fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(super) fn u16(&mut self) -> Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(super) fn u32(&mut self) -> Result<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Reads a text field preceded by its 8-bit length.
+    fn text(&mut self) -> Result<String> {
+        let len = self.u8()? as usize;
+        Ok(decode_text(self.bytes(len)?))
+    }
+
+    /// Reads a descriptor loop preceded by its 12-bit length.
+    fn descriptors(&mut self) -> Result<Vec<Descriptor>> {
+        let len = (self.u16()? & 0x0FFF) as usize;
+        let mut reader = SectionReader::new(self.bytes(len)?);
+        let mut descriptors = Vec::new();
+        while reader.remaining() > 0 {
+            let tag = reader.u8()?;
+            let len = reader.u8()? as usize;
+            descriptors.push(Descriptor {
+                tag,
+                data: reader.bytes(len)?.to_vec(),
+            });
+        }
+        Ok(descriptors)
+    }
+
+    /// Reads a 16-bit Modified Julian Date and 24-bit BCD UTC.
+    fn utc_time(&mut self) -> Result<DateTime<Utc>> {
+        let mjd = self.u16()? as i64;
+        let seconds = self.bcd_duration()?.as_secs() as i64;
+        DateTime::from_timestamp((mjd - MJD_UNIX_EPOCH) * 86_400 + seconds, 0)
+            .ok_or_else(|| VdkError::InvalidData("Invalid UTC time".into()))
+    }
+
+    /// Reads 24-bit BCD hours, minutes and seconds.
+    fn bcd_duration(&mut self) -> Result<Duration> {
+        let hours = from_bcd(self.u8()?)? as u64;
+        let minutes = from_bcd(self.u8()?)? as u64;
+        let seconds = from_bcd(self.u8()?)? as u64;
+        Ok(Duration::from_secs(hours * 3600 + minutes * 60 + seconds))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_utc_time_encoding() {
+        // Example from EN 300 468 annex C: 93/10/13 12:45:00
+        let time = Utc.with_ymd_and_hms(1993, 10, 13, 12, 45, 0).unwrap();
+        let mut buf = BytesMut::new();
+        put_utc_time(&mut buf, &time).unwrap();
+        assert_eq!(&buf[..], &[0xC0, 0x79, 0x12, 0x45, 0x00]);
+        assert_eq!(SectionReader::new(&buf).utc_time().unwrap(), time);
+    }
+
+    #[test]
+    fn test_sdt_and_nit_roundtrip() {
+        let crc = Crc32Mpeg2::new();
+        let service = ServiceDescriptor {
+            service_type: SERVICE_TYPE_DIGITAL_TV,
+            provider_name: "vdkio".into(),
+            service_name: "Nachrichten für alle".into(),
+        };
+        let sdt = SDT {
+            transport_stream_id: 1,
+            original_network_id: 0x2001,
+            version: 3,
+            services: vec![SDTService {
+                service_id: 1,
+                eit_schedule: false,
+                eit_present_following: true,
+                running_status: RUNNING_STATUS_RUNNING,
+                free_ca_mode: false,
+                descriptors: vec![service.to_descriptor().unwrap()],
+            }],
+        };
+        let section = sdt.to_section(&crc).unwrap();
+        assert_eq!(crc.calculate(&section), 0);
+        let parsed = SDT::parse(&section, &crc).unwrap();
+        assert_eq!(parsed, sdt);
+        assert_eq!(parsed.services[0].service_descriptor(), Some(service));
+
+        let nit = NIT {
+            network_id: 0x2001,
+            version: 0,
+            descriptors: vec![NetworkNameDescriptor {
+                name: "Contribution".into(),
+            }
+            .to_descriptor()
+            .unwrap()],
+            transport_streams: vec![NITTransportStream {
+                transport_stream_id: 1,
+                original_network_id: 0x2001,
+                descriptors: Vec::new(),
+            }],
+        };
+        let parsed = SITable::parse(&nit.to_section(&crc).unwrap(), &crc).unwrap();
+        assert_eq!(parsed, Some(SITable::Nit(nit)));
+
+        // A corrupted section is rejected
+        let mut section = section;
+        section[10] ^= 0x01;
+        assert!(SDT::parse(&section, &crc).is_err());
+    }
+
+    #[test]
+    fn test_eit_and_time_tables_roundtrip() {
+        let crc = Crc32Mpeg2::new();
+        let start = Utc.with_ymd_and_hms(2024, 5, 1, 20, 15, 0).unwrap();
+        let eit = EIT {
+            service_id: 1,
+            transport_stream_id: 1,
+            original_network_id: 0x2001,
+            version: 1,
+            section_number: 0,
+            events: vec![EITEvent {
+                event_id: 42,
+                start_time: start,
+                duration: Duration::from_secs(90 * 60),
+                running_status: RUNNING_STATUS_RUNNING,
+                free_ca_mode: false,
+                descriptors: vec![ShortEventDescriptor {
+                    language: "eng".into(),
+                    event_name: "Evening news".into(),
+                    text: "Headlines".into(),
+                }
+                .to_descriptor()
+                .unwrap()],
+            }],
+        };
+        let parsed = EIT::parse(&eit.to_section(&crc).unwrap(), &crc).unwrap();
+        assert_eq!(
+            parsed.events[0].short_event().unwrap().event_name,
+            "Evening news"
+        );
+        assert_eq!(parsed, eit);
+
+        let tdt = SITable::Tdt(TDT { utc_time: start });
+        let section = tdt.to_section(&crc).unwrap();
+        assert_eq!(section.len(), 8);
+        assert_eq!(SITable::parse(&section, &crc).unwrap(), Some(tdt.clone()));
+        assert_eq!(
+            tdt.advanced(Duration::from_secs(61)),
+            SITable::Tdt(TDT {
+                utc_time: Utc.with_ymd_and_hms(2024, 5, 1, 20, 16, 1).unwrap()
+            })
+        );
+
+        let tot = SITable::Tot(TOT {
+            utc_time: start,
+            descriptors: vec![Descriptor {
+                tag: 0x58,
+                data: vec![
+                    b'd', b'e', b'u', 0x02, 0x01, 0x00, 0xC0, 0x79, 0, 0, 0, 0x02, 0x00,
+                ],
+            }],
+        });
+        let section = tot.to_section(&crc).unwrap();
+        assert_eq!(crc.calculate(&section), 0);
+        assert_eq!(SITable::parse(&section, &crc).unwrap(), Some(tot));
+
+        // Tables that are not supported are skipped
+        let other = Section {
+            table_id: 0x46,
+            table_id_extension: 2,
+            version: 0,
+            section_number: 0,
+            last_section_number: 0,
+            payload: vec![0x20, 0x01, 0xFF],
+        };
+        assert_eq!(
+            SITable::parse(&other.to_bytes(&crc).unwrap(), &crc).unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! Codec data shared by the Transport Stream tests.
+
+use crate::av::{CodecData, CodecDataExt, CodecType};
+
+/// Codec data of a test stream, with no parameters unless set.
+#[derive(Debug, Clone)]
+pub(crate) struct TestCodec {
+    codec_type: CodecType,
+    channels: Option<u8>,
+    extra_data: Option<Vec<u8>>,
+    language: Option<&'static str>,
+}
+
+impl TestCodec {
+    pub(crate) fn new(codec_type: CodecType) -> Self {
+        Self {
+            codec_type,
+            channels: None,
+            extra_data: None,
+            language: None,
+        }
+    }
+
+    pub(crate) fn with_channels(mut self, channels: u8) -> Self {
+        self.channels = Some(channels);
+        self
+    }
+
+    pub(crate) fn with_extra_data(mut self, extra_data: &[u8]) -> Self {
+        self.extra_data = Some(extra_data.to_vec());
+        self
+    }
+
+    pub(crate) fn with_language(mut self, language: &'static str) -> Self {
+        self.language = Some(language);
+        self
+    }
+
+    pub(crate) fn boxed(self) -> Box<dyn CodecDataExt> {
+        Box::new(self)
+    }
+}
+
+impl CodecData for TestCodec {
+    fn codec_type(&self) -> CodecType {
+        self.codec_type
+    }
+    fn width(&self) -> Option<u32> {
+        None
+    }
+    fn height(&self) -> Option<u32> {
+        None
+    }
+    fn extra_data(&self) -> Option<&[u8]> {
+        self.extra_data.as_deref()
+    }
+    fn channels(&self) -> Option<u8> {
+        self.channels
+    }
+    fn language(&self) -> Option<&str> {
+        self.language
+    }
+}
@@ -0,0 +1,197 @@
+use super::demuxer::{is_sparse, TSDemuxer};
+use super::muxer::TSMuxer;
+use super::types::PID_PMT;
+use crate::av::Packet;
+use crate::error::{Result, VdkError};
+use crate::format::{Demuxer as FormatDemuxer, Muxer as FormatMuxer};
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite};
+
+/// Copies a clip of a Transport Stream into a new Transport Stream, without
+/// re-encoding.
+///
+/// The clip starts at the last keyframe presented at or before `start`
+/// and ends before the first packet of each stream presented at or after
+/// `end`. Packets of other streams decoded before the keyframe are dropped.
+/// The output starts with PAT and PMT, keeps the programs of the input, and
+/// its timestamps are rebased so the keyframe is decoded at zero.
+///
+/// # Arguments
+///
+/// * `reader` - The Transport Stream to cut
+/// * `writer` - Destination of the clip
+/// * `start` - Start time on the timeline of the input's packets
+/// * `end` - End time on the same timeline
+///
+/// # Returns
+///
+/// The presentation time of the keyframe the clip starts at, or an error if
+/// the times are reversed, the input has no keyframes or fails to read
+pub async fn trim<R, W>(reader: R, writer: W, start: Duration, end: Duration) -> Result<Duration>
+where
+    R: AsyncRead + AsyncSeek + Unpin + Send,
+    W: AsyncWrite + Unpin + Send,
+{
+    if end <= start {
+        return Err(VdkError::InvalidData(format!(
+            "Clip end {:?} is not after its start {:?}",
+            end, start
+        )));
+    }
+
+    let mut demuxer = TSDemuxer::new(reader);
+    let streams = demuxer.streams().await?;
+    let keyframe = demuxer.seek(start).await?;
+    let base = demuxer
+        .index()
+        .and_then(|index| index.keyframe_at(start))
+        .map_or(0, |entry| entry.dts);
+
+    let mut muxer = TSMuxer::new(writer);
+    for (i, program) in demuxer.programs().iter().enumerate() {
+        muxer = muxer.with_program(
+            program.program_number,
+            PID_PMT + i as u16,
+            &program.stream_indices,
+        );
+    }
+    muxer.write_header(&streams).await?;
+
+    // Sparse streams may never reach the end of the clip
+    let mut open: HashSet<usize> = streams
+        .iter()
+        .enumerate()
+        .filter(|(_, stream)| !is_sparse(stream.codec_type()))
+        .map(|(index, _)| index)
+        .collect();
+    let end = end.as_nanos() as i64;
+    while !open.is_empty() {
+        let packet = match demuxer.read_packet().await {
+            Ok(packet) => packet,
+            Err(VdkError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        };
+        if packet.pts.is_some_and(|pts| pts >= end) {
+            open.remove(&packet.stream_index);
+            continue;
+        }
+        if packet.dts.or(packet.pts).is_some_and(|dts| dts < base) {
+            continue;
+        }
+        let rebased = Packet {
+            pts: packet.pts.map(|pts| pts - base),
+            dts: packet.dts.map(|dts| dts - base),
+            ..packet
+        };
+        muxer.write_packet(&rebased).await?;
+    }
+    muxer.write_trailer().await?;
+    Ok(keyframe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::av::CodecType;
+    use crate::format::ts::test_util::TestCodec;
+    use crate::format::ts::{TSIndex, PID_PAT, TS_PACKET_SIZE};
+    use std::io::Cursor;
+
+    /// Two seconds of 25 fps video with a keyframe every 10 frames, and
+    /// AAC frames every 20 ms, starting at 10 s.
+    async fn recording() -> Vec<u8> {
+        let streams = vec![
+            TestCodec::new(CodecType::H264).boxed(),
+            TestCodec::new(CodecType::AAC).boxed(),
+        ];
+        let mut muxer = TSMuxer::new(Cursor::new(Vec::new()));
+        muxer.write_header(&streams).await.unwrap();
+        let ms = 1_000_000i64;
+        for i in 0..100i64 {
+            let time = (10_000 + i * 20) * ms;
+            if i % 2 == 0 {
+                let frame = i / 2;
+                let key = frame % 10 == 0;
+                let mut video = vec![0, 0, 0, 1, if key { 0x65 } else { 0x41 }];
+                video.resize(400, frame as u8);
+                let packet = Packet::new(video)
+                    .with_stream_index(0)
+                    .with_pts(time)
+                    .with_key_flag(key);
+                muxer.write_packet(&packet).await.unwrap();
+            }
+            let mut audio = vec![0xFF, 0xF1, 0x4C, 0x80, 0x01, 0xFF, 0xFC];
+            audio.resize(15, i as u8);
+            let packet = Packet::new(audio).with_stream_index(1).with_pts(time);
+            muxer.write_packet(&packet).await.unwrap();
+        }
+        muxer.flush().await.unwrap();
+        muxer.into_inner().into_inner()
+    }
+
+    #[tokio::test]
+    async fn test_index_seek_and_trim() {
+        let input = recording().await;
+        let ms = 1_000_000i64;
+
+        let index = TSIndex::build(Cursor::new(&input)).await.unwrap();
+        assert_eq!(index.stream_index(), Some(0));
+        let keyframes: Vec<i64> = index.entries().iter().map(|e| e.pts / ms).collect();
+        assert_eq!(keyframes, [10_000, 10_400, 10_800, 11_200, 11_600]);
+        assert_eq!(index.start_time(), Some(Duration::from_secs(10)));
+
+        // Seeking lands on the keyframe before the requested time
+        let mut demuxer = TSDemuxer::new(Cursor::new(input.clone()));
+        demuxer.streams().await.unwrap();
+        for (time, keyframe) in [(10_500, 10_400), (11_250, 11_200), (10_100, 10_000)] {
+            let found = demuxer
+                .seek(Duration::from_millis(time as u64))
+                .await
+                .unwrap();
+            assert_eq!(found, Duration::from_millis(keyframe as u64));
+            let video = loop {
+                let packet = demuxer.read_packet().await.unwrap();
+                if packet.stream_index == 0 {
+                    break packet;
+                }
+            };
+            assert!(video.is_key);
+            assert_eq!(video.pts, Some(keyframe * ms));
+        }
+        assert_eq!(demuxer.index(), Some(&index));
+
+        let mut output = Vec::new();
+        let keyframe = trim(
+            Cursor::new(input),
+            &mut output,
+            Duration::from_millis(10_500),
+            Duration::from_millis(11_000),
+        )
+        .await
+        .unwrap();
+        assert_eq!(keyframe, Duration::from_millis(10_400));
+
+        // PAT and PMT come first
+        let pid = |packet: &[u8]| u16::from_be_bytes([packet[1] & 0x1F, packet[2]]);
+        assert_eq!(pid(&output[..TS_PACKET_SIZE]), PID_PAT);
+        assert_eq!(pid(&output[TS_PACKET_SIZE..]), PID_PMT);
+
+        let mut demuxer = TSDemuxer::new(Cursor::new(output));
+        assert_eq!(demuxer.streams().await.unwrap().len(), 2);
+        let mut packets = Vec::new();
+        while let Ok(packet) = demuxer.read_packet().await {
+            packets.push(packet);
+        }
+        let times = |index| -> Vec<i64> {
+            packets
+                .iter()
+                .filter(|p| p.stream_index == index)
+                .map(|p| p.pts.unwrap() / ms)
+                .collect()
+        };
+        assert_eq!(times(0), (0..15).map(|i| i * 40).collect::<Vec<_>>());
+        assert_eq!(times(1), (0..30).map(|i| i * 20).collect::<Vec<_>>());
+        assert!(packets.iter().find(|p| p.stream_index == 0).unwrap().is_key);
+    }
+}
@@ -1,3 +1,4 @@
+use super::scte35::DESCRIPTOR_REGISTRATION;
 use crate::error::Result;
 use bytes::{BufMut, BytesMut};
 use std::time::Duration;
@@ -7,14 +8,23 @@ use std::time::Duration;
 pub const STREAM_ID_H264: u8 = 0xe0;
 /// Stream ID for H.265 video streams in PES packets
 pub const STREAM_ID_H265: u8 = 0xe1;
+/// Stream ID for MPEG-2 video streams in PES packets
+pub const STREAM_ID_MPEG2_VIDEO: u8 = 0xe0;
 /// Stream ID for AAC audio streams in PES packets
 pub const STREAM_ID_AAC: u8 = 0xc0;
+/// Stream ID for MPEG-1/2 audio streams in PES packets
+pub const STREAM_ID_MPEG_AUDIO: u8 = 0xc0;
+/// Stream ID for private_stream_1 PES packets, used for timed metadata,
+/// AC-3, E-AC-3, Opus, subtitles, teletext and private data
+pub const STREAM_ID_PRIVATE_1: u8 = 0xbd;
 
 // PIDs
 /// PID for Program Association Table (PAT)
 pub const PID_PAT: u16 = 0x0000;
 /// PID for Program Map Table (PMT)
 pub const PID_PMT: u16 = 0x1000;
+/// PID of null packets, used for stuffing
+pub const PID_NULL: u16 = 0x1FFF;
 
 // Table IDs
 /// Table ID for Program Association Table (PAT)
@@ -33,12 +43,81 @@ pub const STREAM_TYPE_H264: u8 = 0x1b;
 pub const STREAM_TYPE_H265: u8 = 0x24;
 /// Stream type for AAC audio streams
 pub const STREAM_TYPE_AAC: u8 = 0x0f;
+/// Stream type for metadata carried in PES packets, e.g. ID3 timed metadata
+pub const STREAM_TYPE_METADATA: u8 = 0x15;
+/// Stream type for SCTE-35 splice information
+pub const STREAM_TYPE_SCTE35: u8 = 0x86;
 /// Stream type for Alignment Descriptor
 pub const STREAM_TYPE_ALIGNMENT_DESCRIPTOR: u8 = 0x06;
+/// Stream type for MPEG-1 video streams
+pub const STREAM_TYPE_MPEG1_VIDEO: u8 = 0x01;
+/// Stream type for MPEG-2 video streams
+pub const STREAM_TYPE_MPEG2_VIDEO: u8 = 0x02;
+/// Stream type for MPEG-1 audio streams
+pub const STREAM_TYPE_MPEG1_AUDIO: u8 = 0x03;
+/// Stream type for MPEG-2 audio streams, e.g. at lower sample rates
+pub const STREAM_TYPE_MPEG2_AUDIO: u8 = 0x04;
+/// Stream type for private data in PES packets, identified by descriptors
+pub const STREAM_TYPE_PRIVATE_DATA: u8 = 0x06;
+/// Stream type for AC-3 audio streams (ATSC)
+pub const STREAM_TYPE_AC3: u8 = 0x81;
+/// Stream type for E-AC-3 audio streams (ATSC)
+pub const STREAM_TYPE_EAC3: u8 = 0x87;
+
+// Descriptor tags
+/// Tag of the metadata_pointer_descriptor in the program info of a PMT
+pub const DESCRIPTOR_METADATA_POINTER: u8 = 0x25;
+/// Tag of the metadata_descriptor in the ES info of a metadata stream
+pub const DESCRIPTOR_METADATA: u8 = 0x26;
+/// Tag of the ISO_639_language_descriptor
+pub const DESCRIPTOR_ISO_639_LANGUAGE: u8 = 0x0a;
+/// Tag of the DVB teletext_descriptor
+pub const DESCRIPTOR_TELETEXT: u8 = 0x56;
+/// Tag of the DVB subtitling_descriptor
+pub const DESCRIPTOR_SUBTITLING: u8 = 0x59;
+/// Tag of the DVB AC-3_descriptor
+pub const DESCRIPTOR_AC3: u8 = 0x6a;
+/// Tag of the DVB enhanced_AC-3_descriptor
+pub const DESCRIPTOR_ENHANCED_AC3: u8 = 0x7a;
+/// Tag of the DVB extension_descriptor
+pub const DESCRIPTOR_EXTENSION: u8 = 0x7f;
+/// Extension tag of the Opus audio descriptor in an extension_descriptor
+pub const EXTENSION_TAG_OPUS: u8 = 0x80;
+
+// Registration format identifiers
+/// Format identifier of AC-3 audio streams
+pub const FORMAT_IDENTIFIER_AC3: [u8; 4] = *b"AC-3";
+/// Format identifier of E-AC-3 audio streams
+pub const FORMAT_IDENTIFIER_EAC3: [u8; 4] = *b"EAC3";
+/// Format identifier of Opus audio streams
+pub const FORMAT_IDENTIFIER_OPUS: [u8; 4] = *b"Opus";
+
+/// DVB subtitling_type of subtitles without a monitor aspect ratio
+const SUBTITLING_TYPE_NORMAL: u8 = 0x10;
+
+/// Teletext type of a teletext subtitle page
+const TELETEXT_TYPE_SUBTITLE: u8 = 0x02;
+
+/// PES_header_data_length of teletext PES packets (ETSI EN 300 472)
+pub const TELETEXT_PES_HEADER_DATA_LENGTH: u8 = 0x24;
+
+/// Application format and format fields identifying ID3 metadata
+const ID3_METADATA_FORMAT: [u8; 11] = [
+    0xFF, 0xFF, b'I', b'D', b'3', b' ', 0xFF, b'I', b'D', b'3', b' ',
+];
 
 // Constants
 /// Size of a Transport Stream packet in bytes
 pub const TS_PACKET_SIZE: usize = 188;
+/// Size of an M2TS (BDAV) packet: a 4-byte TP_extra_header with the
+/// arrival timestamp, followed by a TS packet
+pub const M2TS_PACKET_SIZE: usize = 192;
+/// Size of a TS packet followed by 16 bytes of Reed-Solomon parity
+pub const RS_PACKET_SIZE: usize = 204;
+/// Mask of the 30-bit arrival_time_stamp in a TP_extra_header
+pub const ARRIVAL_TIMESTAMP_MASK: u32 = 0x3FFF_FFFF;
+/// Sync byte at the start of every Transport Stream packet
+pub const TS_SYNC_BYTE: u8 = 0x47;
 /// Size of a Transport Stream header in bytes
 pub const TS_HEADER_SIZE: usize = 4;
 /// Maximum size of a PES header in bytes
@@ -127,7 +206,7 @@ impl PAT {
 ///
 /// Descriptors are used in both PMT and elementary stream info to provide
 /// supplementary information about the stream or program.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Descriptor {
     /// Tag identifying the descriptor type
     pub tag: u8,
@@ -135,6 +214,157 @@ pub struct Descriptor {
     pub data: Vec<u8>,
 }
 
+impl Descriptor {
+    /// Creates the metadata_pointer_descriptor announcing ID3 timed metadata
+    /// in the program info of a PMT.
+    ///
+    /// # Arguments
+    ///
+    /// * `program_number` - Program carrying the metadata stream
+    pub fn id3_metadata_pointer(program_number: u16) -> Self {
+        let mut data = ID3_METADATA_FORMAT.to_vec();
+        // metadata_service_id, then no locator record and same-program carriage
+        data.extend_from_slice(&[0x00, 0x1F]);
+        data.extend_from_slice(&program_number.to_be_bytes());
+        Self {
+            tag: DESCRIPTOR_METADATA_POINTER,
+            data,
+        }
+    }
+
+    /// Creates the metadata_descriptor of an ID3 timed metadata stream.
+    pub fn id3_metadata() -> Self {
+        let mut data = ID3_METADATA_FORMAT.to_vec();
+        // metadata_service_id, then no decoder config and no DSM-CC
+        data.extend_from_slice(&[0x00, 0x0F]);
+        Self {
+            tag: DESCRIPTOR_METADATA,
+            data,
+        }
+    }
+
+    /// Creates a registration_descriptor with a format identifier.
+    ///
+    /// # Arguments
+    ///
+    /// * `format_identifier` - Registered identifier of the stream format
+    pub fn registration(format_identifier: [u8; 4]) -> Self {
+        Self {
+            tag: DESCRIPTOR_REGISTRATION,
+            data: format_identifier.to_vec(),
+        }
+    }
+
+    /// Returns the format identifier of a registration_descriptor.
+    pub fn format_identifier(&self) -> Option<[u8; 4]> {
+        if self.tag != DESCRIPTOR_REGISTRATION {
+            return None;
+        }
+        self.data.get(..4)?.try_into().ok()
+    }
+
+    /// Creates the Opus audio descriptor of an Opus stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `channels` - Number of channels, 1 to 8 in Vorbis channel order
+    pub fn opus_audio(channels: u8) -> Self {
+        Self {
+            tag: DESCRIPTOR_EXTENSION,
+            data: vec![EXTENSION_TAG_OPUS, channels],
+        }
+    }
+
+    /// Returns the number of channels of an Opus audio descriptor.
+    ///
+    /// Only channel configurations 1 to 8 with their implicit channel
+    /// mapping are supported.
+    pub fn opus_channels(&self) -> Option<u8> {
+        match self.data[..] {
+            [EXTENSION_TAG_OPUS, channels, ..] if self.tag == DESCRIPTOR_EXTENSION => {
+                Some(channels).filter(|channels| (1..=8).contains(channels))
+            }
+            _ => None,
+        }
+    }
+
+    /// Creates an ISO_639_language_descriptor with an undefined audio type.
+    ///
+    /// # Arguments
+    ///
+    /// * `language` - ISO 639-2 language code
+    pub fn iso_639_language(language: [u8; 3]) -> Self {
+        let mut data = language.to_vec();
+        data.push(0);
+        Self {
+            tag: DESCRIPTOR_ISO_639_LANGUAGE,
+            data,
+        }
+    }
+
+    /// Creates a subtitling_descriptor of one DVB subtitle service, using
+    /// composition and ancillary page 1.
+    ///
+    /// # Arguments
+    ///
+    /// * `language` - ISO 639-2 language code
+    pub fn dvb_subtitling(language: [u8; 3]) -> Self {
+        let mut data = language.to_vec();
+        data.push(SUBTITLING_TYPE_NORMAL);
+        data.extend_from_slice(&1u16.to_be_bytes());
+        data.extend_from_slice(&1u16.to_be_bytes());
+        Self {
+            tag: DESCRIPTOR_SUBTITLING,
+            data,
+        }
+    }
+
+    /// Creates a teletext_descriptor of one subtitle page, page 888.
+    ///
+    /// # Arguments
+    ///
+    /// * `language` - ISO 639-2 language code
+    pub fn teletext_subtitles(language: [u8; 3]) -> Self {
+        let mut data = language.to_vec();
+        // Magazine 8 is coded as 0
+        data.push(TELETEXT_TYPE_SUBTITLE << 3);
+        data.push(0x88);
+        Self {
+            tag: DESCRIPTOR_TELETEXT,
+            data,
+        }
+    }
+
+    /// Returns the first language code of an ISO_639_language_descriptor,
+    /// subtitling_descriptor or teletext_descriptor.
+    pub fn language(&self) -> Option<String> {
+        if !matches!(
+            self.tag,
+            DESCRIPTOR_ISO_639_LANGUAGE | DESCRIPTOR_SUBTITLING | DESCRIPTOR_TELETEXT
+        ) {
+            return None;
+        }
+        let code = self.data.get(..3)?;
+        code.iter()
+            .all(u8::is_ascii_alphabetic)
+            .then(|| String::from_utf8_lossy(code).to_ascii_lowercase())
+    }
+
+    /// Returns true for a metadata_descriptor of ID3 timed metadata.
+    pub fn is_id3_metadata(&self) -> bool {
+        if self.tag != DESCRIPTOR_METADATA || self.data.len() < 2 {
+            return false;
+        }
+        // An application format of 0xFFFF is followed by its identifier
+        let mut offset = 2;
+        if self.data[..2] == [0xFF, 0xFF] {
+            offset += 4;
+        }
+        self.data.get(offset) == Some(&0xFF)
+            && self.data.get(offset + 1..offset + 5) == Some(&b"ID3 "[..])
+    }
+}
+
 /// Represents elementary stream information in PMT
 ///
 /// Each PMT contains one or more elementary stream info entries,
@@ -161,6 +391,8 @@ pub struct ElementaryStreamInfo {
 /// elementary streams (video, audio, etc.). Each program has its own PMT.
 #[derive(Debug, Clone, Default)]
 pub struct PMT {
+    /// Program number this PMT describes, as listed in the PAT
+    pub program_number: u16,
     /// PID carrying the Program Clock Reference (PCR)
     pub pcr_pid: u16,
     /// Descriptors that apply to the whole program
@@ -172,10 +404,11 @@ pub struct PMT {
 impl PMT {
     /// Creates a new empty Program Map Table
     ///
-    /// Initializes a PMT with default values (PCR PID 0) and empty descriptor
+    /// Initializes a PMT with default values (program number and PCR PID 0) and empty descriptor
     /// and elementary stream info lists.
     pub fn new() -> Self {
         Self {
+            program_number: 0,
             pcr_pid: 0,
             program_descriptors: Vec::new(),
             elementary_stream_infos: Vec::new(),
@@ -460,8 +693,9 @@ pub fn pcr_to_time(pcr: u64) -> Duration {
 ///
 /// 42-bit PCR value
 pub fn time_to_pcr(time: Duration) -> u64 {
-    let ts = time.as_nanos() as u64 * PCR_HZ / 1_000_000_000;
-    let base = ts / 300;
+    let ts = (time.as_nanos() * PCR_HZ as u128 / 1_000_000_000) as u64;
+    // The 33-bit base wraps like PTS/DTS
+    let base = (ts / 300) & 0x1_FFFF_FFFF;
     let ext = ts % 300;
     base << 15 | 0x3f << 9 | ext
 }
@@ -494,5 +728,5 @@ pub fn pts_to_time(pts: u64) -> Duration {
 ///
 /// PTS value at 90kHz clock rate
 pub fn time_to_pts(time: Duration) -> u64 {
-    time.as_nanos() as u64 * PTS_HZ / 1_000_000_000
+    (time.as_nanos() * PTS_HZ as u128 / 1_000_000_000) as u64
 }
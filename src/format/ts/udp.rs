@@ -0,0 +1,657 @@
+use super::types::*;
+use crate::format::rtp::{JitterBuffer, RTPError, RTPPacket, RTPPacketizer};
+use crate::format::rtsp::multicast::bind_multicast;
+use crate::{Result, VdkError};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::UdpSocket;
+use tokio::time::{Instant, Sleep};
+use url::{Host, Url};
+
+/// RTP payload type of MPEG-2 Transport Streams (RFC 3551)
+pub const RTP_PAYLOAD_TYPE_MP2T: u8 = 33;
+
+/// Default number of TS packets per datagram, the most fitting an Ethernet MTU
+pub const DEFAULT_PACKETS_PER_DATAGRAM: usize = 7;
+
+/// Default number of RTP packets held back to restore their order
+const DEFAULT_REORDER_DEPTH: usize = 16;
+
+/// Largest UDP payload
+const MAX_DATAGRAM_SIZE: usize = 65_536;
+
+/// Datagrams queued in a [`UDPSink`] before writes wait for sending
+const MAX_QUEUED_DATAGRAMS: usize = 256;
+
+/// Lag behind the PCR schedule after which pacing restarts from now
+const MAX_PACING_LAG: Duration = Duration::from_millis(100);
+
+/// Range of the 27 MHz program clock reference
+const PCR_WRAP: u64 = (1 << 33) * 300;
+
+/// Endpoint of a TS stream over UDP, from a `udp://` or `rtp://` URL.
+#[derive(Debug, Clone, PartialEq)]
+struct Endpoint {
+    /// RFC 2250 RTP encapsulation
+    rtp: bool,
+    host: Host,
+    port: u16,
+    /// Sender for source-specific multicast, given as `source@group`
+    source: Option<IpAddr>,
+    /// Multicast time-to-live of sent datagrams
+    ttl: Option<u8>,
+}
+
+impl Endpoint {
+    /// Parses URLs of the form `udp://[source@]host:port[?ttl=n]`, with the
+    /// `rtp` scheme for RTP encapsulation.
+    fn parse(url: &str) -> Result<Self> {
+        let url = Url::parse(url).map_err(|e| VdkError::Protocol(format!("Invalid URL: {}", e)))?;
+        let rtp = match url.scheme() {
+            "udp" => false,
+            "rtp" => true,
+            scheme => {
+                return Err(VdkError::Protocol(format!(
+                    "Unsupported scheme {}, expected udp or rtp",
+                    scheme
+                )))
+            }
+        };
+        // Hosts of non-special schemes are opaque to the URL parser
+        let host = match url.host() {
+            Some(Host::Domain(domain)) => match domain.parse() {
+                Ok(IpAddr::V4(ip)) => Host::Ipv4(ip),
+                Ok(IpAddr::V6(ip)) => Host::Ipv6(ip),
+                Err(_) => Host::Domain(domain.to_string()),
+            },
+            Some(host) => host.to_owned(),
+            None => return Err(VdkError::Protocol("URL has no host".into())),
+        };
+        let port = url
+            .port()
+            .ok_or_else(|| VdkError::Protocol("URL has no port".into()))?;
+        let source =
+            match url.username() {
+                "" => None,
+                source => Some(source.parse().map_err(|_| {
+                    VdkError::Protocol(format!("Invalid multicast source {}", source))
+                })?),
+            };
+        let ttl = url
+            .query_pairs()
+            .find(|(key, _)| key == "ttl")
+            .map(|(_, ttl)| {
+                ttl.parse()
+                    .map_err(|_| VdkError::Protocol(format!("Invalid TTL {}", ttl)))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            rtp,
+            host,
+            port,
+            source,
+            ttl,
+        })
+    }
+
+    /// Resolves the host to a socket address.
+    async fn address(&self) -> Result<SocketAddr> {
+        let ip = match &self.host {
+            Host::Ipv4(ip) => IpAddr::V4(*ip),
+            Host::Ipv6(ip) => IpAddr::V6(*ip),
+            Host::Domain(domain) => tokio::net::lookup_host((domain.as_str(), self.port))
+                .await?
+                .next()
+                .ok_or_else(|| VdkError::Protocol(format!("Unknown host {}", domain)))?
+                .ip(),
+        };
+        Ok(SocketAddr::new(ip, self.port))
+    }
+}
+
+/// Transport Stream source receiving UDP or RTP datagrams.
+///
+/// Implements [`AsyncRead`] over the TS packets of the datagrams, so it can
+/// be passed to [`TSDemuxer::new`](super::TSDemuxer::new). With RTP
+/// (RFC 2250), packets are put back in order through a [`JitterBuffer`];
+/// a missing packet is given up on once the reorder depth of later packets
+/// has arrived. Reading never ends, the stream is live.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use vdkio::format::ts::{TSDemuxer, UDPSource};
+/// use vdkio::format::Demuxer;
+///
+/// # async fn example() -> vdkio::Result<()> {
+/// let source = UDPSource::open("rtp://239.1.1.1:5000").await?;
+/// let mut demuxer = TSDemuxer::new(source);
+/// let streams = demuxer.streams().await?;
+/// let packet = demuxer.read_packet().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct UDPSource {
+    socket: UdpSocket,
+    rtp: bool,
+    jitter_buffer: JitterBuffer,
+    reorder_depth: usize,
+    /// Sequence number of the next RTP packet to read
+    next_sequence: Option<u16>,
+    lost_packets: u64,
+    /// Receive buffer
+    datagram: Vec<u8>,
+    /// TS data ready to be read
+    pending: Vec<u8>,
+    position: usize,
+}
+
+impl UDPSource {
+    /// Opens a source for a `udp://` or `rtp://` URL.
+    ///
+    /// A multicast host is joined on the URL's port, as
+    /// `udp://source@group:port` source-specifically. Any other host is the
+    /// local address to receive on, e.g. `udp://0.0.0.0:5000`.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - Address to receive on
+    pub async fn open(url: &str) -> Result<Self> {
+        let endpoint = Endpoint::parse(url)?;
+        let address = endpoint.address().await?;
+        let socket = if address.ip().is_multicast() {
+            bind_multicast(address.ip(), address.port(), endpoint.source, None)?
+        } else {
+            UdpSocket::bind(address).await?
+        };
+        Ok(Self::from_socket(socket, endpoint.rtp))
+    }
+
+    /// Creates a source receiving on an already bound socket.
+    ///
+    /// # Arguments
+    ///
+    /// * `socket` - Socket the datagrams arrive on
+    /// * `rtp` - Whether datagrams are RTP packets
+    pub fn from_socket(socket: UdpSocket, rtp: bool) -> Self {
+        Self {
+            socket,
+            rtp,
+            jitter_buffer: JitterBuffer::new(DEFAULT_REORDER_DEPTH + 1),
+            reorder_depth: DEFAULT_REORDER_DEPTH,
+            next_sequence: None,
+            lost_packets: 0,
+            datagram: vec![0; MAX_DATAGRAM_SIZE],
+            pending: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Sets how many later RTP packets are waited for before a missing one
+    /// is considered lost.
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - Number of packets, 16 by default
+    pub fn with_reorder_depth(mut self, depth: usize) -> Self {
+        self.reorder_depth = depth;
+        self.jitter_buffer = JitterBuffer::new(depth + 1);
+        self
+    }
+
+    /// Returns the local address of the socket.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Returns the number of RTP packets lost so far.
+    pub fn lost_packets(&self) -> u64 {
+        self.lost_packets
+    }
+
+    /// Adds the TS packets of a received datagram.
+    fn handle_datagram(&mut self, data: &[u8]) {
+        if !self.rtp {
+            self.pending.extend_from_slice(data);
+            return;
+        }
+
+        let Ok(packet) = RTPPacket::parse(data) else {
+            return;
+        };
+        // Late or duplicate packets can't be put back in order anymore
+        if let Some(next) = self.next_sequence {
+            if (packet.sequence_number.wrapping_sub(next) as i16) < 0 {
+                return;
+            }
+        }
+        match self.jitter_buffer.push(packet.clone()) {
+            Ok(()) => {}
+            Err(RTPError::SequenceWrapped) => {
+                // The buffer can't order across the wrap, release it first
+                while self.jitter_buffer.peek().is_some() {
+                    self.release_next();
+                }
+                let _ = self.jitter_buffer.push(packet);
+            }
+            Err(_) => {
+                self.release_next();
+                let _ = self.jitter_buffer.push(packet);
+            }
+        }
+
+        while let Some(head) = self.jitter_buffer.peek().map(|p| p.sequence_number) {
+            let in_order = self.next_sequence.is_none() || self.next_sequence == Some(head);
+            if !in_order && self.jitter_buffer.len() <= self.reorder_depth {
+                break;
+            }
+            self.release_next();
+        }
+    }
+
+    /// Moves the earliest buffered RTP packet to the readable data.
+    fn release_next(&mut self) {
+        let Some(packet) = self.jitter_buffer.pop_next() else {
+            return;
+        };
+        if let Some(next) = self.next_sequence {
+            self.lost_packets += packet.sequence_number.wrapping_sub(next) as u64;
+        }
+        self.next_sequence = Some(packet.sequence_number.wrapping_add(1));
+        self.pending.extend_from_slice(&packet.payload);
+    }
+}
+
+impl AsyncRead for UDPSource {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.position < this.pending.len() {
+                let n = buf.remaining().min(this.pending.len() - this.position);
+                buf.put_slice(&this.pending[this.position..this.position + n]);
+                this.position += n;
+                return Poll::Ready(Ok(()));
+            }
+            this.pending.clear();
+            this.position = 0;
+
+            let mut datagram = std::mem::take(&mut this.datagram);
+            let mut received = ReadBuf::new(&mut datagram);
+            let result = this.socket.poll_recv(cx, &mut received);
+            let len = received.filled().len();
+            if let Poll::Ready(Ok(())) = result {
+                this.handle_datagram(&datagram[..len]);
+            }
+            this.datagram = datagram;
+            ready!(result)?;
+        }
+    }
+}
+
+/// Transport Stream sink sending UDP or RTP datagrams.
+///
+/// Implements [`AsyncWrite`], so it can be passed to
+/// [`TSMuxer::new`](super::TSMuxer::new). Written TS packets are grouped
+/// into datagrams of 7 packets, optionally wrapped in RTP (RFC 2250).
+///
+/// Datagrams are paced by the PCRs of the first PID carrying them: the
+/// datagrams between two PCRs are spread evenly over the PCR interval,
+/// instead of leaving in bursts that overflow receiver buffers. Pacing
+/// holds back the datagrams after the last PCR, and flushing waits until
+/// the others are sent, so a muxer writing into the sink runs in real time.
+/// Shutting the sink down sends everything that is left.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use vdkio::format::ts::{TSMuxer, UDPSink};
+///
+/// # async fn example() -> vdkio::Result<()> {
+/// let sink = UDPSink::open("udp://239.1.1.1:5000?ttl=16").await?;
+/// let mut muxer = TSMuxer::new(sink);
+/// # Ok(())
+/// # }
+/// ```
+pub struct UDPSink {
+    socket: UdpSocket,
+    packetizer: Option<RTPPacketizer>,
+    packets_per_datagram: usize,
+    /// Written bytes not forming a complete TS packet yet
+    partial: Vec<u8>,
+    /// TS packets of the datagram being filled
+    datagram: Vec<u8>,
+    /// First PCR in the datagram being filled
+    datagram_pcr: Option<u64>,
+    /// PID whose PCRs pace the output
+    pcr_pid: Option<u16>,
+    /// Last PCR and the time it is sent at
+    last_pcr: Option<(u64, Instant)>,
+    /// Complete datagrams waiting for the next PCR
+    held: Vec<Vec<u8>>,
+    /// Datagrams ready to send, with the time they are due
+    queue: VecDeque<(Instant, Vec<u8>)>,
+    sleep: Pin<Box<Sleep>>,
+    /// Reference of RTP timestamps
+    epoch: Instant,
+}
+
+impl UDPSink {
+    /// Opens a sink sending to a `udp://` or `rtp://` URL.
+    ///
+    /// The `ttl` query parameter sets the multicast time-to-live, as in
+    /// `udp://239.1.1.1:5000?ttl=16`.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - Unicast or multicast destination
+    pub async fn open(url: &str) -> Result<Self> {
+        let endpoint = Endpoint::parse(url)?;
+        let address = endpoint.address().await?;
+        let local: SocketAddr = match address {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = UdpSocket::bind(local).await?;
+        if let (Some(ttl), IpAddr::V4(_)) = (endpoint.ttl, address.ip()) {
+            socket.set_multicast_ttl_v4(ttl as u32)?;
+        }
+        socket.connect(address).await?;
+        Ok(Self::from_socket(socket, endpoint.rtp))
+    }
+
+    /// Creates a sink sending over an already connected socket.
+    ///
+    /// # Arguments
+    ///
+    /// * `socket` - Socket connected to the destination
+    /// * `rtp` - Whether to wrap datagrams in RTP
+    pub fn from_socket(socket: UdpSocket, rtp: bool) -> Self {
+        let epoch = Instant::now();
+        let packetizer = rtp.then(|| {
+            // Differs between sinks, as RFC 3550 asks of SSRCs
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.subsec_nanos());
+            let ssrc = nanos ^ std::process::id().rotate_left(16);
+            RTPPacketizer::new(RTP_PAYLOAD_TYPE_MP2T, ssrc)
+        });
+        Self {
+            socket,
+            packetizer,
+            packets_per_datagram: DEFAULT_PACKETS_PER_DATAGRAM,
+            partial: Vec::new(),
+            datagram: Vec::new(),
+            datagram_pcr: None,
+            pcr_pid: None,
+            last_pcr: None,
+            held: Vec::new(),
+            queue: VecDeque::new(),
+            sleep: Box::pin(tokio::time::sleep_until(epoch)),
+            epoch,
+        }
+    }
+
+    /// Sets the number of TS packets per datagram.
+    ///
+    /// # Arguments
+    ///
+    /// * `packets` - TS packets per datagram, 7 by default
+    pub fn with_packets_per_datagram(mut self, packets: usize) -> Self {
+        self.packets_per_datagram = packets.max(1);
+        self
+    }
+
+    /// Adds a TS packet to the datagram being filled.
+    fn push_packet(&mut self, packet: &[u8]) {
+        if let Some((pid, pcr)) = packet_pcr(packet) {
+            let pcr_pid = *self.pcr_pid.get_or_insert(pid);
+            if pid == pcr_pid && self.datagram_pcr.is_none() {
+                self.datagram_pcr = Some(pcr);
+            }
+        }
+        self.datagram.extend_from_slice(packet);
+        if self.datagram.len() >= self.packets_per_datagram * TS_PACKET_SIZE {
+            self.finish_datagram();
+        }
+    }
+
+    /// Completes the datagram being filled, releasing the held ones when it
+    /// carries a PCR.
+    fn finish_datagram(&mut self) {
+        if self.datagram.is_empty() {
+            return;
+        }
+        self.held.push(std::mem::take(&mut self.datagram));
+        match self.datagram_pcr.take() {
+            Some(pcr) => self.release(Some(pcr)),
+            // Before the first PCR, there is nothing to pace by
+            None if self.last_pcr.is_none() => self.release(None),
+            None => {}
+        }
+    }
+
+    /// Schedules the held datagrams evenly up to the time of `pcr`, or
+    /// right away without a PCR.
+    fn release(&mut self, pcr: Option<u64>) {
+        let now = Instant::now();
+        let (start, end) = match (pcr, self.last_pcr) {
+            (Some(pcr), Some((last, at))) => {
+                let delta = (pcr + PCR_WRAP - last) % PCR_WRAP;
+                let end = at + Duration::from_nanos(delta * 1000 / 27);
+                // Restart after PCR jumps, or when sending fell behind
+                if delta > PCR_HZ || end + MAX_PACING_LAG < now {
+                    (now, now)
+                } else {
+                    (at, end)
+                }
+            }
+            _ => (now, now),
+        };
+        if let Some(pcr) = pcr {
+            self.last_pcr = Some((pcr, end));
+        }
+
+        let count = self.held.len() as u32;
+        for (i, datagram) in std::mem::take(&mut self.held).into_iter().enumerate() {
+            let due = start + (end - start) * (i as u32 + 1) / count;
+            let datagram = match &mut self.packetizer {
+                Some(packetizer) => {
+                    // 90 kHz timestamp of the transmission time
+                    let timestamp = ((due - self.epoch).as_nanos() * 9 / 100_000) as u32;
+                    packetizer
+                        .next_packet(datagram.into(), timestamp, false)
+                        .to_bytes()
+                }
+                None => datagram,
+            };
+            self.queue.push_back((due, datagram));
+        }
+    }
+
+    /// Sends the queued datagrams as they become due.
+    fn poll_send_queue(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while let Some((due, datagram)) = self.queue.front() {
+            if *due > Instant::now() {
+                self.sleep.as_mut().reset(*due);
+                ready!(self.sleep.as_mut().poll(cx));
+            }
+            ready!(self.socket.poll_send(cx, datagram))?;
+            self.queue.pop_front();
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Returns the PID and PCR of a TS packet carrying a PCR.
+fn packet_pcr(packet: &[u8]) -> Option<(u16, u64)> {
+    let has_adaptation = packet[3] & 0x20 != 0;
+    if packet[0] != TS_SYNC_BYTE || !has_adaptation || packet[4] < 7 || packet[5] & 0x10 == 0 {
+        return None;
+    }
+    let pid = (((packet[1] & 0x1F) as u16) << 8) | packet[2] as u16;
+    let pcr = &packet[6..12];
+    let base = ((pcr[0] as u64) << 25)
+        | ((pcr[1] as u64) << 17)
+        | ((pcr[2] as u64) << 9)
+        | ((pcr[3] as u64) << 1)
+        | (pcr[4] as u64 >> 7);
+    let extension = (((pcr[4] & 0x01) as u64) << 8) | pcr[5] as u64;
+    Some((pid, base * 300 + extension))
+}
+
+impl AsyncWrite for UDPSink {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.queue.len() >= MAX_QUEUED_DATAGRAMS {
+            ready!(this.poll_send_queue(cx))?;
+        }
+
+        this.partial.extend_from_slice(buf);
+        let complete = this.partial.len() / TS_PACKET_SIZE * TS_PACKET_SIZE;
+        let data: Vec<u8> = this.partial.drain(..complete).collect();
+        for packet in data.chunks(TS_PACKET_SIZE) {
+            this.push_packet(packet);
+        }
+
+        // Sends what is due, the rest waits for later writes or a flush
+        if let Poll::Ready(Err(e)) = this.poll_send_queue(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_send_queue(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.finish_datagram();
+        if !this.held.is_empty() {
+            this.release(None);
+        }
+        this.poll_send_queue(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::av::{self, Packet};
+    use crate::format::ts::test_util::TestCodec;
+    use crate::format::ts::{TSDemuxer, TSMuxer};
+    use crate::format::{Demuxer as FormatDemuxer, Muxer as FormatMuxer};
+    use bytes::Bytes;
+    use std::net::Ipv4Addr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_udp_url_parsing() {
+        let endpoint = Endpoint::parse("rtp://192.0.2.10@232.1.1.1:5000?ttl=16").unwrap();
+        assert!(endpoint.rtp);
+        assert_eq!(
+            endpoint.host,
+            Host::<String>::Ipv4(Ipv4Addr::new(232, 1, 1, 1))
+        );
+        assert_eq!(endpoint.port, 5000);
+        assert_eq!(
+            endpoint.source,
+            Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 10)))
+        );
+        assert_eq!(endpoint.ttl, Some(16));
+
+        let endpoint = Endpoint::parse("udp://[ff15::1]:1234").unwrap();
+        assert!(!endpoint.rtp);
+        assert!(matches!(endpoint.host, Host::Ipv6(_)));
+        assert_eq!(endpoint.source, None);
+
+        assert!(Endpoint::parse("http://127.0.0.1:5000").is_err());
+        assert!(Endpoint::parse("udp://127.0.0.1").is_err());
+        assert!(Endpoint::parse("udp://127.0.0.1:5000?ttl=300").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_udp_source_rtp_reordering() {
+        let mut source = UDPSource::open("rtp://127.0.0.1:0")
+            .await
+            .unwrap()
+            .with_reorder_depth(2);
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        sender.connect(source.local_addr().unwrap()).await.unwrap();
+
+        // Packet 3 comes late, packet 5 is lost
+        for seq in [1u16, 2, 4, 3, 6, 7, 8] {
+            let payload = Bytes::from(vec![seq as u8; TS_PACKET_SIZE]);
+            let packet = RTPPacket::new(RTP_PAYLOAD_TYPE_MP2T, seq, 0, 1, false, payload);
+            sender.send(&packet.to_bytes()).await.unwrap();
+        }
+
+        let mut data = vec![0u8; TS_PACKET_SIZE * 5];
+        source.read_exact(&mut data).await.unwrap();
+        let order: Vec<u8> = data.chunks(TS_PACKET_SIZE).map(|p| p[0]).collect();
+        assert_eq!(order, vec![1, 2, 3, 4, 6]);
+        assert_eq!(source.lost_packets(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_udp_sink_to_source() {
+        for url in ["udp://127.0.0.1:0", "rtp://127.0.0.1:0"] {
+            let source = UDPSource::open(url).await.unwrap();
+            let address = source.local_addr().unwrap();
+            let scheme = &url[..3];
+            let sink = UDPSink::open(&format!("{}://{}", scheme, address))
+                .await
+                .unwrap();
+
+            // 400 ms of video, sent in real time
+            let start = Instant::now();
+            let mut muxer = TSMuxer::new(sink);
+            // Parameter sets let a live demuxer probe the stream
+            let parameter_sets = [
+                0, 0, 0, 1, 0x67, 66, 0xC0, 30, 0xDA, 0x01, 0x40, 0x16, 0xE4, 0, 0, 0, 1, 0x68,
+                0xCE, 0x38, 0x80,
+            ];
+            let streams = vec![TestCodec::new(av::CodecType::H264)
+                .with_extra_data(&parameter_sets)
+                .boxed()];
+            muxer.write_header(&streams).await.unwrap();
+            for i in 0..10 {
+                let mut data = vec![0, 0, 0, 1, 0x65];
+                data.resize(1000, 0x88);
+                let packet = Packet::new(data)
+                    .with_stream_index(0)
+                    .with_dts(i * 40_000_000 + 100_000_000)
+                    .with_key_flag(true);
+                muxer.write_packet(&packet).await.unwrap();
+            }
+            let mut sink = muxer.into_inner();
+            sink.shutdown().await.unwrap();
+            // PCRs span 360 ms
+            assert!(start.elapsed() >= Duration::from_millis(300));
+
+            let mut demuxer = TSDemuxer::new(source);
+            assert_eq!(demuxer.streams().await.unwrap().len(), 1);
+            // The last two packets wait for the start of a following one
+            for i in 0..8 {
+                let packet = demuxer.read_packet().await.unwrap();
+                assert_eq!(packet.dts, Some(i * 40_000_000 + 100_000_000));
+                assert!(packet.data.ends_with(&[0x88; 995]));
+            }
+        }
+    }
+}
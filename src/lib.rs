@@ -1,6 +1,6 @@
 #![doc(html_root_url = "https://docs.rs/vdkio/0.1.0")]
 #![cfg_attr(docsrs, feature(doc_cfg))]
-#![deny(missing_docs)]
+#![warn(missing_docs)]
 #![deny(rustdoc::missing_crate_level_docs)]
 
 //! # vdkio - Rust Video Development Kit
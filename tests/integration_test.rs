@@ -12,7 +12,7 @@ mod tests {
     use tokio::time::{timeout, Duration}; 
     use vdkio::av;
     use vdkio::av::{CodecData, CodecDataExt};
-    use vdkio::av::Packet;
+    use vdkio::av::{Packet, Timebase, TimestampNormalizer};
     use vdkio::config;
     use vdkio::error::{Result, VdkError};
     use vdkio::format::rtsp::{MediaStream, RTSPClient, TransportInfo};
@@ -305,6 +305,12 @@ mod tests {
         // Read and process RTP packets
         if let Some(mut rx) = rtsp_client.get_packet_receiver() {
             let mut packets = 0;
+            // RTP timestamps of both streams are 32-bit 90 kHz values
+            let mut timestamps = TimestampNormalizer::new()
+                .with_timebase(0, Timebase::MPEG)
+                .with_wraparound(0, 32)
+                .with_timebase(1, Timebase::MPEG)
+                .with_wraparound(1, 32);
             let mut last_write = time::Instant::now();
 
             while packets < 100 {
@@ -333,10 +339,12 @@ mod tests {
                             data.extend_from_slice(&rtp.payload);
                             let payload = Bytes::from(data);
 
-                            let packet = Packet::new(payload)
-                                .with_stream_index(stream_index)
-                                .with_pts((rtp.timestamp as i64 / 90) as i64) // Convert 90kHz to ms
-                                .with_key_flag(rtp.marker); // Use marker bit for keyframe
+                            let packet = timestamps.normalize(
+                                Packet::new(payload)
+                                    .with_stream_index(stream_index)
+                                    .with_pts(rtp.timestamp as i64)
+                                    .with_key_flag(rtp.marker), // Use marker bit for keyframe
+                            );
 
                             muxer.write_packet(&packet).await?;
                             packets += 1;
@@ -3,7 +3,7 @@ use std::path::PathBuf;
 use std::time::Duration;
 use tokio::fs::File;
 use tokio::time::timeout;
-use vdkio::av::{CodecData, CodecDataExt, CodecType, Packet};
+use vdkio::av::{CodecData, CodecDataExt, CodecType, Packet, Timebase, TimestampNormalizer};
 use vdkio::config;
 use vdkio::error::{Result, VdkError};
 use vdkio::format::rtsp::{MediaStream, RTSPClient, TransportInfo};
@@ -120,18 +120,22 @@ async fn test_ts_segment_validation() -> Result<()> {
         let mut segment_count = 0;
         let mut last_pcr = 0i64;
         let mut last_segment_time = 0i64;
+        let mut timestamps = TimestampNormalizer::new()
+            .with_timebase(video_index, Timebase::MPEG)
+            .with_wraparound(video_index, 32);
         
         while segment_count < 3 {
             match timeout(Duration::from_secs(5), rx.recv()).await {
                 Ok(Some(data)) => {
                     if let Ok(rtp) = RTPPacket::parse(&data) {
-                        let pts = (rtp.timestamp as i64 / 90) as i64;
-                        
-                        // Create packet
-                        let packet = Packet::new(Bytes::from(data))
-                            .with_stream_index(video_index)
-                            .with_pts(pts)
-                            .with_key_flag(rtp.marker);
+                        // Create packet, with the RTP timestamp in nanoseconds
+                        let packet = timestamps.normalize(
+                            Packet::new(Bytes::from(data))
+                                .with_stream_index(video_index)
+                                .with_pts(rtp.timestamp as i64)
+                                .with_key_flag(rtp.marker),
+                        );
+                        let pts = packet.pts.unwrap_or_default();
                             
                         // Write packet and check timing
                         muxer.write_packet(&packet).await?;
@@ -139,12 +143,12 @@ async fn test_ts_segment_validation() -> Result<()> {
                         // Validate PCR timing
                         if pts > last_pcr {
                             let pcr_delta = pts - last_pcr;
-                            assert!(pcr_delta <= 100, "PCR jump too large");
+                            assert!(pcr_delta <= 100_000_000, "PCR jump too large");
                             last_pcr = pts;
                         }
                         
                         // Check segment duration
-                        if pts - last_segment_time >= SEGMENT_DURATION.as_millis() as i64 {
+                        if pts - last_segment_time >= SEGMENT_DURATION.as_nanos() as i64 {
                             muxer.write_trailer().await?;
                             segment_count += 1;
                             last_segment_time = pts;